@@ -0,0 +1,179 @@
+use crate::layout::{self, CHAPTER_CLASS, LANDSCAPE_CLASS};
+use crate::process;
+use crate::remote;
+use crate::security;
+use crate::settings::Settings;
+use crate::setup;
+use crate::style;
+use pulldown_cmark::{Event, Tag, TagEnd};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use url::Url;
+
+/// Chromium builds, in the order they're looked for: programs on the PATH, then where the
+/// Chrome installers put it
+const CHROMIUM_CANDIDATES: &[&str] = &[
+    "chromium",
+    "chromium-browser",
+    "google-chrome",
+    "google-chrome-stable",
+    "/Applications/Google Chrome.app/Contents/MacOS/Google Chrome",
+    r"C:\Program Files\Google\Chrome\Application\chrome.exe",
+    r"C:\Program Files (x86)\Google\Chrome\Application\chrome.exe",
+];
+
+/// The Chromium to render with, None if none is installed. Installed paths are only checked
+/// for existence, as running Chrome on Windows opens a window.
+pub fn find_chromium() -> Option<&'static str> {
+    CHROMIUM_CANDIDATES.iter().copied().find(|candidate| {
+        if Path::new(candidate).is_absolute() {
+            Path::new(candidate).is_file()
+        } else {
+            setup::find(candidate).is_some()
+        }
+    })
+}
+
+/// Page setup wkhtmltopdf takes as arguments, as CSS: A4 pages with the margins of the
+/// settings, landscape pages for the marked blocks and chapters starting on right-hand pages
+fn page_css(settings: &Settings) -> String {
+    let margin = if settings.style.enabled {
+        settings.style.margin_mm.max(0.0)
+    } else {
+        style::DEFAULT_MARGIN_MM
+    };
+    let mut css = format!(
+        "@page {{ size: A4; margin: {margin}mm; }}\n\
+         @page landscape {{ size: A4 landscape; }}\n\
+         .{LANDSCAPE_CLASS} {{ page: landscape; }}\n\
+         .{CHAPTER_CLASS} {{ break-before: right; }}\n"
+    );
+    if settings.zoom != 1.0 {
+        css.push_str(&format!("html {{ zoom: {}; }}\n", settings.zoom));
+    }
+    if settings.prints_backgrounds() {
+        css.push_str("* { -webkit-print-color-adjust: exact; print-color-adjust: exact; }\n");
+    }
+    css
+}
+
+/// Warns about the images among `events` that have no alt text, which screen readers can
+/// only announce by their file name. Images meant as decoration are better left out.
+pub fn check_alt_text(events: &[Event], warnings: &mut Vec<String>) {
+    let mut image: Option<(&str, String)> = None;
+    for event in events {
+        match event {
+            Event::Start(Tag::Image { dest_url, .. }) if image.is_none() => {
+                image = Some((dest_url, String::new()));
+            }
+            Event::Text(text) | Event::Code(text) => {
+                if let Some((_, alt)) = &mut image {
+                    alt.push_str(text);
+                }
+            }
+            Event::End(TagEnd::Image) => {
+                if let Some((src, alt)) = image.take()
+                    && alt.trim().is_empty()
+                {
+                    warnings.push(format!(
+                        "Image without alt text for screen readers: '{src}'"
+                    ));
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Renders the complete HTML document `full_html` to a tagged PDF at `pdf_path` with headless
+/// Chromium, whose structure tree of headings, lists, tables and image alt texts lets screen
+/// readers navigate it. The document is rendered in one pass, as merging separately rendered
+/// sections would drop the tags: landscape blocks and chapters are laid out with CSS, page
+/// numbering restarts are left out with a warning added to `warnings`, as is grayscale.
+/// Scripts don't run, and the Content-Security-Policy is always applied, with local files
+/// only loaded from `local_roots`. The HTML is written to `temp_dir` for Chromium.
+pub fn render_pdf(
+    settings: &Settings,
+    full_html: &str,
+    local_roots: &[PathBuf],
+    temp_dir: &Path,
+    pdf_path: &Path,
+    warnings: &mut Vec<String>,
+) -> Result<(), String> {
+    let Some(chromium) = find_chromium() else {
+        return Err(format!(
+            "Accessible PDFs are rendered with Chromium or Chrome, which wasn't found. {}",
+            setup::install_hint("chromium")
+        ));
+    };
+    let (html, restarts) = layout::paged_html(full_html);
+    if restarts {
+        warnings.push(
+            "Page numbering restarts aren't supported for accessible PDFs and were left out"
+                .to_string(),
+        );
+    }
+    if settings.grayscale {
+        warnings.push("Accessible PDFs can't be grayscale and keep their colors".to_string());
+    }
+    // Chromium reads `file:` URLs, only the policy keeps the document to its folders
+    let has_policy =
+        settings.offline || (settings.inject_csp && !settings.csp_policy.trim().is_empty());
+    let csp_meta = if has_policy {
+        String::new()
+    } else {
+        security::csp_meta(&security::scoped_policy(security::DEFAULT_CSP, local_roots))
+    };
+    let style = format!(
+        "{csp_meta}\n<style>\n{}</style>\n</head>",
+        page_css(settings)
+    );
+    let html = match html.find("</head>") {
+        Some(end) => format!(
+            "{}{}{}",
+            &html[..end],
+            style,
+            &html[end + "</head>".len()..]
+        ),
+        None => html,
+    };
+
+    let html_file_path = temp_dir.join("accessible.html");
+    fs::write(&html_file_path, html)
+        .map_err(|e| format!("Failed to write temporary HTML: {}", e))?;
+    let url = Url::from_file_path(&html_file_path)
+        .map_err(|()| format!("Invalid path '{}'", html_file_path.display()))?;
+    // Chromium exits successfully even when it couldn't write the PDF
+    let _ = fs::remove_file(pdf_path);
+    let mut command = Command::new(chromium);
+    command
+        .args([
+            "--headless",
+            "--disable-gpu",
+            "--blink-settings=scriptEnabled=false",
+        ])
+        // The second is the older name of the first
+        .args(["--no-pdf-header-footer", "--print-to-pdf-no-header"])
+        .args(["--export-tagged-pdf", "--generate-pdf-document-outline"]);
+    if !settings.offline
+        && let Some(proxy) = remote::proxy(&settings.proxy)
+    {
+        command.arg(format!("--proxy-server={proxy}"));
+    }
+    command
+        .arg(format!("--print-to-pdf={}", pdf_path.display()))
+        .arg(url.as_str());
+    let output = command.output();
+    let _ = fs::remove_file(&html_file_path);
+
+    match output {
+        Ok(command_output) if command_output.status.success() && pdf_path.is_file() => Ok(()),
+        Ok(command_output) => Err(process::failure("Chromium", &command, &command_output)),
+        Err(e) => Err(format!(
+            "Failed to execute Chromium, which renders accessible PDFs: {}. {}",
+            e,
+            setup::install_hint("chromium")
+        )),
+    }
+}
@@ -0,0 +1,241 @@
+use crate::budget;
+use crate::convert;
+use crate::encryption::{self, Encryption};
+use crate::hook;
+use crate::impose;
+use crate::metadata;
+use crate::optimize::{self, Optimization};
+use crate::paths::TempDir;
+use crate::pdf;
+use crate::pdfa;
+use crate::prepress::{self, PrintMarks};
+use crate::reproducible;
+use crate::settings::Settings;
+use crate::stamp::{self, Bates};
+use lopdf::{Bookmark, Document, Object, TocType, dictionary};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Bookmark style for the per-file entries (bold), the headings below them use the default
+const FILE_BOOKMARK_FORMAT: u32 = 2;
+
+/// A converted input waiting to be merged
+struct Part {
+    input: PathBuf,
+    title: String,
+    document: Document,
+    /// The part's own outline, i.e. its headings
+    headings: Vec<TocType>,
+}
+
+/// Converts every file in `inputs` and combines them into a single PDF at `output`.
+/// The result gets a top-level bookmark per source file with that file's headings nested
+/// beneath it. Files split into landscape sections lose their heading bookmarks.
+/// The post-processing command, cover and appendix PDFs, Bates numbers, print marks, the
+/// booklet, optimization, encryption and PDF/A apply to the combined PDF only, so Bates
+/// numbers run on across files and the booklet takes in all of them. The parts get no
+/// checksum files.
+/// Returns the warnings of all files, prefixed with the file they came from.
+pub fn convert_merged(
+    settings: &Settings,
+    inputs: &[PathBuf],
+    output: &Path,
+) -> Result<Vec<String>, String> {
+    settings.check_pdf_options()?;
+    let part_settings = Settings {
+        post_command: String::new(),
+        encryption: Encryption::default(),
+        pdf_a: false,
+        cover_pdf: String::new(),
+        appendix_pdf: String::new(),
+        optimization: Optimization::default(),
+        bates: Bates::default(),
+        print_marks: PrintMarks::default(),
+        booklet: false,
+        checksums: false,
+        ..settings.clone()
+    };
+    let temp_dir = TempDir::new()?;
+    let mut part_paths = Vec::new();
+    let mut warnings = Vec::new();
+    let mut result = Ok(());
+    for (i, input) in inputs.iter().enumerate() {
+        let part_path = temp_dir.path().join(format!("part_{i}.pdf"));
+        result = convert::convert_file(&part_settings, input, &part_path)
+            .map(|file_warnings| {
+                warnings.extend(
+                    file_warnings
+                        .into_iter()
+                        .map(|warning| format!("{}: {}", input.display(), warning)),
+                );
+            })
+            .map_err(|e| format!("{}: {}", input.display(), e));
+        part_paths.push(part_path);
+        if result.is_err() {
+            break;
+        }
+    }
+
+    let parts = result.and_then(|()| {
+        inputs
+            .iter()
+            .zip(&part_paths)
+            .map(|(input, part_path)| load_part(input, part_path))
+            .collect::<Result<Vec<_>, _>>()
+    });
+    for part_path in &part_paths {
+        let _ = fs::remove_file(part_path);
+    }
+
+    if settings.accessible {
+        warnings.push("Merging drops the tags of accessible PDFs".to_string());
+    }
+    let mut merged = merge_with_bookmarks(parts?)?;
+    pdf::attach_pdfs(settings, &mut merged)?;
+    if settings.bates.enabled {
+        stamp::apply_bates(&mut merged, &settings.bates)?;
+    }
+    if settings.print_marks.enabled {
+        prepress::apply_print_marks(&mut merged, &settings.print_marks)?;
+    }
+    if settings.booklet {
+        impose::apply_booklet(&mut merged)?;
+    }
+    let before = optimize::apply(&mut merged, &settings.optimization)?;
+    // Merging leaves out the parts' document information
+    metadata::apply(&mut merged, &settings.metadata)?;
+    if settings.reproducible {
+        let timestamp = reproducible::timestamp(settings.metadata.creation_date());
+        reproducible::apply(&mut merged, timestamp, temp_dir.path())?;
+    }
+    if settings.encryption.enabled {
+        encryption::apply(&mut merged, &settings.encryption)?;
+    }
+    if let Some(parent) = output.parent() {
+        fs::create_dir_all(parent)
+            .map_err(|e| format!("Failed to create output directory: {}", e))?;
+    }
+    optimize::save(&mut merged, output, &settings.optimization)
+        .map_err(|e| format!("Failed to save merged PDF: {}", e))?;
+    if settings.pdf_a {
+        let timestamp = settings
+            .reproducible
+            .then(|| reproducible::timestamp(settings.metadata.creation_date()));
+        pdfa::convert(output, temp_dir.path(), timestamp)?;
+    }
+    optimize::finish(settings, output, before, temp_dir.path(), &mut warnings)?;
+    hook::run_post_command(&settings.post_command, output, temp_dir.path())?;
+    // The parts' assets were checked as they were converted
+    budget::check(settings, "", output, &mut warnings)?;
+    Ok(warnings)
+}
+
+fn load_part(input: &Path, part_path: &Path) -> Result<Part, String> {
+    let document = Document::load(part_path)
+        .map_err(|e| format!("Failed to read converted '{}': {}", input.display(), e))?;
+    let headings = document.get_toc().map(|toc| toc.toc).unwrap_or_default();
+    let title = input
+        .file_name()
+        .map(|name| name.to_string_lossy().to_string())
+        .unwrap_or_else(|| input.display().to_string());
+    Ok(Part {
+        input: input.to_path_buf(),
+        title,
+        document,
+        headings,
+    })
+}
+
+/// The document at `path`, as its canonical directory and file stem, which a converted file
+/// and links to its PDF have in common
+fn document_key(path: &Path) -> Option<PathBuf> {
+    let parent = path
+        .parent()
+        .filter(|parent| !parent.as_os_str().is_empty())
+        .unwrap_or(Path::new("."));
+    Some(fs::canonicalize(parent).ok()?.join(path.file_stem()?))
+}
+
+/// Concatenates the parts and builds the combined outline. Links from one part to the PDF
+/// of another go to that part's first page instead.
+fn merge_with_bookmarks(parts: Vec<Part>) -> Result<Document, String> {
+    let mut outline = Vec::new();
+    let mut spans = Vec::new(); // Input, first page and page count of each part
+    let mut documents = Vec::new();
+    let mut first_page = 1;
+    for part in parts {
+        let page_count = part.document.get_pages().len();
+        outline.push((part.title, first_page, part.headings));
+        spans.push((part.input, first_page, page_count));
+        documents.push(part.document);
+        first_page += page_count;
+    }
+
+    let mut merged =
+        pdf::merge(documents).map_err(|e| format!("Failed to merge converted files: {}", e))?;
+    let pages = merged.get_pages();
+    let keys: Vec<_> = spans
+        .iter()
+        .map(|(input, _, _)| document_key(input))
+        .collect();
+    for (input, first_page, page_count) in &spans {
+        let base_dir = input.parent().unwrap_or(Path::new(""));
+        let page_ids: Vec<_> = (*first_page..first_page + page_count)
+            .filter_map(|page| pages.get(&(page as u32)).copied())
+            .collect();
+        pdf::replace_link_actions(&mut merged, &page_ids, |action| {
+            let key = document_key(&base_dir.join(pdf::opened_file(action)?))?;
+            let target = keys.iter().position(|other| other.as_ref() == Some(&key))?;
+            let page_id = pages.get(&(spans[target].1 as u32))?;
+            Some(dictionary! {
+                "S" => "GoTo",
+                "D" => vec![(*page_id).into(), "Fit".into()],
+            })
+        });
+    }
+    for (title, first_page, headings) in outline {
+        let Some(&page_id) = pages.get(&(first_page as u32)) else {
+            continue; // A file that produced no pages
+        };
+        let file_id = merged.add_bookmark(
+            Bookmark::new(title, [0.0; 3], FILE_BOOKMARK_FORMAT, page_id),
+            None,
+        );
+
+        // Nest each heading under the closest preceding heading of a higher level
+        let mut parents: Vec<(usize, u32)> = Vec::new();
+        for heading in headings {
+            let Some(&page_id) = pages.get(&((first_page + heading.page - 1) as u32)) else {
+                continue;
+            };
+            while parents
+                .last()
+                .is_some_and(|&(level, _)| level >= heading.level)
+            {
+                parents.pop();
+            }
+            let parent = parents.last().map_or(file_id, |&(_, id)| id);
+            let id = merged.add_bookmark(
+                Bookmark::new(heading.title, [0.0; 3], 0, page_id),
+                Some(parent),
+            );
+            parents.push((heading.level, id));
+        }
+    }
+
+    if let Some(outline_id) = merged.build_outline() {
+        let catalog_id = merged
+            .trailer
+            .get(b"Root")
+            .and_then(Object::as_reference)
+            .map_err(|e| e.to_string())?;
+        let catalog = merged
+            .get_object_mut(catalog_id)
+            .and_then(Object::as_dict_mut)
+            .map_err(|e| e.to_string())?;
+        catalog.set("Outlines", outline_id);
+        catalog.set("PageMode", "UseOutlines");
+    }
+    merged.compress();
+    Ok(merged)
+}
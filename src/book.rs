@@ -0,0 +1,275 @@
+use crate::budget;
+use crate::convert;
+use crate::front_matter::FrontMatter;
+use crate::headings::{self, Heading};
+use crate::images;
+use crate::links;
+use crate::paths::TempDir;
+use crate::security;
+use crate::settings::Settings;
+use crate::toc;
+use pulldown_cmark::{Event, Tag, TagEnd};
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Chapters after the first start on a new page
+const BOOK_CSS: &str = r#".markdown-body .md-chapter {
+  page-break-before: always;
+}
+
+.markdown-body .md-chapter:first-child {
+  page-break-before: auto;
+}
+"#;
+
+/// A book assembled from its chapters, before rendering
+struct BookHtml {
+    html: String,
+    headings: Vec<Heading>,
+    /// Anchor id of the first heading of each chapter, used for links to a whole chapter
+    chapter_starts: Vec<Option<String>>,
+}
+
+/// Reads a book manifest: one chapter Markdown file per line, relative to the manifest.
+/// Blank lines and lines starting with `#` are ignored.
+pub fn read_manifest(manifest: &Path) -> Result<Vec<PathBuf>, String> {
+    let text = fs::read_to_string(manifest).map_err(|e| {
+        format!(
+            "Failed to read book manifest '{}': {}",
+            manifest.display(),
+            e
+        )
+    })?;
+    let base_dir = manifest.parent().unwrap_or(Path::new("."));
+    Ok(text
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(|line| base_dir.join(line))
+        .collect())
+}
+
+/// Builds every chapter listed in `manifest` into a single PDF.
+/// Links between chapters (and to headings within them) become internal links followed by
+/// the page they point to, which takes a first layout pass to find out.
+/// Returns the warnings collected along the way.
+pub fn build(settings: &Settings, manifest: &Path, output: &Path) -> Result<Vec<String>, String> {
+    settings.check_styles()?;
+    settings.check_pdf_options()?;
+    let chapters = read_manifest(manifest)?;
+    if chapters.is_empty() {
+        return Err(format!(
+            "Book manifest '{}' lists no chapters",
+            manifest.display()
+        ));
+    }
+    let sources = chapters
+        .iter()
+        .map(|chapter| {
+            fs::read_to_string(chapter)
+                .map_err(|e| format!("Failed to read chapter '{}': {}", chapter.display(), e))
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    let local_roots: Vec<PathBuf> = chapters
+        .iter()
+        .flat_map(|chapter| settings.local_roots(chapter.parent().unwrap_or(Path::new("."))))
+        .collect();
+
+    // First pass: render without page numbers and read back where each heading ended up
+    let draft = book_html(
+        settings,
+        &chapters,
+        &sources,
+        &local_roots,
+        &HashMap::new(),
+        &mut Vec::new(),
+    );
+    let temp_dir = TempDir::new()?;
+    let mut pages = toc::locate_headings(
+        settings,
+        &draft.html,
+        &local_roots,
+        temp_dir.path(),
+        &draft.headings,
+    )?;
+    for (i, start) in draft.chapter_starts.iter().enumerate() {
+        if let Some(page) = start.as_ref().and_then(|id| pages.get(id)).cloned() {
+            pages.insert(format!("chapter-{i}"), page);
+        }
+    }
+
+    let mut warnings = Vec::new();
+    let book = book_html(
+        settings,
+        &chapters,
+        &sources,
+        &local_roots,
+        &pages,
+        &mut warnings,
+    );
+    security::enforce(settings.url_policy, &warnings)?;
+    if let Some(parent) = output.parent() {
+        fs::create_dir_all(parent)
+            .map_err(|e| format!("Failed to create output directory: {}", e))?;
+    }
+    convert::render_pdf(
+        settings,
+        &book.html,
+        &local_roots,
+        temp_dir.path(),
+        output,
+        &mut warnings,
+    )?;
+    budget::check(settings, &book.html, output, &mut warnings)?;
+    Ok(warnings)
+}
+
+/// Concatenates all chapters into one HTML document. Heading ids are prefixed per chapter
+/// and cross-chapter links rewritten to them, with page numbers from `pages` appended.
+fn book_html(
+    settings: &Settings,
+    chapters: &[PathBuf],
+    sources: &[String],
+    local_roots: &[PathBuf],
+    pages: &HashMap<String, String>,
+    warnings: &mut Vec<String>,
+) -> BookHtml {
+    let chapter_index: HashMap<PathBuf, usize> = chapters
+        .iter()
+        .enumerate()
+        .filter_map(|(i, chapter)| fs::canonicalize(chapter).ok().map(|path| (path, i)))
+        .collect();
+
+    // Links to chapters become anchors below, only the remaining ones point at PDFs
+    let chapter_settings = Settings {
+        rewrite_md_links: false,
+        ..settings.clone()
+    };
+    let mut body = String::new();
+    let mut all_headings = Vec::new();
+    let mut chapter_starts = Vec::new();
+    let mut document_direction = None;
+    let mut document_lang = String::new();
+
+    for (i, (chapter, source)) in chapters.iter().zip(sources).enumerate() {
+        let (front_matter, md_body) = FrontMatter::extract(source);
+        let base_dir = chapter.parent().unwrap_or(Path::new("."));
+        let lang = convert::document_lang(settings, &front_matter);
+        if document_direction.is_none() {
+            document_direction = Some(settings.direction.resolve(&front_matter, md_body));
+            document_lang = lang.to_string();
+        }
+
+        let mut chapter_warnings = Vec::new();
+        let events = convert::render_events(
+            &chapter_settings,
+            md_body,
+            base_dir,
+            lang,
+            &mut chapter_warnings,
+        );
+        warnings.extend(
+            chapter_warnings
+                .into_iter()
+                .map(|warning| format!("{}: {}", chapter.display(), warning)),
+        );
+        let (events, chapter_headings) = headings::assign_ids(events, &chapter_prefix(i));
+        let mut events = rewrite_links(events, i, base_dir, &chapter_index, pages);
+        if settings.rewrite_md_links {
+            events = links::rewrite_md_links(events);
+        }
+
+        chapter_starts.push(chapter_headings.first().map(|heading| heading.id.clone()));
+        all_headings.extend(chapter_headings);
+
+        body.push_str(&format!(
+            "<section class=\"md-chapter\" id=\"chapter-{i}\">\n"
+        ));
+        pulldown_cmark::html::push_html(&mut body, events.into_iter());
+        body.push_str("</section>\n");
+    }
+
+    let direction = document_direction.unwrap_or(settings.direction);
+    BookHtml {
+        html: convert::wrap_document(
+            settings,
+            local_roots,
+            &body,
+            direction,
+            &document_lang,
+            BOOK_CSS,
+        ),
+        headings: all_headings,
+        chapter_starts,
+    }
+}
+
+fn chapter_prefix(chapter: usize) -> String {
+    format!("c{chapter}-")
+}
+
+/// Points links to other chapters (or their headings) at the anchors inside the book and
+/// appends " (p. N)" to every internal link whose target page is known
+fn rewrite_links<'a>(
+    events: Vec<Event<'a>>,
+    chapter: usize,
+    base_dir: &Path,
+    chapter_index: &HashMap<PathBuf, usize>,
+    pages: &HashMap<String, String>,
+) -> Vec<Event<'a>> {
+    let mut out = Vec::with_capacity(events.len());
+    let mut pending_page = None;
+
+    for event in events {
+        match event {
+            Event::Start(Tag::Link {
+                link_type,
+                dest_url,
+                title,
+                id,
+            }) => {
+                let (path, fragment) = dest_url.split_once('#').unwrap_or((&dest_url, ""));
+                let target_chapter = if path.is_empty() {
+                    Some(chapter)
+                } else if images::is_local(path) {
+                    fs::canonicalize(base_dir.join(path))
+                        .ok()
+                        .and_then(|target| chapter_index.get(&target).copied())
+                } else {
+                    None
+                };
+
+                let Some(target_chapter) = target_chapter else {
+                    out.push(Event::Start(Tag::Link {
+                        link_type,
+                        dest_url,
+                        title,
+                        id,
+                    }));
+                    continue;
+                };
+                let anchor = if fragment.is_empty() {
+                    format!("chapter-{target_chapter}")
+                } else {
+                    format!("{}{}", chapter_prefix(target_chapter), fragment)
+                };
+                pending_page = pages.get(&anchor).cloned();
+                out.push(Event::Start(Tag::Link {
+                    link_type,
+                    dest_url: format!("#{anchor}").into(),
+                    title,
+                    id,
+                }));
+            }
+            Event::End(TagEnd::Link) => {
+                out.push(event);
+                if let Some(page) = pending_page.take() {
+                    out.push(Event::Text(format!(" (p. {page})").into()));
+                }
+            }
+            other => out.push(other),
+        }
+    }
+    out
+}
@@ -0,0 +1,163 @@
+use crate::estimate::format_size;
+use crate::settings::Settings;
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+use url::Url;
+
+/// Size a document may reach unless chosen otherwise, in MB: small enough to email
+pub const DEFAULT_BUDGET_MB: f32 = 10.0;
+
+/// How many of the largest assets are listed when the budget is exceeded
+const LARGEST_SHOWN: usize = 5;
+
+/// Attribute and CSS openings of the references that put a file into the document
+const EMBED_OPENINGS: &[&str] = &["src=\"", "src='", "url(", "url(\"", "url('"];
+
+/// Attribute openings of links, which only embed a file in stylesheet `<link>` elements
+const LINK_OPENINGS: &[&str] = &["href=\"", "href='"];
+
+/// What happens when a document is larger than its size budget
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum BudgetCheck {
+    Off,  // Sizes aren't checked
+    Warn, // A warning lists the largest assets
+    Fail, // The conversion fails, listing the largest assets
+}
+
+impl BudgetCheck {
+    /// Returns the display name for the check
+    pub fn name(&self) -> &'static str {
+        match self {
+            BudgetCheck::Off => "Off",
+            BudgetCheck::Warn => "Warn",
+            BudgetCheck::Fail => "Fail",
+        }
+    }
+
+    /// Returns all available checks
+    pub fn all() -> &'static [BudgetCheck] {
+        &[BudgetCheck::Off, BudgetCheck::Warn, BudgetCheck::Fail]
+    }
+
+    /// Identifier used in saved settings
+    pub fn keyword(&self) -> &'static str {
+        match self {
+            BudgetCheck::Off => "off",
+            BudgetCheck::Warn => "warn",
+            BudgetCheck::Fail => "fail",
+        }
+    }
+}
+
+/// Whether the reference following `before` puts a file into the document, rather than
+/// linking to it like a plain link to a video or archive
+fn embeds(before: &str) -> bool {
+    if EMBED_OPENINGS
+        .iter()
+        .any(|opening| before.ends_with(opening))
+    {
+        return true;
+    }
+    LINK_OPENINGS
+        .iter()
+        .any(|opening| before.ends_with(opening))
+        && before.rfind('<').is_some_and(|start| {
+            let tag = before[start..].to_ascii_lowercase();
+            tag.starts_with("<link") && tag.contains("stylesheet")
+        })
+}
+
+/// The local files and data URIs `html` embeds, such as images, fonts and style sheets,
+/// with their size in bytes. Files used several times are listed once.
+pub fn assets(html: &str) -> BTreeMap<String, u64> {
+    let mut assets = BTreeMap::new();
+    let mut embedded = 0;
+    let mut rest = html;
+    // References start with the `f` of `file://` or the `d` of `data:`
+    while let Some(start) = rest.find(['f', 'd']) {
+        let (before, after) = rest.split_at(start);
+        if !embeds(before) {
+            rest = &after[1..];
+            continue;
+        }
+        let end = after.find(['"', '\'', ')']).unwrap_or(after.len());
+        let reference = &after[..end];
+        rest = &after[end..];
+        if let Some(data) = reference.strip_prefix("data:") {
+            let (kind, payload) = data.split_once(',').unwrap_or(("", data));
+            let bytes = if kind.ends_with(";base64") {
+                payload.len() as u64 * 3 / 4
+            } else {
+                payload.len() as u64
+            };
+            let media_type = kind.split(';').next().unwrap_or_default();
+            embedded += 1;
+            assets.insert(format!("embedded {media_type} #{embedded}"), bytes);
+        } else if reference.starts_with("file://")
+            && let Some(path) = Url::parse(&reference.replace("&amp;", "&"))
+                .ok()
+                .and_then(|url| url.to_file_path().ok())
+            && let Ok(metadata) = fs::metadata(&path)
+            && metadata.is_file()
+        {
+            assets.insert(path.to_string_lossy().to_string(), metadata.len());
+        }
+    }
+    assets
+}
+
+/// Checks the PDF at `pdf_path`, rendered from `html`, and the assets it embeds against the
+/// size budget. Going over it adds a warning listing the largest assets, or fails the
+/// conversion in `Fail` mode; the PDF is kept either way.
+pub fn check(
+    settings: &Settings,
+    html: &str,
+    pdf_path: &Path,
+    warnings: &mut Vec<String>,
+) -> Result<(), String> {
+    if settings.size_budget == BudgetCheck::Off {
+        return Ok(());
+    }
+    let budget = (settings.size_budget_mb.max(0.0) * 1_000_000.0) as u64;
+    let assets = assets(html);
+    let asset_bytes: u64 = assets.values().sum();
+    let pdf_bytes = fs::metadata(pdf_path).map_or(0, |metadata| metadata.len());
+    let mut problems = Vec::new();
+    if pdf_bytes > budget {
+        problems.push(format!(
+            "The PDF is {}, over the size budget of {}",
+            format_size(pdf_bytes),
+            format_size(budget)
+        ));
+    }
+    if asset_bytes > budget {
+        problems.push(format!(
+            "Its images and other assets take up {}, over the size budget of {}",
+            format_size(asset_bytes),
+            format_size(budget)
+        ));
+    }
+    if problems.is_empty() {
+        return Ok(());
+    }
+
+    let mut largest: Vec<_> = assets.into_iter().collect();
+    largest.sort_by_key(|(_, bytes)| std::cmp::Reverse(*bytes));
+    let mut message = problems.join(". ");
+    if !largest.is_empty() {
+        let listed: Vec<_> = largest
+            .iter()
+            .take(LARGEST_SHOWN)
+            .map(|(name, bytes)| format!("{} ({})", name, format_size(*bytes)))
+            .collect();
+        message.push_str(&format!(". Largest assets: {}", listed.join(", ")));
+    }
+    match settings.size_budget {
+        BudgetCheck::Fail => Err(message),
+        _ => {
+            warnings.push(message);
+            Ok(())
+        }
+    }
+}
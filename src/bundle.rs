@@ -0,0 +1,157 @@
+use crate::config;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+use url::Url;
+
+/// Folder next to the exported HTML that the assets are copied to
+pub const ASSETS_DIR: &str = "assets";
+
+/// File in the assets folder listing where each asset was copied from
+const MANIFEST: &str = "manifest.txt";
+
+/// Attribute and CSS openings a `file://` URL has to follow to be copied. URLs in the text,
+/// e.g. in code blocks, are left alone.
+pub const REFERENCE_OPENINGS: &[&str] = &[
+    "src=\"", "src='", "href=\"", "href='", "url(", "url(\"", "url('",
+];
+
+/// Encodes the characters of a file name that can't appear in a relative URL as is
+pub fn encode_name(name: &str) -> String {
+    let mut encoded = String::with_capacity(name.len());
+    for byte in name.bytes() {
+        if byte.is_ascii_alphanumeric() || b"-._~".contains(&byte) {
+            encoded.push(byte as char);
+        } else {
+            encoded.push_str(&format!("%{byte:02X}"));
+        }
+    }
+    encoded
+}
+
+/// A name for `source` in the assets folder that no other asset has, e.g. `diagram-2.png`
+/// for a second `diagram.png`
+pub fn unique_name(source: &Path, taken: &HashSet<String>) -> String {
+    let name = source.file_name().map_or_else(
+        || "asset".to_string(),
+        |name| name.to_string_lossy().to_string(),
+    );
+    if !taken.contains(&name) {
+        return name;
+    }
+    let (stem, extension) = match name.rsplit_once('.') {
+        Some((stem, extension)) if !stem.is_empty() => (stem, format!(".{extension}")),
+        _ => (name.as_str(), String::new()),
+    };
+    (2..)
+        .map(|number| format!("{stem}-{number}{extension}"))
+        .find(|candidate| !taken.contains(candidate))
+        .expect("some number is free")
+}
+
+/// Calls `replace` with each `file://` URL `html` refers to from an attribute or CSS
+/// `url()`, decoded and without its fragment, and puts the URL it returns in its place.
+/// References `replace` returns None for are left as they are.
+pub fn replace_file_urls(
+    html: &str,
+    mut replace: impl FnMut(&str) -> Result<Option<String>, String>,
+) -> Result<String, String> {
+    let mut out = String::with_capacity(html.len());
+    let mut rest = html;
+    while let Some(start) = rest.find("file://") {
+        let (before, after) = rest.split_at(start);
+        out.push_str(before);
+        let end = after
+            .find(['"', '\'', ')', ' ', '<', '>'])
+            .unwrap_or(after.len());
+        let (reference, remainder) = after.split_at(end);
+        rest = remainder;
+        if !REFERENCE_OPENINGS
+            .iter()
+            .any(|opening| before.ends_with(opening))
+        {
+            out.push_str(reference);
+            continue;
+        }
+
+        let decoded = reference.replace("&amp;", "&");
+        let (url, fragment) = decoded.split_at(decoded.find('#').unwrap_or(decoded.len()));
+        match replace(url)? {
+            Some(replacement) => {
+                out.push_str(&replacement);
+                out.push_str(&fragment.replace('&', "&amp;"));
+            }
+            None => out.push_str(reference),
+        }
+    }
+    out.push_str(rest);
+    Ok(out)
+}
+
+/// Copies the local files `html` refers to through `file://` URLs, such as images, into an
+/// `assets` folder next to `html_path` and points the references at the copies, so the HTML
+/// can be shared along with the folder. The folder gets a manifest of where each copy came
+/// from. Files that can't be copied keep their URL, with a warning. Returns the rewritten
+/// HTML.
+pub fn copy_assets(
+    html: &str,
+    html_path: &Path,
+    warnings: &mut Vec<String>,
+) -> Result<String, String> {
+    let assets_dir = html_path.parent().unwrap_or(Path::new("")).join(ASSETS_DIR);
+    let mut copies: HashMap<PathBuf, String> = HashMap::new(); // Name of each copied file
+    let mut taken = HashSet::from([MANIFEST.to_string()]);
+    let mut manifest = BTreeMap::new();
+    let out = replace_file_urls(html, |url| {
+        let Some(source) = Url::parse(url)
+            .ok()
+            .and_then(|url| url.to_file_path().ok())
+            .filter(|source| source.is_file())
+        else {
+            warnings.push(format!("Asset not found, not copied: '{}'", url));
+            return Ok(None);
+        };
+        let name = match copies.get(&source) {
+            Some(name) => name.clone(),
+            None => {
+                let name = unique_name(&source, &taken);
+                fs::create_dir_all(&assets_dir).map_err(|e| {
+                    format!(
+                        "Failed to create assets folder '{}': {}",
+                        assets_dir.display(),
+                        e
+                    )
+                })?;
+                if let Err(e) = fs::copy(&source, assets_dir.join(&name)) {
+                    warnings.push(format!(
+                        "Failed to copy asset '{}': {}",
+                        source.display(),
+                        e
+                    ));
+                    return Ok(None);
+                }
+                taken.insert(name.clone());
+                manifest.insert(name.clone(), source.to_string_lossy().to_string());
+                copies.insert(source, name.clone());
+                name
+            }
+        };
+        Ok(Some(format!("{ASSETS_DIR}/{}", encode_name(&name))))
+    })?;
+
+    if !manifest.is_empty() {
+        let manifest_path = assets_dir.join(MANIFEST);
+        let text = format!(
+            "# Copied asset = where it was copied from\n{}",
+            config::format(&manifest)
+        );
+        fs::write(&manifest_path, text).map_err(|e| {
+            format!(
+                "Failed to write asset manifest '{}': {}",
+                manifest_path.display(),
+                e
+            )
+        })?;
+    }
+    Ok(out)
+}
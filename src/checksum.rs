@@ -0,0 +1,118 @@
+use crate::format::OutputFormat;
+use crate::settings::Settings;
+use sha2::{Digest, Sha256};
+use std::fs;
+use std::path::{Path, PathBuf};
+use toml::{Table, Value};
+
+/// A file a build wrote, for the manifest
+pub struct Artifact {
+    pub path: PathBuf,
+    pub format: OutputFormat,
+    pub sources: Vec<PathBuf>, // Files it was built from: the Markdown, or a book's manifest and chapters
+}
+
+/// SHA-256 of the file at `path`, as lowercase hex
+pub fn sha256_file(path: &Path) -> Result<String, String> {
+    let bytes =
+        fs::read(path).map_err(|e| format!("Failed to read '{}': {}", path.display(), e))?;
+    Ok(hex(&Sha256::digest(&bytes)))
+}
+
+/// SHA-256 of `parts` as lowercase hex, for keys and file names kept between runs, which the
+/// standard library's hasher may change. Each part goes in with its length, so splitting the
+/// same bytes differently gives a different key.
+pub fn key(parts: &[&[u8]]) -> String {
+    let mut hasher = Sha256::new();
+    for part in parts {
+        hasher.update((part.len() as u64).to_le_bytes());
+        hasher.update(part);
+    }
+    hex(&hasher.finalize())
+}
+
+fn hex(digest: &[u8]) -> String {
+    digest.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+/// Path of the checksum file written next to `path`, e.g. `report.pdf.sha256`
+pub fn sidecar_path(path: &Path) -> PathBuf {
+    let mut name = path.as_os_str().to_owned();
+    name.push(".sha256");
+    PathBuf::from(name)
+}
+
+/// Writes the SHA-256 of the finished file at `path` to its [`sidecar_path`], in the format
+/// `sha256sum --check` reads, so the file can be verified from the folder it's in
+pub fn write_sidecar(path: &Path) -> Result<(), String> {
+    let hash = sha256_file(path)?;
+    let name = path
+        .file_name()
+        .map(|name| name.to_string_lossy().to_string())
+        .unwrap_or_default();
+    let sidecar = sidecar_path(path);
+    fs::write(&sidecar, format!("{hash}  {name}\n"))
+        .map_err(|e| format!("Failed to write '{}': {}", sidecar.display(), e))
+}
+
+/// Writes the [`write_sidecar`] of each of `files` a conversion wrote, when `settings` ask
+/// for checksum files. Called once the conversion is done, so the sums are of the final files.
+pub fn write_sidecars(settings: &Settings, files: &[PathBuf]) -> Result<(), String> {
+    if settings.checksums {
+        for file in files {
+            write_sidecar(file)?;
+        }
+    }
+    Ok(())
+}
+
+/// Writes a manifest of `artifacts` to `path` as TOML, listing each file with its format,
+/// SHA-256 and sources, so a build's outputs can be verified and traced back. Paths are
+/// relative to the manifest where they're inside its folder.
+pub fn write_manifest(path: &Path, artifacts: &[Artifact]) -> Result<(), String> {
+    let absolute = |path: &Path| std::path::absolute(path).unwrap_or_else(|_| path.to_path_buf());
+    let manifest_path = absolute(path);
+    let base_dir = manifest_path.parent().unwrap_or(Path::new(""));
+    let relative = |path: &Path| {
+        let path = absolute(path);
+        let path = path.strip_prefix(base_dir).unwrap_or(&path);
+        Value::String(path.to_string_lossy().to_string())
+    };
+    let outputs = artifacts
+        .iter()
+        .map(|artifact| {
+            let mut output = Table::new();
+            output.insert("path".to_string(), relative(&artifact.path));
+            output.insert(
+                "format".to_string(),
+                Value::String(artifact.format.keyword().to_string()),
+            );
+            output.insert(
+                "sha256".to_string(),
+                Value::String(sha256_file(&artifact.path)?),
+            );
+            let sources = artifact
+                .sources
+                .iter()
+                .map(|source| relative(source))
+                .collect();
+            output.insert("sources".to_string(), Value::Array(sources));
+            Ok(Value::Table(output))
+        })
+        .collect::<Result<Vec<_>, String>>()?;
+    let mut table = Table::new();
+    table.insert(
+        "generator".to_string(),
+        Value::String(concat!(env!("CARGO_PKG_NAME"), " ", env!("CARGO_PKG_VERSION")).to_string()),
+    );
+    table.insert("output".to_string(), Value::Array(outputs));
+    if let Some(parent) = path
+        .parent()
+        .filter(|parent| !parent.as_os_str().is_empty())
+    {
+        fs::create_dir_all(parent)
+            .map_err(|e| format!("Failed to create manifest directory: {}", e))?;
+    }
+    fs::write(path, table.to_string())
+        .map_err(|e| format!("Failed to write manifest '{}': {}", path.display(), e))
+}
@@ -0,0 +1,534 @@
+use crate::book;
+use crate::checksum::{self, Artifact};
+use crate::convert;
+use crate::format::{self, OutputFormat, Source};
+use crate::project::Project;
+use crate::settings::Settings;
+use crate::stamp::BatesPosition;
+use clap::Parser;
+use std::path::{Path, PathBuf};
+
+/// Convert Markdown to PDF. Starts the GUI when run without arguments.
+#[derive(Parser, Debug)]
+#[command(version, about)]
+pub struct Cli {
+    /// Markdown files to convert
+    #[arg(required_unless_present_any = ["book", "project"])]
+    inputs: Vec<PathBuf>,
+
+    /// Output PDF (defaults to the input path with a .pdf extension).
+    /// Only allowed for a single input, unless merging.
+    #[arg(short, long)]
+    output: Option<PathBuf>,
+
+    /// Combine all inputs into the --output PDF, with a bookmark for each file
+    #[arg(long, requires = "output")]
+    merge: bool,
+
+    /// Build a single PDF from a manifest listing one chapter file per line
+    #[arg(long, value_name = "MANIFEST", conflicts_with = "inputs")]
+    book: Option<PathBuf>,
+
+    /// Build every document of a project file (.md2pdf.toml) saved from the GUI
+    #[arg(long, value_name = "PROJECT", conflicts_with_all = ["inputs", "book", "output", "merge"])]
+    project: Option<PathBuf>,
+
+    /// Replace output PDFs that already exist
+    #[arg(long)]
+    overwrite: bool,
+
+    /// Write to a numbered file name, like `report (2).pdf`, when the output PDF already exists
+    #[arg(long, conflicts_with = "overwrite")]
+    versioned: bool,
+
+    /// Formats to write, separated by commas: pdf, html for the self-contained HTML the PDF
+    /// is rendered from, epub, docx (with pandoc), txt for plain text, man for a man page,
+    /// png or jpeg for an image per page (with pdftoppm), numbered like `report-1.png`, or
+    /// slides for a landscape PDF with a page per slide and reveal for a reveal.js
+    /// presentation, the slides separated by `---` lines. With several, each output gets
+    /// the format's extension and a summary follows. Defaults to pdf, or to the formats of
+    /// the --project.
+    #[arg(long, value_delimiter = ',', value_parser = parse_format)]
+    format: Vec<OutputFormat>,
+
+    /// Resolution of the page images written with --format png or jpeg
+    #[arg(long, value_parser = clap::value_parser!(u32).range(36..=1200))]
+    dpi: Option<u32>,
+
+    /// Write the same PDF for the same input every time, for verifying build artifacts: the
+    /// timestamps come from the front matter date, else SOURCE_DATE_EPOCH, else 1970, and
+    /// the document ID from the content. Can't be combined with encryption, which is random.
+    #[arg(long)]
+    reproducible: bool,
+
+    /// Write PDF/A-2b for archiving, converted by Ghostscript. Can't be combined with
+    /// encryption.
+    #[arg(long)]
+    pdf_a: bool,
+
+    /// Encrypt the PDF, needing this password to open it. Taken from the
+    /// MD_TO_PDF_USER_PASSWORD environment variable when not given, which keeps it out of
+    /// the process list.
+    #[arg(long, value_name = "PASSWORD")]
+    user_password: Option<String>,
+
+    /// Encrypt the PDF, with this password lifting the restrictions. Taken from the
+    /// MD_TO_PDF_OWNER_PASSWORD environment variable when not given, and random when neither
+    /// is set.
+    #[arg(long, value_name = "PASSWORD")]
+    owner_password: Option<String>,
+
+    /// Encrypt the PDF so it can't be printed without the owner password
+    #[arg(long)]
+    no_print: bool,
+
+    /// Encrypt the PDF so its text can't be copied without the owner password
+    #[arg(long)]
+    no_copy: bool,
+
+    /// Encrypt the PDF so it can't be edited or annotated without the owner password
+    #[arg(long)]
+    no_edit: bool,
+
+    /// Put the pages of this PDF, such as a signed cover sheet, in front of the document.
+    /// A `cover` path in the front matter takes precedence.
+    #[arg(long, value_name = "PDF")]
+    cover: Option<PathBuf>,
+
+    /// Put the pages of this PDF, such as legal boilerplate, after the document. An
+    /// `appendix` path in the front matter takes precedence.
+    #[arg(long, value_name = "PDF")]
+    appendix: Option<PathBuf>,
+
+    /// Make the PDF smaller: drop unused objects and pack the rest into compressed object
+    /// streams. The sizes before and after are reported.
+    #[arg(long)]
+    optimize: bool,
+
+    /// Optimize the PDF, recompressing its images as JPEG at this quality where that's
+    /// smaller
+    #[arg(long, value_name = "QUALITY", value_parser = clap::value_parser!(u8).range(1..=100))]
+    image_quality: Option<u8>,
+
+    /// Optimize the PDF and linearize it with qpdf for fast web view, so browsers show the
+    /// first page while the rest downloads
+    #[arg(long)]
+    linearize: bool,
+
+    /// Write a tagged PDF that screen readers can navigate by headings, lists, tables and
+    /// image alt texts, rendered with Chromium or Chrome instead of wkhtmltopdf
+    #[arg(long)]
+    accessible: bool,
+
+    /// Stamp Bates numbers, this prefix followed by a zero-padded counter, on every page,
+    /// including those of the --cover and --appendix. With --merge, the numbers run on
+    /// across all files. An empty prefix stamps just the numbers.
+    #[arg(long, value_name = "PREFIX")]
+    bates: Option<String>,
+
+    /// Number of the first page stamped with --bates
+    #[arg(long, value_name = "NUMBER", requires = "bates")]
+    bates_start: Option<u64>,
+
+    /// Digits the --bates numbers are padded to with zeros
+    #[arg(long, value_name = "DIGITS", requires = "bates", value_parser = clap::value_parser!(u8).range(1..=12))]
+    bates_digits: Option<u8>,
+
+    /// Corner of the --bates numbers: bottom-right, bottom-left, top-right or top-left
+    #[arg(long, value_name = "CORNER", requires = "bates", value_parser = parse_bates_position)]
+    bates_position: Option<BatesPosition>,
+
+    /// Write a `.sha256` file next to each output, or each page image, with its SHA-256 in
+    /// the format `sha256sum --check` reads
+    #[arg(long)]
+    checksums: bool,
+
+    /// Write a TOML manifest of all outputs built to this path, with the SHA-256 of each and
+    /// the Markdown files it was built from, for verifying and tracing the artifacts
+    #[arg(long, value_name = "PATH")]
+    manifest: Option<PathBuf>,
+}
+
+/// Parses a `--format` value
+fn parse_format(text: &str) -> Result<OutputFormat, String> {
+    OutputFormat::from_keyword(text).ok_or_else(|| {
+        let keywords: Vec<_> = OutputFormat::all()
+            .iter()
+            .map(|format| format.keyword())
+            .collect();
+        format!("expected one of {}", keywords.join(", "))
+    })
+}
+
+/// Parses a `--bates-position` value
+fn parse_bates_position(text: &str) -> Result<BatesPosition, String> {
+    BatesPosition::all()
+        .iter()
+        .find(|position| position.keyword() == text)
+        .copied()
+        .ok_or_else(|| {
+            let keywords: Vec<_> = BatesPosition::all()
+                .iter()
+                .map(|position| position.keyword())
+                .collect();
+            format!("expected one of {}", keywords.join(", "))
+        })
+}
+
+/// Runs a conversion from the command line, returning the process exit code
+pub fn run(cli: Cli) -> i32 {
+    let mut settings = Settings::default();
+    apply_options(&cli, &mut settings);
+    let target = |output: PathBuf| target(output, cli.overwrite, cli.versioned);
+    if let Some(path) = &cli.project {
+        return build_project(path, &cli, target);
+    }
+    let formats = if cli.format.is_empty() {
+        vec![OutputFormat::Pdf]
+    } else {
+        cli.format.clone()
+    };
+    if (cli.book.is_some() || cli.merge) && formats != [OutputFormat::Pdf] {
+        eprintln!("--book and --merge only write PDFs, leave out --format");
+        return 2;
+    }
+    let (output, result, sources) = if let Some(manifest) = &cli.book {
+        let output = cli
+            .output
+            .clone()
+            .unwrap_or_else(|| manifest.with_extension("pdf"));
+        let mut sources = vec![manifest.clone()];
+        sources.extend(book::read_manifest(manifest).unwrap_or_default());
+        match target(output) {
+            Ok(output) => (
+                output.clone(),
+                convert_pdf(&settings, Source::Book(manifest), &output),
+                sources,
+            ),
+            Err(message) => (PathBuf::new(), Err(message), sources),
+        }
+    } else if cli.merge {
+        let output = cli
+            .output
+            .clone()
+            .expect("clap enforces --output with --merge");
+        match target(output) {
+            Ok(output) => (
+                output.clone(),
+                convert_pdf(&settings, Source::Merged(&cli.inputs), &output),
+                cli.inputs.clone(),
+            ),
+            Err(message) => (PathBuf::new(), Err(message), cli.inputs.clone()),
+        }
+    } else if cli.inputs.len() > 1 {
+        if cli.output.is_some() {
+            eprintln!("--output needs a single input, or --merge to combine several");
+            return 2;
+        }
+        let outcomes: Vec<Outcome> = cli
+            .inputs
+            .iter()
+            .flat_map(|input| {
+                let outputs = with_extensions(input, &formats);
+                convert_outputs(&settings, input, &outputs, target)
+            })
+            .collect();
+        return finish(&cli, &outcomes);
+    } else {
+        let input = &cli.inputs[0];
+        let outputs = match &cli.output {
+            // The output asked for is used as is for a single format
+            Some(output) if formats.len() == 1 => vec![(formats[0], output.clone())],
+            Some(output) => with_extensions(output, &formats),
+            None => with_extensions(input, &formats),
+        };
+        return finish(&cli, &convert_outputs(&settings, input, &outputs, target));
+    };
+    let outcome = Outcome {
+        sources,
+        ..report(&output, result)
+    };
+    finish(&cli, &[outcome])
+}
+
+/// Applies the output options given on the command line to `settings`: the page image
+/// resolution, cover and appendix PDFs, Bates numbers, optimization, accessible and
+/// reproducible output, PDF/A, encryption and checksum files
+fn apply_options(cli: &Cli, settings: &mut Settings) {
+    if let Some(dpi) = cli.dpi {
+        settings.page_image_dpi = dpi;
+    }
+    // Made absolute, as relative paths in the settings are relative to the document
+    let attached = [
+        (&cli.cover, &mut settings.cover_pdf),
+        (&cli.appendix, &mut settings.appendix_pdf),
+    ];
+    for (given, path) in attached {
+        if let Some(given) = given {
+            let absolute = std::path::absolute(given).unwrap_or_else(|_| given.clone());
+            *path = absolute.to_string_lossy().to_string();
+        }
+    }
+    let optimization = &mut settings.optimization;
+    optimization.enabled |= cli.optimize || cli.image_quality.is_some() || cli.linearize;
+    if let Some(quality) = cli.image_quality {
+        optimization.recompress_images = true;
+        optimization.image_quality = quality;
+    }
+    optimization.linearize |= cli.linearize;
+    if let Some(prefix) = &cli.bates {
+        let bates = &mut settings.bates;
+        bates.enabled = true;
+        bates.prefix = prefix.clone();
+        if let Some(start) = cli.bates_start {
+            bates.start = start;
+        }
+        if let Some(digits) = cli.bates_digits {
+            bates.digits = usize::from(digits);
+        }
+        if let Some(position) = cli.bates_position {
+            bates.position = position;
+        }
+    }
+    settings.accessible |= cli.accessible;
+    settings.checksums |= cli.checksums;
+    settings.reproducible |= cli.reproducible;
+    settings.pdf_a |= cli.pdf_a;
+    let password = |given: &Option<String>, variable: &str| {
+        given
+            .clone()
+            .or_else(|| std::env::var(variable).ok())
+            .filter(|password| !password.is_empty())
+    };
+    let encryption = &mut settings.encryption;
+    if let Some(password) = password(&cli.user_password, "MD_TO_PDF_USER_PASSWORD") {
+        encryption.enabled = true;
+        encryption.user_password = password;
+    }
+    if let Some(password) = password(&cli.owner_password, "MD_TO_PDF_OWNER_PASSWORD") {
+        encryption.enabled = true;
+        encryption.owner_password = password;
+    }
+    let restrictions = [
+        (cli.no_print, &mut encryption.allow_printing),
+        (cli.no_copy, &mut encryption.allow_copying),
+        (cli.no_edit, &mut encryption.allow_editing),
+    ];
+    for (restricted, allowed) in restrictions {
+        if restricted {
+            *allowed = false;
+            encryption.enabled = true;
+        }
+    }
+}
+
+/// Converts `source` to a PDF at `output`, returning the warnings
+fn convert_pdf(settings: &Settings, source: Source, output: &Path) -> Result<Vec<String>, String> {
+    format::Conversions::of(settings, source)
+        .convert(OutputFormat::Pdf, output)
+        .map(|(_, warnings)| warnings)
+}
+
+/// `path` with the extension of each of `formats`, paired with the format
+fn with_extensions(path: &Path, formats: &[OutputFormat]) -> Vec<(OutputFormat, PathBuf)> {
+    formats
+        .iter()
+        .map(|format| (*format, path.with_extension(format.extension())))
+        .collect()
+}
+
+/// Converts `input` to each of `outputs`, a format and the path to write it to, or rather
+/// the path `target` returns for it. The document is read and rendered once for the
+/// formats made from the same rendering. HTML embeds the local images and fonts so it can
+/// be opened anywhere, unless they're copied next to it. Returns the outcome of each.
+fn convert_outputs(
+    settings: &Settings,
+    input: &Path,
+    outputs: &[(OutputFormat, PathBuf)],
+    target: impl Fn(PathBuf) -> Result<PathBuf, String>,
+) -> Vec<Outcome> {
+    let mut conversions = format::Conversions::new(settings, input);
+    let html_settings = Settings {
+        self_contained: settings.self_contained || !settings.copy_assets,
+        ..settings.clone()
+    };
+    let mut html_conversions = format::Conversions::new(&html_settings, input);
+    outputs
+        .iter()
+        .map(|(format, output)| {
+            let conversions = if *format == OutputFormat::Html {
+                &mut html_conversions
+            } else {
+                &mut conversions
+            };
+            let (output, result) = match target(output.clone()) {
+                Ok(output) => (output.clone(), conversions.convert(*format, &output)),
+                Err(message) => (output.clone(), Err(message)),
+            };
+            let files = result
+                .as_ref()
+                .map(|(files, _)| files.clone())
+                .unwrap_or_default();
+            Outcome {
+                format: *format,
+                files,
+                sources: vec![input.to_path_buf()],
+                ..report(&output, result.map(|(_, warnings)| warnings))
+            }
+        })
+        .collect()
+}
+
+/// Converts each document of the project at `path` in order to each of `formats`, or the
+/// project's formats when none are given, and to the other formats the document has a path
+/// for. Writes to the paths `target` returns, with the output options of `cli` applied on
+/// top of the project's settings. Returns 1 when any of them failed.
+fn build_project(
+    path: &Path,
+    cli: &Cli,
+    target: impl Fn(PathBuf) -> Result<PathBuf, String>,
+) -> i32 {
+    let (project, warnings) = match Project::load(path) {
+        Ok(loaded) => loaded,
+        Err(message) => {
+            eprintln!("{message}");
+            return 1;
+        }
+    };
+    for warning in warnings {
+        eprintln!("warning: {warning}");
+    }
+    if project.targets.is_empty() {
+        eprintln!("Project '{}' lists no documents", path.display());
+        return 1;
+    }
+    let formats = if cli.format.is_empty() {
+        &project.formats
+    } else {
+        &cli.format
+    };
+    let mut outcomes = Vec::new();
+    for document in &project.targets {
+        let base_dir = path.parent().unwrap_or(Path::new(""));
+        let (mut settings, warnings) = document.settings(&project.settings, base_dir);
+        apply_options(cli, &mut settings);
+        for warning in warnings {
+            eprintln!("warning: {warning}");
+        }
+        outcomes.extend(convert_outputs(
+            &settings,
+            &document.input,
+            &document.outputs(formats),
+            &target,
+        ));
+    }
+    finish(cli, &outcomes)
+}
+
+/// The path to write `output` to: itself unless it exists, in which case it's replaced only
+/// with `overwrite`, or a numbered variant is used with `versioned`
+fn target(output: PathBuf, overwrite: bool, versioned: bool) -> Result<PathBuf, String> {
+    if !output.exists() || overwrite {
+        Ok(output)
+    } else if versioned {
+        Ok(convert::versioned_path(&output))
+    } else {
+        Err(format!(
+            "'{}' already exists, pass --overwrite to replace it or --versioned to write a numbered copy",
+            output.display()
+        ))
+    }
+}
+
+/// What became of an output, for the summary and manifest of a build
+struct Outcome {
+    output: PathBuf,
+    format: OutputFormat,
+    files: Vec<PathBuf>,   // What was written: the output, or each page image
+    sources: Vec<PathBuf>, // What it was built from
+    warnings: usize,
+    failed: bool,
+}
+
+/// Prints the outcome of a conversion to a PDF at `output` and its warnings
+fn report(output: &Path, result: Result<Vec<String>, String>) -> Outcome {
+    let mut outcome = Outcome {
+        output: output.to_path_buf(),
+        format: OutputFormat::Pdf,
+        files: Vec::new(),
+        sources: Vec::new(),
+        warnings: 0,
+        failed: false,
+    };
+    match result {
+        Ok(warnings) => {
+            outcome.warnings = warnings.len();
+            outcome.files.push(output.to_path_buf());
+            for warning in warnings {
+                eprintln!("warning: {warning}");
+            }
+            println!("Wrote {}", output.display());
+        }
+        Err(message) => {
+            outcome.failed = true;
+            eprintln!("{message}");
+        }
+    }
+    outcome
+}
+
+/// Prints the summary of a build with [`summarize`] and writes the `--manifest` of the
+/// outputs that were built, returning the exit code: 1 when any of them failed
+fn finish(cli: &Cli, outcomes: &[Outcome]) -> i32 {
+    let code = summarize(outcomes);
+    let Some(path) = &cli.manifest else {
+        return code;
+    };
+    let artifacts: Vec<Artifact> = outcomes
+        .iter()
+        .flat_map(|outcome| {
+            outcome.files.iter().map(|file| Artifact {
+                path: file.clone(),
+                format: outcome.format,
+                sources: outcome.sources.clone(),
+            })
+        })
+        .collect();
+    match checksum::write_manifest(path, &artifacts) {
+        Ok(()) => {
+            println!("Wrote manifest {}", path.display());
+            code
+        }
+        Err(message) => {
+            eprintln!("{message}");
+            1
+        }
+    }
+}
+
+/// Prints a summary of the outputs of a build when there are several, returning the exit
+/// code: 1 when any of them failed
+fn summarize(outcomes: &[Outcome]) -> i32 {
+    let failures = outcomes.iter().filter(|outcome| outcome.failed).count();
+    if outcomes.len() > 1 {
+        println!();
+        println!(
+            "Built {} of {} outputs",
+            outcomes.len() - failures,
+            outcomes.len()
+        );
+        for outcome in outcomes {
+            let status = if outcome.failed {
+                "failed".to_string()
+            } else if outcome.warnings == 1 {
+                "1 warning".to_string()
+            } else if outcome.warnings > 1 {
+                format!("{} warnings", outcome.warnings)
+            } else {
+                "ok".to_string()
+            };
+            println!("  {:<10} {}", status, outcome.output.display());
+        }
+    }
+    i32::from(failures > 0)
+}
@@ -0,0 +1,66 @@
+use crate::convert;
+use crate::front_matter::FrontMatter;
+use crate::headings;
+use crate::inliner;
+use crate::security;
+use crate::settings::Settings;
+use crate::text;
+use crate::toc;
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+
+/// The Markdown file at `md_path` as an HTML fragment with the document's styles inlined,
+/// so it keeps its look when pasted into email clients and wikis, together with the plain
+/// text for places that only take text. Images are embedded. Returns the HTML, the text
+/// and the warnings of the conversion.
+pub fn html_fragment(
+    settings: &Settings,
+    md_path: &Path,
+) -> Result<(String, String, Vec<String>), String> {
+    let md_text =
+        fs::read_to_string(md_path).map_err(|e| format!("Failed to read Markdown file: {}", e))?;
+    let base_dir = md_path.parent().unwrap_or(Path::new("."));
+    let mut warnings = Vec::new();
+    let (front_matter, md_body) = FrontMatter::extract(&md_text);
+    let settings = &Settings {
+        // Pasted content can't reach local files, and has no pages
+        self_contained: true,
+        rewrite_md_links: false,
+        columns: 1,
+        landscape_wide_blocks: false,
+        chapters_on_odd_pages: false,
+        ..convert::document_settings(settings, &front_matter, base_dir, &mut warnings)
+    };
+    settings.check_styles()?;
+    let direction = settings.direction.resolve(&front_matter, md_body);
+    let lang = convert::document_lang(settings, &front_matter);
+    let events = convert::render_events(settings, md_body, base_dir, lang, &mut warnings);
+    let (events, headings) = headings::assign_ids(events, "");
+    let events = toc::insert(events, &headings, &HashMap::new());
+    security::enforce(settings.url_policy, &warnings)?;
+    let mut body = String::new();
+    pulldown_cmark::html::push_html(&mut body, events.into_iter());
+
+    let css = convert::document_css(settings, "");
+    let mut root = vec![("class", "markdown-body"), ("dir", direction.html_attr())];
+    if !lang.is_empty() {
+        root.push(("lang", lang));
+    }
+    let html = inliner::inline(&body, &css, &root);
+    Ok((html, text::render(md_body, settings.text_width), warnings))
+}
+
+/// Puts the Markdown file at `md_path` on `clipboard` as formatted HTML, with plain text
+/// for apps that don't take HTML. Returns the warnings of the conversion.
+pub fn copy_html(
+    clipboard: &mut arboard::Clipboard,
+    settings: &Settings,
+    md_path: &Path,
+) -> Result<Vec<String>, String> {
+    let (html, text, warnings) = html_fragment(settings, md_path)?;
+    clipboard
+        .set_html(html, Some(text))
+        .map_err(|e| format!("Failed to copy to the clipboard: {}", e))?;
+    Ok(warnings)
+}
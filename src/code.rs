@@ -0,0 +1,276 @@
+use crate::convert::escape_attr;
+use crate::highlight::{self, Token};
+use crate::layout;
+use pulldown_cmark::{CodeBlockKind, Event, Tag, TagEnd};
+
+/// Characters of code that fit across a portrait page at 100% zoom in a single column
+const PORTRAIT_COLUMNS: f32 = 90.0;
+/// Landscape pages are wider by the ratio of the A4 sides
+const LANDSCAPE_WIDTH_RATIO: f32 = 297.0 / 210.0;
+/// Smallest font size, relative to the usual code font size, that shrunk blocks are scaled to
+const MIN_SHRINK: f32 = 0.4;
+/// Code font size of the GitHub stylesheets, relative to the body text
+const CODE_FONT_PERCENT: f32 = 85.0;
+
+/// What happens to code lines that are too long for the page
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum CodeOverflow {
+    Clip,   // Cut off at the page edge (the renderer's default)
+    Wrap,   // Continued on the next line after a marker
+    Shrink, // Whole block scaled down until its longest line fits
+}
+
+impl CodeOverflow {
+    /// Returns the display name for the overflow mode
+    pub fn name(&self) -> &'static str {
+        match self {
+            CodeOverflow::Clip => "Clip long lines",
+            CodeOverflow::Wrap => "Wrap long lines",
+            CodeOverflow::Shrink => "Shrink to fit",
+        }
+    }
+
+    /// Returns all available overflow modes
+    pub fn all() -> &'static [CodeOverflow] {
+        &[CodeOverflow::Clip, CodeOverflow::Wrap, CodeOverflow::Shrink]
+    }
+
+    /// Identifier used in saved settings
+    pub fn keyword(&self) -> &'static str {
+        match self {
+            CodeOverflow::Clip => "clip",
+            CodeOverflow::Wrap => "wrap",
+            CodeOverflow::Shrink => "shrink",
+        }
+    }
+}
+
+/// Line numbers and wrapping for the blocks produced by [`format_blocks`]
+pub const CODE_CSS: &str = r#".markdown-body pre.md-code > code {
+  display: block;
+}
+
+.markdown-body pre.md-code .md-code-line {
+  display: block;
+}
+
+.markdown-body pre.md-code-numbered {
+  counter-reset: md-code-line;
+}
+
+.markdown-body pre.md-code-numbered .md-code-line {
+  position: relative;
+  padding-left: 3.5em;
+  counter-increment: md-code-line;
+}
+
+.markdown-body pre.md-code-numbered .md-code-line:before {
+  content: counter(md-code-line);
+  position: absolute;
+  left: 0;
+  width: 2.5em;
+  text-align: right;
+  color: #8c959f;
+}
+
+.markdown-body pre.md-code-wrap > code {
+  white-space: pre-wrap;
+  word-wrap: break-word;
+}
+
+.markdown-body .md-code-continuation {
+  color: #8c959f;
+}
+"#;
+
+/// Number of code characters that fit on a line, for the given zoom and text columns
+pub fn line_width(zoom: f32, columns: u8) -> usize {
+    (PORTRAIT_COLUMNS / zoom.max(0.1) / columns.max(1) as f32) as usize
+}
+
+/// A line of code as pieces of text, each either plain or a highlighted token
+type Line = Vec<(Option<Token>, String)>;
+
+/// Renders code blocks with line numbers, syntax highlighting and/or the chosen overflow
+/// handling. `width` is the number of characters that fit on a portrait line, see
+/// [`line_width`]. Leaves the events alone when none of the options is in use.
+pub fn format_blocks<'a>(
+    events: Vec<Event<'a>>,
+    line_numbers: bool,
+    overflow: CodeOverflow,
+    highlight: bool,
+    width: usize,
+) -> Vec<Event<'a>> {
+    if !line_numbers && overflow == CodeOverflow::Clip && !highlight {
+        return events;
+    }
+
+    let mut out = Vec::with_capacity(events.len());
+    let mut block: Option<(CodeBlockKind, String)> = None;
+    let mut landscape = false; // The current block is going on a landscape page
+    for event in events {
+        match event {
+            Event::Start(Tag::CodeBlock(kind)) => block = Some((kind, String::new())),
+            Event::Text(text) if block.is_some() => {
+                if let Some((_, code)) = &mut block {
+                    code.push_str(&text);
+                }
+            }
+            Event::End(TagEnd::CodeBlock) => {
+                if let Some((kind, code)) = block.take() {
+                    let width = if landscape {
+                        (width as f32 * LANDSCAPE_WIDTH_RATIO) as usize
+                    } else {
+                        width
+                    };
+                    out.push(Event::Html(
+                        block_html(
+                            &kind,
+                            &code,
+                            line_numbers,
+                            overflow,
+                            highlight,
+                            width.max(10),
+                        )
+                        .into(),
+                    ));
+                }
+            }
+            Event::Html(ref html) => {
+                if layout::is_landscape_start(html) {
+                    landscape = true;
+                } else if layout::is_landscape_end(html) {
+                    landscape = false;
+                }
+                out.push(event);
+            }
+            other => out.push(other),
+        }
+    }
+    out
+}
+
+fn block_html(
+    kind: &CodeBlockKind,
+    code: &str,
+    line_numbers: bool,
+    overflow: CodeOverflow,
+    highlight: bool,
+    width: usize,
+) -> String {
+    let language = match kind {
+        CodeBlockKind::Fenced(info) => info.split_whitespace().next().unwrap_or(""),
+        CodeBlockKind::Indented => "",
+    };
+    let code = code.strip_suffix('\n').unwrap_or(code);
+    let pieces = highlight
+        .then(|| highlight::highlight(language, code))
+        .flatten();
+    let highlighted = pieces.is_some();
+    let lines = split_lines(pieces.unwrap_or_else(|| vec![(None, code.to_string())]));
+    // Line numbers take up about four characters
+    let width = if line_numbers {
+        width.saturating_sub(4).max(10)
+    } else {
+        width
+    };
+
+    let mut classes = String::from("md-code");
+    if line_numbers {
+        classes.push_str(" md-code-numbered");
+    }
+    if highlighted {
+        classes.push_str(" md-code-highlighted");
+    }
+    let mut style = String::new();
+    match overflow {
+        CodeOverflow::Clip => {}
+        CodeOverflow::Wrap => classes.push_str(" md-code-wrap"),
+        CodeOverflow::Shrink => {
+            let needed = lines.iter().map(line_length).max().unwrap_or(0);
+            if needed > width {
+                let scale = (width as f32 / needed as f32).max(MIN_SHRINK);
+                style = format!(" style=\"font-size: {:.1}%\"", CODE_FONT_PERCENT * scale);
+            }
+        }
+    }
+
+    let code_class = if language.is_empty() {
+        String::new()
+    } else {
+        format!(" class=\"language-{}\"", escape_attr(language))
+    };
+
+    let mut html = format!("<pre class=\"{classes}\"{style}><code{code_class}>");
+    for line in &lines {
+        html.push_str("<span class=\"md-code-line\">");
+        if line_length(line) == 0 {
+            html.push(' '); // Keeps empty lines from collapsing
+        } else if overflow == CodeOverflow::Wrap {
+            html.push_str(&wrap_line(line, width));
+        } else {
+            for (token, text) in line {
+                html.push_str(&piece_html(*token, text));
+            }
+        }
+        html.push_str("</span>");
+    }
+    html.push_str("</code></pre>\n");
+    html
+}
+
+/// Splits highlighted pieces at the line breaks, so tokens spanning lines (like block
+/// comments) are closed and reopened on each line
+fn split_lines(pieces: Line) -> Vec<Line> {
+    let mut lines = vec![Line::new()];
+    for (token, text) in pieces {
+        for (i, part) in text.split('\n').enumerate() {
+            if i > 0 {
+                lines.push(Line::new());
+            }
+            if !part.is_empty()
+                && let Some(line) = lines.last_mut()
+            {
+                line.push((token, part.to_string()));
+            }
+        }
+    }
+    lines
+}
+
+fn line_length(line: &Line) -> usize {
+    line.iter().map(|(_, text)| text.chars().count()).sum()
+}
+
+fn piece_html(token: Option<Token>, text: &str) -> String {
+    match token {
+        Some(token) => format!(
+            "<span class=\"{}\">{}</span>",
+            token.class(),
+            escape_attr(text)
+        ),
+        None => escape_attr(text),
+    }
+}
+
+/// Breaks `line` into rows of at most `width` characters, marking each continuation row
+fn wrap_line(line: &Line, width: usize) -> String {
+    let mut html = String::new();
+    let mut room = width; // Characters left on the current row
+    for (token, text) in line {
+        let chars: Vec<char> = text.chars().collect();
+        let mut rest = &chars[..];
+        while !rest.is_empty() {
+            if room == 0 {
+                html.push_str("\n<span class=\"md-code-continuation\">\u{21aa} </span>");
+                // Continuation rows leave room for the marker
+                room = width.saturating_sub(2).max(1);
+            }
+            let (row, remaining) = rest.split_at(room.min(rest.len()));
+            html.push_str(&piece_html(*token, &row.iter().collect::<String>()));
+            room -= row.len();
+            rest = remaining;
+        }
+    }
+    html
+}
@@ -0,0 +1,89 @@
+use std::collections::BTreeMap;
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+
+/// Directory holding the user's configuration: `$XDG_CONFIG_HOME/md-to-pdf`,
+/// `~/.config/md-to-pdf` or `%APPDATA%\md-to-pdf`
+pub fn dir() -> Option<PathBuf> {
+    let base = env::var_os("XDG_CONFIG_HOME")
+        .filter(|dir| !dir.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| env::var_os("APPDATA").map(PathBuf::from))
+        .or_else(|| env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))?;
+    Some(base.join("md-to-pdf"))
+}
+
+/// Directory for files that can be made again, such as downloads: `$XDG_CACHE_HOME/md-to-pdf`,
+/// `~/.cache/md-to-pdf` or `%LOCALAPPDATA%\md-to-pdf`
+pub fn cache_dir() -> Option<PathBuf> {
+    let base = env::var_os("XDG_CACHE_HOME")
+        .filter(|dir| !dir.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| env::var_os("LOCALAPPDATA").map(PathBuf::from))
+        .or_else(|| env::var_os("HOME").map(|home| PathBuf::from(home).join(".cache")))?;
+    Some(base.join("md-to-pdf"))
+}
+
+/// Reads the `key = value` lines of the configuration file `name`.
+/// A missing or unreadable file gives no entries.
+pub fn load(name: &str) -> BTreeMap<String, String> {
+    dir()
+        .and_then(|dir| fs::read_to_string(dir.join(name)).ok())
+        .map(|text| parse(&text))
+        .unwrap_or_default()
+}
+
+/// Writes `entries` to the configuration file `name`, creating the directory if needed
+pub fn save(name: &str, entries: &BTreeMap<String, String>) -> Result<(), String> {
+    let dir = dir().ok_or("Failed to find the configuration directory")?;
+    fs::create_dir_all(&dir)
+        .map_err(|e| format!("Failed to create configuration directory: {}", e))?;
+    fs::write(dir.join(name), format(entries))
+        .map_err(|e| format!("Failed to save configuration: {}", e))
+}
+
+/// Parses `key = value` lines, skipping lines without `=`
+pub fn parse(text: &str) -> BTreeMap<String, String> {
+    text.lines()
+        .filter_map(|line| line.split_once('='))
+        .map(|(key, value)| {
+            let value = value.strip_prefix(' ').unwrap_or(value);
+            (key.trim().to_string(), unescape(value))
+        })
+        .collect()
+}
+
+/// Formats `entries` as `key = value` lines, the inverse of [`parse`]
+pub fn format(entries: &BTreeMap<String, String>) -> String {
+    entries
+        .iter()
+        .map(|(key, value)| format!("{key} = {}\n", escape(value)))
+        .collect()
+}
+
+/// Keeps values on a single line
+fn escape(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('\n', "\\n")
+        .replace('\r', "\\r")
+}
+
+fn unescape(value: &str) -> String {
+    let mut out = String::with_capacity(value.len());
+    let mut chars = value.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            out.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('n') => out.push('\n'),
+            Some('r') => out.push('\r'),
+            Some(other) => out.push(other),
+            None => out.push('\\'),
+        }
+    }
+    out
+}
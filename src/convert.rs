@@ -0,0 +1,841 @@
+use crate::accessible;
+use crate::budget;
+use crate::bundle;
+use crate::code;
+use crate::embed;
+use crate::format::{self, OutputFormat, Source};
+use crate::front_matter::FrontMatter;
+use crate::headings::{self, Heading};
+use crate::hook;
+use crate::images;
+use crate::layout::{self, Direction, Section};
+use crate::links::{self, LinkStyle};
+use crate::normalize;
+use crate::numbering;
+use crate::paths::TempDir;
+use crate::pdf;
+use crate::process;
+use crate::qr;
+use crate::remote;
+use crate::security::{self, UrlPolicy};
+use crate::settings::Settings;
+use crate::stamp;
+use crate::style::{self, HeadingPreset};
+use crate::svg;
+use crate::theme::{self, StyleSource, Theme};
+use crate::themes;
+use crate::toc;
+use crate::typography::{self, SoftHyphenator};
+use image::RgbaImage;
+use lopdf::Document;
+use pulldown_cmark::{Event, Options};
+use std::collections::HashMap;
+use std::fs;
+use std::iter;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use url::Url;
+
+/// Markdown extensions enabled for every document, the GitHub-flavored set the themes are
+/// built for
+pub fn markdown_options() -> Options {
+    Options::ENABLE_TABLES
+        | Options::ENABLE_FOOTNOTES
+        | Options::ENABLE_STRIKETHROUGH
+        | Options::ENABLE_TASKLISTS
+}
+
+/// Escapes a value for use inside a double-quoted HTML attribute
+pub fn escape_attr(value: &str) -> String {
+    value
+        .replace('&', "&amp;")
+        .replace('"', "&quot;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+}
+
+/// Returns the document language: front matter `lang`, falling back to the settings
+pub fn document_lang<'a>(settings: &'a Settings, front_matter: &'a FrontMatter) -> &'a str {
+    front_matter.get("lang").unwrap_or(settings.language.trim())
+}
+
+/// Applies a document's `theme: github-dark` or `css: ./custom.css` front matter on top of
+/// `settings`, `css` winning if both are given, as well as its `cover` and `appendix` PDFs.
+/// `css` paths and those of the cover and appendix, also when set in `settings`, are
+/// relative to `base_dir`. Unknown themes are reported in `warnings` and leave the selected
+/// stylesheet in place, as do paths the URL policy doesn't allow.
+pub fn document_settings(
+    settings: &Settings,
+    front_matter: &FrontMatter,
+    base_dir: &Path,
+    warnings: &mut Vec<String>,
+) -> Settings {
+    let mut settings = settings.clone();
+    settings.metadata = settings.metadata.with_front_matter(front_matter);
+    let attached = [
+        ("cover", &mut settings.cover_pdf),
+        ("appendix", &mut settings.appendix_pdf),
+    ];
+    for (key, path) in attached {
+        if let Some(value) = front_matter.get(key) {
+            match security::not_allowed(value, base_dir, &settings.allowed_schemes) {
+                Some(warning) if settings.url_policy != UrlPolicy::Any => warnings.push(warning),
+                _ => *path = value.to_string(),
+            }
+        }
+        if !path.trim().is_empty() {
+            *path = base_dir.join(path.trim()).to_string_lossy().to_string();
+        }
+    }
+    if let Some(css) = front_matter.get("css") {
+        if settings.url_policy != UrlPolicy::Any
+            && let Some(warning) = security::not_allowed(css, base_dir, &settings.allowed_schemes)
+        {
+            warnings.push(warning);
+            return settings;
+        }
+        settings.stylesheet.source = StyleSource::File;
+        settings.stylesheet.file = base_dir.join(css).to_string_lossy().to_string();
+    } else if let Some(id) = front_matter.get("theme") {
+        if let Some(theme) = Theme::from_id(id) {
+            settings.theme = theme;
+            settings.user_theme = None;
+            settings.stylesheet.source = StyleSource::BuiltIn;
+        } else if let Some(theme) = themes::find(id) {
+            settings.user_theme = Some(theme.id);
+            settings.stylesheet.source = StyleSource::BuiltIn;
+        } else {
+            warnings.push(format!(
+                "Unknown theme '{}' in front matter, using the selected stylesheet",
+                id
+            ));
+        }
+    }
+    if let Some(keyword) = front_matter.get("headings") {
+        match HeadingPreset::from_keyword(keyword) {
+            Some(preset) => settings.heading_preset = preset,
+            None => warnings.push(format!(
+                "Unknown heading style '{}' in front matter, expected one of: {}",
+                keyword,
+                HeadingPreset::all()
+                    .iter()
+                    .map(HeadingPreset::keyword)
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            )),
+        }
+    }
+    settings
+}
+
+/// Files a conversion of `md_path` depends on besides its images: the Markdown file itself
+/// and the stylesheet file in effect for it, if any
+pub fn source_files(settings: &Settings, md_path: &Path) -> Vec<PathBuf> {
+    let md_text = fs::read_to_string(md_path).unwrap_or_default();
+    let (front_matter, _) = FrontMatter::extract(&md_text);
+    let base_dir = md_path.parent().unwrap_or(Path::new("."));
+    let settings = document_settings(settings, &front_matter, base_dir, &mut Vec::new());
+    iter::once(md_path.to_path_buf())
+        .chain(settings.stylesheet_file())
+        .collect()
+}
+
+/// Parses `md_body` (without front matter) and applies all document transformations.
+/// Problems that don't stop the conversion, like missing images, are added to `warnings`.
+pub fn render_events<'a>(
+    settings: &Settings,
+    md_body: &'a str,
+    base_dir: &Path,
+    lang: &str,
+    warnings: &mut Vec<String>,
+) -> Vec<Event<'a>> {
+    let mut events: Vec<_> = pulldown_cmark::Parser::new_ext(md_body, markdown_options()).collect();
+    let asset_root = settings.asset_root(base_dir);
+    let web_base = Url::parse(settings.base_url.trim())
+        .ok()
+        .filter(|_| asset_root.is_none());
+    // Before hyphenation, which would put soft hyphens into the encoded URLs
+    events = qr::apply(events, web_base.as_ref(), warnings);
+    if settings.hyphenate
+        && let Some(hyphenator) = SoftHyphenator::for_lang(lang)
+    {
+        events = hyphenator.apply(events);
+    }
+    let base_dir = asset_root.as_deref().unwrap_or(base_dir);
+    if let Some(base) = &web_base {
+        events = remote::resolve_relative(events, base);
+    }
+    if settings.url_policy != UrlPolicy::Any {
+        events = security::block_urls(events, base_dir, &settings.allowed_schemes, warnings);
+    }
+    if settings.rewrite_md_links {
+        events = links::rewrite_md_links(events);
+    }
+
+    events = images::apply_color_scheme(events, base_dir, settings.dark_output(), warnings);
+    events = images::select_variants(events, base_dir, settings.image_density);
+    let remote_blocked = if settings.offline {
+        Some("Remote image skipped in offline mode")
+    } else if settings.inject_csp && !security::allows_remote_images(&settings.csp_policy) {
+        Some("Remote image blocked by the Content-Security-Policy")
+    } else {
+        None
+    };
+    if settings.download_images && remote_blocked.is_none() {
+        events = remote::download_images(
+            events,
+            remote::proxy(&settings.proxy).as_deref(),
+            settings.image_cache_age(),
+            warnings,
+        );
+    }
+    events = images::mark_unresolved(events, base_dir, remote_blocked, warnings);
+    events = normalize::apply(events, base_dir, settings, warnings);
+    events = images::apply_attributes(events, warnings);
+    events = svg::apply(
+        events,
+        base_dir,
+        settings.svg_mode,
+        settings.svg_dpi,
+        warnings,
+    );
+    events = images::absolute_sources(events, base_dir);
+    if settings.self_contained {
+        events = embed::inline_images(events, warnings);
+    }
+    // Images are absolute by now, the links left relative point at files in the asset root
+    if asset_root.is_some()
+        && let Ok(root) = std::path::absolute(base_dir)
+        && let Ok(base) = Url::from_directory_path(root)
+    {
+        events = remote::resolve_relative(events, &base);
+    }
+    events = layout::mark_landscape_blocks(events, settings.landscape_wide_blocks);
+    events = numbering::mark_sections(events);
+    if settings.chapters_on_odd_pages {
+        events = layout::mark_chapters(events);
+    }
+    match settings.heading_preset {
+        HeadingPreset::Numbered => {
+            let first_level = headings::top_level(&events);
+            events = headings::number(events, first_level, 3);
+        }
+        HeadingPreset::CenteredChapters => events = headings::number(events, 1, 1),
+        HeadingPreset::Theme | HeadingPreset::SmallCaps => {}
+    }
+    if settings.link_style == LinkStyle::Numbered {
+        events = links::number_links(events);
+    }
+    events = code::format_blocks(
+        events,
+        settings.code_line_numbers,
+        settings.code_overflow,
+        settings.syntax_highlighting,
+        code::line_width(
+            settings.zoom * settings.style.text_scale(),
+            settings.columns,
+        ),
+    );
+    layout::apply_columns(events, settings.columns)
+}
+
+/// Wraps a rendered body into a complete, styled HTML document, which may only load local
+/// files from the folders `local_roots`.
+/// `extra_css` is appended after the other style layers, followed only by the user's
+/// additional CSS so it can override anything.
+pub fn wrap_document(
+    settings: &Settings,
+    local_roots: &[PathBuf],
+    body: &str,
+    direction: Direction,
+    lang: &str,
+    extra_css: &str,
+) -> String {
+    let lang_attr = if lang.is_empty() {
+        String::new()
+    } else {
+        format!(r#" lang="{}""#, escape_attr(lang))
+    };
+    // wkhtmltopdf takes the PDF's title from here
+    let title = match settings.metadata.title.trim() {
+        "" => "Markdown to PDF".to_string(),
+        title => escape_attr(title),
+    };
+
+    let csp_meta = if settings.offline {
+        // The default policy only allows local and inline resources, so wkhtmltopdf can't
+        // fetch anything either
+        security::csp_meta(&security::scoped_policy(security::DEFAULT_CSP, local_roots))
+    } else if settings.inject_csp {
+        security::csp_meta(&security::scoped_policy(&settings.csp_policy, local_roots))
+    } else {
+        String::new()
+    };
+
+    format!(
+        r#"<!DOCTYPE html>
+            <html dir="{}"{}>
+            <head>
+                <meta charset="utf-8">
+                {}
+                <title>{}</title>
+                <style>
+                    {}
+                </style>
+            </head>
+            <body class="markdown-body">
+                {}
+            </body>
+            </html>"#,
+        direction.html_attr(),
+        lang_attr,
+        csp_meta,
+        title,
+        document_css(settings, extra_css),
+        body
+    )
+}
+
+/// The style layers of the document: the theme or custom stylesheet, the styling options
+/// and the CSS of the Markdown extensions, then `extra_css` and the user's additional CSS
+pub fn document_css(settings: &Settings, extra_css: &str) -> String {
+    // Use the custom stylesheet or the actively selected theme's CSS
+    let mut stylesheet = settings.resolved_stylesheet_css().unwrap_or_else(|_| {
+        theme::resolve_color_scheme(settings.theme.css(), settings.color_scheme)
+    });
+    if settings.self_contained
+        && let Some(file) = settings.stylesheet_file()
+    {
+        let base_dir = file.parent().unwrap_or(Path::new("."));
+        stylesheet = embed::inline_css_urls(&stylesheet, base_dir);
+    }
+
+    format!(
+        "{}\n{}\n{}\n{}\n{}\n{}\n{}\n{}\n{}\n{}\n{}\n{}\n{}\n{}\n{}\n{}\n{}\n{}\n{}\n{}\n{}",
+        stylesheet,
+        settings.style.css(settings.dark_output()),
+        if settings.print_media {
+            let margin = if settings.style.enabled {
+                settings.style.margin_mm
+            } else {
+                style::DEFAULT_MARGIN_MM
+            };
+            style::print_css(margin)
+        } else {
+            String::new()
+        },
+        settings.link_style.css(),
+        settings.heading_preset.css(),
+        layout::rtl_css(settings.theme.border_color()),
+        layout::COLUMNS_CSS,
+        code::CODE_CSS,
+        if settings.syntax_highlighting {
+            settings.highlight_palette().css()
+        } else {
+            String::new()
+        },
+        images::PLACEHOLDER_CSS,
+        if settings.fit_images {
+            images::IMAGE_FIT_CSS
+        } else {
+            ""
+        },
+        images::IMAGE_ATTRIBUTE_CSS,
+        svg::SVG_CSS,
+        qr::QR_CSS,
+        if settings.image_backing && settings.dark_output() {
+            images::IMAGE_BACKING_CSS
+        } else {
+            ""
+        },
+        toc::TOC_CSS,
+        typography::css(
+            settings.justify,
+            settings.hyphenate,
+            settings.book_paragraphs,
+        ),
+        if settings.background.enabled {
+            stamp::TRANSPARENT_BODY_CSS
+        } else {
+            ""
+        },
+        if settings.prints_backgrounds() {
+            stamp::PRINT_BACKGROUNDS_CSS
+        } else {
+            ""
+        },
+        extra_css,
+        settings.additional_css,
+    )
+}
+
+/// Renders the Markdown source into a complete, styled HTML document, returning it together
+/// with the document's headings.
+/// `base_dir` is the directory relative paths in the document are resolved against,
+/// `local_roots` the folders it may load local files from, see [`Settings::local_roots`],
+/// and `pages` holds the page labels shown in the table of contents, if known.
+pub fn build_html(
+    settings: &Settings,
+    md_text: &str,
+    base_dir: &Path,
+    local_roots: &[PathBuf],
+    pages: &HashMap<String, String>,
+    warnings: &mut Vec<String>,
+) -> (String, Vec<Heading>) {
+    let (front_matter, md_body) = FrontMatter::extract(md_text);
+    let direction = settings.direction.resolve(&front_matter, md_body);
+    let lang = document_lang(settings, &front_matter);
+
+    let events = render_events(settings, md_body, base_dir, lang, warnings);
+    if settings.accessible {
+        accessible::check_alt_text(&events, warnings);
+    }
+    let (events, headings) = headings::assign_ids(events, "");
+    let events = toc::insert(events, &headings, pages);
+
+    let mut html_body = String::new();
+    pulldown_cmark::html::push_html(&mut html_body, events.into_iter());
+    (
+        wrap_document(settings, local_roots, &html_body, direction, lang, ""),
+        headings,
+    )
+}
+
+/// Returns the first free numbered variant of `path`: `report.pdf` becomes `report (2).pdf`,
+/// and `report (2).pdf` becomes `report (3).pdf`
+pub fn versioned_path(path: &Path) -> PathBuf {
+    let stem = path
+        .file_stem()
+        .map(|stem| stem.to_string_lossy().to_string())
+        .unwrap_or_default();
+    let numbered = stem
+        .strip_suffix(')')
+        .and_then(|rest| rest.rsplit_once(" ("))
+        .and_then(|(base, number)| Some((base.to_string(), number.parse::<u32>().ok()?)));
+    let (base, mut number) = numbered.unwrap_or((stem, 1));
+    let extension = path
+        .extension()
+        .map(|extension| format!(".{}", extension.to_string_lossy()))
+        .unwrap_or_default();
+    loop {
+        number += 1;
+        let candidate = path.with_file_name(format!("{base} ({number}){extension}"));
+        if !candidate.exists() {
+            return candidate;
+        }
+    }
+}
+
+/// A document rendered to the HTML its PDF and page images are made from
+pub struct Rendered {
+    pub settings: Settings, // With the document's front matter applied
+    pub html: String,
+    pub local_roots: Vec<PathBuf>, // Folders the HTML may load local files from
+    pub temp_dir: TempDir,         // For the files of the PDFs rendered from it
+    pub warnings: Vec<String>,     // Of the rendering
+}
+
+/// Converts the Markdown file at `md_path` into a PDF at `pdf_path`.
+/// Returns the warnings collected along the way.
+pub fn convert_file(
+    settings: &Settings,
+    md_path: &Path,
+    pdf_path: &Path,
+) -> Result<Vec<String>, String> {
+    format::convert_file(settings, OutputFormat::Pdf, md_path, pdf_path)
+}
+
+/// Reads the Markdown file at `md_path` and renders it, see [`render_text`]
+pub fn render_file(settings: &Settings, md_path: &Path) -> Result<Rendered, String> {
+    if !md_path.exists() {
+        return Err(format!(
+            "Error: Markdown file not found at '{}'",
+            md_path.display()
+        ));
+    }
+    if !md_path.is_file() {
+        return Err(format!("Error: '{}' is not a file.", md_path.display()));
+    }
+
+    let md_text =
+        fs::read_to_string(md_path).map_err(|e| format!("Failed to read Markdown file: {}", e))?;
+    let base_dir = md_path.parent().unwrap_or(Path::new("."));
+    render_text(settings, &md_text, base_dir)
+}
+
+/// Writes the Markdown file at `md_path` as the HTML document the PDF is rendered from,
+/// with the local files it uses copied next to it if `copy_assets` is set.
+/// Returns the warnings collected along the way.
+pub fn export_html(
+    settings: &Settings,
+    md_path: &Path,
+    html_path: &Path,
+) -> Result<Vec<String>, String> {
+    let md_text =
+        fs::read_to_string(md_path).map_err(|e| format!("Failed to read Markdown file: {}", e))?;
+    let base_dir = md_path.parent().unwrap_or(Path::new("."));
+    let mut warnings = Vec::new();
+    let (front_matter, _) = FrontMatter::extract(&md_text);
+    let settings = &Settings {
+        // Other documents aren't exported along, their links stay as they are
+        rewrite_md_links: false,
+        ..document_settings(settings, &front_matter, base_dir, &mut warnings)
+    };
+    settings.check_styles()?;
+    // Copied assets are next to the HTML
+    let mut local_roots = settings.local_roots(base_dir);
+    local_roots.extend(html_path.parent().map(Path::to_path_buf));
+    let (mut html, _) = build_html(
+        settings,
+        &md_text,
+        base_dir,
+        &local_roots,
+        &HashMap::new(),
+        &mut warnings,
+    );
+    security::enforce(settings.url_policy, &warnings)?;
+    if settings.copy_assets {
+        html = bundle::copy_assets(&html, html_path, &mut warnings)?;
+    }
+    fs::write(html_path, html).map_err(|e| format!("Failed to write HTML file: {}", e))?;
+    Ok(warnings)
+}
+
+/// Converts Markdown text to a PDF at `pdf_path`, resolving relative paths against `base_dir`.
+/// Returns the warnings of the conversion.
+pub fn convert_text(
+    settings: &Settings,
+    md_text: &str,
+    base_dir: &Path,
+    pdf_path: &Path,
+) -> Result<Vec<String>, String> {
+    format::Conversions::of(settings, Source::Text(md_text, base_dir))
+        .convert(OutputFormat::Pdf, pdf_path)
+        .map(|(_, warnings)| warnings)
+}
+
+/// Renders Markdown text to the HTML of its PDF, resolving relative paths against
+/// `base_dir`. With an accurate table of contents, a draft is rendered to find the pages.
+pub fn render_text(
+    settings: &Settings,
+    md_text: &str,
+    base_dir: &Path,
+) -> Result<Rendered, String> {
+    let mut warnings = Vec::new();
+    let (front_matter, _) = FrontMatter::extract(md_text);
+    let settings = document_settings(settings, &front_matter, base_dir, &mut warnings);
+    settings.check_styles()?;
+    let local_roots = settings.local_roots(base_dir);
+    let temp_dir = TempDir::new()?;
+    let (mut full_html, headings) = build_html(
+        &settings,
+        md_text,
+        base_dir,
+        &local_roots,
+        &HashMap::new(),
+        &mut warnings,
+    );
+    security::enforce(settings.url_policy, &warnings)?;
+    if settings.accurate_toc && toc::is_present(&full_html) {
+        // Second pass with the page numbers found in a draft rendering
+        let pages = toc::locate_headings(
+            &settings,
+            &full_html,
+            &local_roots,
+            temp_dir.path(),
+            &headings,
+        )?;
+        if pages.is_empty() && !headings.is_empty() {
+            warnings.push(
+                "Could not find the headings' pages, the table of contents has no page numbers"
+                    .to_string(),
+            );
+        }
+        full_html = build_html(
+            &settings,
+            md_text,
+            base_dir,
+            &local_roots,
+            &pages,
+            &mut Vec::new(),
+        )
+        .0;
+    }
+    Ok(Rendered {
+        settings,
+        html: full_html,
+        local_roots,
+        temp_dir,
+        warnings,
+    })
+}
+
+/// Writes the PDF of `rendered` to `pdf_path` with `settings`, its own or those of an
+/// output made from the PDF. Returns the warnings of the rendering and of writing the PDF.
+pub fn write_pdf(
+    rendered: &Rendered,
+    settings: &Settings,
+    pdf_path: &Path,
+) -> Result<Vec<String>, String> {
+    let mut warnings = rendered.warnings.clone();
+    if let Some(parent) = pdf_path.parent() {
+        fs::create_dir_all(parent)
+            .map_err(|e| format!("Failed to create output directory: {}", e))?;
+    }
+    render_pdf(
+        settings,
+        &rendered.html,
+        &rendered.local_roots,
+        rendered.temp_dir.path(),
+        pdf_path,
+        &mut warnings,
+    )?;
+    budget::check(settings, &rendered.html, pdf_path, &mut warnings)?;
+    Ok(warnings)
+}
+
+/// Downloads the Markdown at `url` and converts it to a PDF at `pdf_path`, with relative
+/// links and images resolved against the URL. Returns the warnings of the conversion.
+pub fn convert_url(settings: &Settings, url: &Url, pdf_path: &Path) -> Result<Vec<String>, String> {
+    if settings.offline {
+        return Err(format!("Offline mode is on, '{}' can't be downloaded", url));
+    }
+    let md_text = remote::download_text(url, remote::proxy(&settings.proxy).as_deref())?;
+    let settings = Settings {
+        base_url: url.to_string(),
+        ..settings.clone()
+    };
+    convert_text(&settings, &md_text, Path::new("."), pdf_path)
+}
+
+/// Renders a complete HTML document to `pdf_path`, splitting off landscape sections if needed,
+/// or with Chromium for accessible PDFs, then applies the enabled post-processing steps and
+/// the post-processing command. The size report of optimized PDFs is added to `warnings`.
+/// wkhtmltopdf only loads local files from `local_roots`, temporary files are written to
+/// `temp_dir`.
+pub fn render_pdf(
+    settings: &Settings,
+    full_html: &str,
+    local_roots: &[PathBuf],
+    temp_dir: &Path,
+    pdf_path: &Path,
+    warnings: &mut Vec<String>,
+) -> Result<(), String> {
+    if settings.accessible {
+        accessible::render_pdf(
+            settings,
+            full_html,
+            local_roots,
+            temp_dir,
+            pdf_path,
+            warnings,
+        )?;
+    } else {
+        let sections = layout::split_sections(full_html);
+        let page_counts = if let [section] = sections.as_slice() {
+            run_wkhtmltopdf(
+                settings,
+                &section.html,
+                local_roots,
+                pdf_path,
+                section.landscape,
+                &temp_dir.join("document"),
+                &[],
+            )?;
+            Vec::new() // A single section starts on the first page, its length doesn't matter
+        } else {
+            render_sections(settings, &sections, local_roots, temp_dir, pdf_path)?
+        };
+        if sections.iter().any(|section| section.numbering.is_some()) {
+            label_pages(pdf_path, &sections, &page_counts)?;
+        }
+    }
+    pdf::post_process(settings, pdf_path, temp_dir, warnings)?;
+    hook::run_post_command(&settings.post_command, pdf_path, temp_dir)
+}
+
+/// Writes `html` to a temporary file and runs wkhtmltopdf on it, loading local files only
+/// from `local_roots`. The temporary file is `temp_path` with an extension added.
+pub fn run_wkhtmltopdf(
+    settings: &Settings,
+    html: &str,
+    local_roots: &[PathBuf],
+    pdf_path: &Path,
+    landscape: bool,
+    temp_path: &Path,
+    extra_args: &[&str],
+) -> Result<(), String> {
+    let html_file_path = temp_path.with_extension("html");
+    if let Err(e) = fs::write(&html_file_path, html) {
+        return Err(format!("Failed to write temporary HTML: {}", e));
+    }
+
+    let mut command = Command::new("wkhtmltopdf");
+    command
+        .args(sandbox_args(settings, local_roots))
+        .args(proxy_args(settings))
+        .args(settings.preset().wkhtmltopdf_args(settings.draft_quality))
+        .args(settings.grayscale.then_some("--grayscale"))
+        .args(settings.prints_backgrounds().then_some("--background"))
+        .args(settings.style.wkhtmltopdf_args())
+        .args(settings.print_media.then_some("--print-media-type"))
+        .args(["--zoom", &settings.zoom.to_string()])
+        .args([
+            "--orientation",
+            if landscape { "Landscape" } else { "Portrait" },
+        ])
+        .args(extra_args)
+        .arg(&html_file_path)
+        .arg(pdf_path);
+    let output = command.output();
+    let _ = fs::remove_file(&html_file_path);
+
+    match output {
+        Ok(command_output) if command_output.status.success() => Ok(()),
+        Ok(command_output) => Err(process::failure("wkhtmltopdf", &command, &command_output)),
+        Err(e) => Err(format!(
+            "Failed to execute wkhtmltopdf. Is it installed and in your PATH? Error: {}\nCommand: {}",
+            e,
+            process::command_line(&command)
+        )),
+    }
+}
+
+/// Arguments of wkhtmltopdf and wkhtmltoimage that only let the document load local files
+/// from `local_roots`, such as its images, and keep its scripts from running unless the
+/// Content-Security-Policy stops them and any URL is allowed
+fn sandbox_args(settings: &Settings, local_roots: &[PathBuf]) -> Vec<String> {
+    let mut args = vec!["--disable-local-file-access".to_string()];
+    for root in local_roots {
+        let root = std::path::absolute(root).unwrap_or_else(|_| root.clone());
+        args.extend(["--allow".to_string(), root.to_string_lossy().to_string()]);
+    }
+    if !settings.inject_csp || settings.url_policy != UrlPolicy::Any {
+        args.push("--disable-javascript".to_string());
+    }
+    args
+}
+
+/// wkhtmltopdf's arguments for loading remote resources through the proxy, which it doesn't
+/// take from the environment itself
+fn proxy_args(settings: &Settings) -> Vec<String> {
+    if settings.offline {
+        return Vec::new();
+    }
+    let Some(proxy) = remote::proxy(&settings.proxy) else {
+        return Vec::new();
+    };
+    let mut args = vec!["--proxy".to_string(), proxy];
+    for host in remote::no_proxy_hosts() {
+        args.extend(["--bypass-proxy-for".to_string(), host]);
+    }
+    args
+}
+
+/// Renders `html` to an image with wkhtmltoimage, `width` pixels wide and cropped to `height`
+/// if given, loading local files only from `local_roots`. `temp_path` with extensions added
+/// is used for the temporary files.
+pub fn run_wkhtmltoimage(
+    settings: &Settings,
+    html: &str,
+    local_roots: &[PathBuf],
+    temp_path: &Path,
+    width: u32,
+    height: Option<u32>,
+) -> Result<RgbaImage, String> {
+    let html_path = temp_path.with_extension("html");
+    let png_path = temp_path.with_extension("png");
+    fs::write(&html_path, html).map_err(|e| format!("Failed to write temporary HTML: {}", e))?;
+    let mut command = Command::new("wkhtmltoimage");
+    command
+        .args(["--quiet", "--format", "png"])
+        .args(sandbox_args(settings, local_roots))
+        .args(["--width", &width.to_string()])
+        .args(
+            height
+                .map(|height| ["--height".to_string(), height.to_string()])
+                .into_iter()
+                .flatten(),
+        )
+        .arg(&html_path)
+        .arg(&png_path);
+    let output = command.output();
+    let _ = fs::remove_file(&html_path);
+
+    let result = match output {
+        Ok(output) if output.status.success() => image::open(&png_path)
+            .map(|image| image.into_rgba8())
+            .map_err(|e| format!("Failed to read rendered image: {}", e)),
+        Ok(output) => Err(process::failure("wkhtmltoimage", &command, &output)),
+        Err(e) => Err(format!(
+            "Failed to execute wkhtmltoimage (installed with wkhtmltopdf): {}",
+            e
+        )),
+    };
+    let _ = fs::remove_file(&png_path);
+    result
+}
+
+/// Renders each section separately, with the parts in `temp_dir`, and merges them into
+/// `pdf_path`. Returns the number of pages of each section, including blank pages padding it.
+fn render_sections(
+    settings: &Settings,
+    sections: &[Section],
+    local_roots: &[PathBuf],
+    temp_dir: &Path,
+    pdf_path: &Path,
+) -> Result<Vec<usize>, String> {
+    let mut parts = Vec::new();
+    let mut result = Ok(Vec::new());
+    for (i, section) in sections.iter().enumerate() {
+        let part_path = temp_dir.join(format!("part_{i}.pdf"));
+        result = run_wkhtmltopdf(
+            settings,
+            &section.html,
+            local_roots,
+            &part_path,
+            section.landscape,
+            &part_path,
+            &[],
+        )
+        .map(|()| Vec::new());
+        parts.push(part_path);
+        if result.is_err() {
+            break;
+        }
+    }
+
+    if result.is_ok() {
+        let recto: Vec<bool> = sections.iter().map(|section| section.recto).collect();
+        result = pdf::merge_files(&parts, &recto, pdf_path)
+            .map_err(|e| format!("Failed to merge document sections: {}", e));
+    }
+    for part in &parts {
+        let _ = fs::remove_file(part);
+    }
+    result
+}
+
+/// Labels the pages of the PDF at `pdf_path` according to the numbering restarts of
+/// `sections`, given the number of pages of each section
+fn label_pages(pdf_path: &Path, sections: &[Section], page_counts: &[usize]) -> Result<(), String> {
+    let mut ranges = Vec::new();
+    let mut first_page = 0;
+    for (section, page_count) in sections
+        .iter()
+        .zip(page_counts.iter().chain(iter::repeat(&0)))
+    {
+        if let Some(style) = section.numbering {
+            ranges.push((first_page, style));
+        }
+        first_page += page_count;
+    }
+
+    let mut document = Document::load(pdf_path)
+        .map_err(|e| format!("Failed to load PDF for page numbering: {}", e))?;
+    numbering::set_page_labels(&mut document, &ranges)?;
+    document
+        .save(pdf_path)
+        .map_err(|e| format!("Failed to save PDF with page numbering: {}", e))?;
+    Ok(())
+}
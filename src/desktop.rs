@@ -0,0 +1,116 @@
+use crate::i18n::tr;
+use notify_rust::Notification;
+use std::ffi::OsStr;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::thread;
+
+/// Opens `path` with the default application for its type
+pub fn open(path: &Path) -> Result<(), String> {
+    launch(path.as_os_str()).map_err(|e| format!("Failed to open '{}': {}", path.display(), e))
+}
+
+/// Opens `url` in the default browser
+pub fn open_url(url: &str) -> Result<(), String> {
+    launch(OsStr::new(url)).map_err(|e| format!("Failed to open '{}': {}", url, e))
+}
+
+/// Hands `target` to the platform's opener, without waiting for it
+fn launch(target: &OsStr) -> std::io::Result<()> {
+    let command = if cfg!(target_os = "windows") {
+        // Not through `cmd /C start`, which would run what follows a `&` in the name
+        Command::new("explorer").arg(target).spawn()
+    } else if cfg!(target_os = "macos") {
+        Command::new("open").arg(target).spawn()
+    } else {
+        Command::new("xdg-open").arg(target).spawn()
+    };
+    command.map(|_| ())
+}
+
+/// Shows `path` in the file manager, selected where the platform supports it
+pub fn reveal(path: &Path) -> Result<(), String> {
+    let command = if cfg!(target_os = "windows") {
+        Command::new("explorer")
+            .arg(format!("/select,{}", path.display()))
+            .spawn()
+    } else if cfg!(target_os = "macos") {
+        Command::new("open").arg("-R").arg(path).spawn()
+    } else {
+        // There's no portable way to select a file, open its folder instead
+        let folder = path
+            .parent()
+            .filter(|folder| !folder.as_os_str().is_empty())
+            .unwrap_or(Path::new("."));
+        Command::new("xdg-open").arg(folder).spawn()
+    };
+    command
+        .map(|_| ())
+        .map_err(|e| format!("Failed to show '{}' in its folder: {}", path.display(), e))
+}
+
+/// Sends the PDF at `path` to the default printer through the platform's print spooler:
+/// the shell's print verb on Windows, `lp` elsewhere. Returns once the job is handed over.
+pub fn print(path: &Path) -> Result<(), String> {
+    let output = if cfg!(target_os = "windows") {
+        let path = path.display().to_string().replace('\'', "''");
+        Command::new("powershell")
+            .args(["-NoProfile", "-Command"])
+            .arg(format!("Start-Process -FilePath '{path}' -Verb Print"))
+            .output()
+    } else {
+        Command::new("lp").arg(path).output()
+    };
+    let output = output.map_err(|e| format!("Failed to print '{}': {}", path.display(), e))?;
+    if !output.status.success() {
+        return Err(format!(
+            "Failed to print '{}': {}",
+            path.display(),
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+    Ok(())
+}
+
+/// Shows a desktop notification offering to show `folder` in the file manager. Where the
+/// notification server has no actions, the folder is named in the text instead. Returns
+/// without waiting for the notification to close.
+pub fn notify(title: &str, body: &str, folder: Option<PathBuf>) {
+    let (title, mut body) = (title.to_string(), body.to_string());
+    thread::spawn(move || {
+        let mut notification = Notification::new();
+        notification.appname("md-to-pdf").summary(&title);
+        if let Some(folder) = &folder {
+            if supports_actions() {
+                notification.action("open", tr!("Show in folder"));
+            } else {
+                body.push('\n');
+                body.push_str(&tr!("Saved in {folder}", folder = folder.display()));
+            }
+        }
+        let Ok(handle) = notification.body(&body).show() else {
+            return;
+        };
+        // Clicking the notification itself is the default action
+        handle.wait_for_action(|action| {
+            if let Some(folder) = &folder
+                && matches!(action, "open" | "default")
+            {
+                let _ = open(folder);
+            }
+        });
+    });
+}
+
+/// Whether notifications can offer actions. Not all Linux notification servers show them.
+#[cfg(all(unix, not(target_os = "macos")))]
+fn supports_actions() -> bool {
+    notify_rust::get_capabilities()
+        .is_ok_and(|capabilities| capabilities.iter().any(|name| name == "actions"))
+}
+
+/// Whether notifications can offer actions, which they always can on Windows and macOS
+#[cfg(not(all(unix, not(target_os = "macos"))))]
+fn supports_actions() -> bool {
+    true
+}
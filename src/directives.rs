@@ -0,0 +1,15 @@
+/// Parses a directive written as an HTML comment, e.g. `<!-- columns: 2 -->`.
+/// Returns the lower-cased directive name and its trimmed value.
+pub fn parse(html: &str) -> Option<(String, String)> {
+    let inner = html
+        .trim()
+        .strip_prefix("<!--")?
+        .strip_suffix("-->")?
+        .trim();
+    let (name, value) = inner.split_once(':')?;
+    let name = name.trim();
+    if name.is_empty() || !name.chars().all(|c| c.is_ascii_alphanumeric() || c == '-') {
+        return None;
+    }
+    Some((name.to_lowercase(), value.trim().to_string()))
+}
@@ -0,0 +1,118 @@
+use crate::convert;
+use crate::front_matter::FrontMatter;
+use crate::headings;
+use crate::paths::TempDir;
+use crate::process;
+use crate::settings::Settings;
+use crate::setup;
+use crate::toc;
+use std::collections::HashMap;
+use std::ffi::OsStr;
+use std::fs;
+use std::path::Path;
+use std::process::Command;
+
+/// The pandoc command converting the HTML file at `html_path` to a Word document at
+/// `docx_path`, styled after `reference` if given. `resource_dir` is where paths left
+/// relative are looked up.
+fn pandoc(
+    html_path: &Path,
+    docx_path: &Path,
+    reference: Option<&Path>,
+    resource_dir: &Path,
+) -> Command {
+    let mut command = Command::new("pandoc");
+    command
+        .args(["--from", "html", "--to", "docx"])
+        .arg("--resource-path")
+        .arg(resource_dir)
+        .args(
+            reference
+                .map(|reference| [OsStr::new("--reference-doc"), reference.as_os_str()])
+                .into_iter()
+                .flatten(),
+        )
+        .arg("--output")
+        .arg(docx_path)
+        .arg(html_path);
+    command
+}
+
+/// Converts the Markdown file at `md_path` to a Word document at `docx_path` with pandoc.
+/// The document goes through the same steps as for the PDF, so the extensions, images and
+/// heading numbers carry over, but its styles come from pandoc or the `docx_reference`
+/// template. Returns the warnings of the conversion.
+pub fn export(
+    settings: &Settings,
+    md_path: &Path,
+    docx_path: &Path,
+) -> Result<Vec<String>, String> {
+    let md_text =
+        fs::read_to_string(md_path).map_err(|e| format!("Failed to read Markdown file: {}", e))?;
+    let base_dir = md_path.parent().unwrap_or(Path::new("."));
+    let mut warnings = Vec::new();
+    let (front_matter, md_body) = FrontMatter::extract(&md_text);
+    let settings = &Settings {
+        // pandoc reads data URIs on every platform, file URLs not
+        self_contained: true,
+        columns: 1,
+        landscape_wide_blocks: false,
+        chapters_on_odd_pages: false,
+        rewrite_md_links: false,
+        ..convert::document_settings(settings, &front_matter, base_dir, &mut warnings)
+    };
+    let lang = convert::document_lang(settings, &front_matter);
+    let events = convert::render_events(settings, md_body, base_dir, lang, &mut warnings);
+    let (events, headings) = headings::assign_ids(events, "");
+    let events = toc::insert(events, &headings, &HashMap::new());
+    let mut body = String::new();
+    pulldown_cmark::html::push_html(&mut body, events.into_iter());
+    // Only a title of the document's own becomes the Word document's title
+    let title = front_matter
+        .get("title")
+        .map(|title| format!("<title>{}</title>", convert::escape_attr(title)))
+        .unwrap_or_default();
+    let html = format!(
+        "<!DOCTYPE html>\n<html lang=\"{}\">\n<head>\n<meta charset=\"utf-8\">\n{}\n</head>\n<body>\n{}\n</body>\n</html>\n",
+        convert::escape_attr(lang),
+        title,
+        body
+    );
+
+    let reference = settings.docx_reference.trim();
+    let reference = (!reference.is_empty()).then(|| base_dir.join(reference));
+    if let Some(reference) = &reference
+        && !reference.is_file()
+    {
+        return Err(format!("Word template '{}' not found", reference.display()));
+    }
+    if let Some(parent) = docx_path.parent() {
+        fs::create_dir_all(parent)
+            .map_err(|e| format!("Failed to create output directory: {}", e))?;
+    }
+    let temp_dir = TempDir::new()?;
+    let html_path = temp_dir.path().join("document.html");
+    fs::write(&html_path, html).map_err(|e| format!("Failed to write temporary HTML: {}", e))?;
+    let mut command = pandoc(&html_path, docx_path, reference.as_deref(), base_dir);
+    let output = command.output();
+    let _ = fs::remove_file(&html_path);
+    match output {
+        Ok(output) if output.status.success() => {
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            warnings.extend(
+                stderr
+                    .lines()
+                    .map(str::trim)
+                    .filter(|line| !line.is_empty())
+                    .map(|line| format!("pandoc: {}", line)),
+            );
+            Ok(warnings)
+        }
+        Ok(output) => Err(process::failure("pandoc", &command, &output)),
+        Err(e) => Err(format!(
+            "Failed to execute pandoc, which Word documents are exported with: {}. {}",
+            e,
+            setup::install_hint("pandoc")
+        )),
+    }
+}
@@ -0,0 +1,246 @@
+use crate::highlight::{self, Token};
+use crate::spell;
+use eframe::egui::text::{LayoutJob, TextFormat};
+use eframe::egui::{Color32, FontId, Stroke};
+use std::fs;
+use std::time::SystemTime;
+
+/// A Markdown file opened for editing in the app
+#[derive(Default)]
+pub struct Editor {
+    pub path: String, // File the text was loaded from, empty before the first load
+    pub text: String, // Text being edited
+    saved: String,    // Text as last loaded or saved
+    modified: Option<SystemTime>, // Modification time of the file when last loaded or saved
+}
+
+impl Editor {
+    /// Loads the file at `path`, discarding unsaved changes
+    pub fn load(&mut self, path: &str) -> Result<(), String> {
+        let text =
+            fs::read_to_string(path).map_err(|e| format!("Failed to read '{}': {}", path, e))?;
+        self.path = path.to_string();
+        self.saved = text.clone();
+        self.text = text;
+        self.modified = modified(path);
+        Ok(())
+    }
+
+    /// Writes the text back to the file it was loaded from
+    pub fn save(&mut self) -> Result<(), String> {
+        fs::write(&self.path, &self.text)
+            .map_err(|e| format!("Failed to save '{}': {}", self.path, e))?;
+        self.saved = self.text.clone();
+        self.modified = modified(&self.path);
+        Ok(())
+    }
+
+    /// Whether the text has changes that aren't saved yet
+    pub fn is_dirty(&self) -> bool {
+        self.text != self.saved
+    }
+
+    /// Whether another program changed the file since it was loaded or saved
+    pub fn changed_on_disk(&self) -> bool {
+        !self.path.is_empty() && modified(&self.path) != self.modified
+    }
+}
+
+fn modified(path: &str) -> Option<SystemTime> {
+    fs::metadata(path)
+        .and_then(|metadata| metadata.modified())
+        .ok()
+}
+
+/// Parts of Markdown source the editor colors
+#[derive(Clone, Copy)]
+enum Kind {
+    Text,
+    Heading,
+    Quote,
+    Marker, // List bullets and numbers, rules and code fences
+    Code,   // Inline code and unhighlighted code blocks
+    Link,
+    Token(Token),
+}
+
+impl Kind {
+    fn color(&self, dark: bool) -> Color32 {
+        let (light, dark_color) = match self {
+            Kind::Text => {
+                return if dark {
+                    Color32::from_gray(220)
+                } else {
+                    Color32::from_gray(30)
+                };
+            }
+            Kind::Heading => ([0x05, 0x50, 0xae], [0x79, 0xc0, 0xff]),
+            Kind::Quote => ([0x6a, 0x73, 0x7d], [0x8b, 0x94, 0x9e]),
+            Kind::Marker => ([0xd7, 0x3a, 0x49], [0xff, 0x7b, 0x72]),
+            Kind::Code => ([0x6f, 0x42, 0xc1], [0xd2, 0xa8, 0xff]),
+            Kind::Link => ([0x03, 0x2f, 0x62], [0xa5, 0xd6, 0xff]),
+            Kind::Token(Token::Comment) => ([0x6a, 0x73, 0x7d], [0x8b, 0x94, 0x9e]),
+            Kind::Token(Token::String) => ([0x03, 0x2f, 0x62], [0xa5, 0xd6, 0xff]),
+            Kind::Token(Token::Number) => ([0x00, 0x5c, 0xc5], [0x79, 0xc0, 0xff]),
+            Kind::Token(Token::Keyword) => ([0xd7, 0x3a, 0x49], [0xff, 0x7b, 0x72]),
+        };
+        let [r, g, b] = if dark { dark_color } else { light };
+        Color32::from_rgb(r, g, b)
+    }
+}
+
+/// Underline of misspelled words
+const MISSPELLED_COLOR: Color32 = Color32::from_rgb(0xe0, 0x30, 0x30);
+
+/// Colors Markdown source for the editor: headings, quotes, list markers, links, inline code,
+/// and fenced code blocks with the same highlighter the PDF uses. Words of the text for which
+/// `misspelled` is true are underlined.
+pub fn layout_job(
+    text: &str,
+    font: FontId,
+    dark: bool,
+    misspelled: &dyn Fn(&str) -> bool,
+) -> LayoutJob {
+    let mut job = LayoutJob::default();
+    let mut append = |text: &str, kind: Kind| {
+        if text.is_empty() {
+            return;
+        }
+        let format = TextFormat::simple(font.clone(), kind.color(dark));
+        let mut start = 0;
+        if matches!(kind, Kind::Text | Kind::Heading | Kind::Quote) {
+            for (offset, word) in spell::words(text) {
+                if !misspelled(word) {
+                    continue;
+                }
+                if offset > start {
+                    job.append(&text[start..offset], 0.0, format.clone());
+                }
+                let underlined = TextFormat {
+                    underline: Stroke::new(1.0, MISSPELLED_COLOR),
+                    ..format.clone()
+                };
+                job.append(word, 0.0, underlined);
+                start = offset + word.len();
+            }
+        }
+        if start < text.len() {
+            job.append(&text[start..], 0.0, format);
+        }
+    };
+
+    let mut fence: Option<(String, String, String)> = None; // Marker, language and body of the open code block
+    for line in text.split_inclusive('\n') {
+        let trimmed = line.trim_start();
+        if let Some((marker, language, body)) = &mut fence {
+            let closing = trimmed.trim_end();
+            if closing.len() >= marker.len() && closing.chars().all(|c| marker.starts_with(c)) {
+                append_code(&mut append, language, body);
+                append(line, Kind::Marker);
+                fence = None;
+            } else {
+                body.push_str(line);
+            }
+            continue;
+        }
+
+        if trimmed.starts_with("```") || trimmed.starts_with("~~~") {
+            let symbol = trimmed.chars().next().unwrap_or('`');
+            let marker: String = trimmed.chars().take_while(|c| *c == symbol).collect();
+            let language = trimmed[marker.len()..]
+                .split_whitespace()
+                .next()
+                .unwrap_or("")
+                .to_string();
+            append(line, Kind::Marker);
+            fence = Some((marker, language, String::new()));
+        } else if trimmed.starts_with('#') {
+            append(line, Kind::Heading);
+        } else if trimmed.starts_with('>') {
+            append(line, Kind::Quote);
+        } else if is_rule(trimmed) {
+            append(line, Kind::Marker);
+        } else {
+            let indent = line.len() - trimmed.len();
+            let marker = list_marker(trimmed);
+            append(&line[..indent], Kind::Text);
+            append(&trimmed[..marker], Kind::Marker);
+            append_inline(&mut append, &trimmed[marker..]);
+        }
+    }
+    // A code block left open runs to the end of the text
+    if let Some((_, language, body)) = &fence {
+        append_code(&mut append, language, body);
+    }
+    job
+}
+
+/// Appends the body of a code block, highlighted if its language is known
+fn append_code(append: &mut impl FnMut(&str, Kind), language: &str, body: &str) {
+    match highlight::highlight(language, body) {
+        Some(pieces) => {
+            for (token, piece) in pieces {
+                append(&piece, token.map_or(Kind::Code, Kind::Token));
+            }
+        }
+        None => append(body, Kind::Code),
+    }
+}
+
+/// Appends a line of text, coloring inline code spans and links
+fn append_inline(append: &mut impl FnMut(&str, Kind), line: &str) {
+    let mut rest = line;
+    while !rest.is_empty() {
+        let next = rest.find(['`', '[']).unwrap_or(rest.len());
+        append(&rest[..next], Kind::Text);
+        rest = &rest[next..];
+        let end = if let Some(code) = rest.strip_prefix('`') {
+            code.find('`').map(|i| (i + 2, Kind::Code))
+        } else if rest.starts_with('[') {
+            // A link is `[text](target)`; anything else keeps the text color
+            rest.find("](")
+                .and_then(|close| rest[close..].find(')').map(|i| (close + i + 1, Kind::Link)))
+        } else {
+            None
+        };
+        match end {
+            Some((end, kind)) => {
+                append(&rest[..end], kind);
+                rest = &rest[end..];
+            }
+            None if !rest.is_empty() => {
+                append(&rest[..1], Kind::Text);
+                rest = &rest[1..];
+            }
+            None => {}
+        }
+    }
+}
+
+/// Length of the list marker (`-`, `*`, `+` or `1.`) and the space after it that `line` starts
+/// with, 0 if it isn't a list item
+fn list_marker(line: &str) -> usize {
+    let digits = line.chars().take_while(char::is_ascii_digit).count();
+    let marker = if digits > 0 && matches!(line[digits..].chars().next(), Some('.' | ')')) {
+        digits + 1
+    } else if line.starts_with(['-', '*', '+']) {
+        1
+    } else {
+        return 0;
+    };
+    if line[marker..].starts_with(' ') {
+        marker + 1
+    } else {
+        0
+    }
+}
+
+/// Whether the line is a thematic break such as `---` or `* * *`
+fn is_rule(line: &str) -> bool {
+    let line = line.trim_end();
+    let Some(symbol) = line.chars().next().filter(|c| matches!(c, '-' | '*' | '_')) else {
+        return false;
+    };
+    line.chars().all(|c| c == symbol || c == ' ')
+        && line.chars().filter(|c| *c == symbol).count() >= 3
+}
@@ -0,0 +1,127 @@
+use pulldown_cmark::{Event, Tag};
+use std::fs;
+use std::path::Path;
+use url::Url;
+
+/// Characters of the standard base64 alphabet
+const BASE64_ALPHABET: &[u8; 64] =
+    b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+/// Encodes `bytes` as padded standard base64
+pub fn base64(bytes: &[u8]) -> String {
+    let mut encoded = String::with_capacity(bytes.len().div_ceil(3) * 4);
+    for chunk in bytes.chunks(3) {
+        let triple = chunk.iter().enumerate().fold(0u32, |triple, (i, byte)| {
+            triple | (*byte as u32) << (16 - 8 * i)
+        });
+        for i in 0..4 {
+            if i <= chunk.len() {
+                let index = (triple >> (18 - 6 * i)) & 0x3f;
+                encoded.push(BASE64_ALPHABET[index as usize] as char);
+            } else {
+                encoded.push('=');
+            }
+        }
+    }
+    encoded
+}
+
+/// Media type of an image or font file, from its extension
+pub fn media_type(path: &Path) -> Option<&'static str> {
+    let extension = path.extension()?.to_str()?.to_ascii_lowercase();
+    Some(match extension.as_str() {
+        "png" => "image/png",
+        "jpg" | "jpeg" => "image/jpeg",
+        "gif" => "image/gif",
+        "webp" => "image/webp",
+        "bmp" => "image/bmp",
+        "svg" => "image/svg+xml",
+        "woff" => "font/woff",
+        "woff2" => "font/woff2",
+        "ttf" => "font/ttf",
+        "otf" => "font/otf",
+        _ => return None,
+    })
+}
+
+/// Media type of image data whose file name doesn't tell, such as a downloaded image
+pub fn sniff_media_type(bytes: &[u8]) -> Option<&'static str> {
+    if let Ok(format) = image::guess_format(bytes) {
+        return Some(format.to_mime_type());
+    }
+    let start = String::from_utf8_lossy(&bytes[..bytes.len().min(512)]);
+    start.contains("<svg").then_some("image/svg+xml")
+}
+
+/// The file at `path` as a `data:` URI
+pub fn data_uri(path: &Path) -> Result<String, String> {
+    let bytes = fs::read(path).map_err(|e| format!("Can't embed '{}': {}", path.display(), e))?;
+    let media_type = media_type(path)
+        .or_else(|| sniff_media_type(&bytes))
+        .ok_or_else(|| format!("Can't embed '{}': unknown file type", path.display()))?;
+    Ok(format!("data:{};base64,{}", media_type, base64(&bytes)))
+}
+
+/// Replaces the `file://` sources of images with the images' data, so the HTML doesn't
+/// depend on any local file. Images that can't be read keep their source, with a warning.
+pub fn inline_images<'a>(events: Vec<Event<'a>>, warnings: &mut Vec<String>) -> Vec<Event<'a>> {
+    events
+        .into_iter()
+        .map(|event| match event {
+            Event::Start(Tag::Image {
+                link_type,
+                dest_url,
+                title,
+                id,
+            }) if dest_url.starts_with("file://") => {
+                let path = Url::parse(&dest_url)
+                    .ok()
+                    .and_then(|url| url.to_file_path().ok());
+                let dest_url = match path.map(|path| data_uri(&path)) {
+                    Some(Ok(uri)) => uri.into(),
+                    Some(Err(message)) => {
+                        warnings.push(message);
+                        dest_url
+                    }
+                    None => dest_url,
+                };
+                Event::Start(Tag::Image {
+                    link_type,
+                    dest_url,
+                    title,
+                    id,
+                })
+            }
+            other => other,
+        })
+        .collect()
+}
+
+/// Replaces the `url()` references of `css` to local files, such as fonts and background
+/// images, with the files' data. Relative references are resolved against `base_dir`, the
+/// stylesheet's directory. References that can't be embedded are left as they are.
+pub fn inline_css_urls(css: &str, base_dir: &Path) -> String {
+    let mut out = String::with_capacity(css.len());
+    let mut rest = css;
+    while let Some(start) = rest.find("url(") {
+        let (before, after) = rest.split_at(start + "url(".len());
+        out.push_str(before);
+        let Some(end) = after.find(')') else {
+            rest = after;
+            break;
+        };
+        let reference = after[..end].trim().trim_matches(['"', '\'']);
+        let local = !(reference.is_empty()
+            || reference.starts_with('#')
+            || reference.starts_with("data:")
+            || reference.contains("://"));
+        let path = reference.split(['?', '#']).next().unwrap_or(reference);
+        match local.then(|| data_uri(&base_dir.join(path)).ok()).flatten() {
+            Some(uri) => out.push_str(&format!("\"{uri}\"")),
+            None => out.push_str(&after[..end]),
+        }
+        rest = &after[end..];
+    }
+    out.push_str(rest);
+    out
+}
@@ -0,0 +1,107 @@
+use lopdf::encryption::crypt_filters::{Aes256CryptFilter, CryptFilter};
+use lopdf::{Document, EncryptionState, EncryptionVersion, Permissions};
+use std::collections::BTreeMap;
+use std::sync::Arc;
+
+/// Name of the crypt filter strings and streams are encrypted with
+const CRYPT_FILTER: &[u8] = b"StdCF";
+
+/// Password protection of PDFs and what readers may do with them. The passwords aren't
+/// saved with the settings.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Encryption {
+    pub enabled: bool,
+    pub user_password: String, // Needed to open the PDF, empty to open it freely
+    pub owner_password: String, // Lifts the restrictions, a random one when empty
+    pub allow_printing: bool,
+    pub allow_copying: bool,
+    pub allow_editing: bool, // Changing, annotating, filling in and rearranging pages
+}
+
+impl Default for Encryption {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            user_password: String::new(),
+            owner_password: String::new(),
+            allow_printing: true,
+            allow_copying: true,
+            allow_editing: true,
+        }
+    }
+}
+
+impl Encryption {
+    /// Whether the PDF is restricted beyond needing a password to open it
+    fn restricted(&self) -> bool {
+        !(self.allow_printing && self.allow_copying && self.allow_editing)
+    }
+
+    /// The permissions of readers without the owner password. Text can always be extracted
+    /// for screen readers.
+    fn permissions(&self) -> Permissions {
+        let mut permissions = Permissions::COPYABLE_FOR_ACCESSIBILITY;
+        if self.allow_printing {
+            permissions |= Permissions::PRINTABLE | Permissions::PRINTABLE_IN_HIGH_QUALITY;
+        }
+        if self.allow_copying {
+            permissions |= Permissions::COPYABLE;
+        }
+        if self.allow_editing {
+            permissions |= Permissions::MODIFIABLE
+                | Permissions::ANNOTABLE
+                | Permissions::FILLABLE
+                | Permissions::ASSEMBLABLE;
+        }
+        permissions
+    }
+}
+
+/// `length` random bytes from the operating system
+fn random_bytes(length: usize) -> Result<Vec<u8>, String> {
+    let mut bytes = vec![0; length];
+    getrandom::getrandom(&mut bytes)
+        .map_err(|e| format!("Failed to generate an encryption key: {}", e))?;
+    Ok(bytes)
+}
+
+/// Encrypts `document` with 256-bit AES, restricted as `encryption` sets. Has to be the last
+/// change to the document. The key, salts and a missing owner password are random, so the
+/// encrypted file differs every time and can't be reproducible.
+pub fn apply(document: &mut Document, encryption: &Encryption) -> Result<(), String> {
+    if encryption.user_password.is_empty() && !encryption.restricted() {
+        return Err(
+            "Encryption needs a password to open the PDF or something to restrict".to_string(),
+        );
+    }
+    let owner_password = if encryption.owner_password.is_empty() {
+        // Nobody can lift the restrictions then, not even with an empty password
+        random_bytes(16)?
+            .iter()
+            .map(|byte| format!("{:02x}", byte))
+            .collect()
+    } else {
+        encryption.owner_password.clone()
+    };
+    let file_encryption_key = random_bytes(32)?;
+    let crypt_filter: Arc<dyn CryptFilter> = Arc::new(Aes256CryptFilter);
+    let version = EncryptionVersion::V5 {
+        encrypt_metadata: true,
+        crypt_filters: BTreeMap::from([(CRYPT_FILTER.to_vec(), crypt_filter)]),
+        file_encryption_key: &file_encryption_key,
+        stream_filter: CRYPT_FILTER.to_vec(),
+        string_filter: CRYPT_FILTER.to_vec(),
+        owner_password: &owner_password,
+        user_password: &encryption.user_password,
+        permissions: encryption.permissions(),
+    };
+    // 256-bit AES came with PDF 1.7's extensions
+    if document.version.as_str() < "1.7" {
+        document.version = "1.7".to_string();
+    }
+    let state = EncryptionState::try_from(version)
+        .map_err(|e| format!("Failed to set up encryption: {}", e))?;
+    document
+        .encrypt(&state)
+        .map_err(|e| format!("Failed to encrypt PDF: {}", e))
+}
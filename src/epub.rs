@@ -0,0 +1,460 @@
+use crate::bundle;
+use crate::checksum;
+use crate::convert;
+use crate::embed;
+use crate::front_matter::FrontMatter;
+use crate::headings::{self, Heading};
+use crate::links::LinkStyle;
+use crate::remote;
+use crate::settings::Settings;
+use crate::toc;
+use crate::xhtml;
+use flate2::Compression;
+use flate2::write::DeflateEncoder;
+use pulldown_cmark::{Event, HeadingLevel, Tag};
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use url::Url;
+
+/// Folder in the book the images are copied to
+const IMAGES_DIR: &str = "images";
+
+/// Adapts the theme, made for fixed pages, to e-readers that lay out the text themselves
+/// and set their own margins
+const REFLOW_CSS: &str = r#".markdown-body {
+  max-width: none !important;
+  margin: 0 !important;
+  padding: 0 !important;
+  box-sizing: border-box;
+}
+.markdown-body img, .markdown-body svg {
+  max-width: 100% !important;
+  height: auto;
+}
+.markdown-body pre {
+  white-space: pre-wrap;
+  word-wrap: break-word;
+}
+.markdown-body table {
+  display: block;
+  overflow-x: auto;
+}
+"#;
+
+const CONTAINER_XML: &str = r#"<?xml version="1.0" encoding="utf-8"?>
+<container version="1.0" xmlns="urn:oasis:names:tc:opendocument:xmlns:container">
+  <rootfiles>
+    <rootfile full-path="OEBPS/content.opf" media-type="application/oebps-package+xml"/>
+  </rootfiles>
+</container>
+"#;
+
+/// One file of the book being written
+struct Entry {
+    name: String, // Path in the archive
+    data: Vec<u8>,
+    compress: bool, // The `mimetype` file has to be stored as is
+}
+
+/// Writes `entries` as a ZIP archive, the container format of EPUB
+fn zip(entries: &[Entry]) -> Result<Vec<u8>, String> {
+    let mut out = Vec::new();
+    let mut central = Vec::new();
+    for entry in entries {
+        let crc = crc32fast::hash(&entry.data);
+        let (method, data) = if entry.compress {
+            let mut encoder = DeflateEncoder::new(Vec::new(), Compression::default());
+            encoder
+                .write_all(&entry.data)
+                .and_then(|()| encoder.finish())
+                .map(|compressed| (8u16, compressed))
+                .map_err(|e| format!("Failed to compress '{}': {}", entry.name, e))?
+        } else {
+            (0, entry.data.clone())
+        };
+        let offset = out.len() as u32;
+        // Version 2.0 needed, UTF-8 names, dated 1980-01-01 so the same document gives the
+        // same book
+        let fields = [20u16, 0x0800, method, 0, 0x21];
+        let sizes = [crc, data.len() as u32, entry.data.len() as u32];
+
+        out.extend(0x04034b50u32.to_le_bytes());
+        for value in fields {
+            out.extend(value.to_le_bytes());
+        }
+        for size in sizes {
+            out.extend(size.to_le_bytes());
+        }
+        out.extend((entry.name.len() as u16).to_le_bytes());
+        out.extend(0u16.to_le_bytes());
+        out.extend(entry.name.as_bytes());
+        out.extend(&data);
+
+        central.extend(0x02014b50u32.to_le_bytes());
+        central.extend(20u16.to_le_bytes());
+        for value in fields {
+            central.extend(value.to_le_bytes());
+        }
+        for size in sizes {
+            central.extend(size.to_le_bytes());
+        }
+        central.extend((entry.name.len() as u16).to_le_bytes());
+        central.extend([0; 12]); // No extra field or comment, disk 0, no attributes
+        central.extend(offset.to_le_bytes());
+        central.extend(entry.name.as_bytes());
+    }
+    let central_offset = out.len() as u32;
+    let count = entries.len() as u16;
+    out.extend(&central);
+    out.extend(0x06054b50u32.to_le_bytes());
+    out.extend([0; 4]);
+    out.extend(count.to_le_bytes());
+    out.extend(count.to_le_bytes());
+    out.extend((central.len() as u32).to_le_bytes());
+    out.extend(central_offset.to_le_bytes());
+    out.extend(0u16.to_le_bytes());
+    Ok(out)
+}
+
+/// Splits the document into chapters, each starting at a `#` heading. Text before the first
+/// one becomes a chapter of its own.
+fn split_chapters(events: Vec<Event<'_>>) -> Vec<Vec<Event<'_>>> {
+    let mut chapters = vec![Vec::new()];
+    for event in events {
+        let starts_chapter = matches!(
+            event,
+            Event::Start(Tag::Heading {
+                level: HeadingLevel::H1,
+                ..
+            })
+        );
+        if starts_chapter && chapters.last().is_some_and(|chapter| !chapter.is_empty()) {
+            chapters.push(Vec::new());
+        }
+        chapters
+            .last_mut()
+            .expect("there's always a chapter")
+            .push(event);
+    }
+    chapters
+}
+
+/// File name of chapter `index` in the book
+fn chapter_file(index: usize) -> String {
+    format!("chapter-{}.xhtml", index + 1)
+}
+
+/// Points links to headings in other chapters at the chapter files
+fn link_chapters(html: &str, chapter: usize, chapter_of: &HashMap<String, usize>) -> String {
+    let mut out = String::with_capacity(html.len());
+    let mut rest = html;
+    while let Some(start) = rest.find("href=\"#") {
+        let (before, after) = rest.split_at(start + "href=\"".len());
+        out.push_str(before);
+        let end = after.find('"').unwrap_or(after.len());
+        let id = &after[1..end];
+        match chapter_of.get(id) {
+            Some(&target) if target != chapter => out.push_str(&chapter_file(target)),
+            _ => {}
+        }
+        rest = after;
+    }
+    out.push_str(rest);
+    out
+}
+
+/// The XHTML page of a chapter
+fn chapter_page(title: &str, lang: &str, direction: &str, body: &str) -> String {
+    format!(
+        r#"<?xml version="1.0" encoding="utf-8"?>
+<!DOCTYPE html>
+<html xmlns="http://www.w3.org/1999/xhtml" xmlns:epub="http://www.idpf.org/2007/ops" lang="{lang}" xml:lang="{lang}" dir="{direction}">
+<head>
+<meta charset="utf-8"/>
+<title>{title}</title>
+<link rel="stylesheet" type="text/css" href="style.css"/>
+</head>
+<body class="markdown-body">
+{body}
+</body>
+</html>
+"#
+    )
+}
+
+/// The navigation page with the table of contents: the chapters with their `##` sections
+fn nav_page(title: &str, lang: &str, chapters: &[(String, Vec<&Heading>)]) -> String {
+    let mut list = String::new();
+    for (i, (chapter_title, sections)) in chapters.iter().enumerate() {
+        let file = chapter_file(i);
+        list.push_str(&format!(
+            "<li><a href=\"{file}\">{}</a>",
+            convert::escape_attr(chapter_title)
+        ));
+        if !sections.is_empty() {
+            list.push_str("<ol>");
+            for section in sections {
+                list.push_str(&format!(
+                    "<li><a href=\"{file}#{}\">{}</a></li>",
+                    convert::escape_attr(&section.id),
+                    convert::escape_attr(&section.text)
+                ));
+            }
+            list.push_str("</ol>");
+        }
+        list.push_str("</li>\n");
+    }
+    format!(
+        r#"<?xml version="1.0" encoding="utf-8"?>
+<!DOCTYPE html>
+<html xmlns="http://www.w3.org/1999/xhtml" xmlns:epub="http://www.idpf.org/2007/ops" lang="{lang}" xml:lang="{lang}">
+<head>
+<meta charset="utf-8"/>
+<title>{title}</title>
+</head>
+<body>
+<nav epub:type="toc" id="toc">
+<h1>{title}</h1>
+<ol>
+{list}</ol>
+</nav>
+</body>
+</html>
+"#
+    )
+}
+
+/// Converts the Markdown file at `md_path` to an EPUB e-book at `epub_path`. Each `#`
+/// heading starts a chapter, the local images are put into the book and the theme's styles
+/// are adapted to reflowing text. Returns the warnings of the conversion.
+pub fn export(
+    settings: &Settings,
+    md_path: &Path,
+    epub_path: &Path,
+) -> Result<Vec<String>, String> {
+    let md_text =
+        fs::read_to_string(md_path).map_err(|e| format!("Failed to read Markdown file: {}", e))?;
+    let base_dir = md_path.parent().unwrap_or(Path::new("."));
+    let mut warnings = Vec::new();
+    let (front_matter, md_body) = FrontMatter::extract(&md_text);
+    let mut settings = Settings {
+        // Page layout is up to the e-reader, and images are files in the book
+        columns: 1,
+        landscape_wide_blocks: false,
+        chapters_on_odd_pages: false,
+        print_media: false,
+        self_contained: false,
+        rewrite_md_links: false,
+        ..convert::document_settings(settings, &front_matter, base_dir, &mut warnings)
+    };
+    // Links can be followed in e-books, and the URL list isn't split into chapters
+    if settings.link_style == LinkStyle::Numbered {
+        settings.link_style = LinkStyle::Parenthesized;
+    }
+    let settings = &settings;
+    settings.check_styles()?;
+    let direction = settings.direction.resolve(&front_matter, md_body);
+    let lang = match convert::document_lang(settings, &front_matter) {
+        "" => "en",
+        lang => lang,
+    };
+
+    let events = convert::render_events(settings, md_body, base_dir, lang, &mut warnings);
+    let (events, headings) = headings::assign_ids(events, "");
+    let events = toc::insert(events, &headings, &HashMap::new());
+    for event in &events {
+        if let Event::Start(Tag::Image { dest_url, .. }) = event
+            && remote::parse_url(dest_url).is_some()
+        {
+            warnings.push(format!(
+                "Remote image not put into the book, turn on downloading remote images: '{}'",
+                dest_url
+            ));
+        }
+    }
+    let title = front_matter
+        .get("title")
+        .map(str::to_string)
+        .or_else(|| {
+            headings
+                .iter()
+                .find(|h| h.level == 1)
+                .map(|h| h.text.clone())
+        })
+        .or_else(|| {
+            md_path
+                .file_stem()
+                .map(|stem| stem.to_string_lossy().to_string())
+        })
+        .unwrap_or_default();
+
+    // Chapters with the headings they hold
+    let chapters = split_chapters(events);
+    let mut chapter_of = HashMap::new();
+    let mut contents = Vec::new();
+    let mut remaining = headings.iter().peekable();
+    for (i, chapter) in chapters.iter().enumerate() {
+        let mut chapter_title = None;
+        let mut sections = Vec::new();
+        for event in chapter {
+            if let Event::Start(Tag::Heading {
+                level,
+                id: Some(id),
+                ..
+            }) = event
+                && let Some(heading) = remaining.next_if(|heading| heading.id == id.as_ref())
+            {
+                chapter_of.insert(heading.id.clone(), i);
+                match level {
+                    HeadingLevel::H1 if chapter_title.is_none() => {
+                        chapter_title = Some(heading.text.clone())
+                    }
+                    HeadingLevel::H2 => sections.push(heading),
+                    _ => {}
+                }
+            }
+        }
+        contents.push((chapter_title.unwrap_or_else(|| title.clone()), sections));
+    }
+
+    let mut entries = vec![
+        Entry {
+            name: "mimetype".to_string(),
+            data: b"application/epub+zip".to_vec(),
+            compress: false,
+        },
+        Entry {
+            name: "META-INF/container.xml".to_string(),
+            data: CONTAINER_XML.as_bytes().to_vec(),
+            compress: true,
+        },
+    ];
+    let mut manifest = String::new();
+    let mut spine = String::new();
+    let mut images: HashMap<PathBuf, String> = HashMap::new(); // Name of each image in the book
+    let mut taken = HashSet::new();
+    for (i, chapter) in chapters.into_iter().enumerate() {
+        let mut body = String::new();
+        let chapter = xhtml::events(chapter, &mut warnings);
+        pulldown_cmark::html::push_html(&mut body, chapter.into_iter());
+        let body = bundle::replace_file_urls(&body, |url| {
+            let Some(source) = Url::parse(url).ok().and_then(|url| url.to_file_path().ok()) else {
+                return Ok(None);
+            };
+            if let Some(name) = images.get(&source) {
+                return Ok(Some(format!("{IMAGES_DIR}/{}", bundle::encode_name(name))));
+            }
+            let data = match fs::read(&source) {
+                Ok(data) => data,
+                Err(e) => {
+                    warnings.push(format!(
+                        "Failed to put '{}' into the book: {}",
+                        source.display(),
+                        e
+                    ));
+                    return Ok(None);
+                }
+            };
+            let name = bundle::unique_name(&source, &taken);
+            let media_type = embed::media_type(&source)
+                .or_else(|| embed::sniff_media_type(&data))
+                .unwrap_or("application/octet-stream");
+            manifest.push_str(&format!(
+                "    <item id=\"image-{}\" href=\"{IMAGES_DIR}/{}\" media-type=\"{media_type}\"/>\n",
+                taken.len() + 1,
+                bundle::encode_name(&name)
+            ));
+            entries.push(Entry {
+                name: format!("OEBPS/{IMAGES_DIR}/{name}"),
+                data,
+                compress: true,
+            });
+            taken.insert(name.clone());
+            images.insert(source, name.clone());
+            Ok(Some(format!("{IMAGES_DIR}/{}", bundle::encode_name(&name))))
+        })?;
+        let body = link_chapters(&body, i, &chapter_of);
+        let properties = if body.contains("<svg") {
+            " properties=\"svg\""
+        } else {
+            ""
+        };
+        let page = chapter_page(
+            &convert::escape_attr(&contents[i].0),
+            &convert::escape_attr(lang),
+            direction.html_attr(),
+            &body,
+        );
+        manifest.push_str(&format!(
+            "    <item id=\"chapter-{n}\" href=\"{}\" media-type=\"application/xhtml+xml\"{properties}/>\n",
+            chapter_file(i),
+            n = i + 1
+        ));
+        spine.push_str(&format!("    <itemref idref=\"chapter-{}\"/>\n", i + 1));
+        entries.push(Entry {
+            name: format!("OEBPS/{}", chapter_file(i)),
+            data: page.into_bytes(),
+            compress: true,
+        });
+    }
+
+    let escaped_title = convert::escape_attr(&title);
+    let escaped_lang = convert::escape_attr(lang);
+    let css = format!("{}\n{}", convert::document_css(settings, ""), REFLOW_CSS);
+    let id = checksum::key(&[title.as_bytes(), md_path.as_os_str().as_encoded_bytes()]);
+    let creator = front_matter
+        .get("author")
+        .map(|author| {
+            format!(
+                "    <dc:creator>{}</dc:creator>\n",
+                convert::escape_attr(author)
+            )
+        })
+        .unwrap_or_default();
+    let package = format!(
+        r#"<?xml version="1.0" encoding="utf-8"?>
+<package xmlns="http://www.idpf.org/2007/opf" version="3.0" unique-identifier="book-id" xml:lang="{escaped_lang}">
+  <metadata xmlns:dc="http://purl.org/dc/elements/1.1/">
+    <dc:identifier id="book-id">urn:md-to-pdf:{id}</dc:identifier>
+    <dc:title>{escaped_title}</dc:title>
+    <dc:language>{escaped_lang}</dc:language>
+{creator}    <meta property="dcterms:modified">{}</meta>
+  </metadata>
+  <manifest>
+    <item id="nav" href="nav.xhtml" media-type="application/xhtml+xml" properties="nav"/>
+    <item id="style" href="style.css" media-type="text/css"/>
+{manifest}  </manifest>
+  <spine>
+{spine}  </spine>
+</package>
+"#,
+        chrono::Utc::now().format("%Y-%m-%dT%H:%M:%SZ")
+    );
+    entries.extend([
+        Entry {
+            name: "OEBPS/content.opf".to_string(),
+            data: package.into_bytes(),
+            compress: true,
+        },
+        Entry {
+            name: "OEBPS/nav.xhtml".to_string(),
+            data: nav_page(&escaped_title, &escaped_lang, &contents).into_bytes(),
+            compress: true,
+        },
+        Entry {
+            name: "OEBPS/style.css".to_string(),
+            data: css.into_bytes(),
+            compress: true,
+        },
+    ]);
+
+    if let Some(parent) = epub_path.parent() {
+        fs::create_dir_all(parent)
+            .map_err(|e| format!("Failed to create output directory: {}", e))?;
+    }
+    fs::write(epub_path, zip(&entries)?)
+        .map_err(|e| format!("Failed to write EPUB file: {}", e))?;
+    Ok(warnings)
+}
@@ -0,0 +1,156 @@
+use crate::convert;
+use crate::front_matter::FrontMatter;
+use crate::i18n::tr;
+use crate::images;
+use crate::settings::Settings;
+use lopdf::Document;
+use pulldown_cmark::{Event, HeadingLevel, Tag, TagEnd};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Rough capacity of an A4 portrait page at 100% zoom with wkhtmltopdf's default margins
+const CHARS_PER_LINE: f32 = 95.0;
+const LINES_PER_PAGE: f32 = 52.0;
+/// Lines an image typically takes up
+const IMAGE_LINES: f32 = 14.0;
+
+/// Typical size of the embedded font subsets and document structure
+const BASE_BYTES: u64 = 40_000;
+/// Typical size of one page's content stream
+const PAGE_BYTES: u64 = 6_000;
+/// How much smaller images get when they're recompressed
+const RECOMPRESSED_IMAGE_RATIO: u64 = 4;
+
+/// Expected page count and file size of a conversion
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Estimate {
+    pub pages: usize,
+    pub bytes: u64,
+}
+
+impl Estimate {
+    /// Formats the estimate as e.g. "≈ 14 pages, ≈ 1.2 MB"
+    pub fn summary(&self) -> String {
+        let pages = if self.pages == 1 {
+            tr!("page")
+        } else {
+            tr!("pages")
+        };
+        format!("≈ {} {}, ≈ {}", self.pages, pages, format_size(self.bytes))
+    }
+}
+
+/// Estimates the output of converting `markdown` without rendering it.
+/// `base_dir` is where the document's images are looked up to account for their size.
+/// Good for relative comparisons (e.g. while adjusting the zoom), not for exact figures.
+pub fn estimate(settings: &Settings, markdown: &str, base_dir: &Path) -> Estimate {
+    let (_, md_body) = FrontMatter::extract(markdown);
+    let zoom = (settings.zoom * settings.style.text_scale()).max(0.1);
+    let chars_per_line = CHARS_PER_LINE / zoom;
+    let recompress = settings.draft_quality || settings.preset().recompress_images;
+
+    let mut lines = 0.0;
+    let mut image_bytes = 0;
+    let mut chars = 0usize; // Text of the current block not yet laid out
+    let mut in_code = false;
+    for event in pulldown_cmark::Parser::new_ext(md_body, convert::markdown_options()) {
+        match event {
+            Event::Start(Tag::CodeBlock(_)) => in_code = true,
+            Event::End(TagEnd::CodeBlock) => {
+                in_code = false;
+                lines += 1.0;
+            }
+            Event::Text(text) if in_code => lines += text.lines().count() as f32,
+            Event::Text(text) | Event::Code(text) => chars += text.chars().count(),
+            Event::SoftBreak => chars += 1,
+            Event::HardBreak => lines += wrap(&mut chars, chars_per_line),
+            Event::Start(Tag::Image { dest_url, .. }) => {
+                lines += IMAGE_LINES;
+                if images::is_local(&dest_url)
+                    && let Ok(metadata) = fs::metadata(base_dir.join(dest_url.as_ref()))
+                {
+                    image_bytes += if recompress {
+                        metadata.len() / RECOMPRESSED_IMAGE_RATIO
+                    } else {
+                        metadata.len()
+                    };
+                }
+            }
+            Event::End(TagEnd::Heading(level)) => {
+                let scale = match level {
+                    HeadingLevel::H1 => 2.0,
+                    HeadingLevel::H2 => 1.5,
+                    _ => 1.25,
+                };
+                lines += wrap(&mut chars, chars_per_line / scale) * scale + 1.0;
+            }
+            Event::End(TagEnd::Paragraph) => lines += wrap(&mut chars, chars_per_line) + 1.0,
+            Event::End(TagEnd::Item) => lines += wrap(&mut chars, chars_per_line),
+            Event::End(TagEnd::TableHead | TagEnd::TableRow) => {
+                chars = 0;
+                lines += 1.5;
+            }
+            Event::Rule => lines += 1.0,
+            _ => {}
+        }
+    }
+    lines += wrap(&mut chars, chars_per_line);
+
+    let lines_per_page = LINES_PER_PAGE / zoom;
+    let pages = ((lines / lines_per_page).ceil() as usize).max(1);
+    Estimate {
+        pages,
+        bytes: BASE_BYTES + PAGE_BYTES * pages as u64 + image_bytes,
+    }
+}
+
+/// Lines taken up by `chars` characters of running text, which are then consumed
+fn wrap(chars: &mut usize, chars_per_line: f32) -> f32 {
+    let lines = (*chars as f32 / chars_per_line).ceil();
+    *chars = 0;
+    lines
+}
+
+/// Formats a byte count as e.g. "850 KB" or "1.2 MB"
+pub fn format_size(bytes: u64) -> String {
+    const KB: f64 = 1000.0;
+    const MB: f64 = KB * 1000.0;
+    let bytes = bytes as f64;
+    if bytes >= MB {
+        format!("{:.1} MB", bytes / MB)
+    } else {
+        format!("{:.0} KB", (bytes / KB).max(1.0))
+    }
+}
+
+/// Corrects the estimates for a document using the outcome of its last real conversion
+#[derive(Debug, Clone)]
+pub struct Calibration {
+    md_path: PathBuf,
+    page_factor: f32,
+    size_factor: f32,
+}
+
+impl Calibration {
+    /// Compares what `estimated` predicted for `md_path` with the PDF it produced
+    pub fn measure(md_path: &Path, estimated: Estimate, pdf_path: &Path) -> Option<Calibration> {
+        let bytes = fs::metadata(pdf_path).ok()?.len();
+        let pages = Document::load(pdf_path).ok()?.get_pages().len();
+        Some(Calibration {
+            md_path: md_path.to_path_buf(),
+            page_factor: pages as f32 / estimated.pages.max(1) as f32,
+            size_factor: bytes as f32 / estimated.bytes.max(1) as f32,
+        })
+    }
+
+    /// Scales `estimate` for `md_path`, if this calibration was measured on that document
+    pub fn apply(&self, md_path: &Path, estimate: Estimate) -> Estimate {
+        if self.md_path != md_path {
+            return estimate;
+        }
+        Estimate {
+            pages: ((estimate.pages as f32 * self.page_factor).round() as usize).max(1),
+            bytes: (estimate.bytes as f32 * self.size_factor) as u64,
+        }
+    }
+}
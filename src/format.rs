@@ -0,0 +1,236 @@
+use crate::batch;
+use crate::book;
+use crate::checksum;
+use crate::convert::{self, Rendered};
+use crate::docx;
+use crate::epub;
+use crate::man;
+use crate::pages::{self, PageImage};
+use crate::settings::Settings;
+use crate::slides;
+use crate::text;
+use std::path::{Path, PathBuf};
+
+/// Kinds of files a Markdown document can be converted to
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum OutputFormat {
+    Pdf,    // Rendered by wkhtmltopdf
+    Html,   // The themed HTML the PDF is rendered from
+    Epub,   // E-book with a chapter per `#` heading
+    Docx,   // Word document, converted by pandoc
+    Text,   // Plain text wrapped at the text width
+    Man,    // Manual page in roff
+    Png,    // An image per page
+    Jpeg,   // An image per page
+    Slides, // Landscape PDF with a page per slide
+    Reveal, // reveal.js presentation
+}
+
+impl OutputFormat {
+    /// Returns the display name for the format
+    pub fn name(&self) -> &'static str {
+        match self {
+            OutputFormat::Pdf => "PDF",
+            OutputFormat::Html => "HTML",
+            OutputFormat::Epub => "EPUB",
+            OutputFormat::Docx => "Word",
+            OutputFormat::Text => "Plain text",
+            OutputFormat::Man => "Man page",
+            OutputFormat::Png => "PNG pages",
+            OutputFormat::Jpeg => "JPEG pages",
+            OutputFormat::Slides => "Slides (PDF)",
+            OutputFormat::Reveal => "Slides (reveal.js)",
+        }
+    }
+
+    /// Returns all available formats
+    pub fn all() -> &'static [OutputFormat] {
+        &[
+            OutputFormat::Pdf,
+            OutputFormat::Html,
+            OutputFormat::Epub,
+            OutputFormat::Docx,
+            OutputFormat::Text,
+            OutputFormat::Man,
+            OutputFormat::Png,
+            OutputFormat::Jpeg,
+            OutputFormat::Slides,
+            OutputFormat::Reveal,
+        ]
+    }
+
+    /// Identifier used on the command line and in saved settings
+    pub fn keyword(&self) -> &'static str {
+        match self {
+            OutputFormat::Pdf => "pdf",
+            OutputFormat::Html => "html",
+            OutputFormat::Epub => "epub",
+            OutputFormat::Docx => "docx",
+            OutputFormat::Text => "txt",
+            OutputFormat::Man => "man",
+            OutputFormat::Png => "png",
+            OutputFormat::Jpeg => "jpeg",
+            OutputFormat::Slides => "slides",
+            OutputFormat::Reveal => "reveal",
+        }
+    }
+
+    /// Extension of the files written in the format. Man pages get that of section 1, the
+    /// user commands. Slides are told apart from the document's PDF and HTML by a `.slides`
+    /// before theirs.
+    pub fn extension(&self) -> &'static str {
+        match self {
+            OutputFormat::Pdf => "pdf",
+            OutputFormat::Html => "html",
+            OutputFormat::Epub => "epub",
+            OutputFormat::Docx => "docx",
+            OutputFormat::Text => "txt",
+            OutputFormat::Man => "1",
+            OutputFormat::Png => "png",
+            OutputFormat::Jpeg => "jpg",
+            OutputFormat::Slides => "slides.pdf",
+            OutputFormat::Reveal => "slides.html",
+        }
+    }
+
+    /// The format named by `keyword`, ignoring case
+    pub fn from_keyword(keyword: &str) -> Option<OutputFormat> {
+        OutputFormat::all()
+            .iter()
+            .copied()
+            .find(|format| format.keyword().eq_ignore_ascii_case(keyword.trim()))
+    }
+}
+
+/// Converts the Markdown file at `input` to `format` at `output`, see [`Conversions::convert`]
+pub fn convert_file(
+    settings: &Settings,
+    format: OutputFormat,
+    input: &Path,
+    output: &Path,
+) -> Result<Vec<String>, String> {
+    Conversions::new(settings, input)
+        .convert(format, output)
+        .map(|(_, warnings)| warnings)
+}
+
+/// What a document is converted from
+#[derive(Clone, Copy)]
+pub enum Source<'a> {
+    File(&'a Path),          // A Markdown file
+    Text(&'a str, &'a Path), // Markdown text, with relative paths resolved against the folder
+    Book(&'a Path),          // The manifest of a book, see `book::build`
+    Merged(&'a [PathBuf]),   // Markdown files combined into one PDF, see `batch::convert_merged`
+}
+
+/// Books and merged documents are put together from PDFs
+const PDF_ONLY: &str = "Books and merged documents are only written as PDFs";
+
+/// The conversions of a document to several formats. The PDF and the page images are made
+/// from one rendering of the document, done when the first of them is written. The other
+/// formats lay the document out their own way and render it themselves. Every output is
+/// written through [`Conversions::convert`], which adds the checksum files.
+pub struct Conversions<'a> {
+    settings: &'a Settings,
+    source: Source<'a>,
+    rendered: Option<Result<Rendered, String>>,
+}
+
+impl<'a> Conversions<'a> {
+    /// The conversions of the Markdown file at `input`
+    pub fn new(settings: &'a Settings, input: &'a Path) -> Self {
+        Self::of(settings, Source::File(input))
+    }
+
+    pub fn of(settings: &'a Settings, source: Source<'a>) -> Self {
+        Conversions {
+            settings,
+            source,
+            rendered: None,
+        }
+    }
+
+    /// The document rendered for its PDF, rendering it the first time
+    fn rendered(&mut self) -> Result<&Rendered, String> {
+        let settings = self.settings;
+        self.rendered
+            .get_or_insert_with(|| match self.source {
+                Source::File(input) => convert::render_file(settings, input),
+                Source::Text(md_text, base_dir) => {
+                    convert::render_text(settings, md_text, base_dir)
+                }
+                Source::Book(_) | Source::Merged(_) => Err(PDF_ONLY.to_string()),
+            })
+            .as_ref()
+            .map_err(String::clone)
+    }
+
+    /// Converts the document to `format` at `output`. With `checksums` set, a `.sha256` file
+    /// goes next to each file written. Returns the files written, the output or each page
+    /// image, and the warnings of the conversion.
+    pub fn convert(
+        &mut self,
+        format: OutputFormat,
+        output: &Path,
+    ) -> Result<(Vec<PathBuf>, Vec<String>), String> {
+        let (files, warnings) = self.write(format, output)?;
+        checksum::write_sidecars(self.settings, &files)?;
+        Ok((files, warnings))
+    }
+
+    /// Writes the document as `format` to `output`, returning the files written and the
+    /// warnings
+    fn write(
+        &mut self,
+        format: OutputFormat,
+        output: &Path,
+    ) -> Result<(Vec<PathBuf>, Vec<String>), String> {
+        let settings = self.settings;
+        // All but the page images are written to `output` alone
+        let single = |result: Result<Vec<String>, String>| {
+            result.map(|warnings| (vec![output.to_path_buf()], warnings))
+        };
+        match (format, self.source) {
+            (OutputFormat::Pdf, Source::Book(manifest)) => {
+                single(book::build(settings, manifest, output))
+            }
+            (OutputFormat::Pdf, Source::Merged(inputs)) => {
+                single(batch::convert_merged(settings, inputs, output))
+            }
+            (_, Source::Book(_) | Source::Merged(_)) => Err(PDF_ONLY.to_string()),
+            (OutputFormat::Pdf, _) => single(
+                self.rendered()
+                    .and_then(|rendered| convert::write_pdf(rendered, &rendered.settings, output)),
+            ),
+            (OutputFormat::Png, _) => self
+                .rendered()
+                .and_then(|rendered| pages::export(rendered, output, PageImage::Png)),
+            (OutputFormat::Jpeg, _) => self
+                .rendered()
+                .and_then(|rendered| pages::export(rendered, output, PageImage::Jpeg)),
+            (format, Source::Text(..)) => Err(format!(
+                "{} is only made from Markdown files",
+                format.name()
+            )),
+            (OutputFormat::Html, Source::File(input)) => {
+                single(convert::export_html(settings, input, output))
+            }
+            (OutputFormat::Epub, Source::File(input)) => {
+                single(epub::export(settings, input, output))
+            }
+            (OutputFormat::Docx, Source::File(input)) => {
+                single(docx::export(settings, input, output))
+            }
+            (OutputFormat::Text, Source::File(input)) => {
+                single(text::export(settings, input, output))
+            }
+            (OutputFormat::Man, Source::File(input)) => single(man::export(input, output)),
+            (OutputFormat::Slides, Source::File(input)) => {
+                single(slides::export_pdf(settings, input, output))
+            }
+            (OutputFormat::Reveal, Source::File(input)) => {
+                single(slides::export_reveal(settings, input, output))
+            }
+        }
+    }
+}
@@ -0,0 +1,52 @@
+use std::collections::HashMap;
+
+/// Key/value pairs read from a YAML-style `---` block at the very top of a Markdown file.
+/// Only flat `key: value` lines are understood, which is all the converter looks at.
+#[derive(Debug, Default, Clone)]
+pub struct FrontMatter {
+    fields: HashMap<String, String>,
+}
+
+impl FrontMatter {
+    /// Splits the front matter off `text`, returning it together with the remaining Markdown.
+    /// Text without a (terminated) front matter block is returned untouched.
+    pub fn extract(text: &str) -> (FrontMatter, &str) {
+        let stripped = text.strip_prefix('\u{feff}').unwrap_or(text);
+        let Some(block) = stripped
+            .strip_prefix("---\n")
+            .or_else(|| stripped.strip_prefix("---\r\n"))
+        else {
+            return (FrontMatter::default(), text);
+        };
+
+        let mut front_matter = FrontMatter::default();
+        let mut offset = 0;
+        for line in block.split_inclusive('\n') {
+            offset += line.len();
+            let line = line.trim_end();
+            if line == "---" || line == "..." {
+                return (front_matter, &block[offset..]);
+            }
+            if line.starts_with('#') || line.starts_with(' ') {
+                continue; // Comments and nested values aren't supported
+            }
+            if let Some((key, value)) = line.split_once(':') {
+                let value = value.trim().trim_matches(|c| c == '"' || c == '\'');
+                front_matter
+                    .fields
+                    .insert(key.trim().to_lowercase(), value.to_string());
+            }
+        }
+
+        // No closing `---`, so this was just a horizontal rule at the top of the document
+        (FrontMatter::default(), text)
+    }
+
+    /// Returns the value for `key` (case-insensitive), if present and non-empty.
+    pub fn get(&self, key: &str) -> Option<&str> {
+        self.fields
+            .get(&key.to_lowercase())
+            .map(String::as_str)
+            .filter(|value| !value.is_empty())
+    }
+}
@@ -0,0 +1,28 @@
+/// Scores how well `query` matches `text`, for filtering lists as the user types. All of the
+/// query's characters must appear in `text` in order, ignoring case and the query's spaces.
+/// Characters at the start of a word or right after the previous match score higher, gaps
+/// lower. Returns None when `text` doesn't match.
+pub fn score(query: &str, text: &str) -> Option<i32> {
+    let text: Vec<char> = text.chars().flat_map(char::to_lowercase).collect();
+    let mut score = 0;
+    let mut position = 0;
+    let mut previous = None;
+    for wanted in query
+        .chars()
+        .filter(|c| !c.is_whitespace())
+        .flat_map(char::to_lowercase)
+    {
+        let found = (position..text.len()).find(|&i| text[i] == wanted)?;
+        score += 1;
+        if found > 0 && previous == Some(found - 1) {
+            score += 5;
+        }
+        if found == 0 || !text[found - 1].is_alphanumeric() {
+            score += 3;
+        }
+        score -= (found - position).min(3) as i32;
+        previous = Some(found);
+        position = found + 1;
+    }
+    Some(score)
+}
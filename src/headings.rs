@@ -0,0 +1,123 @@
+use pulldown_cmark::{Event, Tag, TagEnd};
+use std::collections::HashMap;
+
+/// A heading of the document together with the anchor id it was given
+#[derive(Debug, Clone)]
+pub struct Heading {
+    pub id: String,
+    pub level: u8,
+    pub text: String,
+}
+
+/// Turns heading text into a GitHub-style anchor slug
+pub fn slugify(text: &str) -> String {
+    text.trim()
+        .to_lowercase()
+        .chars()
+        .filter_map(|c| match c {
+            c if c.is_alphanumeric() || c == '-' || c == '_' => Some(c),
+            c if c.is_whitespace() => Some('-'),
+            _ => None,
+        })
+        .collect()
+}
+
+/// Gives every heading without an explicit id a slug of its text, prefixed with `prefix`,
+/// and returns the document's headings in order.
+pub fn assign_ids<'a>(mut events: Vec<Event<'a>>, prefix: &str) -> (Vec<Event<'a>>, Vec<Heading>) {
+    let mut headings = Vec::new();
+    let mut seen: HashMap<String, usize> = HashMap::new();
+
+    for i in 0..events.len() {
+        let Event::Start(Tag::Heading { .. }) = &events[i] else {
+            continue;
+        };
+        let text = heading_text(&events[i + 1..]);
+
+        let Event::Start(Tag::Heading { id, level, .. }) = &mut events[i] else {
+            unreachable!()
+        };
+        let anchor = match id {
+            Some(existing) => existing.to_string(),
+            None => {
+                let slug = slugify(&text);
+                let count = seen.entry(slug.clone()).or_insert(0);
+                let unique = if *count == 0 {
+                    format!("{prefix}{slug}")
+                } else {
+                    format!("{prefix}{slug}-{count}")
+                };
+                *count += 1;
+                *id = Some(unique.clone().into());
+                unique
+            }
+        };
+        headings.push(Heading {
+            id: anchor,
+            level: *level as u8,
+            text,
+        });
+    }
+    (events, headings)
+}
+
+/// Prefixes headings with their section number (1, 1.1, 1.1.1...) for `levels` levels
+/// starting at `first_level`. The numbers are inline HTML, so anchors and the table of contents
+/// keep the plain heading text.
+pub fn number(events: Vec<Event<'_>>, first_level: usize, levels: usize) -> Vec<Event<'_>> {
+    let mut counters = [0usize; 6];
+    let mut out = Vec::with_capacity(events.len());
+    for event in events {
+        let numbered_level = match &event {
+            Event::Start(Tag::Heading { level, .. }) => (*level as usize)
+                .checked_sub(first_level)
+                .filter(|index| *index < levels),
+            _ => None,
+        };
+        out.push(event);
+        if let Some(index) = numbered_level {
+            counters[index] += 1;
+            counters[index + 1..].fill(0);
+            let number: Vec<String> = counters[..=index].iter().map(usize::to_string).collect();
+            out.push(Event::InlineHtml(
+                format!(
+                    "<span class=\"md-heading-number\">{}</span> ",
+                    number.join(".")
+                )
+                .into(),
+            ));
+        }
+    }
+    out
+}
+
+/// Level of the document's top headings: `##` when a single `#` heading is its title,
+/// otherwise `#`
+pub fn top_level(events: &[Event<'_>]) -> usize {
+    let levels: Vec<usize> = events
+        .iter()
+        .filter_map(|event| match event {
+            Event::Start(Tag::Heading { level, .. }) => Some(*level as usize),
+            _ => None,
+        })
+        .collect();
+    let titles = levels.iter().filter(|level| **level == 1).count();
+    if titles == 1 && levels.iter().any(|level| *level > 1) {
+        2
+    } else {
+        1
+    }
+}
+
+/// Collects the plain text of the heading whose content starts at `events`
+fn heading_text(events: &[Event<'_>]) -> String {
+    let mut text = String::new();
+    for event in events {
+        match event {
+            Event::End(TagEnd::Heading(_)) => break,
+            Event::Text(t) | Event::Code(t) => text.push_str(t),
+            _ => {}
+        }
+    }
+    text.trim().to_string()
+}
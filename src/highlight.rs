@@ -0,0 +1,472 @@
+/// Kinds of tokens the highlighter colors, everything else keeps the text color
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Token {
+    Comment,
+    String,
+    Number,
+    Keyword,
+}
+
+impl Token {
+    /// CSS class of the token's span
+    pub fn class(&self) -> &'static str {
+        match self {
+            Token::Comment => "hl-comment",
+            Token::String => "hl-string",
+            Token::Number => "hl-number",
+            Token::Keyword => "hl-keyword",
+        }
+    }
+}
+
+/// Colors for highlighted code, chosen independently of the page theme
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Palette {
+    GitHubLight,
+    GitHubDark,
+    Monokai,
+    SolarizedLight,
+    SolarizedDark,
+}
+
+impl Palette {
+    /// Returns the display name for the palette
+    pub fn name(&self) -> &'static str {
+        match self {
+            Palette::GitHubLight => "GitHub Light",
+            Palette::GitHubDark => "GitHub Dark",
+            Palette::Monokai => "Monokai",
+            Palette::SolarizedLight => "Solarized Light",
+            Palette::SolarizedDark => "Solarized Dark",
+        }
+    }
+
+    /// Returns all available palettes
+    pub fn all() -> &'static [Palette] {
+        &[
+            Palette::GitHubLight,
+            Palette::GitHubDark,
+            Palette::Monokai,
+            Palette::SolarizedLight,
+            Palette::SolarizedDark,
+        ]
+    }
+
+    /// Identifier used in style profiles
+    pub fn keyword(&self) -> &'static str {
+        match self {
+            Palette::GitHubLight => "github-light",
+            Palette::GitHubDark => "github-dark",
+            Palette::Monokai => "monokai",
+            Palette::SolarizedLight => "solarized-light",
+            Palette::SolarizedDark => "solarized-dark",
+        }
+    }
+
+    /// Colors of comments, strings, numbers and keywords, plus the code block background
+    /// and text color for palettes that bring their own (`None` keeps the theme's)
+    fn colors(&self) -> ([&'static str; 4], Option<(&'static str, &'static str)>) {
+        match self {
+            Palette::GitHubLight => (["#59636e", "#0a3069", "#0550ae", "#cf222e"], None),
+            Palette::GitHubDark => (["#9198a1", "#a5d6ff", "#79c0ff", "#ff7b72"], None),
+            Palette::Monokai => (
+                ["#75715e", "#e6db74", "#ae81ff", "#f92672"],
+                Some(("#272822", "#f8f8f2")),
+            ),
+            Palette::SolarizedLight => (
+                ["#93a1a1", "#2aa198", "#d33682", "#859900"],
+                Some(("#fdf6e3", "#657b83")),
+            ),
+            Palette::SolarizedDark => (
+                ["#586e75", "#2aa198", "#d33682", "#859900"],
+                Some(("#002b36", "#839496")),
+            ),
+        }
+    }
+
+    /// Returns the stylesheet coloring the spans produced by [`highlight`]
+    pub fn css(&self) -> String {
+        let (colors, block) = self.colors();
+        let tokens = [Token::Comment, Token::String, Token::Number, Token::Keyword];
+        let mut css = String::new();
+        if let Some((background, text)) = block {
+            css.push_str(&format!(
+                ".markdown-body pre.md-code-highlighted {{\n  color: {text};\n  background-color: {background};\n}}\n\n"
+            ));
+        }
+        for (token, color) in tokens.iter().zip(colors) {
+            css.push_str(&format!(
+                ".markdown-body pre .{} {{\n  color: {color};\n}}\n\n",
+                token.class()
+            ));
+        }
+        css.push_str(".markdown-body pre .hl-comment {\n  font-style: italic;\n}\n");
+        css
+    }
+}
+
+/// What the tokenizer needs to know about a language
+struct Syntax {
+    line_comments: &'static [&'static str],
+    block_comment: Option<(&'static str, &'static str)>,
+    quotes: &'static [char],
+    keywords: &'static [&'static str],
+    case_insensitive: bool,
+}
+
+const RUST: Syntax = Syntax {
+    line_comments: &["//"],
+    block_comment: Some(("/*", "*/")),
+    quotes: &['"'], // ' also starts lifetimes
+    keywords: &[
+        "as", "async", "await", "break", "const", "continue", "crate", "dyn", "else", "enum",
+        "extern", "false", "fn", "for", "if", "impl", "in", "let", "loop", "match", "mod", "move",
+        "mut", "pub", "ref", "return", "self", "Self", "static", "struct", "super", "trait",
+        "true", "type", "unsafe", "use", "where", "while",
+    ],
+    case_insensitive: false,
+};
+
+const C: Syntax = Syntax {
+    line_comments: &["//"],
+    block_comment: Some(("/*", "*/")),
+    quotes: &['"', '\''],
+    keywords: &[
+        "auto",
+        "bool",
+        "break",
+        "case",
+        "char",
+        "class",
+        "const",
+        "continue",
+        "default",
+        "delete",
+        "do",
+        "double",
+        "else",
+        "enum",
+        "extern",
+        "false",
+        "float",
+        "for",
+        "goto",
+        "if",
+        "inline",
+        "int",
+        "long",
+        "namespace",
+        "new",
+        "nullptr",
+        "private",
+        "protected",
+        "public",
+        "return",
+        "short",
+        "signed",
+        "sizeof",
+        "static",
+        "struct",
+        "switch",
+        "template",
+        "this",
+        "true",
+        "typedef",
+        "union",
+        "unsigned",
+        "using",
+        "virtual",
+        "void",
+        "volatile",
+        "while",
+        "#include",
+        "#define",
+    ],
+    case_insensitive: false,
+};
+
+const JAVA: Syntax = Syntax {
+    line_comments: &["//"],
+    block_comment: Some(("/*", "*/")),
+    quotes: &['"', '\''],
+    keywords: &[
+        "abstract",
+        "boolean",
+        "break",
+        "byte",
+        "case",
+        "catch",
+        "char",
+        "class",
+        "continue",
+        "default",
+        "do",
+        "double",
+        "else",
+        "enum",
+        "extends",
+        "false",
+        "final",
+        "finally",
+        "float",
+        "for",
+        "if",
+        "implements",
+        "import",
+        "instanceof",
+        "int",
+        "interface",
+        "long",
+        "new",
+        "null",
+        "package",
+        "private",
+        "protected",
+        "public",
+        "return",
+        "static",
+        "super",
+        "switch",
+        "this",
+        "throw",
+        "throws",
+        "true",
+        "try",
+        "var",
+        "void",
+        "while",
+    ],
+    case_insensitive: false,
+};
+
+const JAVASCRIPT: Syntax = Syntax {
+    line_comments: &["//"],
+    block_comment: Some(("/*", "*/")),
+    quotes: &['"', '\'', '`'],
+    keywords: &[
+        "as",
+        "async",
+        "await",
+        "break",
+        "case",
+        "catch",
+        "class",
+        "const",
+        "continue",
+        "default",
+        "delete",
+        "do",
+        "else",
+        "export",
+        "extends",
+        "false",
+        "finally",
+        "for",
+        "from",
+        "function",
+        "if",
+        "import",
+        "in",
+        "instanceof",
+        "interface",
+        "let",
+        "new",
+        "null",
+        "of",
+        "return",
+        "static",
+        "super",
+        "switch",
+        "this",
+        "throw",
+        "true",
+        "try",
+        "type",
+        "typeof",
+        "undefined",
+        "var",
+        "void",
+        "while",
+        "yield",
+    ],
+    case_insensitive: false,
+};
+
+const GO: Syntax = Syntax {
+    line_comments: &["//"],
+    block_comment: Some(("/*", "*/")),
+    quotes: &['"', '\'', '`'],
+    keywords: &[
+        "break",
+        "case",
+        "chan",
+        "const",
+        "continue",
+        "default",
+        "defer",
+        "else",
+        "false",
+        "fallthrough",
+        "for",
+        "func",
+        "go",
+        "goto",
+        "if",
+        "import",
+        "interface",
+        "map",
+        "nil",
+        "package",
+        "range",
+        "return",
+        "select",
+        "struct",
+        "switch",
+        "true",
+        "type",
+        "var",
+    ],
+    case_insensitive: false,
+};
+
+const PYTHON: Syntax = Syntax {
+    line_comments: &["#"],
+    block_comment: None,
+    quotes: &['"', '\''],
+    keywords: &[
+        "False", "None", "True", "and", "as", "assert", "async", "await", "break", "class",
+        "continue", "def", "del", "elif", "else", "except", "finally", "for", "from", "global",
+        "if", "import", "in", "is", "lambda", "nonlocal", "not", "or", "pass", "raise", "return",
+        "self", "try", "while", "with", "yield",
+    ],
+    case_insensitive: false,
+};
+
+const SHELL: Syntax = Syntax {
+    line_comments: &["#"],
+    block_comment: None,
+    quotes: &['"', '\''],
+    keywords: &[
+        "case", "do", "done", "echo", "elif", "else", "esac", "exit", "export", "fi", "for",
+        "function", "if", "in", "local", "return", "then", "while",
+    ],
+    case_insensitive: false,
+};
+
+const SQL: Syntax = Syntax {
+    line_comments: &["--"],
+    block_comment: Some(("/*", "*/")),
+    quotes: &['\''],
+    keywords: &[
+        "and", "as", "by", "create", "delete", "desc", "distinct", "drop", "from", "group",
+        "having", "insert", "into", "is", "join", "key", "left", "limit", "not", "null", "on",
+        "or", "order", "primary", "select", "set", "table", "union", "update", "values", "where",
+    ],
+    case_insensitive: true,
+};
+
+/// Data languages: only comments, strings and numbers (plus literals) are highlighted
+const DATA: Syntax = Syntax {
+    line_comments: &["#"],
+    block_comment: None,
+    quotes: &['"', '\''],
+    keywords: &["true", "false", "null"],
+    case_insensitive: false,
+};
+
+const JSON: Syntax = Syntax {
+    line_comments: &[],
+    block_comment: None,
+    quotes: &['"'],
+    keywords: &["true", "false", "null"],
+    case_insensitive: false,
+};
+
+fn syntax(language: &str) -> Option<&'static Syntax> {
+    Some(match language.to_lowercase().as_str() {
+        "rust" | "rs" => &RUST,
+        "c" | "h" | "cpp" | "c++" | "cc" | "hpp" | "cs" | "csharp" => &C,
+        "java" | "kotlin" | "kt" | "scala" => &JAVA,
+        "javascript" | "js" | "jsx" | "typescript" | "ts" | "tsx" => &JAVASCRIPT,
+        "go" | "golang" => &GO,
+        "python" | "py" => &PYTHON,
+        "sh" | "bash" | "shell" | "zsh" | "console" => &SHELL,
+        "sql" => &SQL,
+        "yaml" | "yml" | "toml" | "ini" => &DATA,
+        "json" => &JSON,
+        _ => return None,
+    })
+}
+
+/// Splits `code` into plain and highlighted pieces, or returns None for languages the
+/// highlighter doesn't know
+pub fn highlight(language: &str, code: &str) -> Option<Vec<(Option<Token>, String)>> {
+    let syntax = syntax(language)?;
+    let mut pieces: Vec<(Option<Token>, String)> = Vec::new();
+    let mut push = |token: Option<Token>, text: &str| match pieces.last_mut() {
+        Some((last, piece)) if *last == token => piece.push_str(text),
+        _ => pieces.push((token, text.to_string())),
+    };
+
+    let mut rest = code;
+    while let Some(c) = rest.chars().next() {
+        let (token, len) = if syntax
+            .line_comments
+            .iter()
+            .any(|marker| rest.starts_with(marker))
+        {
+            (Some(Token::Comment), rest.find('\n').unwrap_or(rest.len()))
+        } else if let Some((start, end)) = syntax.block_comment
+            && rest.starts_with(start)
+        {
+            let len = rest[start.len()..]
+                .find(end)
+                .map_or(rest.len(), |i| start.len() + i + end.len());
+            (Some(Token::Comment), len)
+        } else if syntax.quotes.contains(&c) {
+            (Some(Token::String), string_len(rest, c))
+        } else if c.is_ascii_digit() {
+            let len = rest
+                .find(|c: char| !(c.is_ascii_alphanumeric() || c == '.' || c == '_'))
+                .unwrap_or(rest.len());
+            (Some(Token::Number), len)
+        } else if c.is_alphabetic() || c == '_' || c == '#' {
+            let len = rest
+                .char_indices()
+                .skip(1)
+                .find(|(_, c)| !(c.is_alphanumeric() || *c == '_'))
+                .map_or(rest.len(), |(i, _)| i);
+            let word = &rest[..len];
+            let is_keyword = syntax.keywords.iter().any(|keyword| {
+                if syntax.case_insensitive {
+                    keyword.eq_ignore_ascii_case(word)
+                } else {
+                    *keyword == word
+                }
+            });
+            (is_keyword.then_some(Token::Keyword), len)
+        } else {
+            (None, c.len_utf8())
+        };
+        push(token, &rest[..len]);
+        rest = &rest[len..];
+    }
+    Some(pieces)
+}
+
+/// Length of the string literal `text` starts with, up to its closing `quote`. Strings left
+/// open end at the line break, except backtick strings which may span lines.
+fn string_len(text: &str, quote: char) -> usize {
+    let mut chars = text.char_indices().skip(1);
+    while let Some((i, c)) = chars.next() {
+        match c {
+            '\\' => {
+                chars.next();
+            }
+            '\n' if quote != '`' => return i,
+            c if c == quote => return i + c.len_utf8(),
+            _ => {}
+        }
+    }
+    text.len()
+}
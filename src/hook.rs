@@ -0,0 +1,87 @@
+use crate::process;
+use std::fs;
+use std::path::Path;
+use std::process::Command;
+
+/// Runs the user's post-processing command on the finished PDF at `pdf_path`.
+/// `{input}` in the command is replaced with a copy of the PDF and `{output}` with the path
+/// the command should write its result to. The result (or the input, for commands that
+/// modify it in place) replaces the PDF only when the command succeeds. Both are in
+/// `temp_dir`. A blank command does nothing.
+pub fn run_post_command(command: &str, pdf_path: &Path, temp_dir: &Path) -> Result<(), String> {
+    let args = split_command(command);
+    let Some((program, _)) = args.split_first() else {
+        return Ok(());
+    };
+
+    let input = temp_dir.join("post_process_input.pdf");
+    let output = temp_dir.join("post_process_output.pdf");
+    let _ = fs::remove_file(&output);
+    fs::copy(pdf_path, &input)
+        .map_err(|e| format!("Failed to prepare PDF for post-processing: {}", e))?;
+
+    let result = run(program, &args[1..], &input, &output).and_then(|()| {
+        let result = if output.is_file() { &output } else { &input };
+        fs::copy(result, pdf_path)
+            .map(|_| ())
+            .map_err(|e| format!("Failed to replace PDF with post-processed file: {}", e))
+    });
+    let _ = fs::remove_file(&input);
+    let _ = fs::remove_file(&output);
+    result
+}
+
+fn run(program: &str, args: &[String], input: &Path, output: &Path) -> Result<(), String> {
+    let input = input.to_string_lossy();
+    let output = output.to_string_lossy();
+    let args = args
+        .iter()
+        .map(|arg| arg.replace("{input}", &input).replace("{output}", &output));
+
+    let mut command = Command::new(program);
+    command.args(args);
+    match command.output() {
+        Ok(command_output) if command_output.status.success() => Ok(()),
+        Ok(command_output) => Err(process::failure(
+            "Post-processing command",
+            &command,
+            &command_output,
+        )),
+        Err(e) => Err(format!(
+            "Failed to execute post-processing command '{}': {}",
+            program, e
+        )),
+    }
+}
+
+/// Splits a command line into arguments at whitespace, honouring single and double quotes
+fn split_command(command: &str) -> Vec<String> {
+    let mut args = Vec::new();
+    let mut current = String::new();
+    let mut in_arg = false;
+    let mut quote = None;
+    for c in command.chars() {
+        match (quote, c) {
+            (Some(q), c) if c == q => quote = None,
+            (Some(_), c) => current.push(c),
+            (None, '"' | '\'') => {
+                quote = Some(c);
+                in_arg = true;
+            }
+            (None, c) if c.is_whitespace() => {
+                if in_arg {
+                    args.push(std::mem::take(&mut current));
+                    in_arg = false;
+                }
+            }
+            (None, c) => {
+                current.push(c);
+                in_arg = true;
+            }
+        }
+    }
+    if in_arg {
+        args.push(current);
+    }
+    args
+}
@@ -0,0 +1,222 @@
+use std::collections::HashMap;
+use std::env;
+use std::sync::OnceLock;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+/// Language of the app's own text, independent of the document language
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Copy)]
+pub enum Locale {
+    English,
+    German,
+    French,
+}
+
+impl Locale {
+    /// Returns the language's name, in the language itself
+    pub fn name(&self) -> &'static str {
+        match self {
+            Locale::English => "English",
+            Locale::German => "Deutsch",
+            Locale::French => "Français",
+        }
+    }
+
+    /// Returns all languages the app is translated to
+    pub fn all() -> &'static [Locale] {
+        &[Locale::English, Locale::German, Locale::French]
+    }
+
+    /// Identifier used in saved settings, the language's ISO 639-1 code
+    pub fn keyword(&self) -> &'static str {
+        match self {
+            Locale::English => "en",
+            Locale::German => "de",
+            Locale::French => "fr",
+        }
+    }
+
+    /// Finds the language of a locale name such as `de_DE.UTF-8`, `fr-CA` or `en`
+    pub fn from_tag(tag: &str) -> Option<Locale> {
+        let language = tag.split(['_', '-', '.', '@']).next()?.to_lowercase();
+        Locale::all()
+            .iter()
+            .find(|locale| locale.keyword() == language)
+            .copied()
+    }
+
+    /// The translations, in gettext's PO format. English is the source language.
+    fn catalog_source(&self) -> Option<&'static str> {
+        match self {
+            Locale::English => None,
+            Locale::German => Some(include_str!("../locales/de.po")),
+            Locale::French => Some(include_str!("../locales/fr.po")),
+        }
+    }
+}
+
+/// Index into [`Locale::all`] of the language text is translated to
+static CURRENT: AtomicUsize = AtomicUsize::new(0);
+
+/// Translates text from now on to `locale`
+pub fn set(locale: Locale) {
+    let index = Locale::all().iter().position(|other| *other == locale);
+    CURRENT.store(index.unwrap_or(0), Ordering::Relaxed);
+}
+
+/// The language text is translated to
+pub fn current() -> Locale {
+    Locale::all()[CURRENT.load(Ordering::Relaxed)]
+}
+
+/// The OS's language, if the app is translated to it, otherwise English
+pub fn detect() -> Locale {
+    system_locales()
+        .iter()
+        .find_map(|tag| Locale::from_tag(tag))
+        .unwrap_or(Locale::English)
+}
+
+/// Locale names of the user's preferred languages, most preferred first
+fn system_locales() -> Vec<String> {
+    // `LANGUAGE` is a list such as `de:en`, the others hold one locale. "C" and "POSIX" name
+    // no language.
+    let mut tags: Vec<String> = ["LANGUAGE", "LC_ALL", "LC_MESSAGES", "LANG"]
+        .iter()
+        .filter_map(|name| env::var(name).ok())
+        .flat_map(|value| value.split(':').map(str::to_string).collect::<Vec<_>>())
+        .filter(|tag| !tag.is_empty() && tag != "C" && tag != "POSIX")
+        .collect();
+    tags.extend(platform_locale());
+    tags
+}
+
+#[cfg(target_os = "windows")]
+fn platform_locale() -> Option<String> {
+    #[link(name = "kernel32")]
+    unsafe extern "system" {
+        fn GetUserDefaultLocaleName(name: *mut u16, length: i32) -> i32;
+    }
+    // LOCALE_NAME_MAX_LENGTH
+    let mut name = [0u16; 85];
+    // SAFETY: The buffer's length is passed along, the call writes at most that many characters
+    let length = unsafe { GetUserDefaultLocaleName(name.as_mut_ptr(), name.len() as i32) };
+    // The length includes the terminating null, 0 means the call failed
+    (length > 1).then(|| String::from_utf16_lossy(&name[..length as usize - 1]))
+}
+
+#[cfg(target_os = "macos")]
+fn platform_locale() -> Option<String> {
+    let output = std::process::Command::new("defaults")
+        .args(["read", "-g", "AppleLocale"])
+        .output()
+        .ok()?;
+    let locale = String::from_utf8_lossy(&output.stdout).trim().to_string();
+    (output.status.success() && !locale.is_empty()).then_some(locale)
+}
+
+#[cfg(not(any(target_os = "windows", target_os = "macos")))]
+fn platform_locale() -> Option<String> {
+    None // The environment variables are all there is
+}
+
+/// Translations of `locale` by their English text, read on first use
+fn catalog(locale: Locale) -> Option<&'static HashMap<String, String>> {
+    static CATALOGS: OnceLock<HashMap<Locale, HashMap<String, String>>> = OnceLock::new();
+    CATALOGS
+        .get_or_init(|| {
+            Locale::all()
+                .iter()
+                .filter_map(|locale| Some((*locale, parse(locale.catalog_source()?))))
+                .collect()
+        })
+        .get(&locale)
+}
+
+/// Translates `text` to the current language. Text without a translation stays English.
+pub fn t(text: &'static str) -> &'static str {
+    catalog(current())
+        .and_then(|catalog| catalog.get(text))
+        .map_or(text, String::as_str)
+}
+
+/// Replaces the `{name}` placeholders of a translated text with their values
+pub fn fill(text: &str, values: &[(&str, String)]) -> String {
+    let mut text = text.to_string();
+    for (name, value) in values {
+        text = text.replace(&format!("{{{name}}}"), value);
+    }
+    text
+}
+
+/// Translates a text literal to the current language, filling in its `{name}` placeholders:
+/// `tr!("Saved {path}", path = path.display())`
+macro_rules! tr {
+    ($text:literal) => {
+        $crate::i18n::t($text)
+    };
+    ($text:literal, $($name:ident = $value:expr),+ $(,)?) => {
+        $crate::i18n::fill($crate::i18n::t($text), &[$((stringify!($name), $value.to_string())),+])
+    };
+}
+pub(crate) use tr;
+
+/// Reads the `msgid`/`msgstr` pairs of a PO file. Entries with an empty `msgstr` aren't
+/// translated yet and are left out.
+fn parse(source: &str) -> HashMap<String, String> {
+    let mut catalog = HashMap::new();
+    let mut id = None;
+    let mut current: Option<(bool, String)> = None; // Whether it's the msgstr, and its text so far
+    let mut finish = |current: Option<(bool, String)>, id: &mut Option<String>| match current {
+        Some((false, text)) => *id = Some(text),
+        Some((true, text)) => {
+            if let Some(id) = id.take()
+                && !id.is_empty()
+                && !text.is_empty()
+            {
+                catalog.insert(id, text);
+            }
+        }
+        None => {}
+    };
+    for line in source.lines().map(str::trim) {
+        if let Some(rest) = line.strip_prefix("msgid ") {
+            finish(current.take(), &mut id);
+            current = Some((false, unquote(rest)));
+        } else if let Some(rest) = line.strip_prefix("msgstr ") {
+            finish(current.take(), &mut id);
+            current = Some((true, unquote(rest)));
+        } else if line.starts_with('"')
+            && let Some((_, text)) = &mut current
+        {
+            text.push_str(&unquote(line));
+        } else if line.is_empty() || line.starts_with('#') {
+            finish(current.take(), &mut id);
+        }
+    }
+    finish(current, &mut id);
+    catalog
+}
+
+/// The text of a quoted PO string, with its escapes resolved
+fn unquote(quoted: &str) -> String {
+    let inner = quoted
+        .trim()
+        .strip_prefix('"')
+        .and_then(|rest| rest.strip_suffix('"'))
+        .unwrap_or("");
+    let mut text = String::new();
+    let mut chars = inner.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            text.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('n') => text.push('\n'),
+            Some('t') => text.push('\t'),
+            Some(other) => text.push(other),
+            None => {}
+        }
+    }
+    text
+}
@@ -0,0 +1,687 @@
+use crate::checksum;
+use crate::convert::escape_attr;
+use pulldown_cmark::{CowStr, Event, Tag, TagEnd};
+use std::collections::HashMap;
+use std::path::Path;
+use url::Url;
+
+/// Pixel density that images are picked for. Print output benefits from high resolution
+/// assets while screen output should stay lightweight.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Density {
+    Screen,
+    Print,
+}
+
+impl Density {
+    /// Returns the display name for the density
+    pub fn name(&self) -> &'static str {
+        match self {
+            Density::Screen => "Screen (1x)",
+            Density::Print => "Print (high-res)",
+        }
+    }
+
+    /// Returns all available densities
+    pub fn all() -> &'static [Density] {
+        &[Density::Screen, Density::Print]
+    }
+
+    /// Identifier used in saved settings
+    pub fn keyword(&self) -> &'static str {
+        match self {
+            Density::Screen => "screen",
+            Density::Print => "print",
+        }
+    }
+
+    /// `@Nx` scale suffixes to look for, best match first
+    fn scales(&self) -> &'static [u8] {
+        match self {
+            Density::Screen => &[1, 2, 3],
+            Density::Print => &[3, 2, 1],
+        }
+    }
+}
+
+/// Returns true for image sources that refer to a file next to the Markdown document
+pub fn is_local(src: &str) -> bool {
+    !(src.is_empty() || src.starts_with('#') || src.starts_with("data:") || src.contains("://"))
+}
+
+/// Splits `images/logo@2x.png` into (`images/logo`, `.png`), dropping any `@Nx` suffix
+fn split_variant(src: &str) -> (&str, &str) {
+    let file_start = src.rfind('/').map_or(0, |i| i + 1);
+    let (stem, ext) = match src[file_start..].rfind('.') {
+        Some(dot) => src.split_at(file_start + dot),
+        None => (src, ""),
+    };
+    let stem = match stem.rsplit_once('@') {
+        Some((base, scale))
+            if scale.len() == 2 && scale.ends_with('x') && scale.as_bytes()[0].is_ascii_digit() =>
+        {
+            base
+        }
+        _ => stem,
+    };
+    (stem, ext)
+}
+
+/// Picks the `name@2x.png`-style variant of a local image that best suits `density`,
+/// falling back to the original source when no variant exists on disk.
+pub fn select_variant(src: &str, base_dir: &Path, density: Density) -> String {
+    if !is_local(src) {
+        return src.to_string();
+    }
+    let (stem, ext) = split_variant(src);
+    for scale in density.scales() {
+        let candidate = if *scale == 1 {
+            format!("{stem}{ext}")
+        } else {
+            format!("{stem}@{scale}x{ext}")
+        };
+        if base_dir.join(&candidate).is_file() {
+            return candidate;
+        }
+    }
+    src.to_string()
+}
+
+/// Applies [`select_variant`] to every image in the document
+pub fn select_variants<'a>(
+    events: Vec<Event<'a>>,
+    base_dir: &Path,
+    density: Density,
+) -> Vec<Event<'a>> {
+    events
+        .into_iter()
+        .map(|event| match event {
+            Event::Start(Tag::Image {
+                link_type,
+                dest_url,
+                title,
+                id,
+            }) => {
+                let dest_url = select_variant(&dest_url, base_dir, density).into();
+                Event::Start(Tag::Image {
+                    link_type,
+                    dest_url,
+                    title,
+                    id,
+                })
+            }
+            other => other,
+        })
+        .collect()
+}
+
+/// Points local images at their absolute `file://` URL, since the HTML is rendered from a
+/// temporary file where paths relative to the Markdown file wouldn't be found. Runs after
+/// the steps that look for the images on disk.
+pub fn absolute_sources<'a>(events: Vec<Event<'a>>, base_dir: &Path) -> Vec<Event<'a>> {
+    let base_dir = if base_dir.as_os_str().is_empty() {
+        Path::new(".")
+    } else {
+        base_dir
+    };
+    let base_dir = std::path::absolute(base_dir).unwrap_or_else(|_| base_dir.to_path_buf());
+    events
+        .into_iter()
+        .map(|event| match event {
+            Event::Start(Tag::Image {
+                link_type,
+                dest_url,
+                title,
+                id,
+            }) if is_local(&dest_url) => {
+                let dest_url = file_url(&dest_url, &base_dir).map_or(dest_url, Into::into);
+                Event::Start(Tag::Image {
+                    link_type,
+                    dest_url,
+                    title,
+                    id,
+                })
+            }
+            other => other,
+        })
+        .collect()
+}
+
+/// The `file://` URL of the local image `src`, keeping its query and fragment
+fn file_url(src: &str, base_dir: &Path) -> Option<String> {
+    let (path, suffix) = src.split_at(src.find(['?', '#']).unwrap_or(src.len()));
+    let url = Url::from_file_path(base_dir.join(percent_decode(path))).ok()?;
+    Some(format!("{url}{suffix}"))
+}
+
+/// Fragment of images shown only in dark output, as on GitHub
+const DARK_ONLY: &str = "#gh-dark-mode-only";
+/// Fragment of images shown only in light output, as on GitHub
+const LIGHT_ONLY: &str = "#gh-light-mode-only";
+/// Fragment of images whose lightness is flipped in dark output, e.g. black line diagrams
+const INVERT_IN_DARK: &str = "#invert-in-dark";
+
+/// Puts images on a light card in dark output, so transparent diagrams stay readable
+pub const IMAGE_BACKING_CSS: &str = r#".markdown-body img {
+  background-color: #ffffff;
+  padding: .5em;
+  border-radius: 6px;
+}
+"#;
+
+/// Adapts the images to light or `dark` output: images marked `#gh-dark-mode-only` or
+/// `#gh-light-mode-only` are dropped from the other one, images marked `#invert-in-dark` get
+/// their lightness flipped in dark output, and HTML `<picture>` elements are reduced to the
+/// image for the matching `prefers-color-scheme` source, which wkhtmltopdf doesn't pick itself.
+pub fn apply_color_scheme<'a>(
+    events: Vec<Event<'a>>,
+    base_dir: &Path,
+    dark: bool,
+    warnings: &mut Vec<String>,
+) -> Vec<Event<'a>> {
+    let hidden = if dark { LIGHT_ONLY } else { DARK_ONLY };
+    let mut out = Vec::with_capacity(events.len());
+    let mut skipping = 0usize; // Depth of nested images inside a dropped image
+    let mut html_block: Option<Vec<CowStr<'a>>> = None;
+    for event in events {
+        if skipping > 0 {
+            match event {
+                Event::Start(Tag::Image { .. }) => skipping += 1,
+                Event::End(TagEnd::Image) => skipping -= 1,
+                _ => {}
+            }
+            continue;
+        }
+        match event {
+            Event::Start(Tag::Image { ref dest_url, .. }) if dest_url.ends_with(hidden) => {
+                skipping = 1;
+            }
+            Event::Start(Tag::Image {
+                link_type,
+                dest_url,
+                title,
+                id,
+            }) if dark && dest_url.ends_with(INVERT_IN_DARK) => {
+                let dest_url = match invert_lightness(&dest_url, base_dir) {
+                    Ok(path) => path.into(),
+                    Err(message) => {
+                        warnings.push(message);
+                        dest_url
+                    }
+                };
+                out.push(Event::Start(Tag::Image {
+                    link_type,
+                    dest_url,
+                    title,
+                    id,
+                }));
+            }
+            // Raw HTML blocks come in pieces, a <picture> element can span several.
+            // Blocks without one are passed on piece by piece, directives rely on that.
+            Event::Start(Tag::HtmlBlock) => {
+                html_block = Some(Vec::new());
+                out.push(event);
+            }
+            Event::Html(html) if html_block.is_some() => {
+                if let Some(block) = &mut html_block {
+                    block.push(html);
+                }
+            }
+            Event::End(TagEnd::HtmlBlock) => {
+                if let Some(block) = html_block.take() {
+                    let html = block.concat();
+                    if html.contains("<picture") {
+                        out.push(Event::Html(resolve_pictures(&html, dark).into()));
+                    } else {
+                        out.extend(block.into_iter().map(Event::Html));
+                    }
+                }
+                out.push(event);
+            }
+            other => out.push(other),
+        }
+    }
+    out
+}
+
+/// Replaces each `<picture>` in `html` with its `<img>`, using the `srcset` of the source
+/// whose `prefers-color-scheme` query matches the output
+fn resolve_pictures(html: &str, dark: bool) -> String {
+    let scheme = if dark { "dark" } else { "light" };
+    let mut out = String::with_capacity(html.len());
+    let mut rest = html;
+    while let Some(start) = rest.find("<picture") {
+        let Some(length) = rest[start..].find("</picture>") else {
+            break;
+        };
+        let picture = &rest[start..start + length];
+        out.push_str(&rest[..start]);
+        rest = &rest[start + length + "</picture>".len()..];
+
+        let source = tags(picture, "<source")
+            .find(|tag| {
+                attribute(tag, "media").is_some_and(|media| {
+                    let media: String = media.chars().filter(|c| !c.is_whitespace()).collect();
+                    media.contains(&format!("prefers-color-scheme:{scheme}"))
+                })
+            })
+            .and_then(|tag| attribute(tag, "srcset"))
+            .and_then(|srcset| srcset.split_whitespace().next().map(str::to_string));
+        let Some(img) = tags(picture, "<img").next() else {
+            continue;
+        };
+        match (source, attribute(img, "src")) {
+            (Some(source), Some(src)) => {
+                out.push_str(&img.replacen(&src, &escape_attr(&source), 1));
+            }
+            (Some(source), None) => {
+                out.push_str(&img.replacen(
+                    "<img",
+                    &format!("<img src=\"{}\"", escape_attr(&source)),
+                    1,
+                ));
+            }
+            (None, _) => out.push_str(img),
+        }
+    }
+    out.push_str(rest);
+    out
+}
+
+/// The tags in `html` starting with `prefix`, e.g. `<img`, each up to its closing `>`
+fn tags<'a>(html: &'a str, prefix: &'a str) -> impl Iterator<Item = &'a str> {
+    html.match_indices(prefix).filter_map(move |(start, _)| {
+        html[start..]
+            .find('>')
+            .map(|end| &html[start..=start + end])
+    })
+}
+
+/// Value of the attribute `name` of an HTML tag, quoted or not
+pub fn attribute(tag: &str, name: &str) -> Option<String> {
+    let lower = tag.to_ascii_lowercase();
+    let mut search = 0;
+    while let Some(found) = lower[search..].find(name) {
+        let at = search + found;
+        search = at + name.len();
+        let preceded_by_space = lower[..at].ends_with(|c: char| c.is_whitespace());
+        let after = lower[at + name.len()..].trim_start();
+        if !preceded_by_space || !after.starts_with('=') {
+            continue;
+        }
+        let value_start = tag.len() - after.len() + 1;
+        let value = tag[value_start..].trim_start();
+        return Some(match value.chars().next()? {
+            quote @ ('"' | '\'') => value[1..].split(quote).next()?.to_string(),
+            _ => value
+                .split(|c: char| c.is_whitespace() || c == '>')
+                .next()?
+                .to_string(),
+        });
+    }
+    None
+}
+
+/// Writes a copy of the local image `src` with its lightness flipped but its hues kept, and
+/// returns the copy's path
+fn invert_lightness(src: &str, base_dir: &Path) -> Result<String, String> {
+    let path = src.split(['?', '#']).next().unwrap_or(src);
+    let source = base_dir.join(percent_decode(path));
+    let image = image::open(&source)
+        .map_err(|e| format!("Failed to invert image '{}' for dark mode: {}", path, e))?;
+    let mut image = image.into_rgba8();
+    for pixel in image.pixels_mut() {
+        let [r, g, b, _] = pixel.0;
+        let luma = 0.299 * r as f32 + 0.587 * g as f32 + 0.114 * b as f32;
+        let shift = 255.0 - 2.0 * luma;
+        for channel in &mut pixel.0[..3] {
+            *channel = (*channel as f32 + shift).round().clamp(0.0, 255.0) as u8;
+        }
+    }
+    let key = checksum::key(&[source.as_os_str().as_encoded_bytes()]);
+    let inverted = std::env::temp_dir().join(format!("md-to-pdf-inverted-{key}.png"));
+    image
+        .save(&inverted)
+        .map_err(|e| format!("Failed to write inverted image: {}", e))?;
+    Ok(inverted.to_string_lossy().to_string())
+}
+
+/// Styles the boxes that [`mark_unresolved`] puts in place of images that can't be loaded
+pub const PLACEHOLDER_CSS: &str = r#".markdown-body .md-image-missing {
+  display: inline-block;
+  max-width: 100%;
+  padding: .5em 1em;
+  border: 2px dashed #cf222e;
+  border-radius: 6px;
+  color: #cf222e;
+  font-size: 85%;
+  text-align: left;
+}
+
+.markdown-body .md-image-missing-alt {
+  display: block;
+  font-weight: 600;
+}
+
+.markdown-body .md-image-missing code {
+  color: inherit;
+  word-break: break-all;
+}
+"#;
+
+/// Why an image can't be shown. `remote_blocked` is why http(s) images can't be loaded at
+/// all, if they can't.
+fn unresolved_reason(
+    src: &str,
+    base_dir: &Path,
+    remote_blocked: Option<&'static str>,
+) -> Option<&'static str> {
+    let src = src.trim();
+    if let Some(path) = src.strip_prefix("file://") {
+        return (!Path::new(&percent_decode(path)).is_file()).then_some("Image not found");
+    }
+    if src.starts_with("http://") || src.starts_with("https://") {
+        return remote_blocked;
+    }
+    if !is_local(src) {
+        return None;
+    }
+    let path = src.split(['?', '#']).next().unwrap_or(src);
+    let found = base_dir.join(path).is_file() || base_dir.join(percent_decode(path)).is_file();
+    (!found).then_some("Image not found")
+}
+
+/// Decodes `%XX` escapes in a URL path, leaving malformed escapes as they are
+pub fn percent_decode(path: &str) -> String {
+    let bytes = path.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] == b'%'
+            && let Some(byte) = path
+                .get(i + 1..i + 3)
+                .and_then(|hex| u8::from_str_radix(hex, 16).ok())
+        {
+            decoded.push(byte);
+            i += 3;
+        } else {
+            decoded.push(bytes[i]);
+            i += 1;
+        }
+    }
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
+/// Replaces images that are missing on disk (or remote images that can't be loaded)
+/// with a visible box showing their alt text and source, and records a warning for each.
+/// `remote_blocked` is why http(s) images can't be loaded at all, if they can't.
+pub fn mark_unresolved<'a>(
+    events: Vec<Event<'a>>,
+    base_dir: &Path,
+    remote_blocked: Option<&'static str>,
+    warnings: &mut Vec<String>,
+) -> Vec<Event<'a>> {
+    let mut out = Vec::with_capacity(events.len());
+    // Source, reason and alt text of the image being replaced
+    let mut pending: Option<(String, &str, String)> = None;
+    let mut nested = 0usize;
+
+    for event in events {
+        match &event {
+            Event::Start(Tag::Image { dest_url, .. }) if pending.is_none() => {
+                if let Some(reason) = unresolved_reason(dest_url, base_dir, remote_blocked) {
+                    pending = Some((dest_url.to_string(), reason, String::new()));
+                    out.push(Event::InlineHtml(
+                        "<span class=\"md-image-missing\"><span class=\"md-image-missing-alt\">"
+                            .into(),
+                    ));
+                    continue;
+                }
+            }
+            Event::Start(Tag::Image { .. }) => nested += 1,
+            Event::End(TagEnd::Image) if nested > 0 => nested -= 1,
+            Event::End(TagEnd::Image) => {
+                if let Some((src, reason, alt)) = pending.take() {
+                    warnings.push(if alt.trim().is_empty() {
+                        format!("{reason}: '{src}'")
+                    } else {
+                        format!("{reason}: '{src}' ({})", alt.trim())
+                    });
+                    out.push(Event::InlineHtml(
+                        format!("</span><code>{}</code></span>", escape_attr(&src)).into(),
+                    ));
+                    continue;
+                }
+            }
+            Event::Text(text) | Event::Code(text) => {
+                if let Some((_, _, alt)) = &mut pending {
+                    alt.push_str(text);
+                }
+            }
+            _ => {}
+        }
+        out.push(event);
+    }
+    out
+}
+
+/// Scales images down to the printable width, keeping their aspect ratio, so large
+/// screenshots don't overflow the page or get clipped at the margin
+pub const IMAGE_FIT_CSS: &str = r#".markdown-body img {
+  max-width: 100% !important;
+  height: auto !important;
+  box-sizing: border-box;
+  page-break-inside: avoid;
+}
+"#;
+
+/// Styles the boxes that [`apply_attributes`] puts around images with a size or alignment
+/// of their own
+pub const IMAGE_ATTRIBUTE_CSS: &str = r#".markdown-body .md-image-box {
+  display: inline-block;
+  max-width: 100%;
+}
+
+.markdown-body .md-image-width img {
+  width: 100%;
+}
+
+.markdown-body .md-image-height img {
+  height: 100% !important;
+  width: auto;
+}
+
+.markdown-body .md-image-width.md-image-height img {
+  width: 100%;
+}
+
+.markdown-body .md-image-center {
+  display: block;
+  margin-left: auto;
+  margin-right: auto;
+  text-align: center;
+}
+
+.markdown-body .md-image-left {
+  float: left;
+  margin: 0 1em .5em 0;
+}
+
+.markdown-body .md-image-right {
+  float: right;
+  margin: 0 0 .5em 1em;
+}
+"#;
+
+/// Splits the attribute block off the end of an image title, e.g. `Chart {width=50%}`
+/// into `Chart` and `width=50%`
+fn split_attributes(title: &str) -> (&str, Option<&str>) {
+    let trimmed = title.trim_end();
+    if let Some(body) = trimmed.strip_suffix('}')
+        && let Some(start) = body.rfind('{')
+    {
+        return (title[..start].trim_end(), Some(&body[start + 1..]));
+    }
+    (title, None)
+}
+
+/// Splits the attribute block off the start of the text after an image, e.g.
+/// `{width=300px align=center} and more` into `width=300px align=center` and ` and more`
+fn split_trailing_attributes(text: &str) -> Option<(&str, &str)> {
+    let rest = text.strip_prefix('{')?;
+    let end = rest.find('}')?;
+    Some((&rest[..end], &rest[end + 1..]))
+}
+
+/// The CSS length `value` stands for: a positive number with a CSS unit, or pixels
+/// without one
+fn css_length(value: &str) -> Option<String> {
+    let end = value
+        .find(|c: char| !(c.is_ascii_digit() || c == '.'))
+        .unwrap_or(value.len());
+    let (number, unit) = value.split_at(end);
+    number.parse::<f32>().ok().filter(|number| *number > 0.0)?;
+    match unit {
+        "" => Some(format!("{number}px")),
+        "px" | "%" | "em" | "rem" | "pt" | "pc" | "mm" | "cm" | "in" => Some(value.to_string()),
+        _ => None,
+    }
+}
+
+/// Size and alignment set for an image in an attribute block
+#[derive(Default)]
+struct ImageAttributes {
+    width: Option<String>,
+    height: Option<String>,
+    align: Option<&'static str>,
+}
+
+impl ImageAttributes {
+    /// Reads the `key=value` pairs of `block` into the attributes, warning about the ones
+    /// that aren't understood
+    fn parse(&mut self, block: &str, src: &str, warnings: &mut Vec<String>) {
+        // Hyphenation may have put soft hyphens into the words
+        let block = block.replace('\u{ad}', "");
+        for attribute in block.split_whitespace() {
+            match attribute.split_once('=') {
+                Some((key @ ("width" | "height"), value)) => match css_length(value) {
+                    Some(length) if key == "width" => self.width = Some(length),
+                    Some(length) => self.height = Some(length),
+                    None => warnings.push(format!("Invalid image {key} '{value}' for '{src}'")),
+                },
+                Some(("align", value)) => match value {
+                    "left" => self.align = Some("left"),
+                    "center" => self.align = Some("center"),
+                    "right" => self.align = Some("right"),
+                    _ => warnings.push(format!("Invalid image alignment '{value}' for '{src}'")),
+                },
+                _ => warnings.push(format!("Unknown image attribute '{attribute}' for '{src}'")),
+            }
+        }
+    }
+
+    /// The opening tag of the box that applies the attributes, None if none are set
+    fn box_tag(&self) -> Option<String> {
+        let mut classes = vec!["md-image-box".to_string()];
+        let mut style = Vec::new();
+        if let Some(width) = &self.width {
+            classes.push("md-image-width".to_string());
+            style.push(format!("width: {width}"));
+        }
+        if let Some(height) = &self.height {
+            classes.push("md-image-height".to_string());
+            style.push(format!("height: {height}"));
+        }
+        if let Some(align) = self.align {
+            classes.push(format!("md-image-{align}"));
+        }
+        if classes.len() == 1 {
+            return None;
+        }
+        let style = if style.is_empty() {
+            String::new()
+        } else {
+            format!(" style=\"{}\"", escape_attr(&style.join("; ")))
+        };
+        Some(format!("<span class=\"{}\"{style}>", classes.join(" ")))
+    }
+}
+
+/// Applies the size and alignment set in attribute blocks, either right after an image,
+/// e.g. `![Chart](chart.png){width=300px align=center}`, or at the end of its title, e.g.
+/// `![Chart](chart.png "Sales {width=50%}")`. The blocks are removed from the text and the
+/// image is put in a box with the attributes' styles. `width` and `height` take CSS
+/// lengths, pixels without a unit, and `align` is `left`, `center` or `right`. Invalid
+/// attributes are ignored with a warning.
+pub fn apply_attributes<'a>(
+    mut events: Vec<Event<'a>>,
+    warnings: &mut Vec<String>,
+) -> Vec<Event<'a>> {
+    // Blocks right after images, by the index of the image's start
+    let mut trailing = HashMap::new();
+    let mut starts = Vec::new(); // Indexes of the open images' starts, innermost last
+    for i in 0..events.len() {
+        match &events[i] {
+            Event::Start(Tag::Image { .. }) => starts.push(i),
+            Event::End(TagEnd::Image) => {
+                let Some(start) = starts.pop() else {
+                    continue;
+                };
+                if let Some(Event::Text(text)) = events.get(i + 1)
+                    && let Some((block, rest)) = split_trailing_attributes(text)
+                {
+                    trailing.insert(start, block.to_string());
+                    events[i + 1] = Event::Text(rest.to_string().into());
+                }
+            }
+            _ => {}
+        }
+    }
+
+    let mut out = Vec::with_capacity(events.len());
+    let mut boxed = Vec::new(); // Whether each open image is in a box, innermost last
+    for (i, event) in events.into_iter().enumerate() {
+        match event {
+            Event::Start(Tag::Image {
+                link_type,
+                dest_url,
+                title,
+                id,
+            }) => {
+                let mut attributes = ImageAttributes::default();
+                let (text, block) = split_attributes(&title);
+                if let Some(block) = block {
+                    attributes.parse(block, &dest_url, warnings);
+                }
+                if let Some(block) = trailing.get(&i) {
+                    attributes.parse(block, &dest_url, warnings);
+                }
+                let tag = attributes.box_tag();
+                boxed.push(tag.is_some());
+                if let Some(tag) = tag {
+                    out.push(Event::InlineHtml(tag.into()));
+                }
+                let title = if block.is_some() {
+                    text.to_string().into()
+                } else {
+                    title
+                };
+                out.push(Event::Start(Tag::Image {
+                    link_type,
+                    dest_url,
+                    title,
+                    id,
+                }));
+            }
+            Event::End(TagEnd::Image) => {
+                out.push(event);
+                if boxed.pop() == Some(true) {
+                    out.push(Event::InlineHtml("</span>".into()));
+                }
+            }
+            Event::Text(text) if text.is_empty() => {}
+            other => out.push(other),
+        }
+    }
+    out
+}
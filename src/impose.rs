@@ -0,0 +1,132 @@
+use crate::pdf;
+use lopdf::content::{Content, Operation};
+use lopdf::{Dictionary, Document, Object, ObjectId, Stream, dictionary};
+
+/// Order in which the pages of a saddle-stitched booklet of `page_count` pages (a multiple
+/// of four) are printed, as (left, right) page indices for each side of each sheet
+fn booklet_order(page_count: usize) -> Vec<(usize, usize)> {
+    (0..page_count / 2)
+        .map(|i| {
+            if i % 2 == 0 {
+                (page_count - 1 - i, i)
+            } else {
+                (i, page_count - 1 - i)
+            }
+        })
+        .collect()
+}
+
+/// Rearranges `document` into a booklet: pages are placed two to a landscape sheet in
+/// saddle-stitch order, padded with blank pages to a multiple of four, so that the printed
+/// sheets (duplex, flipped on the short edge) can be folded and stapled in the middle.
+/// Page labels and bookmarks no longer apply to the sheets and are dropped.
+pub fn apply_booklet(document: &mut Document) -> Result<(), String> {
+    let page_ids: Vec<ObjectId> = document.get_pages().into_values().collect();
+    let Some(&first_page) = page_ids.first() else {
+        return Ok(());
+    };
+    let [x0, y0, x1, y1] = pdf::media_box(document, first_page).unwrap_or([0.0, 0.0, 595.0, 842.0]);
+    let (width, height) = (x1 - x0, y1 - y0);
+
+    let mut forms = Vec::with_capacity(page_ids.len());
+    for &page_id in &page_ids {
+        forms.push(page_form(document, page_id)?);
+    }
+
+    let pages_id = document
+        .catalog()
+        .and_then(|catalog| catalog.get(b"Pages"))
+        .and_then(Object::as_reference)
+        .map_err(|e| format!("Failed to read page tree: {}", e))?;
+    let padded = page_ids.len().div_ceil(4) * 4;
+    let mut sheets = Vec::new();
+    for (left, right) in booklet_order(padded) {
+        let mut operations = Vec::new();
+        let mut xobjects = Dictionary::new();
+        for (slot, index) in [(0.0, left), (1.0, right)] {
+            let Some(&(form_id, [fx0, fy0, fx1, fy1])) = forms.get(index) else {
+                continue; // Blank padding page
+            };
+            // Fit the page into its half of the sheet, centered
+            let (form_width, form_height) = (fx1 - fx0, fy1 - fy0);
+            let scale = (width / form_width).min(height / form_height);
+            let x = slot * width + (width - form_width * scale) / 2.0 - fx0 * scale;
+            let y = (height - form_height * scale) / 2.0 - fy0 * scale;
+            let name = format!("Page{index}");
+            operations.extend([
+                Operation::new("q", vec![]),
+                Operation::new(
+                    "cm",
+                    vec![
+                        scale.into(),
+                        0.into(),
+                        0.into(),
+                        scale.into(),
+                        x.into(),
+                        y.into(),
+                    ],
+                ),
+                Operation::new("Do", vec![Object::Name(name.clone().into_bytes())]),
+                Operation::new("Q", vec![]),
+            ]);
+            xobjects.set(name, form_id);
+        }
+
+        let data = Content { operations }.encode().map_err(|e| e.to_string())?;
+        let content_id = document.add_object(Stream::new(Dictionary::new(), data));
+        sheets.push(document.add_object(dictionary! {
+            "Type" => "Page",
+            "Parent" => pages_id,
+            "MediaBox" => vec![0.into(), 0.into(), (width * 2.0).into(), height.into()],
+            "Resources" => dictionary! { "XObject" => xobjects },
+            "Contents" => content_id,
+        }));
+    }
+
+    for page_id in page_ids {
+        document.objects.remove(&page_id);
+    }
+    document.objects.insert(
+        pages_id,
+        Object::Dictionary(dictionary! {
+            "Type" => "Pages",
+            "Count" => sheets.len() as u32,
+            "Kids" => sheets.into_iter().map(Object::Reference).collect::<Vec<_>>(),
+        }),
+    );
+    let catalog = document.catalog_mut().map_err(|e| e.to_string())?;
+    catalog.remove(b"PageLabels");
+    catalog.remove(b"Outlines");
+    catalog.remove(b"PageMode");
+    document.prune_objects();
+    Ok(())
+}
+
+/// Turns the page into a form XObject that draws it, returning the form and its bounding box
+fn page_form(document: &mut Document, page_id: ObjectId) -> Result<(ObjectId, [f32; 4]), String> {
+    let bbox = pdf::media_box(document, page_id).unwrap_or([0.0, 0.0, 595.0, 842.0]);
+    // Separate the content streams, they may not end in whitespace
+    let mut content = Vec::new();
+    for stream_id in document.get_page_contents(page_id) {
+        if let Ok(stream) = document.get_object(stream_id).and_then(Object::as_stream) {
+            content.extend(
+                stream
+                    .decompressed_content()
+                    .unwrap_or_else(|_| stream.content.clone()),
+            );
+            content.push(b'\n');
+        }
+    }
+    let resources = pdf::inherited_attribute(document, page_id, b"Resources")
+        .unwrap_or_else(|| Object::Dictionary(Dictionary::new()));
+    let form = Stream::new(
+        dictionary! {
+            "Type" => "XObject",
+            "Subtype" => "Form",
+            "BBox" => bbox.iter().map(|&value| Object::Real(value)).collect::<Vec<_>>(),
+            "Resources" => resources,
+        },
+        content,
+    );
+    Ok((document.add_object(form), bbox))
+}
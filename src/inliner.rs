@@ -0,0 +1,801 @@
+use crate::convert;
+use std::collections::HashMap;
+use std::ops::Range;
+
+/// Properties left out of inline styles: they only matter for paged output or aren't
+/// understood by email clients
+const SKIPPED_PROPERTIES: &[&str] = &[
+    "page-break-before",
+    "page-break-after",
+    "page-break-inside",
+    "break-before",
+    "break-after",
+    "break-inside",
+    "orphans",
+    "widows",
+    "print-color-adjust",
+    "color-scheme",
+    "content",
+];
+
+/// Elements without content or end tag
+const VOID_ELEMENTS: &[&str] = &[
+    "area", "base", "br", "col", "embed", "hr", "img", "input", "link", "meta", "source", "track",
+    "wbr",
+];
+
+/// Elements whose content isn't markup
+const RAW_TEXT_ELEMENTS: &[&str] = &["script", "style", "textarea", "title"];
+
+/// How a compound selector relates to the one before it
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Combinator {
+    Descendant, // `a b`
+    Child,      // `a > b`
+    Adjacent,   // `a + b`
+    Sibling,    // `a ~ b`
+}
+
+/// Condition on an attribute, e.g. `[type="checkbox"]`
+#[derive(Debug, Clone)]
+struct AttributeTest {
+    name: String,
+    operator: String, // `=`, `~=`, `^=`, `$=`, `*=` or `|=`, empty for presence
+    value: String,
+}
+
+/// Pseudo-classes that depend on the document's structure only
+#[derive(Debug, Clone)]
+enum PseudoClass {
+    FirstChild,
+    LastChild,
+    NthChild(i64, i64), // Matches positions `a * n + b`
+    Not(Box<Compound>),
+}
+
+/// Selector of a single element, e.g. `td.numeric:first-child`
+#[derive(Debug, Clone, Default)]
+struct Compound {
+    tag: Option<String>, // None for any element
+    ids: Vec<String>,
+    classes: Vec<String>,
+    attributes: Vec<AttributeTest>,
+    pseudo_classes: Vec<PseudoClass>,
+}
+
+/// A rule's declarations for one of its selectors
+struct Rule {
+    parts: Vec<(Combinator, Compound)>, // The combinator of the first is ignored
+    specificity: (usize, usize, usize),
+    order: usize,
+    declarations: Vec<(String, String, bool)>, // Property, value and `!important`
+}
+
+/// An element of the HTML being styled
+struct Element {
+    name: String,
+    attributes: Vec<(String, String, Range<usize>)>, // Name, decoded value and source span
+    tag: Option<(Range<usize>, bool)>, // Span of the start tag and whether it closes itself
+    parent: Option<usize>,
+    children: Vec<usize>,
+}
+
+impl Element {
+    fn attribute(&self, name: &str) -> Option<&str> {
+        self.attributes
+            .iter()
+            .find(|(attribute, _, _)| attribute == name)
+            .map(|(_, value, _)| value.as_str())
+    }
+}
+
+/// Removes the `/* ... */` comments from `css`
+fn strip_comments(css: &str) -> String {
+    let mut out = String::with_capacity(css.len());
+    let mut rest = css;
+    while let Some(start) = rest.find("/*") {
+        out.push_str(&rest[..start]);
+        rest = rest[start + 2..]
+            .find("*/")
+            .map_or("", |end| &rest[start + 2 + end + 2..]);
+    }
+    out.push_str(rest);
+    out
+}
+
+/// Splits `text` at `separator` where it's outside quotes, parentheses and brackets
+fn split_top_level(text: &str, separator: char) -> Vec<&str> {
+    let mut parts = Vec::new();
+    let mut depth = 0usize;
+    let mut quote = None;
+    let mut start = 0;
+    for (i, c) in text.char_indices() {
+        match (quote, c) {
+            (Some(q), c) if c == q => quote = None,
+            (Some(_), _) => {}
+            (None, '"' | '\'') => quote = Some(c),
+            (None, '(' | '[') => depth += 1,
+            (None, ')' | ']') => depth = depth.saturating_sub(1),
+            (None, c) if c == separator && depth == 0 => {
+                parts.push(&text[start..i]);
+                start = i + c.len_utf8();
+            }
+            _ => {}
+        }
+    }
+    parts.push(&text[start..]);
+    parts
+}
+
+/// Offset just past the `}` closing the block that starts at the first `{` of `css`
+fn block_end(css: &str) -> usize {
+    let mut depth = 0usize;
+    let mut quote = None;
+    for (i, c) in css.char_indices() {
+        match (quote, c) {
+            (Some(q), c) if c == q => quote = None,
+            (Some(_), _) => {}
+            (None, '"' | '\'') => quote = Some(c),
+            (None, '{') => depth += 1,
+            (None, '}') => {
+                depth = depth.saturating_sub(1);
+                if depth == 0 {
+                    return i + 1;
+                }
+            }
+            _ => {}
+        }
+    }
+    css.len()
+}
+
+/// Parses the declarations of a rule or `style` attribute
+fn parse_declarations(block: &str) -> Vec<(String, String, bool)> {
+    split_top_level(block, ';')
+        .into_iter()
+        .filter_map(|declaration| {
+            let (property, value) = declaration.split_once(':')?;
+            // Custom properties are case-sensitive
+            let property = property.trim();
+            let property = if property.starts_with("--") {
+                property.to_string()
+            } else {
+                property.to_ascii_lowercase()
+            };
+            let mut value = value.trim();
+            let important = value.to_ascii_lowercase().ends_with("!important");
+            if important {
+                value = value[..value.len() - "!important".len()].trim_end();
+            }
+            (!property.is_empty() && !value.is_empty())
+                .then(|| (property, value.to_string(), important))
+        })
+        .collect()
+}
+
+fn is_ident_char(c: char) -> bool {
+    c.is_alphanumeric() || c == '-' || c == '_'
+}
+
+/// Reads an identifier at the start of `text`, returning it and the rest
+fn take_ident(text: &str) -> (&str, &str) {
+    let end = text.find(|c| !is_ident_char(c)).unwrap_or(text.len());
+    text.split_at(end)
+}
+
+/// Parses the `an+b` argument of `:nth-child`
+fn parse_nth(argument: &str) -> Option<(i64, i64)> {
+    let argument: String = argument
+        .chars()
+        .filter(|c| !c.is_whitespace())
+        .collect::<String>()
+        .to_ascii_lowercase();
+    match argument.as_str() {
+        "odd" => return Some((2, 1)),
+        "even" => return Some((2, 0)),
+        _ => {}
+    }
+    let Some((a, b)) = argument.split_once('n') else {
+        return Some((0, argument.parse().ok()?));
+    };
+    let a = match a {
+        "" | "+" => 1,
+        "-" => -1,
+        a => a.parse().ok()?,
+    };
+    let b = if b.is_empty() { 0 } else { b.parse().ok()? };
+    Some((a, b))
+}
+
+/// Parses a compound selector, None if it uses anything that can't be inlined, like
+/// `:hover` or `::before`
+fn parse_compound(text: &str) -> Option<Compound> {
+    let mut compound = Compound::default();
+    let mut rest = text;
+    if let Some(after) = rest.strip_prefix('*') {
+        rest = after;
+    } else {
+        let (tag, after) = take_ident(rest);
+        if !tag.is_empty() {
+            compound.tag = Some(tag.to_ascii_lowercase());
+            rest = after;
+        }
+    }
+    while let Some(c) = rest.chars().next() {
+        match c {
+            '.' | '#' => {
+                let (name, after) = take_ident(&rest[1..]);
+                if name.is_empty() {
+                    return None;
+                }
+                if c == '.' {
+                    compound.classes.push(name.to_string());
+                } else {
+                    compound.ids.push(name.to_string());
+                }
+                rest = after;
+            }
+            '[' => {
+                let end = rest.find(']')?;
+                let inner = &rest[1..end];
+                rest = &rest[end + 1..];
+                let operator_start = inner.find(['=', '~', '^', '$', '*', '|']);
+                let test = match operator_start {
+                    None => AttributeTest {
+                        name: inner.trim().to_ascii_lowercase(),
+                        operator: String::new(),
+                        value: String::new(),
+                    },
+                    Some(start) => {
+                        let equals = start + inner[start..].find('=')?;
+                        let value = inner[equals + 1..].trim();
+                        let value = value
+                            .strip_suffix(" i")
+                            .unwrap_or(value)
+                            .trim()
+                            .trim_matches(['"', '\'']);
+                        AttributeTest {
+                            name: inner[..start].trim().to_ascii_lowercase(),
+                            operator: inner[start..=equals].to_string(),
+                            value: value.to_string(),
+                        }
+                    }
+                };
+                compound.attributes.push(test);
+            }
+            ':' => {
+                let (name, after) = take_ident(&rest[1..]);
+                rest = after;
+                let pseudo = match name.to_ascii_lowercase().as_str() {
+                    "first-child" => PseudoClass::FirstChild,
+                    "last-child" => PseudoClass::LastChild,
+                    "nth-child" | "not" => {
+                        let end = rest.find(')')?;
+                        let argument = rest.strip_prefix('(')?.get(..end - 1)?;
+                        rest = &rest[end + 1..];
+                        if name.eq_ignore_ascii_case("not") {
+                            PseudoClass::Not(Box::new(parse_compound(argument.trim())?))
+                        } else {
+                            let (a, b) = parse_nth(argument)?;
+                            PseudoClass::NthChild(a, b)
+                        }
+                    }
+                    _ => return None,
+                };
+                compound.pseudo_classes.push(pseudo);
+            }
+            _ => return None,
+        }
+    }
+    Some(compound)
+}
+
+/// Parses a complex selector, e.g. `.markdown-body table tr:nth-child(2n)`
+fn parse_selector(text: &str) -> Option<Vec<(Combinator, Compound)>> {
+    // Split into compound selectors and combinators, outside brackets and arguments
+    let mut words = Vec::new();
+    let mut word = String::new();
+    let mut depth = 0usize;
+    let mut quote = None;
+    for c in text.chars() {
+        match (quote, c) {
+            (Some(q), c) => {
+                if c == q {
+                    quote = None;
+                }
+                word.push(c);
+            }
+            (None, '"' | '\'') => {
+                quote = Some(c);
+                word.push(c);
+            }
+            (None, '(' | '[') => {
+                depth += 1;
+                word.push(c);
+            }
+            (None, ')' | ']') => {
+                depth = depth.saturating_sub(1);
+                word.push(c);
+            }
+            (None, '>' | '+' | '~') if depth == 0 => {
+                words.push(std::mem::take(&mut word));
+                words.push(c.to_string());
+            }
+            (None, c) if c.is_whitespace() && depth == 0 => {
+                words.push(std::mem::take(&mut word));
+            }
+            (None, c) => word.push(c),
+        }
+    }
+    words.push(word);
+
+    let mut parts = Vec::new();
+    let mut combinator = Combinator::Descendant;
+    for word in words
+        .iter()
+        .map(String::as_str)
+        .filter(|word| !word.is_empty())
+    {
+        match word {
+            ">" => combinator = Combinator::Child,
+            "+" => combinator = Combinator::Adjacent,
+            "~" => combinator = Combinator::Sibling,
+            word => {
+                parts.push((combinator, parse_compound(word)?));
+                combinator = Combinator::Descendant;
+            }
+        }
+    }
+    (!parts.is_empty()).then_some(parts)
+}
+
+/// Counts of ids, classes and tags in `compound`, by which rules override each other
+fn compound_specificity(compound: &Compound) -> (usize, usize, usize) {
+    let mut specificity = (
+        compound.ids.len(),
+        compound.classes.len() + compound.attributes.len(),
+        usize::from(compound.tag.is_some()),
+    );
+    for pseudo in &compound.pseudo_classes {
+        match pseudo {
+            PseudoClass::Not(inner) => {
+                let (a, b, c) = compound_specificity(inner);
+                specificity = (specificity.0 + a, specificity.1 + b, specificity.2 + c);
+            }
+            _ => specificity.1 += 1,
+        }
+    }
+    specificity
+}
+
+/// Parses the rules of `css` outside at-rules, which are skipped along with their blocks.
+/// Custom properties are collected into `variables`.
+fn parse_rules(css: &str, variables: &mut HashMap<String, String>) -> Vec<Rule> {
+    let css = strip_comments(css);
+    let mut rules = Vec::new();
+    let mut rest = css.as_str();
+    let mut order = 0;
+    while let Some(open) = rest.find('{') {
+        // Statements like `@import url(...);` can come before the selector
+        let prelude = rest[..open].rsplit(';').next().unwrap_or_default().trim();
+        let end = open + block_end(&rest[open..]);
+        let block = &rest[open + 1..end];
+        let block = block.strip_suffix('}').unwrap_or(block);
+        rest = &rest[end..];
+        if prelude.starts_with('@') {
+            continue;
+        }
+        let declarations = parse_declarations(block);
+        for (property, value, _) in &declarations {
+            if property.starts_with("--") {
+                variables.insert(property.clone(), value.clone());
+            }
+        }
+        let declarations: Vec<_> = declarations
+            .into_iter()
+            .filter(|(property, _, _)| {
+                !property.starts_with('-') && !SKIPPED_PROPERTIES.contains(&property.as_str())
+            })
+            .collect();
+        if declarations.is_empty() {
+            continue;
+        }
+        for selector in split_top_level(prelude, ',') {
+            let Some(parts) = parse_selector(selector) else {
+                continue;
+            };
+            let specificity = parts.iter().fold((0, 0, 0), |total, (_, compound)| {
+                let (a, b, c) = compound_specificity(compound);
+                (total.0 + a, total.1 + b, total.2 + c)
+            });
+            rules.push(Rule {
+                parts,
+                specificity,
+                order,
+                declarations: declarations.clone(),
+            });
+            order += 1;
+        }
+    }
+    rules
+}
+
+/// Replaces the `var(--name, fallback)` references in `value` with the variables' values
+fn resolve_variables(value: &str, variables: &HashMap<String, String>, depth: usize) -> String {
+    let mut out = String::new();
+    let mut rest = value;
+    while let Some(start) = rest.find("var(") {
+        out.push_str(&rest[..start]);
+        let inner_start = start + "var(".len();
+        let mut depth_parens = 1usize;
+        let mut end = rest.len();
+        for (i, c) in rest[inner_start..].char_indices() {
+            match c {
+                '(' => depth_parens += 1,
+                ')' => {
+                    depth_parens -= 1;
+                    if depth_parens == 0 {
+                        end = inner_start + i;
+                        break;
+                    }
+                }
+                _ => {}
+            }
+        }
+        let inner = &rest[inner_start..end];
+        let (name, fallback) = inner.split_once(',').unwrap_or((inner, ""));
+        let replacement = variables
+            .get(name.trim())
+            .map_or(fallback.trim(), String::as_str);
+        if depth < 8 {
+            out.push_str(&resolve_variables(replacement, variables, depth + 1));
+        }
+        rest = rest.get(end + 1..).unwrap_or_default();
+    }
+    out.push_str(rest);
+    out
+}
+
+/// Decodes the character references attribute values commonly hold
+fn decode_attribute(value: &str) -> String {
+    value
+        .replace("&quot;", "\"")
+        .replace("&#39;", "'")
+        .replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&amp;", "&")
+}
+
+/// Parses the elements of `html`, below a root element standing for the `<body>`
+fn parse_elements(html: &str, root: Element) -> Vec<Element> {
+    let mut elements = vec![root];
+    let mut open = vec![0]; // Elements not closed yet, innermost last
+    let bytes = html.as_bytes();
+    let mut i = 0;
+    while let Some(offset) = html[i..].find('<') {
+        let start = i + offset;
+        let rest = &html[start..];
+        if rest.starts_with("<!--") {
+            i = rest.find("-->").map_or(html.len(), |end| start + end + 3);
+        } else if rest.starts_with("<!") || rest.starts_with("<?") {
+            i = rest.find('>').map_or(html.len(), |end| start + end + 1);
+        } else if let Some(after) = rest.strip_prefix("</") {
+            let (name, _) = take_ident(after);
+            let name = name.to_ascii_lowercase();
+            if let Some(position) = open.iter().rposition(|&index| elements[index].name == name)
+                && position > 0
+            {
+                open.truncate(position);
+            }
+            i = rest.find('>').map_or(html.len(), |end| start + end + 1);
+        } else if rest[1..].starts_with(|c: char| c.is_ascii_alphabetic()) {
+            let (name, _) = take_ident(&rest[1..]);
+            let name = name.to_ascii_lowercase();
+            let mut position = start + 1 + name.len();
+            let mut attributes = Vec::new();
+            let mut self_closing = false;
+            loop {
+                while position < html.len() && bytes[position].is_ascii_whitespace() {
+                    position += 1;
+                }
+                if position >= html.len() {
+                    break;
+                }
+                if html[position..].starts_with("/>") {
+                    self_closing = true;
+                    position += 2;
+                    break;
+                }
+                if bytes[position] == b'>' {
+                    position += 1;
+                    break;
+                }
+                if bytes[position] == b'/' {
+                    position += 1;
+                    continue;
+                }
+                let attribute_start = position;
+                let name_end = html[position..]
+                    .find(|c: char| c.is_whitespace() || matches!(c, '=' | '>' | '/'))
+                    .map_or(html.len(), |end| position + end);
+                let attribute = html[position..name_end].to_ascii_lowercase();
+                position = name_end;
+                let mut value = String::new();
+                if html[position..].starts_with('=') {
+                    position += 1;
+                    match bytes.get(position) {
+                        Some(&quote @ (b'"' | b'\'')) => {
+                            let value_end = html[position + 1..]
+                                .find(quote as char)
+                                .map_or(html.len(), |end| position + 1 + end);
+                            value = decode_attribute(&html[position + 1..value_end]);
+                            position = (value_end + 1).min(html.len());
+                        }
+                        _ => {
+                            let value_end = html[position..]
+                                .find(|c: char| c.is_whitespace() || c == '>')
+                                .map_or(html.len(), |end| position + end);
+                            value = decode_attribute(&html[position..value_end]);
+                            position = value_end;
+                        }
+                    }
+                }
+                if attribute.is_empty() {
+                    position += 1;
+                    continue;
+                }
+                attributes.push((attribute, value, attribute_start..position));
+            }
+            let parent = *open.last().unwrap_or(&0);
+            let index = elements.len();
+            let void = self_closing || VOID_ELEMENTS.contains(&name.as_str());
+            elements.push(Element {
+                name: name.clone(),
+                attributes,
+                tag: Some((start..position, self_closing)),
+                parent: Some(parent),
+                children: Vec::new(),
+            });
+            elements[parent].children.push(index);
+            i = position;
+            if RAW_TEXT_ELEMENTS.contains(&name.as_str()) {
+                let closing = format!("</{}", name);
+                i = html[i..]
+                    .to_ascii_lowercase()
+                    .find(&closing)
+                    .map_or(html.len(), |end| i + end);
+            } else if !void {
+                open.push(index);
+            }
+        } else {
+            i = start + 1;
+        }
+    }
+    elements
+}
+
+/// Position of `index` among its parent's children, from 1, and their number
+fn position(elements: &[Element], index: usize) -> (usize, usize) {
+    elements[index]
+        .parent
+        .map(|parent| {
+            let siblings = &elements[parent].children;
+            let position = siblings.iter().position(|&i| i == index).unwrap_or(0);
+            (position + 1, siblings.len())
+        })
+        .unwrap_or((1, 1))
+}
+
+fn attribute_matches(element: &Element, test: &AttributeTest) -> bool {
+    let Some(value) = element.attribute(&test.name) else {
+        return false;
+    };
+    let expected = test.value.as_str();
+    match test.operator.as_str() {
+        "" => true,
+        "=" => value == expected,
+        "~=" => value.split_whitespace().any(|word| word == expected),
+        "^=" => !expected.is_empty() && value.starts_with(expected),
+        "$=" => !expected.is_empty() && value.ends_with(expected),
+        "*=" => !expected.is_empty() && value.contains(expected),
+        "|=" => value == expected || value.starts_with(&format!("{}-", expected)),
+        _ => false,
+    }
+}
+
+fn compound_matches(elements: &[Element], index: usize, compound: &Compound) -> bool {
+    let element = &elements[index];
+    if compound
+        .tag
+        .as_ref()
+        .is_some_and(|tag| *tag != element.name)
+    {
+        return false;
+    }
+    if !compound
+        .ids
+        .iter()
+        .all(|id| element.attribute("id") == Some(id))
+    {
+        return false;
+    }
+    let classes = element.attribute("class").unwrap_or_default();
+    if !compound
+        .classes
+        .iter()
+        .all(|class| classes.split_whitespace().any(|c| c == class))
+    {
+        return false;
+    }
+    if !compound
+        .attributes
+        .iter()
+        .all(|test| attribute_matches(element, test))
+    {
+        return false;
+    }
+    compound.pseudo_classes.iter().all(|pseudo| {
+        let (position, count) = position(elements, index);
+        match pseudo {
+            PseudoClass::FirstChild => position == 1,
+            PseudoClass::LastChild => position == count,
+            PseudoClass::NthChild(a, b) => {
+                let offset = position as i64 - b;
+                match a {
+                    0 => offset == 0,
+                    a => offset % a == 0 && offset / a >= 0,
+                }
+            }
+            PseudoClass::Not(inner) => !compound_matches(elements, index, inner),
+        }
+    })
+}
+
+/// Whether the element at `index` matches the selector `parts[..=last]`
+fn selector_matches(
+    elements: &[Element],
+    index: usize,
+    parts: &[(Combinator, Compound)],
+    last: usize,
+) -> bool {
+    let (combinator, compound) = &parts[last];
+    if !compound_matches(elements, index, compound) {
+        return false;
+    }
+    if last == 0 {
+        return true;
+    }
+    let previous_siblings = || {
+        elements[index]
+            .parent
+            .map(|parent| {
+                let siblings = &elements[parent].children;
+                let position = siblings.iter().position(|&i| i == index).unwrap_or(0);
+                siblings[..position].to_vec()
+            })
+            .unwrap_or_default()
+    };
+    match combinator {
+        Combinator::Child => elements[index]
+            .parent
+            .is_some_and(|parent| selector_matches(elements, parent, parts, last - 1)),
+        Combinator::Descendant => {
+            let mut ancestor = elements[index].parent;
+            while let Some(current) = ancestor {
+                if selector_matches(elements, current, parts, last - 1) {
+                    return true;
+                }
+                ancestor = elements[current].parent;
+            }
+            false
+        }
+        Combinator::Adjacent => previous_siblings()
+            .last()
+            .is_some_and(|&sibling| selector_matches(elements, sibling, parts, last - 1)),
+        Combinator::Sibling => previous_siblings()
+            .iter()
+            .any(|&sibling| selector_matches(elements, sibling, parts, last - 1)),
+    }
+}
+
+/// The declarations of `rules` that apply to the element at `index` in cascade order, the
+/// winning value of each property last, followed by its own `style` attribute
+fn computed_style(elements: &[Element], index: usize, rules: &[Rule]) -> Vec<(String, String)> {
+    let mut matched: Vec<(&Rule, &(String, String, bool))> = rules
+        .iter()
+        .filter(|rule| selector_matches(elements, index, &rule.parts, rule.parts.len() - 1))
+        .flat_map(|rule| {
+            rule.declarations
+                .iter()
+                .map(move |declaration| (rule, declaration))
+        })
+        .collect();
+    matched.sort_by_key(|(rule, (_, _, important))| (*important, rule.specificity, rule.order));
+    let mut style: Vec<(String, String)> = Vec::new();
+    let inline = elements[index]
+        .attribute("style")
+        .map(parse_declarations)
+        .unwrap_or_default();
+    let declarations = matched
+        .into_iter()
+        .map(|(_, (property, value, _))| (property.clone(), value.clone()))
+        .chain(
+            inline
+                .into_iter()
+                .map(|(property, value, _)| (property, value)),
+        );
+    for (property, value) in declarations {
+        match style.iter_mut().find(|(existing, _)| *existing == property) {
+            Some(entry) => entry.1 = value,
+            None => style.push((property, value)),
+        }
+    }
+    style
+}
+
+/// Applies `css` to the HTML `body` as `style` attributes, for places that ignore style
+/// sheets like email clients. Returns the body wrapped in a `div` with `root_attributes`
+/// standing for the `<body>`, e.g. `class="markdown-body"`. Only selectors that don't
+/// depend on state or pseudo-elements are applied, and at-rules such as `@media` are left
+/// out.
+pub fn inline(body: &str, css: &str, root_attributes: &[(&str, &str)]) -> String {
+    let mut variables = HashMap::new();
+    let rules = parse_rules(css, &mut variables);
+    let root = Element {
+        name: "body".to_string(),
+        attributes: root_attributes
+            .iter()
+            .map(|(name, value)| (name.to_string(), value.to_string(), 0..0))
+            .collect(),
+        tag: None,
+        parent: None,
+        children: Vec::new(),
+    };
+    let elements = parse_elements(body, root);
+    let style_attribute = |index: usize| {
+        let style: Vec<String> = computed_style(&elements, index, &rules)
+            .into_iter()
+            .filter_map(|(property, value)| {
+                let value = resolve_variables(&value, &variables, 0);
+                (!value.trim().is_empty() && !value.contains("var("))
+                    .then(|| format!("{}: {}", property, value))
+            })
+            .collect();
+        style.join("; ")
+    };
+
+    let mut out = String::from("<div");
+    for (name, value) in root_attributes {
+        out.push_str(&format!(" {}=\"{}\"", name, convert::escape_attr(value)));
+    }
+    let root_style = style_attribute(0);
+    if !root_style.is_empty() {
+        out.push_str(&format!(" style=\"{}\"", convert::escape_attr(&root_style)));
+    }
+    out.push('>');
+    let mut copied = 0;
+    for (index, element) in elements.iter().enumerate().skip(1) {
+        let Some((span, self_closing)) = &element.tag else {
+            continue;
+        };
+        let style = style_attribute(index);
+        if style.is_empty() {
+            continue;
+        }
+        out.push_str(&body[copied..span.start]);
+        // The name as written, SVG's are case-sensitive
+        out.push_str(&body[span.start..span.start + 1 + element.name.len()]);
+        for (name, _, source) in &element.attributes {
+            if name != "style" {
+                out.push(' ');
+                out.push_str(&body[source.clone()]);
+            }
+        }
+        out.push_str(&format!(" style=\"{}\"", convert::escape_attr(&style)));
+        out.push_str(if *self_closing { " />" } else { ">" });
+        copied = span.end;
+    }
+    out.push_str(&body[copied..]);
+    out.push_str("</div>");
+    out
+}
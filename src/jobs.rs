@@ -0,0 +1,155 @@
+use crate::convert;
+use crate::settings::Settings;
+use std::path::PathBuf;
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Where a job is in the queue
+#[derive(Debug, Clone, PartialEq)]
+pub enum State {
+    Queued,
+    Running,
+    Done(Vec<String>), // Warnings of the conversion
+    Failed(String),
+    Cancelled,
+}
+
+impl State {
+    /// Returns the display name for the state
+    pub fn name(&self) -> &'static str {
+        match self {
+            State::Queued => "Queued",
+            State::Running => "Converting",
+            State::Done(warnings) if warnings.is_empty() => "Done",
+            State::Done(_) => "Done, with warnings",
+            State::Failed(_) => "Failed",
+            State::Cancelled => "Cancelled",
+        }
+    }
+
+    /// Whether the job has stopped, one way or another
+    pub fn is_finished(&self) -> bool {
+        !matches!(self, State::Queued | State::Running)
+    }
+}
+
+/// A conversion waiting in or run by the queue
+pub struct Job {
+    id: u64,
+    pub input: PathBuf,
+    pub output: PathBuf,
+    pub state: State,
+    settings: Settings, // As they were when the job was added
+    started: Option<Instant>,
+    duration: Option<Duration>, // Set once the job has finished
+}
+
+impl Job {
+    /// How long the job ran, or has been running
+    pub fn duration(&self) -> Option<Duration> {
+        self.duration
+            .or_else(|| self.started.map(|started| started.elapsed()))
+    }
+}
+
+/// Result of a job, sent back by the thread converting it along with the job's id
+type Finished = (u64, Result<Vec<String>, String>);
+
+/// Conversions run one after the other on a background thread, in the order they were added
+pub struct Queue {
+    pub jobs: Vec<Job>,
+    channel: (Sender<Finished>, Receiver<Finished>),
+    running: bool,
+    next_id: u64,
+}
+
+impl Default for Queue {
+    fn default() -> Self {
+        Self {
+            jobs: Vec::new(),
+            channel: mpsc::channel(),
+            running: false,
+            next_id: 0,
+        }
+    }
+}
+
+impl Queue {
+    /// Queues converting `input` to `output` with `settings`
+    pub fn add(&mut self, input: PathBuf, output: PathBuf, settings: &Settings) {
+        self.next_id += 1;
+        self.jobs.push(Job {
+            id: self.next_id,
+            input,
+            output,
+            state: State::Queued,
+            settings: settings.clone(),
+            started: None,
+            duration: None,
+        });
+    }
+
+    /// Takes the result of the running job, and starts the next queued one. Returns the
+    /// indices of the jobs that finished since the last call.
+    pub fn poll(&mut self) -> Vec<usize> {
+        let mut finished = Vec::new();
+        while let Ok((id, result)) = self.channel.1.try_recv() {
+            self.running = false;
+            let Some(index) = self.jobs.iter().position(|job| job.id == id) else {
+                continue;
+            };
+            finished.push(index);
+            let job = &mut self.jobs[index];
+            job.duration = job.started.map(|started| started.elapsed());
+            job.state = match result {
+                Ok(warnings) => State::Done(warnings),
+                Err(message) => State::Failed(message),
+            };
+        }
+        if self.running {
+            return finished;
+        }
+        let Some(job) = self.jobs.iter_mut().find(|job| job.state == State::Queued) else {
+            return finished;
+        };
+        job.state = State::Running;
+        job.started = Some(Instant::now());
+        self.running = true;
+        let id = job.id;
+        let (settings, input, output) =
+            (job.settings.clone(), job.input.clone(), job.output.clone());
+        let sender = self.channel.0.clone();
+        thread::spawn(move || {
+            let _ = sender.send((id, convert::convert_file(&settings, &input, &output)));
+        });
+        finished
+    }
+
+    /// Whether a job is queued or being converted
+    pub fn is_busy(&self) -> bool {
+        self.jobs.iter().any(|job| !job.state.is_finished())
+    }
+
+    /// Takes a queued job off the queue. A job already being converted runs to the end.
+    pub fn cancel(&mut self, index: usize) {
+        if self.jobs[index].state == State::Queued {
+            self.jobs[index].state = State::Cancelled;
+        }
+    }
+
+    /// Queues a failed or cancelled job again
+    pub fn retry(&mut self, index: usize) {
+        let job = &mut self.jobs[index];
+        if matches!(job.state, State::Failed(_) | State::Cancelled) {
+            job.state = State::Queued;
+            job.started = None;
+            job.duration = None;
+        }
+    }
+
+    /// Removes the finished jobs from the list
+    pub fn clear_finished(&mut self) {
+        self.jobs.retain(|job| !job.state.is_finished());
+    }
+}
@@ -0,0 +1,459 @@
+use crate::directives;
+use crate::front_matter::FrontMatter;
+use crate::numbering::{self, NumberStyle};
+use pulldown_cmark::{Event, HeadingLevel, Tag, TagEnd};
+
+/// Enum to represent the text direction of the generated document
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Direction {
+    Auto,
+    LeftToRight,
+    RightToLeft,
+}
+
+/// Language codes (ISO 639) that are written right-to-left
+const RTL_LANGUAGES: &[&str] = &[
+    "ar", "arc", "ckb", "dv", "fa", "he", "iw", "ps", "sd", "ug", "ur", "yi",
+];
+
+impl Direction {
+    /// Returns the display name for the direction
+    pub fn name(&self) -> &'static str {
+        match self {
+            Direction::Auto => "Auto-detect",
+            Direction::LeftToRight => "Left to right",
+            Direction::RightToLeft => "Right to left",
+        }
+    }
+
+    /// Returns all available directions
+    pub fn all() -> &'static [Direction] {
+        &[
+            Direction::Auto,
+            Direction::LeftToRight,
+            Direction::RightToLeft,
+        ]
+    }
+
+    /// Identifier used in saved settings
+    pub fn keyword(&self) -> &'static str {
+        match self {
+            Direction::Auto => "auto",
+            Direction::LeftToRight => "ltr",
+            Direction::RightToLeft => "rtl",
+        }
+    }
+
+    /// Resolves `Auto` to a concrete direction. Front matter `dir` wins, then the script of
+    /// the front matter `lang`, and finally whichever script dominates the text itself.
+    pub fn resolve(self, front_matter: &FrontMatter, markdown: &str) -> Direction {
+        if self != Direction::Auto {
+            return self;
+        }
+
+        match front_matter.get("dir").map(str::to_lowercase).as_deref() {
+            Some("rtl") => return Direction::RightToLeft,
+            Some("ltr") => return Direction::LeftToRight,
+            _ => {}
+        }
+
+        if let Some(lang) = front_matter.get("lang") {
+            let primary = lang
+                .split(['-', '_'])
+                .next()
+                .unwrap_or_default()
+                .to_lowercase();
+            return if RTL_LANGUAGES.contains(&primary.as_str()) {
+                Direction::RightToLeft
+            } else {
+                Direction::LeftToRight
+            };
+        }
+
+        let (mut rtl, mut ltr) = (0usize, 0usize);
+        for c in markdown.chars().filter(|c| c.is_alphabetic()) {
+            if is_rtl_char(c) {
+                rtl += 1;
+            } else {
+                ltr += 1;
+            }
+        }
+        if rtl > ltr {
+            Direction::RightToLeft
+        } else {
+            Direction::LeftToRight
+        }
+    }
+
+    /// Value for the HTML `dir` attribute. Only meaningful once resolved.
+    pub fn html_attr(&self) -> &'static str {
+        match self {
+            Direction::RightToLeft => "rtl",
+            _ => "ltr",
+        }
+    }
+}
+
+/// Hebrew, Arabic, Syriac, Thaana, N'Ko and the Hebrew/Arabic presentation forms
+fn is_rtl_char(c: char) -> bool {
+    matches!(c,
+        '\u{0590}'..='\u{08FF}'
+        | '\u{FB1D}'..='\u{FDFF}'
+        | '\u{FE70}'..='\u{FEFF}')
+}
+
+/// Mirrors the GitHub stylesheets' left-hand list indents and quote borders for RTL documents.
+/// `border_color` should match the active theme's blockquote border.
+pub fn rtl_css(border_color: &str) -> String {
+    format!(
+        r#"html[dir="rtl"] .markdown-body ul,
+html[dir="rtl"] .markdown-body ol {{
+  padding-left: 0;
+  padding-right: 2em;
+}}
+
+html[dir="rtl"] .markdown-body blockquote,
+html[dir="rtl"] .markdown-body .markdown-alert {{
+  border-left: none;
+  border-right: .25em solid {border_color};
+}}
+
+html[dir="rtl"] .markdown-body .task-list-item input {{
+  margin: 0 -1.6em .25em .2em;
+}}
+"#
+    )
+}
+
+/// Maximum number of text columns supported by the layout option
+pub const MAX_COLUMNS: u8 = 3;
+
+/// Column rules for the `md-columns-N` wrappers created by [`apply_columns`]
+pub const COLUMNS_CSS: &str = r#".markdown-body .md-columns-2,
+.markdown-body .md-columns-3 {
+  -webkit-column-gap: 2em;
+  column-gap: 2em;
+}
+
+.markdown-body .md-columns-2 {
+  -webkit-column-count: 2;
+  column-count: 2;
+}
+
+.markdown-body .md-columns-3 {
+  -webkit-column-count: 3;
+  column-count: 3;
+}
+
+.markdown-body .md-columns-2 h1,
+.markdown-body .md-columns-3 h1 {
+  -webkit-column-span: all;
+  column-span: all;
+}
+
+.markdown-body .md-columns-2 pre,
+.markdown-body .md-columns-2 table,
+.markdown-body .md-columns-2 blockquote,
+.markdown-body .md-columns-3 pre,
+.markdown-body .md-columns-3 table,
+.markdown-body .md-columns-3 blockquote {
+  -webkit-column-break-inside: avoid;
+  break-inside: avoid;
+}
+"#;
+
+/// Wraps the document in column containers. The body starts with `default_columns` columns
+/// and every top-level `<!-- columns: N -->` directive switches the count from there on.
+pub fn apply_columns(events: Vec<Event<'_>>, default_columns: u8) -> Vec<Event<'_>> {
+    let mut out = Vec::with_capacity(events.len() + 2);
+    let mut open = false;
+    if default_columns > 1 {
+        out.push(open_columns(default_columns));
+        open = true;
+    }
+
+    let mut depth = 0usize;
+    for event in events {
+        match &event {
+            Event::Start(Tag::HtmlBlock) | Event::End(TagEnd::HtmlBlock) => {}
+            Event::Start(_) => depth += 1,
+            Event::End(_) => depth = depth.saturating_sub(1),
+            Event::Html(html) if depth == 0 => {
+                if let Some((name, value)) = directives::parse(html)
+                    && name == "columns"
+                    && let Ok(count) = value.parse::<u8>()
+                {
+                    let count = count.clamp(1, MAX_COLUMNS);
+                    if open {
+                        out.push(Event::Html("</div>\n".into()));
+                    }
+                    out.push(open_columns(count));
+                    open = true;
+                    continue;
+                }
+            }
+            _ => {}
+        }
+        out.push(event);
+    }
+
+    if open {
+        out.push(Event::Html("</div>\n".into()));
+    }
+    out
+}
+
+fn open_columns(count: u8) -> Event<'static> {
+    Event::Html(format!("<div class=\"md-columns-{count}\">\n").into())
+}
+
+/// Code blocks with lines longer than this are considered too wide for a portrait page
+const WIDE_CODE_LINE_CHARS: usize = 100;
+/// Tables with more columns than this are considered too wide for a portrait page
+const WIDE_TABLE_COLUMNS: usize = 6;
+
+/// Markers placed around blocks that go on their own landscape page
+const LANDSCAPE_START: &str = "<!--md-to-pdf:landscape-start-->";
+const LANDSCAPE_END: &str = "<!--md-to-pdf:landscape-end-->";
+/// Marker left by [`mark_chapters`] before each chapter heading
+const CHAPTER_START: &str = "<!--md-to-pdf:chapter-start-->";
+
+/// Class of the elements [`paged_html`] wraps landscape blocks in
+pub const LANDSCAPE_CLASS: &str = "md-landscape";
+/// Class of the elements [`paged_html`] puts before chapters
+pub const CHAPTER_CLASS: &str = "md-chapter";
+
+/// Returns true for the marker [`mark_landscape_blocks`] puts before a landscape block
+pub fn is_landscape_start(html: &str) -> bool {
+    html == LANDSCAPE_START
+}
+
+/// Returns true for the marker [`mark_landscape_blocks`] puts after a landscape block
+pub fn is_landscape_end(html: &str) -> bool {
+    html == LANDSCAPE_END
+}
+
+/// A slice of the document rendered on its own pages with a single page orientation
+pub struct Section {
+    pub landscape: bool,
+    /// Page numbering restarting with this section, if any
+    pub numbering: Option<NumberStyle>,
+    /// The section starts a chapter and has to begin on a right-hand (odd) page
+    pub recto: bool,
+    pub html: String,
+}
+
+/// Marks top-level tables and code blocks that should be placed on landscape pages:
+/// those preceded by `<!-- landscape: on -->` and, when `auto` is set, any that are too
+/// wide for a portrait page (unless preceded by `<!-- landscape: off -->`).
+pub fn mark_landscape_blocks(events: Vec<Event<'_>>, auto: bool) -> Vec<Event<'_>> {
+    let mut out = Vec::with_capacity(events.len());
+    let mut depth = 0usize;
+    let mut directive: Option<bool> = None;
+    let mut landscape_block = false;
+
+    for (i, event) in events.iter().enumerate() {
+        match event {
+            Event::Start(Tag::HtmlBlock) | Event::End(TagEnd::HtmlBlock) => {}
+            Event::Html(html) if depth == 0 => {
+                if let Some((name, value)) = directives::parse(html)
+                    && name == "landscape"
+                {
+                    directive = Some(matches!(value.as_str(), "on" | "true" | "yes"));
+                    continue;
+                }
+            }
+            Event::Start(tag) => {
+                if depth == 0 {
+                    let wide = match tag {
+                        Tag::Table(alignments) => alignments.len() > WIDE_TABLE_COLUMNS,
+                        Tag::CodeBlock(_) => code_block_is_wide(&events[i + 1..]),
+                        _ => false,
+                    };
+                    let is_block = matches!(tag, Tag::Table(_) | Tag::CodeBlock(_));
+                    if is_block && directive.unwrap_or(auto && wide) {
+                        out.push(Event::Html(LANDSCAPE_START.into()));
+                        landscape_block = true;
+                    }
+                    directive = None;
+                }
+                depth += 1;
+            }
+            Event::End(_) => {
+                depth = depth.saturating_sub(1);
+                if depth == 0 && landscape_block {
+                    out.push(event.clone());
+                    out.push(Event::Html(LANDSCAPE_END.into()));
+                    landscape_block = false;
+                    continue;
+                }
+            }
+            _ => {}
+        }
+        out.push(event.clone());
+    }
+    out
+}
+
+/// Marks each top-level `#` heading as the start of a chapter, so that [`split_sections`]
+/// can start it on a page of its own
+pub fn mark_chapters(events: Vec<Event<'_>>) -> Vec<Event<'_>> {
+    let mut out = Vec::with_capacity(events.len());
+    let mut depth = 0usize;
+    for event in events {
+        match &event {
+            Event::Start(Tag::HtmlBlock) | Event::End(TagEnd::HtmlBlock) => {}
+            Event::Start(tag) => {
+                if depth == 0
+                    && let Tag::Heading {
+                        level: HeadingLevel::H1,
+                        ..
+                    } = tag
+                {
+                    out.push(Event::Html(CHAPTER_START.into()));
+                }
+                depth += 1;
+            }
+            Event::End(_) => depth = depth.saturating_sub(1),
+            _ => {}
+        }
+        out.push(event);
+    }
+    out
+}
+
+/// Kinds of section breaks found by [`split_sections`]
+enum Break {
+    Landscape,
+    Numbering(usize, NumberStyle), // End of the marker and the style it restarts with
+    Chapter,
+}
+
+/// Checks the text of the code block starting at `events` for overly long lines
+fn code_block_is_wide(events: &[Event<'_>]) -> bool {
+    events
+        .iter()
+        .take_while(|event| !matches!(event, Event::End(TagEnd::CodeBlock)))
+        .any(|event| match event {
+            Event::Text(text) => text
+                .lines()
+                .any(|line| line.chars().count() > WIDE_CODE_LINE_CHARS),
+            _ => false,
+        })
+}
+
+/// Splits a complete HTML document at the markers left by [`mark_landscape_blocks`],
+/// [`numbering::mark_sections`] and [`mark_chapters`] into standalone documents, each
+/// rendered on its own pages with a single page orientation.
+pub fn split_sections(full_html: &str) -> Vec<Section> {
+    let body_start = full_html
+        .find("<body")
+        .and_then(|start| full_html[start..].find('>').map(|end| start + end + 1));
+    let body_end = full_html.rfind("</body>");
+    let (Some(body_start), Some(body_end)) = (body_start, body_end) else {
+        return vec![Section {
+            landscape: false,
+            numbering: None,
+            recto: false,
+            html: full_html.to_string(),
+        }];
+    };
+    let (head, body, tail) = (
+        &full_html[..body_start],
+        &full_html[body_start..body_end],
+        &full_html[body_end..],
+    );
+
+    let mut sections = Vec::new();
+    let mut restart = None; // Numbering restart for the next non-empty section
+    let mut recto = false; // The next non-empty section starts a chapter
+    let push = |sections: &mut Vec<Section>,
+                restart: &mut Option<NumberStyle>,
+                recto: &mut bool,
+                landscape: bool,
+                html: &str| {
+        if landscape || !html.trim().is_empty() {
+            sections.push(Section {
+                landscape,
+                numbering: restart.take(),
+                recto: std::mem::take(recto),
+                html: format!("{head}{html}{tail}"),
+            });
+        }
+    };
+
+    let mut rest = body;
+    loop {
+        let next = [
+            rest.find(LANDSCAPE_START)
+                .map(|start| (start, Break::Landscape)),
+            numbering::find_marker(rest)
+                .map(|(start, end, style)| (start, Break::Numbering(end, style))),
+            rest.find(CHAPTER_START)
+                .map(|start| (start, Break::Chapter)),
+        ]
+        .into_iter()
+        .flatten()
+        .min_by_key(|(start, _)| *start);
+        let Some((start, kind)) = next else {
+            break;
+        };
+
+        push(
+            &mut sections,
+            &mut restart,
+            &mut recto,
+            false,
+            &rest[..start],
+        );
+        match kind {
+            Break::Landscape => {
+                let after = &rest[start + LANDSCAPE_START.len()..];
+                let end = after.find(LANDSCAPE_END).unwrap_or(after.len());
+                push(&mut sections, &mut restart, &mut recto, true, &after[..end]);
+                rest = after.get(end + LANDSCAPE_END.len()..).unwrap_or_default();
+            }
+            Break::Numbering(end, style) => {
+                restart = Some(style);
+                rest = &rest[end..];
+            }
+            Break::Chapter => {
+                recto = true;
+                rest = &rest[start + CHAPTER_START.len()..];
+            }
+        }
+    }
+    push(&mut sections, &mut restart, &mut recto, false, rest);
+    if sections.is_empty() {
+        sections.push(Section {
+            landscape: false,
+            numbering: None,
+            recto: false,
+            html: format!("{head}{rest}{tail}"),
+        });
+    }
+    sections
+}
+
+/// Turns the markers [`split_sections`] splits at into elements CSS paged media lays out,
+/// for renderers that take the document in one pass: landscape blocks are wrapped in a
+/// [`LANDSCAPE_CLASS`] element and chapters get a [`CHAPTER_CLASS`] element before them.
+/// Numbering restarts are removed, returns whether there were any.
+pub fn paged_html(full_html: &str) -> (String, bool) {
+    let mut html = full_html
+        .replace(
+            LANDSCAPE_START,
+            &format!("<div class=\"{LANDSCAPE_CLASS}\">"),
+        )
+        .replace(LANDSCAPE_END, "</div>")
+        .replace(
+            CHAPTER_START,
+            &format!("<div class=\"{CHAPTER_CLASS}\"></div>"),
+        );
+    let mut restarts = false;
+    while let Some((start, end, _)) = numbering::find_marker(&html) {
+        html.replace_range(start..end, "");
+        restarts = true;
+    }
+    (html, restarts)
+}
@@ -0,0 +1,172 @@
+use crate::convert::escape_attr;
+use crate::images;
+use pulldown_cmark::{Event, Tag, TagEnd};
+use url::Url;
+
+/// Scheme that marks links to other documents' PDFs until the PDF is written, when they
+/// become links to the files. wkhtmltopdf would resolve plain relative links against the
+/// temporary HTML file.
+pub const FILE_LINK_SCHEME: &str = "md-to-pdf-file:";
+
+/// How links appear in the PDF, which matters for documents read on paper
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum LinkStyle {
+    Theme,         // As the theme styles them
+    Underlined,    // Colored and underlined
+    Plain,         // Like the surrounding text
+    Parenthesized, // URL written in parentheses after the link text
+    Numbered,      // Numbered references to a list of URLs at the end of the document
+}
+
+impl LinkStyle {
+    /// Returns the display name for the link style
+    pub fn name(&self) -> &'static str {
+        match self {
+            LinkStyle::Theme => "As in the theme",
+            LinkStyle::Underlined => "Colored and underlined",
+            LinkStyle::Plain => "Plain text",
+            LinkStyle::Parenthesized => "URL in parentheses",
+            LinkStyle::Numbered => "Numbered URL list",
+        }
+    }
+
+    /// Returns all available link styles
+    pub fn all() -> &'static [LinkStyle] {
+        &[
+            LinkStyle::Theme,
+            LinkStyle::Underlined,
+            LinkStyle::Plain,
+            LinkStyle::Parenthesized,
+            LinkStyle::Numbered,
+        ]
+    }
+
+    /// Identifier used in style profiles
+    pub fn keyword(&self) -> &'static str {
+        match self {
+            LinkStyle::Theme => "theme",
+            LinkStyle::Underlined => "underlined",
+            LinkStyle::Plain => "plain",
+            LinkStyle::Parenthesized => "parenthesized",
+            LinkStyle::Numbered => "numbered",
+        }
+    }
+
+    /// Returns the CSS for the link style, layered after the theme and style options
+    pub fn css(&self) -> &'static str {
+        match self {
+            LinkStyle::Theme => "",
+            LinkStyle::Underlined => ".markdown-body a {\n  text-decoration: underline;\n}\n",
+            LinkStyle::Plain => {
+                ".markdown-body a {\n  color: inherit;\n  text-decoration: none;\n}\n"
+            }
+            LinkStyle::Parenthesized => concat!(
+                ".markdown-body a[href]:not([href^=\"#\"]):not([href^=\"md-to-pdf-file:\"]):after {\n",
+                "  content: \" (\" attr(href) \")\";\n",
+                "  font-size: 85%;\n",
+                "  word-break: break-all;\n",
+                "}\n",
+            ),
+            LinkStyle::Numbered => concat!(
+                ".markdown-body .md-link-note {\n",
+                "  font-size: 75%;\n",
+                "  vertical-align: super;\n",
+                "  line-height: 0;\n",
+                "}\n\n",
+                ".markdown-body .md-link-notes {\n",
+                "  font-size: 85%;\n",
+                "  word-break: break-all;\n",
+                "}\n",
+            ),
+        }
+    }
+}
+
+/// Follows each external link with a reference number and lists the numbered URLs at the end
+/// of the document. Links to the same URL share a number; links within the document are left
+/// alone.
+pub fn number_links(events: Vec<Event<'_>>) -> Vec<Event<'_>> {
+    let mut urls: Vec<String> = Vec::new();
+    let mut open: Vec<Option<usize>> = Vec::new(); // Number of each open link, None if internal
+    let mut out = Vec::with_capacity(events.len());
+    for event in events {
+        match &event {
+            Event::Start(Tag::Link { dest_url, .. }) => {
+                let number = (!dest_url.starts_with('#')).then(|| {
+                    match urls.iter().position(|url| url == dest_url.as_ref()) {
+                        Some(index) => index + 1,
+                        None => {
+                            urls.push(dest_url.to_string());
+                            urls.len()
+                        }
+                    }
+                });
+                open.push(number);
+                out.push(event);
+            }
+            Event::End(TagEnd::Link) => {
+                out.push(event);
+                if let Some(Some(number)) = open.pop() {
+                    out.push(Event::InlineHtml(
+                        format!("<span class=\"md-link-note\">[{number}]</span>").into(),
+                    ));
+                }
+            }
+            _ => out.push(event),
+        }
+    }
+
+    if !urls.is_empty() {
+        let mut html = String::from("<div class=\"md-link-notes\">\n<hr />\n<ol>\n");
+        for url in &urls {
+            let url = url.strip_prefix(FILE_LINK_SCHEME).unwrap_or(url);
+            html.push_str(&format!("<li>{}</li>\n", escape_attr(url)));
+        }
+        html.push_str("</ol>\n</div>\n");
+        out.push(Event::Html(html.into()));
+    }
+    out
+}
+
+/// Returns true for paths of Markdown files
+fn is_markdown(path: &str) -> bool {
+    let extension = path.rsplit_once('.').map_or("", |(_, extension)| extension);
+    ["md", "markdown"]
+        .iter()
+        .any(|markdown| extension.eq_ignore_ascii_case(markdown))
+}
+
+/// Points links to other Markdown documents at the PDFs they're converted to, e.g.
+/// `guide.md#setup` at `guide.pdf#setup`, so cross-references keep working in the delivered
+/// files. The links are marked with [`FILE_LINK_SCHEME`] until the PDF is written.
+pub fn rewrite_md_links(events: Vec<Event<'_>>) -> Vec<Event<'_>> {
+    events
+        .into_iter()
+        .map(|event| match event {
+            Event::Start(Tag::Link {
+                link_type,
+                dest_url,
+                title,
+                id,
+            }) => {
+                let (path, fragment) =
+                    dest_url.split_at(dest_url.find('#').unwrap_or(dest_url.len()));
+                let path = path.split('?').next().unwrap_or(path);
+                let dest_url =
+                    if images::is_local(path) && Url::parse(path).is_err() && is_markdown(path) {
+                        let (stem, _) = path.rsplit_once('.').unwrap_or((path, ""));
+                        format!("{FILE_LINK_SCHEME}{stem}.pdf{fragment}").into()
+                    } else {
+                        dest_url
+                    };
+                Event::Start(Tag::Link {
+                    link_type,
+                    dest_url,
+                    title,
+                    id,
+                })
+            }
+            other => other,
+        })
+        .collect()
+}
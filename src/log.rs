@@ -0,0 +1,97 @@
+use chrono::{DateTime, Local};
+use std::collections::VecDeque;
+
+/// Number of entries kept, older ones are dropped
+const LIMIT: usize = 1000;
+
+/// How serious a logged event is
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Level {
+    Info,
+    Warning,
+    Error,
+}
+
+impl Level {
+    /// Returns the display name for the level
+    pub fn name(&self) -> &'static str {
+        match self {
+            Level::Info => "Info",
+            Level::Warning => "Warning",
+            Level::Error => "Error",
+        }
+    }
+}
+
+/// An event shown in the log
+pub struct Entry {
+    pub time: DateTime<Local>,
+    pub level: Level,
+    pub message: String, // May span several lines, e.g. a backend's output
+}
+
+/// What happened in the app, oldest first
+#[derive(Default)]
+pub struct Log {
+    entries: VecDeque<Entry>,
+}
+
+impl Log {
+    /// Logs an event that went as expected
+    pub fn info(&mut self, message: impl Into<String>) {
+        self.push(Level::Info, message.into());
+    }
+
+    /// Logs something that worked, but maybe not as the user wanted
+    pub fn warning(&mut self, message: impl Into<String>) {
+        self.push(Level::Warning, message.into());
+    }
+
+    /// Logs something that failed
+    pub fn error(&mut self, message: impl Into<String>) {
+        self.push(Level::Error, message.into());
+    }
+
+    fn push(&mut self, level: Level, message: String) {
+        if self.entries.len() == LIMIT {
+            self.entries.pop_front();
+        }
+        self.entries.push_back(Entry {
+            time: Local::now(),
+            level,
+            message,
+        });
+    }
+
+    pub fn entries(&self) -> impl Iterator<Item = &Entry> {
+        self.entries.iter()
+    }
+
+    /// The most recent entry, which the status line shows
+    pub fn last(&self) -> Option<&Entry> {
+        self.entries.back()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn clear(&mut self) {
+        self.entries.clear();
+    }
+
+    /// The whole log as plain text, one entry per line with the date, for bug reports
+    pub fn text(&self) -> String {
+        self.entries
+            .iter()
+            .map(|entry| {
+                format!(
+                    "{} [{}] {}\n",
+                    entry.time.format("%Y-%m-%d %H:%M:%S"),
+                    entry.level.name(),
+                    entry.message
+                )
+            })
+            .collect()
+    }
+}
@@ -0,0 +1,364 @@
+use crate::convert;
+use crate::front_matter::FrontMatter;
+use pulldown_cmark::{Alignment, Event, HeadingLevel, Tag, TagEnd};
+use std::fs;
+use std::path::Path;
+
+/// Manual section of pages that don't name one: user commands
+const DEFAULT_SECTION: &str = "1";
+
+/// Escapes `text` for roff: backslashes, and hyphens so options like `--help` can be
+/// copied from the page
+fn escape(text: &str) -> String {
+    text.replace('\\', "\\e").replace('-', "\\-")
+}
+
+/// Escapes `text` for a quoted macro argument
+fn escape_argument(text: &str) -> String {
+    escape(text).replace('"', "\\(dq")
+}
+
+/// Table cells being read, written when the table ends
+struct Table {
+    alignments: Vec<Alignment>,
+    rows: Vec<Vec<String>>, // The header first
+}
+
+/// Writes the document as roff with the man macros
+struct Writer {
+    out: String,
+    fonts: Vec<&'static str>, // Fonts of the open emphasis, innermost last
+    lists: Vec<Option<u64>>,  // Number of each open list's next item, None for bullets
+    item_start: bool,         // Whether an item just started, its tag setting the indent
+    links: Vec<String>,       // URLs of the open links, innermost last
+    heading: Option<HeadingLevel>,
+    code: bool,           // Whether a code block is being read
+    table: Option<Table>, // Table being read
+    cell: String,         // Text of the table cell being read
+}
+
+impl Writer {
+    fn new() -> Self {
+        Writer {
+            out: String::new(),
+            fonts: Vec::new(),
+            lists: Vec::new(),
+            item_start: false,
+            links: Vec::new(),
+            heading: None,
+            code: false,
+            table: None,
+            cell: String::new(),
+        }
+    }
+
+    /// Writes a request, which has to start a line
+    fn request(&mut self, line: &str) {
+        if !self.out.is_empty() && !self.out.ends_with('\n') {
+            self.out.push('\n');
+        }
+        self.out.push_str(line);
+        self.out.push('\n');
+    }
+
+    /// Writes text, keeping it from being read as a request at the start of a line
+    fn text(&mut self, text: &str) {
+        if self.table.is_some() {
+            self.cell.push_str(text);
+            return;
+        }
+        self.item_start = false;
+        let mut text = match self.heading {
+            Some(HeadingLevel::H1) => escape_argument(&text.to_uppercase()),
+            Some(HeadingLevel::H2) => escape_argument(text),
+            _ => escape(text),
+        };
+        if self.out.is_empty() || self.out.ends_with('\n') {
+            text = text.trim_start().to_string();
+            if text.starts_with(['.', '\'']) {
+                self.out.push_str("\\&");
+            }
+        }
+        self.out.push_str(&text);
+    }
+
+    /// Switches to `font` until the emphasis ends. Tables are set in a single font.
+    fn push_font(&mut self, font: &'static str) {
+        self.fonts.push(font);
+        if self.table.is_none() {
+            self.out.push_str(&format!("\\f{}", font));
+        }
+    }
+
+    /// Switches back to the font outside the emphasis that ended
+    fn pop_font(&mut self) {
+        self.fonts.pop();
+        let font = self.fonts.last().copied().unwrap_or("R");
+        if self.table.is_none() {
+            self.out.push_str(&format!("\\f{}", font));
+        }
+    }
+
+    /// Starts a line with `macro_name` taking the heading text as its argument
+    fn heading_macro(&mut self, macro_name: &str) {
+        if !self.out.is_empty() && !self.out.ends_with('\n') {
+            self.out.push('\n');
+        }
+        self.out.push_str(&format!("{} \"", macro_name));
+    }
+
+    fn start(&mut self, tag: Tag) {
+        match tag {
+            Tag::Paragraph => {
+                if self.item_start {
+                    self.item_start = false;
+                } else if self.lists.is_empty() {
+                    self.request(".PP");
+                } else {
+                    // A further paragraph of the item, indented like its first
+                    self.request(".IP");
+                }
+            }
+            Tag::Heading { level, .. } => {
+                self.heading = Some(level);
+                match level {
+                    HeadingLevel::H1 => self.heading_macro(".SH"),
+                    HeadingLevel::H2 => self.heading_macro(".SS"),
+                    _ => {
+                        self.request(".PP");
+                        self.push_font("B");
+                    }
+                }
+            }
+            Tag::BlockQuote => {
+                // Back from the indent of a list before it
+                if self.lists.is_empty() {
+                    self.request(".PP");
+                }
+                self.request(".RS 4");
+            }
+            Tag::CodeBlock(_) => {
+                if !self.item_start {
+                    self.request(if self.lists.is_empty() { ".PP" } else { ".IP" });
+                }
+                self.item_start = false;
+                self.request(".RS 4");
+                self.request(".nf");
+                self.request(".ft B");
+                self.code = true;
+            }
+            Tag::List(start) => {
+                if !self.lists.is_empty() {
+                    self.request(".RS");
+                }
+                self.lists.push(start);
+                self.item_start = false;
+            }
+            Tag::Item => {
+                let tag = match self.lists.last_mut() {
+                    Some(Some(number)) => {
+                        *number += 1;
+                        format!(".IP \"{}.\" 4", *number - 1)
+                    }
+                    _ => ".IP \\(bu 2".to_string(),
+                };
+                self.request(&tag);
+                self.item_start = true;
+            }
+            Tag::FootnoteDefinition(label) => {
+                self.request(&format!(".IP \"[{}]\" 4", escape_argument(&label)));
+                self.item_start = true;
+            }
+            Tag::Table(alignments) => {
+                self.table = Some(Table {
+                    alignments,
+                    rows: Vec::new(),
+                });
+            }
+            Tag::TableHead | Tag::TableRow => {
+                if let Some(table) = &mut self.table {
+                    table.rows.push(Vec::new());
+                }
+            }
+            Tag::Emphasis => self.push_font("I"),
+            Tag::Strong => self.push_font("B"),
+            Tag::Link { dest_url, .. } => self.links.push(dest_url.to_string()),
+            _ => {}
+        }
+    }
+
+    fn end(&mut self, tag: TagEnd) {
+        match tag {
+            TagEnd::Heading(level) => {
+                self.heading = None;
+                match level {
+                    HeadingLevel::H1 | HeadingLevel::H2 => self.out.push('"'),
+                    _ => self.pop_font(),
+                }
+                if !self.out.ends_with('\n') {
+                    self.out.push('\n');
+                }
+            }
+            TagEnd::BlockQuote => self.request(".RE"),
+            TagEnd::CodeBlock => {
+                self.code = false;
+                self.request(".ft R");
+                self.request(".fi");
+                self.request(".RE");
+            }
+            TagEnd::Item => self.item_start = false,
+            TagEnd::List(_) => {
+                self.item_start = false;
+                self.lists.pop();
+                if !self.lists.is_empty() {
+                    self.request(".RE");
+                }
+            }
+            TagEnd::TableCell => {
+                let cell = std::mem::take(&mut self.cell);
+                let cell = cell.split_whitespace().collect::<Vec<_>>().join(" ");
+                if let Some(row) = self.table.as_mut().and_then(|table| table.rows.last_mut()) {
+                    row.push(cell);
+                }
+            }
+            TagEnd::Table => {
+                if let Some(table) = self.table.take() {
+                    self.write_table(table);
+                }
+            }
+            TagEnd::Emphasis | TagEnd::Strong => self.pop_font(),
+            TagEnd::Link => {
+                let url = self.links.pop().unwrap_or_default();
+                // Autolinks show their URL already, and anchors lead nowhere in a man page
+                if !url.is_empty() && !url.starts_with('#') && !self.out.ends_with(&escape(&url)) {
+                    self.text(&format!(" <{}>", url));
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// Writes `table` for tbl, the header in bold. The page is marked for tbl at the top.
+    fn write_table(&mut self, table: Table) {
+        let columns = table.rows.iter().map(Vec::len).max().unwrap_or(0);
+        let format = |suffix: &str| {
+            (0..columns)
+                .map(|column| {
+                    let alignment = match table.alignments.get(column) {
+                        Some(Alignment::Center) => "c",
+                        Some(Alignment::Right) => "r",
+                        _ => "l",
+                    };
+                    format!("{}{}", alignment, suffix)
+                })
+                .collect::<Vec<_>>()
+                .join(" ")
+        };
+        self.request(".PP");
+        self.request(".TS");
+        self.request("tab(\t);");
+        self.request(&format("b"));
+        self.request(&format!("{}.", format("")));
+        for row in &table.rows {
+            let cells: Vec<String> = (0..columns)
+                .map(|column| {
+                    let cell = escape(row.get(column).map_or("", String::as_str));
+                    if cell.starts_with(['.', '\'']) {
+                        format!("\\&{}", cell)
+                    } else {
+                        cell
+                    }
+                })
+                .collect();
+            self.request(&cells.join("\t"));
+        }
+        self.request(".TE");
+    }
+
+    fn event(&mut self, event: Event) {
+        match event {
+            Event::Start(tag) => self.start(tag),
+            Event::End(tag) => self.end(tag),
+            Event::Text(text) if self.code => {
+                for line in text.lines() {
+                    let line = escape(line);
+                    if line.starts_with(['.', '\'']) {
+                        self.request(&format!("\\&{}", line));
+                    } else {
+                        self.request(&line);
+                    }
+                }
+            }
+            Event::Text(text) => self.text(&text),
+            Event::Code(code) => {
+                if self.table.is_some() {
+                    self.cell.push_str(&code);
+                } else {
+                    self.push_font("B");
+                    self.text(&code);
+                    self.pop_font();
+                }
+            }
+            Event::FootnoteReference(label) => self.text(&format!("[{}]", label)),
+            Event::SoftBreak => match &self.table {
+                Some(_) => self.cell.push(' '),
+                None if self.heading.is_some() => self.out.push(' '),
+                None => self.out.push('\n'),
+            },
+            Event::HardBreak => self.request(".br"),
+            Event::Rule => {
+                self.request(".PP");
+                self.request(".ce 1");
+                self.request("* * *");
+            }
+            Event::TaskListMarker(checked) => self.text(if checked { "[x] " } else { "[ ] " }),
+            // HTML has no roff form
+            _ => {}
+        }
+    }
+}
+
+/// Renders `md_body` (without front matter) as a man page named `name` in manual
+/// `section`: `#` headings start sections, `##` headings subsections, and code is set in
+/// bold. `date` goes in the footer when given.
+pub fn render(md_body: &str, name: &str, section: &str, date: &str) -> String {
+    let mut writer = Writer::new();
+    for event in pulldown_cmark::Parser::new_ext(md_body, convert::markdown_options()) {
+        writer.event(event);
+    }
+    let mut page = String::new();
+    if writer.out.contains("\n.TS\n") {
+        page.push_str("'\\\" t\n");
+    }
+    page.push_str(&format!(
+        ".TH \"{}\" \"{}\" \"{}\"\n",
+        escape_argument(&name.to_uppercase()),
+        escape_argument(section),
+        escape_argument(date)
+    ));
+    page.push_str(writer.out.trim_end());
+    page.push('\n');
+    page
+}
+
+/// Converts the Markdown file at `md_path` to a man page at `man_path`. The page is named
+/// after the front matter `title` or the file, in the front matter `section` or section 1.
+pub fn export(md_path: &Path, man_path: &Path) -> Result<Vec<String>, String> {
+    let md_text =
+        fs::read_to_string(md_path).map_err(|e| format!("Failed to read Markdown file: {}", e))?;
+    let (front_matter, md_body) = FrontMatter::extract(&md_text);
+    let stem = md_path
+        .file_stem()
+        .map(|stem| stem.to_string_lossy().to_string())
+        .unwrap_or_default();
+    let name = front_matter.get("title").unwrap_or(stem.as_str());
+    let section = front_matter.get("section").unwrap_or(DEFAULT_SECTION);
+    let date = front_matter.get("date").unwrap_or_default();
+    if let Some(parent) = man_path.parent() {
+        fs::create_dir_all(parent)
+            .map_err(|e| format!("Failed to create output directory: {}", e))?;
+    }
+    fs::write(man_path, render(md_body, name, section, date))
+        .map_err(|e| format!("Failed to write man page: {}", e))?;
+    Ok(Vec::new())
+}
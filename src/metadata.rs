@@ -0,0 +1,129 @@
+use crate::front_matter::FrontMatter;
+use chrono::{NaiveDate, NaiveDateTime};
+use lopdf::{Dictionary, Document, Object};
+
+/// Date and time forms accepted for the creation date, besides a plain date
+const DATE_TIME_FORMATS: &[&str] = &[
+    "%Y-%m-%d %H:%M:%S",
+    "%Y-%m-%dT%H:%M:%S",
+    "%Y-%m-%d %H:%M",
+    "%Y-%m-%dT%H:%M",
+];
+
+/// Document properties written into PDFs, which viewers show and search indexes read.
+/// Empty fields are left as wkhtmltopdf wrote them.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Metadata {
+    pub title: String,
+    pub author: String,
+    pub subject: String,
+    pub keywords: String, // Separated by commas
+    pub date: String,     // Creation date as YYYY-MM-DD, optionally followed by HH:MM[:SS]
+}
+
+impl Metadata {
+    /// Whether no property is set, so the PDF needn't be touched
+    pub fn is_empty(&self) -> bool {
+        self.title.trim().is_empty()
+            && self.author.trim().is_empty()
+            && self.subject.trim().is_empty()
+            && self.keywords.trim().is_empty()
+            && self.date.trim().is_empty()
+    }
+
+    /// These properties with those the front matter sets instead: `title`, `author`,
+    /// `subject` or else `description`, `keywords` (also as a `[a, b]` list) and `date`
+    pub fn with_front_matter(&self, front_matter: &FrontMatter) -> Metadata {
+        let field = |keys: &[&str], current: &str| {
+            keys.iter()
+                .find_map(|key| front_matter.get(key))
+                .unwrap_or(current)
+                .to_string()
+        };
+        let keywords = field(&["keywords"], &self.keywords);
+        Metadata {
+            title: field(&["title"], &self.title),
+            author: field(&["author"], &self.author),
+            subject: field(&["subject", "description"], &self.subject),
+            keywords: keywords
+                .trim_start_matches('[')
+                .trim_end_matches(']')
+                .to_string(),
+            date: field(&["date"], &self.date),
+        }
+    }
+
+    /// The creation date, None when not set or not in a form that's understood
+    pub fn creation_date(&self) -> Option<NaiveDateTime> {
+        parse_date(&self.date)
+    }
+}
+
+/// Reads a date written as YYYY-MM-DD, optionally followed by a time
+pub fn parse_date(text: &str) -> Option<NaiveDateTime> {
+    let text = text.trim();
+    DATE_TIME_FORMATS
+        .iter()
+        .find_map(|format| NaiveDateTime::parse_from_str(text, format).ok())
+        .or_else(|| {
+            NaiveDate::parse_from_str(text, "%Y-%m-%d")
+                .ok()?
+                .and_hms_opt(0, 0, 0)
+        })
+}
+
+/// `date` in the PDF date format, e.g. `D:20240131120000`
+fn pdf_date(date: NaiveDateTime) -> String {
+    date.format("D:%Y%m%d%H%M%S").to_string()
+}
+
+/// The document information dictionary of `document`, added if it has none
+pub fn info_mut(document: &mut Document) -> Result<&mut Dictionary, String> {
+    let info_id = match document.trailer.get(b"Info").and_then(Object::as_reference) {
+        Ok(id) => id,
+        Err(_) => {
+            let id = document.add_object(Dictionary::new());
+            document.trailer.set("Info", id);
+            id
+        }
+    };
+    document
+        .get_dictionary_mut(info_id)
+        .map_err(|e| format!("Failed to read PDF metadata: {}", e))
+}
+
+/// Writes `metadata` into the document information dictionary of `document`, with this app
+/// as the creator. Keywords are normalized to a comma-separated list.
+pub fn apply(document: &mut Document, metadata: &Metadata) -> Result<(), String> {
+    let info = info_mut(document)?;
+    let keywords = metadata
+        .keywords
+        .split(',')
+        .map(|keyword| keyword.trim().trim_matches(['"', '\'']))
+        .filter(|keyword| !keyword.is_empty())
+        .collect::<Vec<_>>()
+        .join(", ");
+    let fields = [
+        ("Title", metadata.title.trim()),
+        ("Author", metadata.author.trim()),
+        ("Subject", metadata.subject.trim()),
+        ("Keywords", keywords.as_str()),
+    ];
+    for (key, value) in fields {
+        if !value.is_empty() {
+            info.set(key, lopdf::text_string(value));
+        }
+    }
+    info.set(
+        "Creator",
+        lopdf::text_string(concat!(
+            env!("CARGO_PKG_NAME"),
+            " ",
+            env!("CARGO_PKG_VERSION")
+        )),
+    );
+    if let Some(date) = metadata.creation_date() {
+        info.set("CreationDate", Object::string_literal(pdf_date(date)));
+    }
+    Ok(())
+}
@@ -0,0 +1,159 @@
+use crate::checksum;
+use crate::images;
+use crate::settings::Settings;
+use crate::style;
+use image::codecs::jpeg::JpegEncoder;
+use image::imageops::FilterType;
+use image::metadata::Orientation;
+use image::{DynamicImage, ImageDecoder, ImageFormat, ImageReader};
+use pulldown_cmark::{Event, Tag};
+use std::fs::{self, File};
+use std::io::BufWriter;
+use std::path::{Path, PathBuf};
+use std::time::UNIX_EPOCH;
+
+/// Resolution images are downsampled to unless chosen otherwise
+pub const DEFAULT_IMAGE_DPI: u32 = 200;
+
+/// Width of the pages wkhtmltopdf prints on, A4 portrait
+const PAGE_WIDTH_MM: f32 = 210.0;
+
+/// Quality of JPEG photos written after rotating or downsampling
+const JPEG_QUALITY: u8 = 90;
+
+/// Width of the page's printable area in inches, the widest an image is shown at
+fn printable_width_in(settings: &Settings) -> f32 {
+    let margin = if settings.style.enabled {
+        settings.style.margin_mm.max(0.0)
+    } else {
+        style::DEFAULT_MARGIN_MM
+    };
+    (PAGE_WIDTH_MM - 2.0 * margin).max(1.0) / 25.4
+}
+
+/// Widest an image needs to be in pixels to print at `dpi`, or None to keep every size.
+/// Images are never made smaller than they are shown at, which at a zoom below 1 is more
+/// than 96 pixels per inch of page.
+fn max_width(settings: &Settings) -> Option<u32> {
+    if !settings.downsample_images {
+        return None;
+    }
+    let dpi = (settings.image_dpi as f32).max(96.0 / settings.zoom.max(0.1));
+    Some((printable_width_in(settings) * dpi).ceil() as u32)
+}
+
+/// Rotates the image at `path` as its EXIF orientation says and scales it down to at most
+/// `max_width` pixels wide. Returns the path of the corrected copy, None if the image is
+/// fine as it is. Only the header is read for images that need neither.
+fn normalize(path: &Path, max_width: Option<u32>) -> Result<Option<PathBuf>, String> {
+    let error = |e: image::ImageError| format!("Failed to read image '{}': {}", path.display(), e);
+    let reader = ImageReader::open(path)
+        .map_err(|e| format!("Failed to read image '{}': {}", path.display(), e))?
+        .with_guessed_format()
+        .map_err(|e| format!("Failed to read image '{}': {}", path.display(), e))?;
+    // Animated GIFs would lose their animation, and SVG isn't a raster format
+    let format = match reader.format() {
+        Some(format @ (ImageFormat::Jpeg | ImageFormat::Png)) => format,
+        Some(ImageFormat::WebP | ImageFormat::Bmp) => ImageFormat::Png,
+        _ => return Ok(None),
+    };
+    let mut decoder = reader.into_decoder().map_err(error)?;
+    let orientation = decoder.orientation().unwrap_or(Orientation::NoTransforms);
+    let (width, height) = decoder.dimensions();
+    let turned = matches!(
+        orientation,
+        Orientation::Rotate90
+            | Orientation::Rotate270
+            | Orientation::Rotate90FlipH
+            | Orientation::Rotate270FlipH
+    );
+    let upright_width = if turned { height } else { width };
+    let too_wide = max_width.is_some_and(|max_width| upright_width > max_width);
+    if orientation == Orientation::NoTransforms && !too_wide {
+        return Ok(None);
+    }
+
+    let mut image = DynamicImage::from_decoder(decoder).map_err(error)?;
+    image.apply_orientation(orientation);
+    if let Some(max_width) = max_width.filter(|_| too_wide) {
+        image = image.resize(max_width, u32::MAX, FilterType::Lanczos3);
+    }
+
+    let modified = fs::metadata(path)
+        .and_then(|metadata| metadata.modified())
+        .ok()
+        .and_then(|modified| modified.duration_since(UNIX_EPOCH).ok())
+        .map_or(0, |since| since.as_nanos());
+    let key = checksum::key(&[
+        path.as_os_str().as_encoded_bytes(),
+        &max_width.unwrap_or(0).to_le_bytes(),
+        &modified.to_le_bytes(),
+    ]);
+    let extension = if format == ImageFormat::Jpeg {
+        "jpg"
+    } else {
+        format.extensions_str()[0]
+    };
+    let normalized = std::env::temp_dir().join(format!("md-to-pdf-normalized-{key}.{extension}"));
+    let written = if format == ImageFormat::Jpeg {
+        File::create(&normalized)
+            .map_err(image::ImageError::IoError)
+            .and_then(|file| {
+                let encoder = JpegEncoder::new_with_quality(BufWriter::new(file), JPEG_QUALITY);
+                image.into_rgb8().write_with_encoder(encoder)
+            })
+    } else {
+        image.save_with_format(&normalized, format)
+    };
+    written.map_err(|e| format!("Failed to write corrected image: {}", e))?;
+    Ok(Some(normalized))
+}
+
+/// Rotates the local photos of the document that carry an EXIF orientation, which
+/// wkhtmltopdf ignores, and downsamples images larger than the target resolution needs
+/// when enabled. Images that can't be read are left as they are, with a warning.
+pub fn apply<'a>(
+    events: Vec<Event<'a>>,
+    base_dir: &Path,
+    settings: &Settings,
+    warnings: &mut Vec<String>,
+) -> Vec<Event<'a>> {
+    let max_width = max_width(settings);
+    events
+        .into_iter()
+        .map(|event| match event {
+            Event::Start(Tag::Image {
+                link_type,
+                dest_url,
+                title,
+                id,
+            }) if images::is_local(&dest_url) => {
+                let (path, suffix) =
+                    dest_url.split_at(dest_url.find(['?', '#']).unwrap_or(dest_url.len()));
+                let mut file = base_dir.join(path);
+                if !file.is_file() {
+                    file = base_dir.join(images::percent_decode(path));
+                }
+                let normalized = normalize(&file, max_width).map(|normalized| {
+                    normalized
+                        .map(|normalized| format!("{}{}", normalized.to_string_lossy(), suffix))
+                });
+                let dest_url = match normalized {
+                    Ok(Some(normalized)) => normalized.into(),
+                    Ok(None) => dest_url,
+                    Err(message) => {
+                        warnings.push(message);
+                        dest_url
+                    }
+                };
+                Event::Start(Tag::Image {
+                    link_type,
+                    dest_url,
+                    title,
+                    id,
+                })
+            }
+            other => other,
+        })
+        .collect()
+}
@@ -0,0 +1,227 @@
+use crate::directives;
+use lopdf::{Dictionary, Document, Object, dictionary};
+use pulldown_cmark::{Event, Tag, TagEnd};
+
+/// How the pages of a section are numbered
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum NumberStyle {
+    Arabic,
+    LowerRoman,
+    UpperRoman,
+}
+
+impl NumberStyle {
+    /// Parses a `page-numbers` directive value
+    fn parse(value: &str) -> Option<NumberStyle> {
+        match value.trim() {
+            "arabic" | "decimal" => Some(NumberStyle::Arabic),
+            "roman" | "lower-roman" => Some(NumberStyle::LowerRoman),
+            "Roman" | "upper-roman" => Some(NumberStyle::UpperRoman),
+            _ => None,
+        }
+    }
+
+    /// Marker keyword, also the directive value
+    fn keyword(&self) -> &'static str {
+        match self {
+            NumberStyle::Arabic => "arabic",
+            NumberStyle::LowerRoman => "lower-roman",
+            NumberStyle::UpperRoman => "upper-roman",
+        }
+    }
+
+    /// Formats page `number` (1 and up) in this style
+    fn format(&self, number: usize) -> String {
+        match self {
+            NumberStyle::Arabic => number.to_string(),
+            NumberStyle::LowerRoman => roman(number).to_lowercase(),
+            NumberStyle::UpperRoman => roman(number),
+        }
+    }
+
+    /// PDF page label style name
+    fn label_style(&self) -> &'static str {
+        match self {
+            NumberStyle::Arabic => "D",
+            NumberStyle::LowerRoman => "r",
+            NumberStyle::UpperRoman => "R",
+        }
+    }
+}
+
+/// Start of the markers left by [`mark_sections`], followed by the style keyword and `-->`
+const MARKER_PREFIX: &str = "<!--md-to-pdf:page-numbers=";
+
+/// Turns top-level `<!-- page-numbers: roman -->` / `<!-- page-numbers: arabic -->` directives
+/// into section markers. Each one starts a new page whose numbering restarts at 1 in that style.
+pub fn mark_sections(events: Vec<Event<'_>>) -> Vec<Event<'_>> {
+    let mut depth = 0usize;
+    events
+        .into_iter()
+        .map(|event| match &event {
+            Event::Start(Tag::HtmlBlock) | Event::End(TagEnd::HtmlBlock) => event,
+            Event::Start(_) => {
+                depth += 1;
+                event
+            }
+            Event::End(_) => {
+                depth = depth.saturating_sub(1);
+                event
+            }
+            Event::Html(html) if depth == 0 => match directives::parse(html) {
+                Some((name, value)) if name == "page-numbers" => match NumberStyle::parse(&value) {
+                    Some(style) => {
+                        Event::Html(format!("{MARKER_PREFIX}{}-->", style.keyword()).into())
+                    }
+                    None => event,
+                },
+                _ => event,
+            },
+            _ => event,
+        })
+        .collect()
+}
+
+/// Finds the first section marker in `html`, returning its start, end and style
+pub fn find_marker(html: &str) -> Option<(usize, usize, NumberStyle)> {
+    let start = html.find(MARKER_PREFIX)?;
+    let keyword_start = start + MARKER_PREFIX.len();
+    let keyword_len = html[keyword_start..].find("-->")?;
+    let style = match &html[keyword_start..keyword_start + keyword_len] {
+        "arabic" => NumberStyle::Arabic,
+        "lower-roman" => NumberStyle::LowerRoman,
+        "upper-roman" => NumberStyle::UpperRoman,
+        _ => return None,
+    };
+    Some((start, keyword_start + keyword_len + 3, style))
+}
+
+/// Sets the document's page labels (the page numbers viewers display), with numbering
+/// restarting in `style` at each `(first page index, style)` in `ranges`.
+/// Pages before the first range are numbered in Arabic numerals.
+pub fn set_page_labels(
+    document: &mut Document,
+    ranges: &[(usize, NumberStyle)],
+) -> Result<(), String> {
+    let mut nums = Vec::new();
+    if ranges.first().is_none_or(|&(start, _)| start > 0) {
+        nums.push(Object::Integer(0));
+        nums.push(Object::Dictionary(dictionary! { "S" => "D" }));
+    }
+    for &(start, style) in ranges {
+        nums.push(Object::Integer(start as i64));
+        nums.push(Object::Dictionary(dictionary! {
+            "S" => style.label_style(),
+            "St" => 1,
+        }));
+    }
+
+    let catalog = document.catalog_mut().map_err(|e| e.to_string())?;
+    catalog.set("PageLabels", dictionary! { "Nums" => nums });
+    Ok(())
+}
+
+/// Label of the page at `index` under the numbering restarts in `ranges`,
+/// as written by [`set_page_labels`]
+pub fn label(index: usize, ranges: &[(usize, NumberStyle)]) -> String {
+    match ranges.iter().rev().find(|(start, _)| *start <= index) {
+        Some(&(start, style)) => style.format(index - start + 1),
+        None => (index + 1).to_string(),
+    }
+}
+
+/// Returns the label of every page, following the document's `/PageLabels`
+/// (plain page numbers when it has none)
+pub fn page_labels(document: &Document) -> Vec<String> {
+    let page_count = document.get_pages().len();
+    let mut ranges: Vec<(usize, Dictionary)> = document
+        .catalog()
+        .and_then(|catalog| catalog.get(b"PageLabels"))
+        .and_then(|labels| match labels {
+            Object::Reference(id) => document.get_dictionary(*id),
+            other => other.as_dict(),
+        })
+        .and_then(|labels| labels.get(b"Nums"))
+        .and_then(Object::as_array)
+        .map(|nums| {
+            nums.chunks(2)
+                .filter_map(|pair| {
+                    let start = pair.first()?.as_i64().ok()?;
+                    let label = match pair.get(1)? {
+                        Object::Reference(id) => document.get_dictionary(*id).ok()?,
+                        other => other.as_dict().ok()?,
+                    };
+                    Some((start.max(0) as usize, label.clone()))
+                })
+                .collect()
+        })
+        .unwrap_or_default();
+    if ranges.is_empty() {
+        ranges.push((0, dictionary! { "S" => "D" }));
+    }
+
+    (0..page_count)
+        .map(|index| {
+            let Some((start, label)) = ranges.iter().rev().find(|(start, _)| *start <= index)
+            else {
+                return (index + 1).to_string();
+            };
+            let first = label
+                .get(b"St")
+                .and_then(Object::as_i64)
+                .unwrap_or(1)
+                .max(1) as usize;
+            let prefix = label
+                .get(b"P")
+                .and_then(Object::as_str)
+                .map(|prefix| String::from_utf8_lossy(prefix).into_owned())
+                .unwrap_or_default();
+            let number = first + index - start;
+            let number = match label.get(b"S").and_then(Object::as_name) {
+                Ok(b"D") => number.to_string(),
+                Ok(b"r") => roman(number).to_lowercase(),
+                Ok(b"R") => roman(number),
+                Ok(b"a") => letters(number).to_lowercase(),
+                Ok(b"A") => letters(number),
+                _ => String::new(),
+            };
+            format!("{prefix}{number}")
+        })
+        .collect()
+}
+
+/// Upper case Roman numeral for `number` (1 and up)
+pub fn roman(mut number: usize) -> String {
+    const NUMERALS: &[(usize, &str)] = &[
+        (1000, "M"),
+        (900, "CM"),
+        (500, "D"),
+        (400, "CD"),
+        (100, "C"),
+        (90, "XC"),
+        (50, "L"),
+        (40, "XL"),
+        (10, "X"),
+        (9, "IX"),
+        (5, "V"),
+        (4, "IV"),
+        (1, "I"),
+    ];
+    let mut out = String::new();
+    for &(value, numeral) in NUMERALS {
+        while number >= value {
+            out.push_str(numeral);
+            number -= value;
+        }
+    }
+    out
+}
+
+/// Letter numbering as used by PDF page labels: A..Z, then AA..ZZ, and so on
+fn letters(number: usize) -> String {
+    if number == 0 {
+        return String::new();
+    }
+    let letter = (b'A' + ((number - 1) % 26) as u8) as char;
+    letter.to_string().repeat((number - 1) / 26 + 1)
+}
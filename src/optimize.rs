@@ -0,0 +1,241 @@
+use crate::estimate::format_size;
+use crate::process;
+use crate::settings::Settings;
+use crate::setup;
+use image::ImageFormat;
+use image::codecs::jpeg::JpegEncoder;
+use image::{DynamicImage, ExtendedColorType};
+use lopdf::{Document, Object, SaveOptions, Stream};
+use std::fs;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+use std::process::{Command, Stdio};
+
+/// JPEG quality images are recompressed at unless chosen otherwise
+pub const DEFAULT_IMAGE_QUALITY: u8 = 80;
+
+/// How PDFs are made smaller after rendering
+#[derive(Debug, Clone, PartialEq)]
+pub struct Optimization {
+    pub enabled: bool,
+    pub recompress_images: bool, // Re-encode images as JPEG where that's smaller, which is lossy
+    pub image_quality: u8,       // JPEG quality of recompressed images, 1 to 100
+    pub linearize: bool, // Reorder for fast web view with qpdf, so the first page shows while loading
+}
+
+impl Default for Optimization {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            recompress_images: false,
+            image_quality: DEFAULT_IMAGE_QUALITY,
+            linearize: false,
+        }
+    }
+}
+
+/// The pixels of an 8-bit RGB or gray image stream, None for other images, such as masks,
+/// indexed or CMYK colors and filters that can't be decoded
+fn decode_image(stream: &Stream) -> Option<DynamicImage> {
+    let dict = &stream.dict;
+    if dict.get(b"Subtype").and_then(Object::as_name).ok()? != b"Image"
+        || dict.has(b"Decode")
+        || dict
+            .get(b"ImageMask")
+            .and_then(Object::as_bool)
+            .unwrap_or(false)
+        || dict
+            .get(b"BitsPerComponent")
+            .and_then(Object::as_i64)
+            .ok()?
+            != 8
+    {
+        return None;
+    }
+    let color_space = dict.get(b"ColorSpace").and_then(Object::as_name).ok()?;
+    let width = u32::try_from(dict.get(b"Width").and_then(Object::as_i64).ok()?).ok()?;
+    let height = u32::try_from(dict.get(b"Height").and_then(Object::as_i64).ok()?).ok()?;
+
+    if stream.filters().ok()? == [b"DCTDecode".as_slice()] {
+        let image = image::load_from_memory_with_format(&stream.content, ImageFormat::Jpeg).ok()?;
+        return match color_space {
+            b"DeviceRGB" => Some(DynamicImage::ImageRgb8(image.to_rgb8())),
+            b"DeviceGray" => Some(DynamicImage::ImageLuma8(image.to_luma8())),
+            _ => None,
+        };
+    }
+    let pixels = stream.get_plain_content().ok()?;
+    match color_space {
+        b"DeviceRGB" => {
+            image::RgbImage::from_raw(width, height, pixels).map(DynamicImage::ImageRgb8)
+        }
+        b"DeviceGray" => {
+            image::GrayImage::from_raw(width, height, pixels).map(DynamicImage::ImageLuma8)
+        }
+        _ => None,
+    }
+}
+
+/// Re-encodes the RGB and gray images of `document` as JPEG at `quality`, keeping each one
+/// only where it's smaller than the image was. Transparency is kept, as it's a separate
+/// image.
+fn recompress_images(document: &mut Document, quality: u8) {
+    for object in document.objects.values_mut() {
+        let Object::Stream(stream) = object else {
+            continue;
+        };
+        let Some(image) = decode_image(stream) else {
+            continue;
+        };
+        let color_type = match image {
+            DynamicImage::ImageLuma8(_) => ExtendedColorType::L8,
+            _ => ExtendedColorType::Rgb8,
+        };
+        let mut jpeg = Vec::new();
+        let encoded = JpegEncoder::new_with_quality(&mut jpeg, quality.clamp(1, 100)).encode(
+            image.as_bytes(),
+            image.width(),
+            image.height(),
+            color_type,
+        );
+        if encoded.is_ok() && jpeg.len() < stream.content.len() {
+            stream.dict.remove(b"DecodeParms");
+            stream.dict.set("Filter", "DCTDecode");
+            stream.set_content(jpeg);
+        }
+    }
+}
+
+/// Makes `document` smaller when `optimization` is enabled: recompresses images as it sets,
+/// drops objects nothing refers to any more, such as those of pages replaced while
+/// post-processing, and compresses the streams that aren't. Returns the size in bytes the
+/// document had before, for the [`report`], None when optimization is off.
+pub fn apply(document: &mut Document, optimization: &Optimization) -> Result<Option<u64>, String> {
+    if !optimization.enabled {
+        return Ok(None);
+    }
+    let mut bytes = Vec::new();
+    document
+        .save_to(&mut bytes)
+        .map_err(|e| format!("Failed to serialize PDF: {}", e))?;
+    if optimization.recompress_images {
+        recompress_images(document, optimization.image_quality);
+    }
+    document.prune_objects();
+    document.renumber_objects();
+    document.compress();
+    Ok(Some(bytes.len() as u64))
+}
+
+/// Saves `document` to `path`, with the objects packed into compressed object streams when
+/// optimization is enabled, which needs PDF 1.5
+pub fn save(
+    document: &mut Document,
+    path: &Path,
+    optimization: &Optimization,
+) -> lopdf::Result<()> {
+    let mut writer = BufWriter::new(fs::File::create(path)?);
+    let options = SaveOptions::builder()
+        .use_object_streams(optimization.enabled)
+        .use_xref_streams(optimization.enabled)
+        .build();
+    document.save_with_options(&mut writer, options)?;
+    writer.flush()?;
+    Ok(())
+}
+
+/// Finishes optimizing the PDF at `path` once it's written, `before` being what [`apply`]
+/// returned: linearizes it if enabled, with the temporary file in `temp_dir`, and adds the
+/// [`report`] to `warnings`
+pub fn finish(
+    settings: &Settings,
+    path: &Path,
+    before: Option<u64>,
+    temp_dir: &Path,
+    warnings: &mut Vec<String>,
+) -> Result<(), String> {
+    let Some(before) = before else {
+        return Ok(());
+    };
+    if settings.optimization.linearize {
+        let encryption = &settings.encryption;
+        let password = if encryption.enabled {
+            encryption.user_password.as_str()
+        } else {
+            ""
+        };
+        // Reproducible PDFs aren't encrypted, see `Settings::check_pdf_options`
+        linearize(path, temp_dir, password, settings.reproducible)?;
+    }
+    warnings.push(report(before, path));
+    Ok(())
+}
+
+/// Rewrites the PDF at `path` linearized with qpdf, so viewers can show the first page
+/// before the rest has downloaded. `password` opens an encrypted PDF, which stays encrypted.
+/// With `deterministic`, the document ID comes from the content, as for reproducible PDFs.
+/// The linearized file is written to `temp_dir` first.
+fn linearize(
+    path: &Path,
+    temp_dir: &Path,
+    password: &str,
+    deterministic: bool,
+) -> Result<(), String> {
+    let output = temp_dir.join("linearized.pdf");
+    let mut command = Command::new("qpdf");
+    command.arg("--linearize");
+    if deterministic {
+        command.arg("--deterministic-id");
+    }
+    // Read from stdin, which keeps the password out of the process list
+    command
+        .arg("--password-file=-")
+        .arg(path)
+        .arg(&output)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped());
+    let result = command
+        .spawn()
+        .and_then(|mut child| {
+            if let Some(mut stdin) = child.stdin.take() {
+                stdin.write_all(password.as_bytes())?;
+            }
+            child.wait_with_output()
+        })
+        .map_err(|e| {
+            format!(
+                "Failed to execute qpdf, which linearizes PDFs: {}. {}",
+                e,
+                setup::install_hint("qpdf")
+            )
+        })
+        .and_then(|command_output| {
+            // Exit code 3 means it succeeded with warnings about the input
+            if command_output.status.success() || command_output.status.code() == Some(3) {
+                fs::copy(&output, path)
+                    .map(|_| ())
+                    .map_err(|e| format!("Failed to replace PDF with the linearized file: {}", e))
+            } else {
+                Err(process::failure("qpdf", &command, &command_output))
+            }
+        });
+    let _ = fs::remove_file(&output);
+    result
+}
+
+/// Line reporting how an optimized PDF at `path` compares to its `before` size in bytes
+fn report(before: u64, path: &Path) -> String {
+    let after = fs::metadata(path).map_or(0, |metadata| metadata.len());
+    let change = if before > 0 {
+        (after as f64 / before as f64 - 1.0) * 100.0
+    } else {
+        0.0
+    };
+    format!(
+        "Optimized the PDF from {} to {} ({:+.0}%)",
+        format_size(before),
+        format_size(after),
+        change
+    )
+}
@@ -0,0 +1,131 @@
+use crate::budget::BudgetCheck;
+use crate::convert::{self, Rendered};
+use crate::encryption::Encryption;
+use crate::optimize::Optimization;
+use crate::paths::TempDir;
+use crate::process;
+use crate::settings::Settings;
+use crate::setup;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+/// Resolution of page images unless chosen otherwise: sharp on screens, small enough for
+/// slides and web pages
+pub const DEFAULT_IMAGE_DPI: u32 = 150;
+
+/// Quality of JPEG page images, out of 100
+const JPEG_QUALITY: u32 = 90;
+
+/// File formats pages can be exported in
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum PageImage {
+    Png,
+    Jpeg,
+}
+
+impl PageImage {
+    /// Extension of the image files
+    fn extension(&self) -> &'static str {
+        match self {
+            PageImage::Png => "png",
+            PageImage::Jpeg => "jpg",
+        }
+    }
+}
+
+/// Path of the image of page `number` (from 1) exported to `image_path`: the page number
+/// goes after the file name, zero-padded so the pages sort in order, e.g. `report-07.png`
+fn page_path(image_path: &Path, number: usize, page_count: usize, kind: PageImage) -> PathBuf {
+    let stem = image_path
+        .file_stem()
+        .map(|stem| stem.to_string_lossy().to_string())
+        .unwrap_or_default();
+    let digits = page_count.to_string().len();
+    image_path.with_file_name(format!("{}-{:0digits$}.{}", stem, number, kind.extension()))
+}
+
+/// Renders the pages of the PDF at `pdf_path` with pdftoppm into `dir`, one image per page
+/// at `dpi`. Returns the images in page order.
+fn rasterize(
+    pdf_path: &Path,
+    dir: &Path,
+    dpi: u32,
+    kind: PageImage,
+) -> Result<Vec<PathBuf>, String> {
+    let mut command = Command::new("pdftoppm");
+    command.args(["-r", &dpi.to_string()]);
+    match kind {
+        PageImage::Png => command.arg("-png"),
+        PageImage::Jpeg => {
+            command.args(["-jpeg", "-jpegopt", &format!("quality={}", JPEG_QUALITY)])
+        }
+    };
+    command.arg(pdf_path).arg(dir.join("page"));
+    match command.output() {
+        Ok(output) if output.status.success() => {}
+        Ok(output) => return Err(process::failure("pdftoppm", &command, &output)),
+        Err(e) => {
+            return Err(format!(
+                "Failed to execute pdftoppm, which page images are rendered with: {}. {}",
+                e,
+                setup::install_hint("pdftoppm")
+            ));
+        }
+    }
+    // Named page-1.png or page-01.png etc., depending on the number of pages
+    let mut pages: Vec<(usize, PathBuf)> = fs::read_dir(dir)
+        .map_err(|e| format!("Failed to read rendered pages: {}", e))?
+        .filter_map(|entry| {
+            let path = entry.ok()?.path();
+            let number = path
+                .file_stem()?
+                .to_str()?
+                .strip_prefix("page-")?
+                .parse()
+                .ok()?;
+            Some((number, path))
+        })
+        .collect();
+    pages.sort();
+    Ok(pages.into_iter().map(|(_, path)| path).collect())
+}
+
+/// Writes the `rendered` document as one image per page, at the resolution of the settings.
+/// The images are named after `image_path` with the page number added, e.g.
+/// `report-1.png`. The post-processing command, size budget, cover and appendix PDFs,
+/// optimization and encryption only apply to PDFs. Returns the images written, in page
+/// order, and the warnings of the conversion.
+pub fn export(
+    rendered: &Rendered,
+    image_path: &Path,
+    kind: PageImage,
+) -> Result<(Vec<PathBuf>, Vec<String>), String> {
+    let settings = Settings {
+        post_command: String::new(),
+        size_budget: BudgetCheck::Off,
+        encryption: Encryption::default(),
+        pdf_a: false,
+        cover_pdf: String::new(),
+        appendix_pdf: String::new(),
+        optimization: Optimization::default(),
+        ..rendered.settings.clone()
+    };
+    let temp_dir = TempDir::new()?;
+    let pdf_path = temp_dir.path().join("document.pdf");
+    convert::write_pdf(rendered, &settings, &pdf_path).and_then(|warnings| {
+        let pages = rasterize(&pdf_path, temp_dir.path(), settings.page_image_dpi, kind)?;
+        if let Some(parent) = image_path.parent() {
+            fs::create_dir_all(parent)
+                .map_err(|e| format!("Failed to create output directory: {}", e))?;
+        }
+        let mut written = Vec::new();
+        for (i, page) in pages.iter().enumerate() {
+            let target = page_path(image_path, i + 1, pages.len(), kind);
+            fs::copy(page, &target)
+                .map_err(|e| format!("Failed to write '{}': {}", target.display(), e))?;
+            written.push(target);
+        }
+        Ok((written, warnings))
+    })
+}
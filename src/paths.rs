@@ -0,0 +1,97 @@
+use std::fs::{self, OpenOptions};
+use std::path::{Path, PathBuf};
+use std::process;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+/// Extensions of the files the app converts
+const MARKDOWN_EXTENSIONS: &[&str] = &["md", "markdown"];
+
+/// Temporary folders created by this process so far
+static TEMP_DIRS: AtomicUsize = AtomicUsize::new(0);
+
+/// A folder of its own in the system's temporary folder for the files of one conversion, so
+/// conversions running at the same time, here or in other instances, don't overwrite each
+/// other's files. It's removed with its content when dropped.
+pub struct TempDir {
+    path: PathBuf,
+}
+
+impl TempDir {
+    pub fn new() -> Result<Self, String> {
+        let number = TEMP_DIRS.fetch_add(1, Ordering::Relaxed);
+        let path = std::env::temp_dir().join(format!("md-to-pdf-{}-{number}", process::id()));
+        // Left over from a process with the same ID that didn't clean up
+        let _ = fs::remove_dir_all(&path);
+        fs::create_dir_all(&path)
+            .map_err(|e| format!("Failed to create temporary folder: {}", e))?;
+        Ok(Self { path })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.path);
+    }
+}
+
+/// Why the Markdown file at `path` can't be converted, None if it can or no path was given yet
+pub fn input_problem(path: &str) -> Option<&'static str> {
+    if path.trim().is_empty() {
+        return None;
+    }
+    let path = Path::new(path);
+    if !path.exists() {
+        return Some("The file doesn't exist");
+    }
+    if !path.is_file() {
+        return Some("This is a folder, not a file");
+    }
+    let markdown = path.extension().is_some_and(|extension| {
+        MARKDOWN_EXTENSIONS
+            .iter()
+            .any(|known| extension.eq_ignore_ascii_case(known))
+    });
+    if !markdown {
+        return Some("Not a Markdown file (.md or .markdown)");
+    }
+    None
+}
+
+/// Why a PDF can't be written to `path`, None if it can or no path was given yet. Checks
+/// that the folder can be written to by creating and removing a file in it.
+pub fn output_problem(path: &str, input: &str) -> Option<&'static str> {
+    if path.trim().is_empty() {
+        return None;
+    }
+    let path = Path::new(path);
+    if path.is_dir() {
+        return Some("This is a folder, choose a file name in it");
+    }
+    if !input.trim().is_empty() && path == Path::new(input) {
+        return Some("This would replace the Markdown file");
+    }
+    if fs::metadata(path).is_ok_and(|metadata| metadata.permissions().readonly()) {
+        return Some("The file is read-only");
+    }
+    let folder = path
+        .parent()
+        .filter(|folder| !folder.as_os_str().is_empty())
+        .unwrap_or(Path::new("."));
+    if !folder.is_dir() {
+        return Some("The folder doesn't exist");
+    }
+    let probe = folder.join(format!(".md-to-pdf-{}.tmp", process::id()));
+    match OpenOptions::new().write(true).create_new(true).open(&probe) {
+        Ok(_) => {
+            let _ = fs::remove_file(&probe);
+            None
+        }
+        // Left over from a run that didn't clean up, the folder is writable
+        Err(e) if e.kind() == std::io::ErrorKind::AlreadyExists => None,
+        Err(_) => Some("The folder can't be written to"),
+    }
+}
@@ -0,0 +1,532 @@
+use crate::encryption;
+use crate::images;
+use crate::impose;
+use crate::links;
+use crate::metadata;
+use crate::optimize;
+use crate::pdfa;
+use crate::prepress;
+use crate::reproducible;
+use crate::settings::Settings;
+use crate::stamp;
+use lopdf::content::Content;
+use lopdf::{Dictionary, Document, Object, ObjectId, Stream, dictionary};
+use std::path::Path;
+
+/// Page attributes that may be inherited from a parent `Pages` node and therefore have to
+/// be copied onto the page itself when it's moved into another page tree
+const INHERITABLE_PAGE_KEYS: &[&[u8]] = &[b"Resources", b"MediaBox", b"CropBox", b"Rotate"];
+
+/// Concatenates the PDFs at `inputs` into a single document written to `output`.
+/// Inputs flagged in `recto` are made to start on an odd page by inserting a blank page
+/// before them where needed. Returns the number of pages of each input, counting any blank
+/// page inserted after it.
+pub fn merge_files(
+    inputs: &[impl AsRef<Path>],
+    recto: &[bool],
+    output: &Path,
+) -> lopdf::Result<Vec<usize>> {
+    let mut documents: Vec<Document> = Vec::new();
+    let mut page_counts: Vec<usize> = Vec::new();
+    for (i, input) in inputs.iter().enumerate() {
+        let document = Document::load(input)?;
+        let total: usize = page_counts.iter().sum();
+        if recto.get(i).copied().unwrap_or(false)
+            && total % 2 == 1
+            && let (Some(previous), Some(count)) = (documents.last(), page_counts.last_mut())
+        {
+            let media_box = previous
+                .get_pages()
+                .into_values()
+                .last()
+                .and_then(|page_id| media_box(previous, page_id))
+                .unwrap_or([0.0, 0.0, 595.0, 842.0]);
+            documents.push(blank_document(media_box));
+            *count += 1;
+        }
+        page_counts.push(document.get_pages().len());
+        documents.push(document);
+    }
+    let mut merged = merge(documents)?;
+    merged.compress();
+    merged.save(output)?;
+    Ok(page_counts)
+}
+
+/// A document with a single empty page of the given size
+fn blank_document(media_box: [f32; 4]) -> Document {
+    let mut document = Document::with_version("1.5");
+    let pages_id = document.new_object_id();
+    let content_id = document.add_object(Stream::new(Dictionary::new(), Vec::new()));
+    let page_id = document.add_object(dictionary! {
+        "Type" => "Page",
+        "Parent" => pages_id,
+        "MediaBox" => media_box.iter().map(|&value| Object::Real(value)).collect::<Vec<_>>(),
+        "Resources" => Dictionary::new(),
+        "Contents" => content_id,
+    });
+    document.objects.insert(
+        pages_id,
+        Object::Dictionary(dictionary! {
+            "Type" => "Pages",
+            "Count" => 1,
+            "Kids" => vec![Object::Reference(page_id)],
+        }),
+    );
+    let catalog_id = document.add_object(dictionary! {
+        "Type" => "Catalog",
+        "Pages" => pages_id,
+    });
+    document.trailer.set("Root", catalog_id);
+    document
+}
+
+/// Concatenates `documents` page by page. Outlines of the inputs are dropped.
+pub fn merge(documents: Vec<Document>) -> lopdf::Result<Document> {
+    let mut merged = Document::with_version("1.5");
+    let pages_id = merged.new_object_id();
+    let mut next_id = pages_id.0 + 1;
+    let mut kids: Vec<ObjectId> = Vec::new();
+
+    for mut document in documents {
+        document.renumber_objects_with(next_id);
+        next_id = document.max_id + 1;
+
+        let page_ids: Vec<ObjectId> = document.get_pages().into_values().collect();
+        for &page_id in &page_ids {
+            let mut page = document.get_dictionary(page_id)?.clone();
+            for key in INHERITABLE_PAGE_KEYS {
+                if !page.has(key)
+                    && let Some(value) = inherited_attribute(&document, page_id, key)
+                {
+                    page.set(*key, value);
+                }
+            }
+            page.set("Parent", pages_id);
+            document.objects.insert(page_id, Object::Dictionary(page));
+        }
+        kids.extend(page_ids);
+
+        for (id, object) in document.objects {
+            match object.type_name().unwrap_or(b"") {
+                b"Catalog" | b"Pages" | b"Outlines" | b"Outline" => {}
+                _ => {
+                    merged.objects.insert(id, object);
+                }
+            }
+        }
+    }
+
+    merged.objects.insert(
+        pages_id,
+        Object::Dictionary(dictionary! {
+            "Type" => "Pages",
+            "Count" => kids.len() as u32,
+            "Kids" => kids.into_iter().map(Object::Reference).collect::<Vec<_>>(),
+        }),
+    );
+    merged.max_id = next_id - 1;
+    let catalog_id = merged.add_object(dictionary! {
+        "Type" => "Catalog",
+        "Pages" => pages_id,
+    });
+    merged.trailer.set("Root", catalog_id);
+    Ok(merged)
+}
+
+/// Adds the pages of `other` to `document`, in front of its first page when `before`, else
+/// after its last. Unlike [`merge`], the catalog of `document` stays, so its outline, links
+/// and page labels keep working; the page labels move back past pages inserted in front.
+/// The outline of `other` is dropped.
+pub fn insert_pages(
+    document: &mut Document,
+    mut other: Document,
+    before: bool,
+) -> Result<(), String> {
+    let pages_id = document
+        .catalog()
+        .and_then(|catalog| catalog.get(b"Pages"))
+        .and_then(Object::as_reference)
+        .map_err(|e| format!("Failed to read PDF page tree: {}", e))?;
+    other.renumber_objects_with(document.max_id + 1);
+    document.max_id = other.max_id;
+    if other.version > document.version {
+        document.version = other.version.clone();
+    }
+
+    let page_ids: Vec<ObjectId> = other.get_pages().into_values().collect();
+    for &page_id in &page_ids {
+        let mut page = other
+            .get_dictionary(page_id)
+            .map_err(|e| e.to_string())?
+            .clone();
+        for key in INHERITABLE_PAGE_KEYS {
+            if !page.has(key)
+                && let Some(value) = inherited_attribute(&other, page_id, key)
+            {
+                page.set(*key, value);
+            }
+        }
+        page.set("Parent", pages_id);
+        other.objects.insert(page_id, Object::Dictionary(page));
+    }
+    for (id, object) in other.objects {
+        match object.type_name().unwrap_or(b"") {
+            b"Catalog" | b"Pages" | b"Outlines" | b"Outline" => {}
+            _ => {
+                document.objects.insert(id, object);
+            }
+        }
+    }
+
+    let pages = document
+        .get_dictionary_mut(pages_id)
+        .map_err(|e| format!("Failed to read PDF page tree: {}", e))?;
+    let count = pages.get(b"Count").and_then(Object::as_i64).unwrap_or(0);
+    pages.set("Count", count + page_ids.len() as i64);
+    let kids = pages
+        .get_mut(b"Kids")
+        .and_then(Object::as_array_mut)
+        .map_err(|e| format!("Failed to read PDF page tree: {}", e))?;
+    let inserted = page_ids.iter().map(|&id| Object::Reference(id));
+    if before {
+        kids.splice(0..0, inserted);
+    } else {
+        kids.extend(inserted);
+    }
+
+    if before
+        && let Ok(catalog) = document.catalog_mut()
+        && let Ok(Object::Dictionary(labels)) = catalog.get_mut(b"PageLabels")
+        && let Ok(nums) = labels.get_mut(b"Nums").and_then(Object::as_array_mut)
+    {
+        for index in nums.iter_mut().step_by(2) {
+            if let Object::Integer(start) = index {
+                *start += page_ids.len() as i64;
+            }
+        }
+        nums.splice(
+            0..0,
+            [
+                Object::Integer(0),
+                Object::Dictionary(dictionary! { "S" => "D" }),
+            ],
+        );
+    }
+    Ok(())
+}
+
+/// Loads the cover or appendix PDF at `path` to insert into the document
+fn load_attached(path: &str, name: &str) -> Result<Document, String> {
+    Document::load(path).map_err(|e| format!("Failed to read {} PDF '{}': {}", name, path, e))
+}
+
+/// Looks up `key` on the page or the closest ancestor `Pages` node that defines it
+pub fn inherited_attribute(document: &Document, page_id: ObjectId, key: &[u8]) -> Option<Object> {
+    let mut node = document.get_dictionary(page_id).ok()?;
+    loop {
+        if let Ok(value) = node.get(key) {
+            return Some(value.clone());
+        }
+        let parent = node.get(b"Parent").and_then(Object::as_reference).ok()?;
+        node = document.get_dictionary(parent).ok()?;
+    }
+}
+
+/// Returns the page's MediaBox as `[x0, y0, x1, y1]`
+pub fn media_box(document: &Document, page_id: ObjectId) -> Option<[f32; 4]> {
+    page_box(document, page_id, b"MediaBox")
+}
+
+/// Returns one of the page's boxes (e.g. `TrimBox`) as `[x0, y0, x1, y1]`
+pub fn page_box(document: &Document, page_id: ObjectId, key: &[u8]) -> Option<[f32; 4]> {
+    let values = inherited_attribute(document, page_id, key)?;
+    let values = values.as_array().ok()?;
+    let mut media_box = [0.0; 4];
+    for (slot, value) in media_box.iter_mut().zip(values) {
+        *slot = value.as_float().ok()?;
+    }
+    Some(media_box)
+}
+
+/// Registers `object_id` as `/name` in the page's `category` resources (e.g. `Font`),
+/// copying shared or inherited resource dictionaries so other pages aren't affected
+pub fn add_page_resource(
+    document: &mut Document,
+    page_id: ObjectId,
+    category: &[u8],
+    name: &[u8],
+    object_id: ObjectId,
+) -> Result<(), String> {
+    let mut resources = match inherited_attribute(document, page_id, b"Resources") {
+        Some(Object::Reference(id)) => document
+            .get_dictionary(id)
+            .map_err(|e| e.to_string())?
+            .clone(),
+        Some(Object::Dictionary(dictionary)) => dictionary,
+        _ => Dictionary::new(),
+    };
+    let mut entries = match resources.get(category) {
+        Ok(Object::Reference(id)) => document
+            .get_dictionary(*id)
+            .map_err(|e| e.to_string())?
+            .clone(),
+        Ok(Object::Dictionary(dictionary)) => dictionary.clone(),
+        _ => Dictionary::new(),
+    };
+    entries.set(name, object_id);
+    resources.set(category, entries);
+
+    let page = document
+        .get_object_mut(page_id)
+        .and_then(Object::as_dict_mut)
+        .map_err(|e| e.to_string())?;
+    page.set("Resources", resources);
+    Ok(())
+}
+
+/// Draws `content` on top of the page's existing content
+pub fn append_page_content(
+    document: &mut Document,
+    page_id: ObjectId,
+    content: Content,
+) -> Result<(), String> {
+    let data = content.encode().map_err(|e| e.to_string())?;
+    // Wrap the existing content in q/Q so its graphics state can't leak into ours
+    document
+        .add_page_contents(page_id, data)
+        .map_err(|e| e.to_string())?;
+    let contents = document
+        .get_dictionary(page_id)
+        .and_then(|page| page.get(b"Contents"))
+        .and_then(Object::as_array)
+        .map_err(|e| e.to_string())?
+        .clone();
+    let save_id = document.add_object(Stream::new(Dictionary::new(), b"q\n".to_vec()));
+    let restore_id = document.add_object(Stream::new(Dictionary::new(), b"\nQ\n".to_vec()));
+    let mut wrapped = vec![Object::Reference(save_id)];
+    wrapped.extend(contents[..contents.len() - 1].iter().cloned());
+    wrapped.push(Object::Reference(restore_id));
+    wrapped.push(contents[contents.len() - 1].clone());
+    document
+        .get_object_mut(page_id)
+        .and_then(Object::as_dict_mut)
+        .map_err(|e| e.to_string())?
+        .set("Contents", wrapped);
+    Ok(())
+}
+
+/// Draws `content` underneath the page's existing content
+pub fn prepend_page_content(
+    document: &mut Document,
+    page_id: ObjectId,
+    content: Content,
+) -> Result<(), String> {
+    let data = content.encode().map_err(|e| e.to_string())?;
+    let content_id = document.add_object(Stream::new(Dictionary::new(), data));
+    let page = document
+        .get_object_mut(page_id)
+        .and_then(Object::as_dict_mut)
+        .map_err(|e| e.to_string())?;
+    let mut contents = vec![Object::Reference(content_id)];
+    match page.get(b"Contents") {
+        Ok(Object::Reference(id)) => contents.push(Object::Reference(*id)),
+        Ok(Object::Array(existing)) => contents.extend(existing.iter().cloned()),
+        _ => {}
+    }
+    page.set("Contents", contents);
+    Ok(())
+}
+
+/// Replaces the actions of the link annotations on the pages `page_ids` for which `replace`
+/// returns a new one. Returns how many were replaced.
+pub fn replace_link_actions(
+    document: &mut Document,
+    page_ids: &[ObjectId],
+    mut replace: impl FnMut(&Dictionary) -> Option<Dictionary>,
+) -> usize {
+    let mut replaced = 0;
+    for &page_id in page_ids {
+        let annotations = match document
+            .get_dictionary(page_id)
+            .and_then(|page| page.get(b"Annots"))
+        {
+            Ok(Object::Array(annotations)) => annotations.clone(),
+            Ok(Object::Reference(id)) => document
+                .get_object(*id)
+                .and_then(Object::as_array)
+                .cloned()
+                .unwrap_or_default(),
+            _ => continue,
+        };
+        // wkhtmltopdf writes annotations as objects of their own
+        for annotation_id in annotations
+            .iter()
+            .filter_map(|annotation| annotation.as_reference().ok())
+        {
+            let action = match document
+                .get_dictionary(annotation_id)
+                .and_then(|annotation| annotation.get(b"A"))
+            {
+                Ok(Object::Dictionary(action)) => action.clone(),
+                Ok(Object::Reference(id)) => match document.get_dictionary(*id) {
+                    Ok(action) => action.clone(),
+                    Err(_) => continue,
+                },
+                _ => continue,
+            };
+            if let Some(action) = replace(&action)
+                && let Ok(annotation) = document.get_dictionary_mut(annotation_id)
+            {
+                annotation.set("A", action);
+                replaced += 1;
+            }
+        }
+    }
+    replaced
+}
+
+/// The target of a link marked by [`links::rewrite_md_links`], as the path of the PDF and
+/// the fragment, None for any other action
+fn file_link_target(action: &Dictionary) -> Option<(String, String)> {
+    let uri = action.get(b"URI").ok()?.as_str().ok()?;
+    let target = String::from_utf8_lossy(uri);
+    let target = target.strip_prefix(links::FILE_LINK_SCHEME)?;
+    let (path, fragment) = target.split_once('#').unwrap_or((target, ""));
+    Some((
+        images::percent_decode(path),
+        images::percent_decode(fragment),
+    ))
+}
+
+/// Turns the links to other documents' PDFs into links that open those files, relative to
+/// this one, at the heading named by their fragment. Returns true if there were any.
+fn open_file_links(document: &mut Document) -> bool {
+    let page_ids: Vec<ObjectId> = document.get_pages().into_values().collect();
+    let replaced = replace_link_actions(document, &page_ids, |action| {
+        let (path, fragment) = file_link_target(action)?;
+        let destination = if fragment.is_empty() {
+            Object::Array(vec![0.into(), "Fit".into()])
+        } else {
+            Object::string_literal(fragment)
+        };
+        Some(dictionary! {
+            "S" => "GoToR",
+            "F" => Object::string_literal(path),
+            "D" => destination,
+            "NewWindow" => false,
+        })
+    });
+    replaced > 0
+}
+
+/// The PDF path of a link to another document that's been turned into a file link, None
+/// for any other action
+pub fn opened_file(action: &Dictionary) -> Option<String> {
+    if action.get(b"S").and_then(Object::as_name).ok()? != b"GoToR" {
+        return None;
+    }
+    let path = action.get(b"F").ok()?.as_str().ok()?;
+    Some(String::from_utf8_lossy(path).into_owned())
+}
+
+/// Applies the post-processing steps enabled in `settings` to the PDF at `path`, with the
+/// temporary files in `temp_dir`. The size report of optimized PDFs is added to `warnings`.
+pub fn post_process(
+    settings: &Settings,
+    path: &Path,
+    temp_dir: &Path,
+    warnings: &mut Vec<String>,
+) -> Result<(), String> {
+    settings.check_pdf_options()?;
+    let before = edit(settings, path, temp_dir)?;
+    if settings.pdf_a {
+        let timestamp = settings
+            .reproducible
+            .then(|| reproducible::timestamp(settings.metadata.creation_date()));
+        pdfa::convert(path, temp_dir, timestamp)?;
+    }
+    optimize::finish(settings, path, before, temp_dir, warnings)
+}
+
+/// Inserts the cover and appendix PDFs of `settings`, if any, around the pages of `document`
+pub fn attach_pdfs(settings: &Settings, document: &mut Document) -> Result<(), String> {
+    let cover = settings.cover_pdf.trim();
+    if !cover.is_empty() {
+        insert_pages(document, load_attached(cover, "cover")?, true)?;
+    }
+    let appendix = settings.appendix_pdf.trim();
+    if !appendix.is_empty() {
+        insert_pages(document, load_attached(appendix, "appendix")?, false)?;
+    }
+    Ok(())
+}
+
+/// Makes the changes enabled in `settings` to the PDF at `path`, which was rendered from
+/// files in `temp_dir`. Returns the size it had before optimizing, if it was optimized.
+fn edit(settings: &Settings, path: &Path, temp_dir: &Path) -> Result<Option<u64>, String> {
+    let background = settings.page_background();
+    let stamped = background.enabled
+        || settings.page_numbers
+        || settings.watermark.enabled
+        || settings.bates.enabled
+        || settings.print_marks.enabled
+        || settings.booklet
+        || settings.encryption.enabled
+        || settings.optimization.enabled
+        || !settings.cover_pdf.trim().is_empty()
+        || !settings.appendix_pdf.trim().is_empty();
+    let described = !settings.metadata.is_empty() || settings.reproducible;
+    if !stamped && !described && !settings.rewrite_md_links {
+        return Ok(None);
+    }
+
+    let mut document = Document::load(path)
+        .map_err(|e| format!("Failed to load PDF for post-processing: {}", e))?;
+    let linked = settings.rewrite_md_links && open_file_links(&mut document);
+    if !stamped && !described && !linked {
+        return Ok(None); // Nothing changed, the file stays as wkhtmltopdf wrote it
+    }
+    if background.enabled {
+        // Backgrounds run into the bleed, so they still reach the edge after trimming
+        let bleed = if settings.print_marks.enabled {
+            settings.print_marks.bleed()
+        } else {
+            0.0
+        };
+        stamp::apply_background(&mut document, &background, bleed)?;
+    }
+    if settings.page_numbers {
+        stamp::apply_page_numbers(&mut document)?;
+    }
+    if settings.watermark.enabled {
+        stamp::apply_watermark(&mut document, &settings.watermark)?;
+    }
+    // Added after stamping, so covers and appendices are left as they are, but before
+    // printing and imposition, which take in all pages
+    attach_pdfs(settings, &mut document)?;
+    if settings.bates.enabled {
+        // Every page of a production is numbered, the attached ones too
+        stamp::apply_bates(&mut document, &settings.bates)?;
+    }
+    if settings.print_marks.enabled {
+        prepress::apply_print_marks(&mut document, &settings.print_marks)?;
+    }
+    if settings.booklet {
+        impose::apply_booklet(&mut document)?;
+    }
+    let before = optimize::apply(&mut document, &settings.optimization)?;
+    if described {
+        metadata::apply(&mut document, &settings.metadata)?;
+    }
+    if settings.reproducible {
+        let timestamp = reproducible::timestamp(settings.metadata.creation_date());
+        reproducible::apply(&mut document, timestamp, temp_dir)?;
+    }
+    if settings.encryption.enabled {
+        encryption::apply(&mut document, &settings.encryption)?;
+    }
+    optimize::save(&mut document, path, &settings.optimization)
+        .map_err(|e| format!("Failed to save post-processed PDF: {}", e))?;
+    Ok(before)
+}
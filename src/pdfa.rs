@@ -0,0 +1,75 @@
+use crate::process;
+use crate::setup;
+use chrono::NaiveDateTime;
+use std::fs;
+use std::path::Path;
+use std::process::Command;
+
+/// Name of the Ghostscript command line program on this platform
+pub const GHOSTSCRIPT: &str = if cfg!(target_os = "windows") {
+    "gswin64c"
+} else {
+    "gs"
+};
+
+/// PostScript setting up the output intent PDF/A requires: the sRGB profile that comes
+/// with Ghostscript, which the colors are converted to
+const PDFA_DEF: &str = r#"%!
+/ICCProfile (%rom%iccprofiles/srgb.icc) def
+[/_objdef {icc_PDFA} /type /stream /OBJ pdfmark
+[{icc_PDFA} << /N 3 >> /PUT pdfmark
+[{icc_PDFA} ICCProfile (r) file /PUT pdfmark
+[/_objdef {OutputIntent_PDFA} /type /dict /OBJ pdfmark
+[{OutputIntent_PDFA} <<
+  /Type /OutputIntent
+  /S /GTS_PDFA1
+  /DestOutputProfile {icc_PDFA}
+  /OutputConditionIdentifier (sRGB)
+>> /PUT pdfmark
+[{Catalog} << /OutputIntents [ {OutputIntent_PDFA} ] >> /PUT pdfmark
+"#;
+
+/// Rewrites the PDF at `path` as PDF/A-2b with Ghostscript, which embeds all fonts, adds
+/// the XMP metadata from the document information and the sRGB output intent. With a
+/// `timestamp`, Ghostscript writes it as the dates, for reproducible output. The temporary
+/// files are written to `temp_dir`.
+pub fn convert(
+    path: &Path,
+    temp_dir: &Path,
+    timestamp: Option<NaiveDateTime>,
+) -> Result<(), String> {
+    let definition = temp_dir.join("pdfa_def.ps");
+    let output = temp_dir.join("pdfa_output.pdf");
+    fs::write(&definition, PDFA_DEF)
+        .map_err(|e| format!("Failed to write temporary PDF/A definition: {}", e))?;
+
+    let mut command = Command::new(GHOSTSCRIPT);
+    command
+        .args(["-dPDFA=2", "-dBATCH", "-dNOPAUSE", "-dQUIET"])
+        .args(["-sDEVICE=pdfwrite", "-sColorConversionStrategy=RGB"])
+        // Leave out what PDF/A doesn't allow, rather than failing or writing plain PDF
+        .arg("-dPDFACompatibilityPolicy=1")
+        .arg(format!("-sOutputFile={}", output.display()))
+        .arg(&definition)
+        .arg(path);
+    if let Some(timestamp) = timestamp {
+        command.env(
+            "SOURCE_DATE_EPOCH",
+            timestamp.and_utc().timestamp().to_string(),
+        );
+    }
+    let result = match command.output() {
+        Ok(command_output) if command_output.status.success() => fs::copy(&output, path)
+            .map(|_| ())
+            .map_err(|e| format!("Failed to replace PDF with the PDF/A file: {}", e)),
+        Ok(command_output) => Err(process::failure("Ghostscript", &command, &command_output)),
+        Err(e) => Err(format!(
+            "Failed to execute Ghostscript, which writes PDF/A: {}. {}",
+            e,
+            setup::install_hint(GHOSTSCRIPT)
+        )),
+    };
+    let _ = fs::remove_file(&definition);
+    let _ = fs::remove_file(&output);
+    result
+}
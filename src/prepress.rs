@@ -0,0 +1,117 @@
+use crate::pdf;
+use lopdf::content::{Content, Operation};
+use lopdf::{Document, Object};
+use std::path::Path;
+
+const POINTS_PER_MM: f32 = 72.0 / 25.4;
+/// Length of the crop marks and their distance from the bleed edge, in points
+const MARK_LENGTH: f32 = 18.0;
+const MARK_OFFSET: f32 = 6.0;
+/// Line width of the crop marks, in points
+const MARK_WIDTH: f32 = 0.25;
+/// Largest bleed offered, in millimetres
+pub const MAX_BLEED_MM: f32 = 10.0;
+
+/// Bleed and crop marks for sending the output to a print shop
+#[derive(Debug, Clone, PartialEq)]
+pub struct PrintMarks {
+    pub enabled: bool,
+    pub bleed_mm: f32, // How far backgrounds extend past the trim edge
+}
+
+impl Default for PrintMarks {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            bleed_mm: 3.0,
+        }
+    }
+}
+
+impl PrintMarks {
+    /// Bleed in points
+    pub fn bleed(&self) -> f32 {
+        self.bleed_mm.clamp(0.0, MAX_BLEED_MM) * POINTS_PER_MM
+    }
+}
+
+/// Enlarges every page by the bleed plus room for crop marks and draws the marks at the
+/// corners of the original page, which becomes the page's TrimBox
+pub fn apply_print_marks(document: &mut Document, marks: &PrintMarks) -> Result<(), String> {
+    let bleed = marks.bleed();
+    let margin = bleed + MARK_OFFSET + MARK_LENGTH;
+
+    for page_id in document.get_pages().into_values() {
+        let [x0, y0, x1, y1] =
+            pdf::media_box(document, page_id).unwrap_or([0.0, 0.0, 595.0, 842.0]);
+        let page = document
+            .get_object_mut(page_id)
+            .and_then(Object::as_dict_mut)
+            .map_err(|e| e.to_string())?;
+        page.set("TrimBox", rect([x0, y0, x1, y1]));
+        page.set(
+            "BleedBox",
+            rect([x0 - bleed, y0 - bleed, x1 + bleed, y1 + bleed]),
+        );
+        page.set(
+            "MediaBox",
+            rect([x0 - margin, y0 - margin, x1 + margin, y1 + margin]),
+        );
+        page.remove(b"CropBox");
+
+        let mut operations = vec![
+            Operation::new("q", vec![]),
+            Operation::new("G", vec![0.into()]),
+            Operation::new("w", vec![MARK_WIDTH.into()]),
+        ];
+        let (near, far) = (bleed + MARK_OFFSET, margin);
+        for (x, y, dx, dy) in [
+            (x0, y0, -1.0, -1.0),
+            (x1, y0, 1.0, -1.0),
+            (x0, y1, -1.0, 1.0),
+            (x1, y1, 1.0, 1.0),
+        ] {
+            // A horizontal mark level with the trim edge and a vertical one next to it
+            line(&mut operations, (x + dx * near, y), (x + dx * far, y));
+            line(&mut operations, (x, y + dy * near), (x, y + dy * far));
+        }
+        operations.push(Operation::new("Q", vec![]));
+        pdf::append_page_content(document, page_id, Content { operations })?;
+    }
+    Ok(())
+}
+
+fn line(operations: &mut Vec<Operation>, from: (f32, f32), to: (f32, f32)) {
+    operations.extend([
+        Operation::new("m", vec![from.0.into(), from.1.into()]),
+        Operation::new("l", vec![to.0.into(), to.1.into()]),
+        Operation::new("S", vec![]),
+    ]);
+}
+
+fn rect(values: [f32; 4]) -> Object {
+    Object::Array(values.iter().map(|&value| Object::Real(value)).collect())
+}
+
+/// Describes the trim size(s) of the PDF at `path`, e.g. "Trim size 210 × 297 mm, 3 mm bleed",
+/// or returns None if it has no crop marks
+pub fn trim_summary(path: &Path) -> Option<String> {
+    let document = Document::load(path).ok()?;
+    let mut sizes: Vec<String> = Vec::new();
+    let mut bleed = 0.0;
+    for page_id in document.get_pages().into_values() {
+        let trim = pdf::page_box(&document, page_id, b"TrimBox")?;
+        if let Some(bleed_box) = pdf::page_box(&document, page_id, b"BleedBox") {
+            bleed = (trim[0] - bleed_box[0]) / POINTS_PER_MM;
+        }
+        let size = format!(
+            "{:.0} × {:.0} mm",
+            (trim[2] - trim[0]) / POINTS_PER_MM,
+            (trim[3] - trim[1]) / POINTS_PER_MM
+        );
+        if !sizes.contains(&size) {
+            sizes.push(size);
+        }
+    }
+    (!sizes.is_empty()).then(|| format!("Trim size {}, {:.0} mm bleed", sizes.join(" / "), bleed))
+}
@@ -0,0 +1,67 @@
+/// JPEG quality and resolution used when a preset recompresses images
+const RECOMPRESSED_IMAGE_QUALITY: u32 = 60;
+const RECOMPRESSED_IMAGE_DPI: u32 = 150;
+/// JPEG quality and resolution used for draft quality output, regardless of the preset
+const DRAFT_IMAGE_QUALITY: u32 = 40;
+const DRAFT_IMAGE_DPI: u32 = 96;
+
+/// A named set of output size/quality options.
+/// Each preset carries its own toggles so e.g. "Archival" can keep originals
+/// while "Email" compresses aggressively.
+#[derive(Debug, Clone, PartialEq)]
+pub struct OutputPreset {
+    pub name: String,
+    /// Compress the embedded font programs (and other PDF streams)
+    pub compress_fonts: bool,
+    /// Re-encode images as lower quality, lower resolution JPEGs
+    pub recompress_images: bool,
+}
+
+impl OutputPreset {
+    /// Returns the presets available out of the box
+    pub fn builtin() -> Vec<OutputPreset> {
+        vec![
+            OutputPreset {
+                name: "Archival".to_string(),
+                compress_fonts: false,
+                recompress_images: false,
+            },
+            OutputPreset {
+                name: "Standard".to_string(),
+                compress_fonts: true,
+                recompress_images: false,
+            },
+            OutputPreset {
+                name: "Email".to_string(),
+                compress_fonts: true,
+                recompress_images: true,
+            },
+        ]
+    }
+
+    /// wkhtmltopdf arguments implementing the preset.
+    /// wkhtmltopdf always subsets fonts, so the font toggle controls stream compression.
+    /// `draft` trades quality for speed and size: low quality rendering and downsampled images.
+    pub fn wkhtmltopdf_args(&self, draft: bool) -> Vec<String> {
+        let mut args = Vec::new();
+        if !self.compress_fonts {
+            args.push("--no-pdf-compression".to_string());
+        }
+        if draft {
+            args.push("--lowquality".to_string());
+            args.push("--image-quality".to_string());
+            args.push(DRAFT_IMAGE_QUALITY.to_string());
+            args.push("--image-dpi".to_string());
+            args.push(DRAFT_IMAGE_DPI.to_string());
+        } else if self.recompress_images {
+            args.push("--image-quality".to_string());
+            args.push(RECOMPRESSED_IMAGE_QUALITY.to_string());
+            args.push("--image-dpi".to_string());
+            args.push(RECOMPRESSED_IMAGE_DPI.to_string());
+        } else {
+            args.push("--image-quality".to_string());
+            args.push("100".to_string());
+        }
+        args
+    }
+}
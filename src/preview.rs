@@ -0,0 +1,52 @@
+use crate::convert;
+use crate::front_matter::FrontMatter;
+use crate::paths::TempDir;
+use crate::settings::Settings;
+use image::RgbaImage;
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::Sender;
+use std::thread;
+
+/// Width the preview is rendered at, in pixels; about an A4 page at 96 dpi
+pub const WIDTH: u32 = 794;
+
+/// A finished preview, or why it couldn't be rendered
+pub type Rendered = Result<RgbaImage, String>;
+
+/// Renders the Markdown file at `md_path` the way it would be converted, as one tall image
+pub fn render(settings: &Settings, md_path: &Path) -> Rendered {
+    let md_text = fs::read_to_string(md_path)
+        .map_err(|e| format!("Failed to read '{}': {}", md_path.display(), e))?;
+    let (front_matter, _) = FrontMatter::extract(&md_text);
+    let base_dir = md_path.parent().unwrap_or(Path::new("."));
+    let mut warnings = Vec::new();
+    let settings = convert::document_settings(settings, &front_matter, base_dir, &mut warnings);
+    settings.check_styles()?;
+    let local_roots = settings.local_roots(base_dir);
+    let (html, _) = convert::build_html(
+        &settings,
+        &md_text,
+        base_dir,
+        &local_roots,
+        &HashMap::new(),
+        &mut warnings,
+    );
+    let temp_dir = TempDir::new()?;
+    convert::run_wkhtmltoimage(
+        &settings,
+        &html,
+        &local_roots,
+        &temp_dir.path().join("preview"),
+        WIDTH,
+        None,
+    )
+}
+
+/// Renders the preview on a background thread and sends the result when it's ready
+pub fn render_in_background(settings: Settings, md_path: PathBuf, sender: Sender<Rendered>) {
+    thread::spawn(move || {
+        let _ = sender.send(render(&settings, &md_path));
+    });
+}
@@ -0,0 +1,56 @@
+use crate::remote;
+use std::ffi::OsStr;
+use std::process::{Command, Output};
+
+/// Formats `command` as it would be typed in a shell, quoting arguments with spaces or quotes.
+/// Passwords in URLs, such as the proxy's, are left out.
+pub fn command_line(command: &Command) -> String {
+    let quote = |arg: &OsStr| {
+        let arg = remote::redact_credentials(&arg.to_string_lossy());
+        if arg.is_empty() || arg.contains(char::is_whitespace) || arg.contains(['"', '\'']) {
+            format!("\"{}\"", arg.replace('\\', "\\\\").replace('"', "\\\""))
+        } else {
+            arg.to_string()
+        }
+    };
+    std::iter::once(command.get_program())
+        .chain(command.get_args())
+        .map(quote)
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Error message for a command that exited unsuccessfully: a one-line summary naming `what`
+/// failed, followed by the command line and everything it printed
+pub fn failure(what: &str, command: &Command, output: &Output) -> String {
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let mut message = format!("{} failed ({})", what, output.status);
+    if let Some(line) = summary_line(&stderr) {
+        message.push_str(": ");
+        message.push_str(line);
+    }
+    format!(
+        "{}\nCommand: {}\n\nStderr:\n{}\nStdout:\n{}",
+        message,
+        command_line(command),
+        stderr.trim_end(),
+        stdout.trim_end()
+    )
+}
+
+/// The line of a command's error output most likely to explain the failure: the last one
+/// mentioning an error, otherwise the last one. Progress bars redrawn with `\r` count as lines.
+fn summary_line(stderr: &str) -> Option<&str> {
+    let lines: Vec<&str> = stderr
+        .split(['\n', '\r'])
+        .map(str::trim)
+        .filter(|line| !line.is_empty())
+        .collect();
+    lines
+        .iter()
+        .rev()
+        .find(|line| line.to_lowercase().contains("error"))
+        .or(lines.last())
+        .copied()
+}
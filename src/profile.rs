@@ -0,0 +1,311 @@
+use crate::config;
+use crate::highlight::Palette;
+use crate::links::LinkStyle;
+use crate::settings::Settings;
+use crate::style::{self, HeadingPreset, TableBorders, TableWidth};
+use crate::theme::{ColorScheme, CustomStylesheet, Theme};
+use crate::themes;
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+use std::str::FromStr;
+
+/// File extension of style profiles
+pub const EXTENSION: &str = "mdpdfstyle";
+
+/// Format version written to profiles, for telling future formats apart
+const VERSION: &str = "1";
+
+/// Prefix of the custom stylesheet's entries
+const STYLESHEET_PREFIX: &str = "stylesheet.";
+
+/// Writes the appearance settings (theme, stylesheet and overrides, page setup, page numbers
+/// and watermark) to a style profile at `path`, in the configuration files' `key = value`
+/// format so profiles can be reviewed and versioned by teams
+pub fn export(settings: &Settings, path: &Path) -> Result<(), String> {
+    fs::write(path, config::format(&entries(settings)))
+        .map_err(|e| format!("Failed to write style profile '{}': {}", path.display(), e))
+}
+
+/// Applies the style profile at `path` to `settings`. Settings the profile doesn't mention
+/// are left alone, and relative file paths are resolved against the profile's directory.
+/// Values that can't be used are skipped and returned as warnings.
+pub fn import(path: &Path, settings: &mut Settings) -> Result<Vec<String>, String> {
+    let text = fs::read_to_string(path)
+        .map_err(|e| format!("Failed to read style profile '{}': {}", path.display(), e))?;
+    let entries = config::parse(&text);
+    if !entries.contains_key("version") {
+        return Err(format!(
+            "'{}' is not a style profile (no version entry)",
+            path.display()
+        ));
+    }
+    Ok(apply(
+        &entries,
+        path.parent().unwrap_or(Path::new(".")),
+        settings,
+    ))
+}
+
+/// The style profile entries of the appearance settings
+pub fn entries(settings: &Settings) -> BTreeMap<String, String> {
+    let mut entries = BTreeMap::new();
+    let mut set = |key: &str, value: String| {
+        entries.insert(key.to_string(), value);
+    };
+    set("version", VERSION.to_string());
+    set("theme", settings.theme.id().to_string());
+    set(
+        "user_theme",
+        settings.user_theme.clone().unwrap_or_default(),
+    );
+    set("color_scheme", settings.color_scheme.keyword().to_string());
+    set("additional_css", settings.additional_css.clone());
+    set("print_media", settings.print_media.to_string());
+    set("link_style", settings.link_style.keyword().to_string());
+    set("headings", settings.heading_preset.keyword().to_string());
+    set("book_paragraphs", settings.book_paragraphs.to_string());
+    set("image_backing", settings.image_backing.to_string());
+    set("force_backgrounds", settings.force_backgrounds.to_string());
+    set(
+        "syntax_highlighting",
+        settings.syntax_highlighting.to_string(),
+    );
+    set(
+        "highlight_palette",
+        settings
+            .highlight_palette
+            .map_or("theme", |palette| palette.keyword())
+            .to_string(),
+    );
+
+    let style = &settings.style;
+    set("style.enabled", style.enabled.to_string());
+    set("style.font_size", style.font_size.to_string());
+    set("style.line_height", style.line_height.to_string());
+    set("style.heading_scale", style.heading_scale.to_string());
+    set("style.link_color", style::hex(style.link_color));
+    set("style.table_striping", style.table_striping.to_string());
+    set("style.table_width", style.table_width.keyword().to_string());
+    set(
+        "style.table_borders",
+        style.table_borders.keyword().to_string(),
+    );
+    set(
+        "style.table_header_shading",
+        style.table_header_shading.to_string(),
+    );
+    set("style.margin_mm", style.margin_mm.to_string());
+
+    set("preset", settings.preset().name.clone());
+    set("zoom", settings.zoom.to_string());
+    set("columns", settings.columns.to_string());
+    set(
+        "chapters_on_odd_pages",
+        settings.chapters_on_odd_pages.to_string(),
+    );
+    set("page_numbers", settings.page_numbers.to_string());
+
+    set("style.accents", style.accents.enabled.to_string());
+    for (keyword, color) in style.accents.colors() {
+        set(&format!("style.accent.{keyword}"), style::hex(color));
+    }
+
+    let watermark = &settings.watermark;
+    set("watermark.enabled", watermark.enabled.to_string());
+    set("watermark.text", watermark.text.clone());
+    set("watermark.image", watermark.image.clone());
+    set("watermark.opacity", watermark.opacity.to_string());
+    set("watermark.rotation", watermark.rotation.to_string());
+
+    for (key, value) in settings.stylesheet.entries() {
+        entries.insert(format!("{STYLESHEET_PREFIX}{key}"), value);
+    }
+    entries
+}
+
+/// Applies style profile entries to `settings`, resolving relative file paths against
+/// `base_dir`. Returns warnings for the values that can't be used.
+pub fn apply(
+    entries: &BTreeMap<String, String>,
+    base_dir: &Path,
+    settings: &mut Settings,
+) -> Vec<String> {
+    let mut warnings = Vec::new();
+    let mut reader = Reader {
+        entries,
+        warnings: &mut warnings,
+    };
+
+    if let Some(theme) = reader.keyword("theme", Theme::all(), Theme::id) {
+        settings.theme = theme;
+    }
+    if let Some(id) = entries.get("user_theme") {
+        if !id.is_empty() && themes::find(id).is_none() {
+            reader
+                .warnings
+                .push(format!("Style profile: theme '{id}' is not installed"));
+        }
+        settings.user_theme = (!id.is_empty()).then(|| id.clone());
+    }
+    if let Some(scheme) = reader.keyword("color_scheme", ColorScheme::all(), ColorScheme::keyword) {
+        settings.color_scheme = scheme;
+    }
+    if let Some(css) = entries.get("additional_css") {
+        settings.additional_css = css.clone();
+    }
+    reader.value("print_media", &mut settings.print_media);
+    if let Some(style) = reader.keyword("link_style", LinkStyle::all(), LinkStyle::keyword) {
+        settings.link_style = style;
+    }
+    reader.value("book_paragraphs", &mut settings.book_paragraphs);
+    reader.value("image_backing", &mut settings.image_backing);
+    if let Some(preset) = reader.keyword("headings", HeadingPreset::all(), HeadingPreset::keyword) {
+        settings.heading_preset = preset;
+    }
+    reader.value("force_backgrounds", &mut settings.force_backgrounds);
+    reader.value("syntax_highlighting", &mut settings.syntax_highlighting);
+    if entries.get("highlight_palette").map(String::as_str) == Some("theme") {
+        settings.highlight_palette = None;
+    } else if let Some(palette) =
+        reader.keyword("highlight_palette", Palette::all(), Palette::keyword)
+    {
+        settings.highlight_palette = Some(palette);
+    }
+
+    let style = &mut settings.style;
+    reader.value("style.enabled", &mut style.enabled);
+    reader.value("style.font_size", &mut style.font_size);
+    reader.value("style.line_height", &mut style.line_height);
+    reader.value("style.heading_scale", &mut style.heading_scale);
+    reader.color("style.link_color", &mut style.link_color);
+    reader.value("style.table_striping", &mut style.table_striping);
+    if let Some(width) = reader.keyword("style.table_width", TableWidth::all(), TableWidth::keyword)
+    {
+        style.table_width = width;
+    }
+    if let Some(borders) = reader.keyword(
+        "style.table_borders",
+        TableBorders::all(),
+        TableBorders::keyword,
+    ) {
+        style.table_borders = borders;
+    }
+    reader.value(
+        "style.table_header_shading",
+        &mut style.table_header_shading,
+    );
+    reader.value("style.margin_mm", &mut style.margin_mm);
+
+    if let Some(name) = entries.get("preset") {
+        match settings
+            .presets
+            .iter()
+            .position(|preset| &preset.name == name)
+        {
+            Some(index) => settings.selected_preset = index,
+            None => reader.invalid("preset", name),
+        }
+    }
+    reader.value("zoom", &mut settings.zoom);
+    reader.value("columns", &mut settings.columns);
+    reader.value("chapters_on_odd_pages", &mut settings.chapters_on_odd_pages);
+    reader.value("page_numbers", &mut settings.page_numbers);
+
+    reader.value("style.accents", &mut style.accents.enabled);
+    for (keyword, _, color) in style.accents.colors_mut() {
+        reader.color(&format!("style.accent.{keyword}"), color);
+    }
+
+    let watermark = &mut settings.watermark;
+    reader.value("watermark.enabled", &mut watermark.enabled);
+    if let Some(text) = entries.get("watermark.text") {
+        watermark.text = text.clone();
+    }
+    if let Some(image) = entries.get("watermark.image") {
+        watermark.image = resolve_path(base_dir, image);
+    }
+    reader.value("watermark.opacity", &mut watermark.opacity);
+    reader.value("watermark.rotation", &mut watermark.rotation);
+
+    let stylesheet: BTreeMap<String, String> = entries
+        .iter()
+        .filter_map(|(key, value)| {
+            key.strip_prefix(STYLESHEET_PREFIX)
+                .map(|key| (key.to_string(), value.clone()))
+        })
+        .collect();
+    if !stylesheet.is_empty() {
+        settings.stylesheet = CustomStylesheet::from_entries(&stylesheet);
+        settings.stylesheet.file = resolve_path(base_dir, &settings.stylesheet.file);
+    }
+    warnings
+}
+
+/// Reads typed values from profile entries, collecting warnings for unusable ones
+pub struct Reader<'a> {
+    pub entries: &'a BTreeMap<String, String>,
+    pub warnings: &'a mut Vec<String>,
+}
+
+impl Reader<'_> {
+    /// Parses the entry `key` into `target`, if present and valid
+    pub fn value<T: FromStr>(&mut self, key: &str, target: &mut T) {
+        if let Some(value) = self.entries.get(key) {
+            match value.trim().parse() {
+                Ok(parsed) => *target = parsed,
+                Err(_) => self.invalid(key, value),
+            }
+        }
+    }
+
+    /// Looks up the entry `key` among `options` by their keyword
+    pub fn keyword<T: Copy>(
+        &mut self,
+        key: &str,
+        options: &[T],
+        keyword: fn(&T) -> &'static str,
+    ) -> Option<T> {
+        let value = self.entries.get(key)?;
+        let found = options
+            .iter()
+            .find(|option| keyword(option) == value.trim());
+        if found.is_none() {
+            self.invalid(key, value);
+        }
+        found.copied()
+    }
+
+    /// Parses the `#rrggbb` entry `key` into `target`, if present and valid
+    pub fn color(&mut self, key: &str, target: &mut [u8; 3]) {
+        if let Some(value) = self.entries.get(key) {
+            match parse_color(value) {
+                Some(color) => *target = color,
+                None => self.invalid(key, value),
+            }
+        }
+    }
+
+    fn invalid(&mut self, key: &str, value: &str) {
+        self.warnings
+            .push(format!("Style profile: ignored invalid {key} '{value}'"));
+    }
+}
+
+/// Parses a `#rrggbb` color
+fn parse_color(value: &str) -> Option<[u8; 3]> {
+    let hex = value.trim().strip_prefix('#')?;
+    if hex.len() != 6 {
+        return None;
+    }
+    let channel = |i: usize| u8::from_str_radix(hex.get(i..i + 2)?, 16).ok();
+    Some([channel(0)?, channel(2)?, channel(4)?])
+}
+
+/// Makes a relative path from a profile relative to the profile's directory
+fn resolve_path(base_dir: &Path, path: &str) -> String {
+    if path.trim().is_empty() || Path::new(path.trim()).is_absolute() {
+        return path.to_string();
+    }
+    base_dir.join(path.trim()).to_string_lossy().to_string()
+}
@@ -0,0 +1,69 @@
+use crate::config;
+use crate::session;
+use crate::settings::Settings;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Extension of the files named settings profiles are stored in
+const EXTENSION: &str = "conf";
+
+/// Directory holding the named settings profiles, e.g. `~/.config/md-to-pdf/profiles`.
+/// Unlike style profiles, these store every setting.
+fn dir() -> Option<PathBuf> {
+    config::dir().map(|dir| dir.join("profiles"))
+}
+
+/// Path of the profile `name`, rejecting names that aren't plain file names
+fn path(name: &str) -> Result<PathBuf, String> {
+    let name = name.trim();
+    if name.is_empty() || name.starts_with('.') || name.contains(['/', '\\', ':']) {
+        return Err(format!("'{name}' can't be used as a profile name"));
+    }
+    let dir = dir().ok_or("Failed to find the configuration directory")?;
+    Ok(dir.join(format!("{name}.{EXTENSION}")))
+}
+
+/// Names of the saved profiles, sorted
+pub fn list() -> Vec<String> {
+    let Some(entries) = dir().and_then(|dir| fs::read_dir(dir).ok()) else {
+        return Vec::new();
+    };
+    let mut names: Vec<String> = entries
+        .filter_map(|entry| {
+            let path = entry.ok()?.path();
+            if path.extension()? != EXTENSION {
+                return None;
+            }
+            Some(path.file_stem()?.to_string_lossy().to_string())
+        })
+        .collect();
+    names.sort_by_key(|name| name.to_lowercase());
+    names
+}
+
+/// Reads the settings of the profile `name`. Settings the profile doesn't store keep their
+/// defaults.
+pub fn load(name: &str) -> Result<Settings, String> {
+    let path = path(name)?;
+    let text = fs::read_to_string(&path)
+        .map_err(|e| format!("Failed to read profile '{}': {}", name, e))?;
+    let mut settings = Settings::default();
+    session::apply_settings(&config::parse(&text), Path::new(""), &mut settings);
+    Ok(settings)
+}
+
+/// Saves `settings` as the profile `name`, replacing a profile of that name
+pub fn save(name: &str, settings: &Settings) -> Result<(), String> {
+    let path = path(name)?;
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)
+            .map_err(|e| format!("Failed to create profile directory: {}", e))?;
+    }
+    fs::write(&path, config::format(&session::settings_entries(settings)))
+        .map_err(|e| format!("Failed to save profile '{}': {}", name, e))
+}
+
+/// Deletes the profile `name`
+pub fn remove(name: &str) -> Result<(), String> {
+    fs::remove_file(path(name)?).map_err(|e| format!("Failed to delete profile '{}': {}", name, e))
+}
@@ -0,0 +1,248 @@
+use crate::format::OutputFormat;
+use crate::session;
+use crate::settings::Settings;
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use toml::{Table, Value};
+
+/// File name ending of project files
+pub const EXTENSION: &str = "md2pdf.toml";
+
+/// Format version written to projects, for telling future formats apart
+const VERSION: i64 = 1;
+
+/// An input file and the PDF and other files it's converted to
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Target {
+    pub input: PathBuf,
+    pub output: PathBuf,
+    pub outputs: Vec<(OutputFormat, PathBuf)>, // Paths of other formats, when not next to the PDF
+    pub overrides: BTreeMap<String, String>, // Settings of this input that differ from the project's
+}
+
+/// Inputs converted with shared settings, saved to a `.md2pdf.toml` file so they can be
+/// opened again or built from the command line
+pub struct Project {
+    pub targets: Vec<Target>,       // In the order they're built
+    pub formats: Vec<OutputFormat>, // Written for every document, in this order
+    pub settings: Settings,
+}
+
+impl Project {
+    /// Writes the project to `path`. Files next to or below it are saved relative to it, so
+    /// the project can be moved along with its inputs.
+    pub fn save(&self, path: &Path) -> Result<(), String> {
+        let base_dir = path.parent().unwrap_or(Path::new(""));
+        let mut table = Table::new();
+        table.insert("version".to_string(), Value::Integer(VERSION));
+        if self.formats != [OutputFormat::Pdf] {
+            let formats = self
+                .formats
+                .iter()
+                .map(|format| Value::String(format.keyword().to_string()))
+                .collect();
+            table.insert("formats".to_string(), Value::Array(formats));
+        }
+        table.insert(
+            "settings".to_string(),
+            Value::Table(to_table(&session::settings_entries(&self.settings))),
+        );
+        let documents = self
+            .targets
+            .iter()
+            .map(|target| {
+                let mut document = Table::new();
+                let relative = |path: &Path| {
+                    let path = path.strip_prefix(base_dir).unwrap_or(path);
+                    Value::String(path.to_string_lossy().to_string())
+                };
+                document.insert("input".to_string(), relative(&target.input));
+                document.insert("output".to_string(), relative(&target.output));
+                if !target.outputs.is_empty() {
+                    let outputs = target
+                        .outputs
+                        .iter()
+                        .map(|(format, path)| (format.keyword().to_string(), relative(path)))
+                        .collect();
+                    document.insert("outputs".to_string(), Value::Table(outputs));
+                }
+                if !target.overrides.is_empty() {
+                    document.insert(
+                        "settings".to_string(),
+                        Value::Table(to_table(&target.overrides)),
+                    );
+                }
+                Value::Table(document)
+            })
+            .collect();
+        table.insert("document".to_string(), Value::Array(documents));
+        fs::write(path, table.to_string())
+            .map_err(|e| format!("Failed to write project '{}': {}", path.display(), e))
+    }
+
+    /// Reads the project at `path`. Settings it doesn't mention keep their defaults, and
+    /// relative paths are resolved against the project's directory. Values that can't be
+    /// used are skipped and returned as warnings.
+    pub fn load(path: &Path) -> Result<(Project, Vec<String>), String> {
+        let text = fs::read_to_string(path)
+            .map_err(|e| format!("Failed to read project '{}': {}", path.display(), e))?;
+        let table: Table = text
+            .parse()
+            .map_err(|e| format!("Failed to read project '{}': {}", path.display(), e))?;
+        if !table.contains_key("version") {
+            return Err(format!(
+                "'{}' is not a project (no version entry)",
+                path.display()
+            ));
+        }
+        let base_dir = path.parent().unwrap_or(Path::new(""));
+        let mut warnings = Vec::new();
+        let entries = from_table(table.get("settings"), "settings", &mut warnings);
+        let mut settings = Settings::default();
+        warnings.extend(session::apply_settings(&entries, base_dir, &mut settings));
+
+        let formats = match table.get("formats") {
+            Some(Value::Array(keywords)) => {
+                let mut formats = Vec::new();
+                for keyword in keywords {
+                    match keyword.as_str().and_then(OutputFormat::from_keyword) {
+                        Some(format) if !formats.contains(&format) => formats.push(format),
+                        Some(_) => {}
+                        None => warnings.push(format!("Project: ignored unknown format {keyword}")),
+                    }
+                }
+                formats
+            }
+            Some(_) => {
+                warnings.push("Project: ignored 'formats', which isn't a list".to_string());
+                vec![OutputFormat::Pdf]
+            }
+            None => vec![OutputFormat::Pdf],
+        };
+
+        let documents = match table.get("document") {
+            Some(Value::Array(documents)) => documents.as_slice(),
+            Some(_) => {
+                warnings.push("Project: ignored 'document', which isn't a list".to_string());
+                &[]
+            }
+            None => &[],
+        };
+        let mut targets = Vec::new();
+        for (index, document) in documents.iter().enumerate() {
+            let Some(input) = document.get("input").and_then(Value::as_str) else {
+                warnings.push(format!("Project: document {} has no input", index + 1));
+                continue;
+            };
+            let input = base_dir.join(input);
+            let output = match document.get("output").and_then(Value::as_str) {
+                Some(output) => base_dir.join(output),
+                None => input.with_extension("pdf"),
+            };
+            let section = format!("document {} outputs", index + 1);
+            let outputs = from_table(document.get("outputs"), &section, &mut warnings)
+                .into_iter()
+                .filter_map(
+                    |(keyword, path)| match OutputFormat::from_keyword(&keyword) {
+                        Some(format) => Some((format, base_dir.join(path))),
+                        None => {
+                            warnings.push(format!(
+                                "Project: ignored unknown format {keyword} in {section}"
+                            ));
+                            None
+                        }
+                    },
+                )
+                .collect();
+            let section = format!("document {} settings", index + 1);
+            let overrides = from_table(document.get("settings"), &section, &mut warnings);
+            targets.push(Target {
+                input,
+                output,
+                outputs,
+                overrides,
+            });
+        }
+        Ok((
+            Project {
+                targets,
+                formats,
+                settings,
+            },
+            warnings,
+        ))
+    }
+}
+
+impl Target {
+    /// The project's settings with this input's overrides applied, and the warnings for the
+    /// overrides that can't be used. Relative paths in them are resolved against `base_dir`,
+    /// the project's directory, like those of the project's settings.
+    pub fn settings(&self, project: &Settings, base_dir: &Path) -> (Settings, Vec<String>) {
+        let mut settings = project.clone();
+        let warnings = session::apply_settings(&self.overrides, base_dir, &mut settings);
+        (settings, warnings)
+    }
+
+    /// The files to write this input to, in `formats` followed by the other formats it has
+    /// a path for. The PDF is written to `output`, and formats without a path of their own
+    /// next to it with their extension.
+    pub fn outputs(&self, formats: &[OutputFormat]) -> Vec<(OutputFormat, PathBuf)> {
+        let mut all = formats.to_vec();
+        for (format, _) in &self.outputs {
+            if !all.contains(format) {
+                all.push(*format);
+            }
+        }
+        all.into_iter()
+            .map(|format| {
+                let path = match self.outputs.iter().find(|(other, _)| *other == format) {
+                    Some((_, path)) => path.clone(),
+                    None if format == OutputFormat::Pdf => self.output.clone(),
+                    None => self.output.with_extension(format.extension()),
+                };
+                (format, path)
+            })
+            .collect()
+    }
+}
+
+/// Settings entries as a TOML table. Values stay strings, as in the other configuration files.
+fn to_table(entries: &BTreeMap<String, String>) -> Table {
+    entries
+        .iter()
+        .map(|(key, value)| (key.clone(), Value::String(value.clone())))
+        .collect()
+}
+
+/// Settings entries from a TOML table. Numbers and booleans are accepted as well as strings,
+/// since projects are also written by hand.
+fn from_table(
+    table: Option<&Value>,
+    section: &str,
+    warnings: &mut Vec<String>,
+) -> BTreeMap<String, String> {
+    let Some(table) = table else {
+        return BTreeMap::new();
+    };
+    let Some(table) = table.as_table() else {
+        warnings.push(format!("Project: ignored {section}, which isn't a table"));
+        return BTreeMap::new();
+    };
+    let mut entries = BTreeMap::new();
+    for (key, value) in table {
+        let value = match value {
+            Value::String(text) => text.clone(),
+            Value::Integer(number) => number.to_string(),
+            Value::Float(number) => number.to_string(),
+            Value::Boolean(flag) => flag.to_string(),
+            _ => {
+                warnings.push(format!("Project: ignored invalid {key} in {section}"));
+                continue;
+            }
+        };
+        entries.insert(key.clone(), value);
+    }
+    entries
+}
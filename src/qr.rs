@@ -0,0 +1,147 @@
+use crate::convert::escape_attr;
+use pulldown_cmark::{CowStr, Event, Tag, TagEnd};
+use qrcode::{Color, QrCode};
+use url::Url;
+
+/// Opening of a QR code directive in the text, e.g. `{{qr:https://example.com}}`
+const DIRECTIVE_OPENING: &str = "{{qr:";
+const DIRECTIVE_CLOSING: &str = "}}";
+
+/// Attribute block after a link that puts a QR code of its URL next to it
+const LINK_CLASS: &str = "{.qr}";
+
+/// Blank modules around the code that scanners need to find it
+const QUIET_ZONE: usize = 4;
+
+/// Styles of the QR codes: a fixed size that scans well in print, next to the text
+pub const QR_CSS: &str = r#".markdown-body .md-qr {
+  display: inline-block;
+  vertical-align: middle;
+  margin: 0 0.25em;
+}
+.markdown-body .md-qr svg {
+  width: 2.5cm;
+  height: 2.5cm;
+}
+"#;
+
+/// The QR code of `text` as inline SVG in a `md-qr` span, black on white so it scans on
+/// dark themes too
+pub fn qr_html(text: &str) -> Result<String, String> {
+    let code = QrCode::new(text.as_bytes())
+        .map_err(|e| format!("Failed to make a QR code of '{}': {}", text, e))?;
+    let width = code.width();
+    let size = width + 2 * QUIET_ZONE;
+    let mut path = String::new();
+    for (i, color) in code.to_colors().into_iter().enumerate() {
+        if color == Color::Dark {
+            let (x, y) = (i % width + QUIET_ZONE, i / width + QUIET_ZONE);
+            path.push_str(&format!("M{x} {y}h1v1h-1z"));
+        }
+    }
+    let label = escape_attr(text);
+    Ok(format!(
+        "<span class=\"md-qr\" title=\"{label}\"><svg xmlns=\"http://www.w3.org/2000/svg\" \
+         viewBox=\"0 0 {size} {size}\" shape-rendering=\"crispEdges\" role=\"img\" \
+         aria-label=\"QR code: {label}\"><rect width=\"{size}\" height=\"{size}\" \
+         fill=\"#fff\"/><path fill=\"#000\" d=\"{path}\"/></svg></span>"
+    ))
+}
+
+/// Joins runs of text events, which the parser splits at characters such as `_`, so
+/// directives aren't cut apart
+fn join_text(events: Vec<Event<'_>>) -> Vec<Event<'_>> {
+    let mut out: Vec<Event> = Vec::with_capacity(events.len());
+    for event in events {
+        match (out.last_mut(), event) {
+            (Some(Event::Text(last)), Event::Text(text)) => {
+                *last = CowStr::from(format!("{last}{text}"));
+            }
+            (_, event) => out.push(event),
+        }
+    }
+    out
+}
+
+/// Replaces the `{{qr:...}}` directives in `text` with QR codes of their contents.
+/// Directives that can't be encoded are left as they are, with a warning.
+fn replace_directives<'a>(text: &str, warnings: &mut Vec<String>) -> Vec<Event<'a>> {
+    let mut out = Vec::new();
+    let mut rest = text;
+    while let Some(start) = rest.find(DIRECTIVE_OPENING) {
+        let after = &rest[start + DIRECTIVE_OPENING.len()..];
+        let Some(end) = after.find(DIRECTIVE_CLOSING) else {
+            break;
+        };
+        let content = after[..end].trim();
+        let directive_end = start + DIRECTIVE_OPENING.len() + end + DIRECTIVE_CLOSING.len();
+        match qr_html(content) {
+            Ok(html) => {
+                out.push(Event::Text(rest[..start].to_string().into()));
+                out.push(Event::InlineHtml(html.into()));
+            }
+            Err(message) => {
+                warnings.push(message);
+                out.push(Event::Text(rest[..directive_end].to_string().into()));
+            }
+        }
+        rest = &rest[directive_end..];
+    }
+    out.push(Event::Text(rest.to_string().into()));
+    out.retain(|event| !matches!(event, Event::Text(text) if text.is_empty()));
+    out
+}
+
+/// Puts QR codes into the document: in place of `{{qr:https://example.com}}` directives,
+/// which may hold any text, and after links marked with `{.qr}`, e.g.
+/// `[Slides](https://example.com/slides){.qr}`. Relative link URLs are resolved against
+/// `base` when given, and skipped with a warning otherwise since a code of them would lead
+/// nowhere. Directives in code are left alone.
+pub fn apply<'a>(
+    events: Vec<Event<'a>>,
+    base: Option<&Url>,
+    warnings: &mut Vec<String>,
+) -> Vec<Event<'a>> {
+    let events = join_text(events);
+    let mut out = Vec::with_capacity(events.len());
+    let mut links = Vec::new(); // URLs of the open links, innermost last
+    let mut marked = None; // URL of the link just closed
+    for event in events {
+        let pending = marked.take();
+        match event {
+            Event::Start(Tag::Link { ref dest_url, .. }) => {
+                links.push(dest_url.to_string());
+                out.push(event);
+            }
+            Event::End(TagEnd::Link) => {
+                marked = links.pop();
+                out.push(event);
+            }
+            Event::Text(mut text) => {
+                if let Some(url) = pending
+                    && let Some(rest) = text.strip_prefix(LINK_CLASS)
+                {
+                    let absolute = Url::parse(&url)
+                        .ok()
+                        .or_else(|| base.and_then(|base| base.join(&url).ok()));
+                    match absolute.map(|absolute| qr_html(absolute.as_str())) {
+                        Some(Ok(html)) => out.push(Event::InlineHtml(html.into())),
+                        Some(Err(message)) => warnings.push(message),
+                        None => warnings.push(format!(
+                            "No QR code for the relative link '{}', set a web base URL",
+                            url
+                        )),
+                    }
+                    text = rest.to_string().into();
+                }
+                if text.contains(DIRECTIVE_OPENING) {
+                    out.extend(replace_directives(&text, warnings));
+                } else if !text.is_empty() {
+                    out.push(Event::Text(text));
+                }
+            }
+            other => out.push(other),
+        }
+    }
+    out
+}
@@ -0,0 +1,89 @@
+use crate::config;
+use crate::profile;
+use crate::settings::Settings;
+use std::collections::BTreeMap;
+use std::path::Path;
+
+/// Configuration file the recent conversions are kept in
+const RECENT_CONFIG: &str = "recent.conf";
+
+/// Number of conversions remembered
+const LIMIT: usize = 10;
+
+/// A conversion that can be run again
+#[derive(Clone)]
+pub struct Recent {
+    pub input: String,
+    pub output: String,
+    pub settings: Settings, // Appearance settings used, as stored in style profiles
+}
+
+impl Recent {
+    /// Short description of the conversion: file names and theme
+    pub fn label(&self) -> String {
+        let name = |path: &str| {
+            Path::new(path)
+                .file_name()
+                .map_or(path.to_string(), |name| name.to_string_lossy().to_string())
+        };
+        let theme = match &self.settings.user_theme {
+            Some(id) => id.clone(),
+            None => self.settings.theme.name().to_string(),
+        };
+        format!("{} → {} ({})", name(&self.input), name(&self.output), theme)
+    }
+}
+
+/// Reads the remembered conversions, most recent first. Settings the entries don't store
+/// are taken from `base`.
+pub fn load(base: &Settings) -> Vec<Recent> {
+    let entries = config::load(RECENT_CONFIG);
+    let mut recent = Vec::new();
+    for index in 0..LIMIT {
+        let prefix = format!("{index}.");
+        let (Some(input), Some(output)) = (
+            entries.get(&format!("{prefix}input")),
+            entries.get(&format!("{prefix}output")),
+        ) else {
+            break;
+        };
+        let profile: BTreeMap<String, String> = entries
+            .iter()
+            .filter_map(|(key, value)| {
+                key.strip_prefix(&prefix)
+                    .and_then(|key| key.strip_prefix("settings."))
+                    .map(|key| (key.to_string(), value.clone()))
+            })
+            .collect();
+        let mut settings = base.clone();
+        profile::apply(&profile, Path::new(""), &mut settings);
+        recent.push(Recent {
+            input: input.clone(),
+            output: output.clone(),
+            settings,
+        });
+    }
+    recent
+}
+
+/// Puts `conversion` at the top of `recent`, replacing an earlier run with the same input and
+/// output, and remembers the list for the next start
+pub fn record(recent: &mut Vec<Recent>, conversion: Recent) -> Result<(), String> {
+    recent.retain(|other| other.input != conversion.input || other.output != conversion.output);
+    recent.insert(0, conversion);
+    recent.truncate(LIMIT);
+    save(recent)
+}
+
+/// Remembers `recent` for the next start
+pub fn save(recent: &[Recent]) -> Result<(), String> {
+    let mut entries = BTreeMap::new();
+    for (index, conversion) in recent.iter().enumerate() {
+        entries.insert(format!("{index}.input"), conversion.input.clone());
+        entries.insert(format!("{index}.output"), conversion.output.clone());
+        for (key, value) in profile::entries(&conversion.settings) {
+            entries.insert(format!("{index}.settings.{key}"), value);
+        }
+    }
+    config::save(RECENT_CONFIG, &entries)
+}
@@ -0,0 +1,351 @@
+use crate::checksum;
+use crate::config;
+use crate::process;
+use pulldown_cmark::{Event, Tag};
+use std::collections::BTreeMap;
+use std::fs;
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use url::Url;
+
+/// Seconds a download may take before it's given up
+const DOWNLOAD_TIMEOUT: u32 = 60;
+
+/// Seconds an image download may take, shorter since a document can have many
+const IMAGE_TIMEOUT: u32 = 30;
+
+/// Largest image downloaded, in bytes. Larger ones are left for wkhtmltopdf to load.
+const MAX_IMAGE_BYTES: u64 = 20 * 1024 * 1024;
+
+/// Days downloaded images are reused for unless chosen otherwise
+pub const DEFAULT_CACHE_DAYS: u32 = 7;
+
+/// File in the image cache listing, for the hash of each URL, when it was downloaded and
+/// the file holding its content
+const CACHE_INDEX: &str = "index.txt";
+
+/// Environment variables a proxy is taken from when none is set, in order of preference
+const PROXY_VARIABLES: &[&str] = &[
+    "HTTPS_PROXY",
+    "https_proxy",
+    "HTTP_PROXY",
+    "http_proxy",
+    "ALL_PROXY",
+    "all_proxy",
+];
+
+/// The proxy downloads go through: `setting` if it isn't empty, otherwise the one in the
+/// `HTTPS_PROXY` or `HTTP_PROXY` environment variables, None for a direct connection
+pub fn proxy(setting: &str) -> Option<String> {
+    let setting = setting.trim();
+    if !setting.is_empty() {
+        return Some(setting.to_string());
+    }
+    PROXY_VARIABLES
+        .iter()
+        .filter_map(|name| std::env::var(name).ok())
+        .map(|value| value.trim().to_string())
+        .find(|value| !value.is_empty())
+}
+
+/// Where the `user:password@` part of `url` is, None if it has none
+fn credentials(url: &str) -> Option<Range<usize>> {
+    let start = url.find("://")? + 3;
+    let authority = url[start..]
+        .find(['/', '?', '#'])
+        .map_or(&url[start..], |end| &url[start..start + end]);
+    let at = authority.rfind('@')?;
+    Some(start..start + at + 1)
+}
+
+/// `url` without its user name and password, which aren't saved with the settings
+pub fn without_credentials(url: &str) -> String {
+    let mut url = url.to_string();
+    if let Some(range) = credentials(&url) {
+        url.replace_range(range, "");
+    }
+    url
+}
+
+/// `text` with the user name and password of the URL in it replaced by `***`, for showing
+/// command lines and settings
+pub fn redact_credentials(text: &str) -> String {
+    let mut text = text.to_string();
+    if let Some(range) = credentials(&text) {
+        text.replace_range(range, "***@");
+    }
+    text
+}
+
+/// Hosts the `NO_PROXY` environment variable says to reach directly
+pub fn no_proxy_hosts() -> Vec<String> {
+    ["NO_PROXY", "no_proxy"]
+        .iter()
+        .filter_map(|name| std::env::var(name).ok())
+        .flat_map(|value| {
+            value
+                .split(',')
+                .map(|host| host.trim().to_string())
+                .filter(|host| !host.is_empty())
+                .collect::<Vec<_>>()
+        })
+        .collect()
+}
+
+/// The http(s) URL in `text`, e.g. text dropped or pasted onto the window, None if it's
+/// anything else
+pub fn parse_url(text: &str) -> Option<Url> {
+    let text = text.trim();
+    if text.contains(char::is_whitespace) {
+        return None;
+    }
+    Url::parse(text)
+        .ok()
+        .filter(|url| matches!(url.scheme(), "http" | "https"))
+}
+
+/// curl fetching `url` through `proxy`, following redirects and failing on HTTP errors
+fn curl(url: &str, timeout: u32, proxy: Option<&str>) -> Command {
+    let mut command = Command::new("curl");
+    command
+        .args(["--fail", "--silent", "--show-error", "--location"])
+        .args(["--max-time", &timeout.to_string()])
+        .args(proxy.map(|proxy| ["--proxy", proxy]).into_iter().flatten())
+        .arg(url);
+    command
+}
+
+/// Runs a curl `command`, returning what it wrote to stdout
+fn run(mut command: Command) -> Result<Vec<u8>, String> {
+    match command.output() {
+        Ok(output) if output.status.success() => Ok(output.stdout),
+        Ok(output) => Err(process::failure("Download", &command, &output)),
+        Err(e) => Err(format!(
+            "Failed to execute curl: {}. Is curl installed and in your PATH?",
+            e
+        )),
+    }
+}
+
+/// Downloads the text at `url` with curl through `proxy`, following redirects
+pub fn download_text(url: &Url, proxy: Option<&str>) -> Result<String, String> {
+    let bytes = run(curl(url.as_str(), DOWNLOAD_TIMEOUT, proxy))?;
+    String::from_utf8(bytes).map_err(|_| format!("'{}' is not a text file", url))
+}
+
+/// Directory downloaded images are cached in, e.g. `~/.cache/md-to-pdf/images`, so
+/// watch mode and repeated builds don't fetch them again
+pub fn cache_dir() -> PathBuf {
+    config::cache_dir()
+        .unwrap_or_else(std::env::temp_dir)
+        .join("images")
+}
+
+/// Seconds since the Unix epoch
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |elapsed| elapsed.as_secs())
+}
+
+/// Reads the cache index: the hash of each URL, mapped to when it was downloaded and the
+/// name of the file holding it
+fn read_index(cache: &Path) -> BTreeMap<String, (u64, String)> {
+    let text = fs::read_to_string(cache.join(CACHE_INDEX)).unwrap_or_default();
+    config::parse(&text)
+        .into_iter()
+        .filter_map(|(key, value)| {
+            let (time, name) = value.split_once(' ')?;
+            Some((key, (time.parse().ok()?, name.to_string())))
+        })
+        .collect()
+}
+
+/// Writes the cache index and deletes the cached files no entry refers to any more
+fn write_index(cache: &Path, index: &BTreeMap<String, (u64, String)>) -> Result<(), String> {
+    let entries = index
+        .iter()
+        .map(|(key, (time, name))| (key.clone(), format!("{time} {name}")))
+        .collect();
+    fs::write(cache.join(CACHE_INDEX), config::format(&entries))
+        .map_err(|e| format!("Failed to write image cache index: {}", e))?;
+    for entry in fs::read_dir(cache).into_iter().flatten().flatten() {
+        let name = entry.file_name().to_string_lossy().to_string();
+        let used = name == CACHE_INDEX
+            || name.ends_with(".part")
+            || index.values().any(|(_, used)| *used == name);
+        if !used {
+            let _ = fs::remove_file(entry.path());
+        }
+    }
+    Ok(())
+}
+
+/// Downloads the image at `url` through `proxy` and returns the file it's saved to. Images
+/// are cached by their content, so converting again within `max_age` doesn't fetch them
+/// again, and URLs serving the same image share a file. When an expired image can't be
+/// fetched again, the cached copy is used.
+pub fn download_image(
+    url: &str,
+    proxy: Option<&str>,
+    max_age: Duration,
+) -> Result<PathBuf, String> {
+    let cache = cache_dir();
+    fs::create_dir_all(&cache)
+        .map_err(|e| format!("Failed to create image cache '{}': {}", cache.display(), e))?;
+    let key = checksum::key(&[url.as_bytes()]);
+    let cached = read_index(&cache)
+        .remove(&key)
+        .map(|(time, name)| (time, cache.join(name)))
+        .filter(|(_, path)| path.is_file());
+    if let Some((time, path)) = &cached
+        && now().saturating_sub(*time) < max_age.as_secs()
+    {
+        return Ok(path.clone());
+    }
+
+    // The extension tells wkhtmltopdf how to read SVG images
+    let extension = Url::parse(url)
+        .ok()
+        .and_then(|url| {
+            let name = url.path_segments()?.next_back()?.to_string();
+            let (_, extension) = name.rsplit_once('.')?;
+            (extension.len() <= 5 && extension.chars().all(|c| c.is_ascii_alphanumeric()))
+                .then(|| extension.to_ascii_lowercase())
+        })
+        .unwrap_or_else(|| "img".to_string());
+    let partial = cache.join(format!("{key}.part"));
+    let mut command = curl(url, IMAGE_TIMEOUT, proxy);
+    command
+        .args(["--max-filesize", &MAX_IMAGE_BYTES.to_string()])
+        .arg("--output")
+        .arg(&partial);
+    let result = run(command).and_then(|_| {
+        let bytes =
+            fs::read(&partial).map_err(|e| format!("Failed to cache image '{}': {}", url, e))?;
+        let name = format!("{}.{}", checksum::key(&[&bytes]), extension);
+        let path = cache.join(&name);
+        if !path.is_file() {
+            fs::rename(&partial, &path)
+                .map_err(|e| format!("Failed to cache image '{}': {}", url, e))?;
+        }
+        // Read again in case another conversion updated it meanwhile
+        let mut index = read_index(&cache);
+        index.insert(key, (now(), name));
+        write_index(&cache, &index)?;
+        Ok(path)
+    });
+    let _ = fs::remove_file(&partial);
+    match (result, cached) {
+        (Err(_), Some((_, path))) => Ok(path),
+        (result, _) => result,
+    }
+}
+
+/// Empties the image cache, returning how many images were deleted
+pub fn clear_image_cache() -> Result<usize, String> {
+    let mut count = 0;
+    for entry in fs::read_dir(cache_dir()).into_iter().flatten().flatten() {
+        fs::remove_file(entry.path()).map_err(|e| format!("Failed to clear image cache: {}", e))?;
+        if entry.file_name() != CACHE_INDEX {
+            count += 1;
+        }
+    }
+    Ok(count)
+}
+
+/// Downloads the http(s) images of the document through `proxy`, reusing those cached
+/// within `max_age`, and points them at the downloaded files. Images that fail to download
+/// keep their URL, with a warning.
+pub fn download_images<'a>(
+    events: Vec<Event<'a>>,
+    proxy: Option<&str>,
+    max_age: Duration,
+    warnings: &mut Vec<String>,
+) -> Vec<Event<'a>> {
+    events
+        .into_iter()
+        .map(|event| match event {
+            Event::Start(Tag::Image {
+                link_type,
+                dest_url,
+                title,
+                id,
+            }) if parse_url(&dest_url).is_some() => {
+                let dest_url = match download_image(&dest_url, proxy, max_age) {
+                    Ok(path) => path.to_string_lossy().to_string().into(),
+                    Err(message) => {
+                        warnings.push(format!(
+                            "Failed to download image '{}': {}",
+                            dest_url, message
+                        ));
+                        dest_url
+                    }
+                };
+                Event::Start(Tag::Image {
+                    link_type,
+                    dest_url,
+                    title,
+                    id,
+                })
+            }
+            other => other,
+        })
+        .collect()
+}
+
+/// Name for the PDF of the document at `url`: its file name with a `.pdf` extension, e.g.
+/// `README.pdf` for a raw README link
+pub fn pdf_name(url: &Url) -> String {
+    let name = url
+        .path_segments()
+        .and_then(|mut segments| segments.next_back())
+        .filter(|name| !name.is_empty())
+        .unwrap_or("download");
+    PathBuf::from(name)
+        .with_extension("pdf")
+        .to_string_lossy()
+        .to_string()
+}
+
+/// Makes the relative destinations of links and images absolute, resolving them against
+/// `base` like a browser showing the document at that URL would. Links within the document
+/// are left alone.
+pub fn resolve_relative<'a>(events: Vec<Event<'a>>, base: &Url) -> Vec<Event<'a>> {
+    let resolve = |dest: &str| {
+        if dest.starts_with('#') || Url::parse(dest).is_ok() {
+            return None;
+        }
+        base.join(dest).ok().map(|url| url.to_string().into())
+    };
+    events
+        .into_iter()
+        .map(|event| match event {
+            Event::Start(Tag::Link {
+                link_type,
+                dest_url,
+                title,
+                id,
+            }) => Event::Start(Tag::Link {
+                link_type,
+                dest_url: resolve(&dest_url).unwrap_or(dest_url),
+                title,
+                id,
+            }),
+            Event::Start(Tag::Image {
+                link_type,
+                dest_url,
+                title,
+                id,
+            }) => Event::Start(Tag::Image {
+                link_type,
+                dest_url: resolve(&dest_url).unwrap_or(dest_url),
+                title,
+                id,
+            }),
+            event => event,
+        })
+        .collect()
+}
@@ -0,0 +1,102 @@
+use crate::metadata;
+use chrono::{DateTime, NaiveDateTime};
+use lopdf::{Document, Object, StringFormat};
+use std::path::Path;
+
+/// Environment variable fixing the timestamps of builds, see reproducible-builds.org
+const SOURCE_DATE_EPOCH: &str = "SOURCE_DATE_EPOCH";
+
+/// The timestamp written into reproducible PDFs: `date` when the document has one, else the
+/// time in `SOURCE_DATE_EPOCH`, else the start of 1970
+pub fn timestamp(date: Option<NaiveDateTime>) -> NaiveDateTime {
+    date.or_else(|| {
+        let seconds = std::env::var(SOURCE_DATE_EPOCH).ok()?.trim().parse().ok()?;
+        Some(DateTime::from_timestamp(seconds, 0)?.naive_utc())
+    })
+    .unwrap_or_default()
+}
+
+/// Replaces `from` with `to` in `bytes`
+fn replace_bytes(bytes: &[u8], from: &[u8], to: &[u8]) -> Vec<u8> {
+    let mut result = Vec::with_capacity(bytes.len());
+    let mut rest = bytes;
+    while let Some(index) = rest.windows(from.len()).position(|window| window == from) {
+        result.extend_from_slice(&rest[..index]);
+        result.extend_from_slice(to);
+        rest = &rest[index + from.len()..];
+    }
+    result.extend_from_slice(rest);
+    result
+}
+
+/// Removes the temporary folder the HTML was rendered from out of the strings of `object`,
+/// such as the targets of links wkhtmltopdf resolved against it, so they don't depend on
+/// the machine
+fn strip_temp_dir(object: &mut Object, temp_dir: &[u8]) {
+    match object {
+        Object::String(bytes, _) if bytes.windows(temp_dir.len()).any(|w| w == temp_dir) => {
+            let stripped = replace_bytes(bytes, &[b"file://", temp_dir].concat(), b"");
+            *bytes = replace_bytes(&stripped, temp_dir, b"");
+        }
+        Object::Array(items) => {
+            for item in items {
+                strip_temp_dir(item, temp_dir);
+            }
+        }
+        Object::Dictionary(dictionary) => {
+            for (_, value) in dictionary.iter_mut() {
+                strip_temp_dir(value, temp_dir);
+            }
+        }
+        Object::Stream(stream) => {
+            for (_, value) in stream.dict.iter_mut() {
+                strip_temp_dir(value, temp_dir);
+            }
+        }
+        _ => {}
+    }
+}
+
+/// An ID for `document` derived from its content, so it only changes with the content
+fn content_id(document: &mut Document) -> Result<Vec<u8>, String> {
+    document.trailer.remove(b"ID");
+    let mut bytes = Vec::new();
+    document
+        .save_to(&mut bytes)
+        .map_err(|e| format!("Failed to serialize PDF: {}", e))?;
+    // 16 bytes as the specification suggests, from four differently seeded checksums
+    let mut id = Vec::with_capacity(16);
+    for seed in 0u8..4 {
+        let mut hasher = crc32fast::Hasher::new();
+        hasher.update(&[seed]);
+        hasher.update(&bytes);
+        id.extend_from_slice(&hasher.finalize().to_be_bytes());
+    }
+    Ok(id)
+}
+
+/// Makes `document` the same for the same input on any machine: the creation and
+/// modification dates are set to `timestamp`, temporary paths are removed and the document
+/// ID is derived from the content. Has to run after all other changes. `temp_dir` is the
+/// folder the document was rendered in.
+pub fn apply(
+    document: &mut Document,
+    timestamp: NaiveDateTime,
+    temp_dir: &Path,
+) -> Result<(), String> {
+    let temp_dir = temp_dir.join("");
+    let temp_dir = temp_dir.to_string_lossy();
+    for object in document.objects.values_mut() {
+        strip_temp_dir(object, temp_dir.as_bytes());
+    }
+
+    let date = Object::string_literal(timestamp.format("D:%Y%m%d%H%M%SZ").to_string());
+    let info = metadata::info_mut(document)?;
+    info.set("CreationDate", date.clone());
+    info.set("ModDate", date);
+
+    let id = content_id(document)?;
+    let id = Object::String(id, StringFormat::Hexadecimal);
+    document.trailer.set("ID", vec![id.clone(), id]);
+    Ok(())
+}
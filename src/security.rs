@@ -0,0 +1,774 @@
+use crate::convert::escape_attr;
+use crate::images;
+use pulldown_cmark::{CowStr, Event, Tag, TagEnd};
+use std::path::{Component, Path, PathBuf};
+use url::Url;
+
+/// Default Content-Security-Policy for generated HTML: no scripts, frames or remote
+/// resources, only inline styles plus local and embedded images/fonts. The local files are
+/// narrowed to the document's folders by [`scoped_policy`].
+pub const DEFAULT_CSP: &str = "default-src 'none'; script-src 'none'; object-src 'none'; frame-src 'none'; \
+style-src 'unsafe-inline'; img-src 'self' file: data:; font-src 'self' file: data:";
+
+/// Builds the `<meta>` tag that applies `policy` to the document.
+/// Returns an empty string for a blank policy.
+pub fn csp_meta(policy: &str) -> String {
+    let policy = policy.trim();
+    if policy.is_empty() {
+        return String::new();
+    }
+    format!(
+        r#"<meta http-equiv="Content-Security-Policy" content="{}">"#,
+        escape_attr(policy)
+    )
+}
+
+/// `policy` with the sources that allow any local file, `'self'` and `file:`, narrowed to
+/// the folders `roots`, so the document can't load files from elsewhere on the computer
+pub fn scoped_policy(policy: &str, roots: &[PathBuf]) -> String {
+    let mut sources: Vec<String> = roots
+        .iter()
+        .filter_map(|root| std::path::absolute(root).ok())
+        .filter_map(|root| Url::from_directory_path(root).ok())
+        .map(|url| {
+            // A wildcard host matches the empty one of local files. Semicolons and commas
+            // would end the directive.
+            url.as_str()
+                .replacen("file://", "file://*", 1)
+                .replace(';', "%3B")
+                .replace(',', "%2C")
+        })
+        .collect();
+    sources.sort();
+    sources.dedup();
+    policy
+        .split(';')
+        .map(|directive| {
+            let mut scoped = Vec::new();
+            let mut narrowed = false;
+            for source in directive.split_whitespace() {
+                if source.eq_ignore_ascii_case("'self'") || source.eq_ignore_ascii_case("file:") {
+                    if !narrowed {
+                        scoped.extend(sources.iter().map(String::as_str));
+                        narrowed = true;
+                    }
+                } else {
+                    scoped.push(source);
+                }
+            }
+            scoped.join(" ")
+        })
+        .collect::<Vec<_>>()
+        .join("; ")
+}
+
+/// Returns true when `policy` lets the document load http(s) images,
+/// going by its `img-src` directive or, failing that, `default-src`
+pub fn allows_remote_images(policy: &str) -> bool {
+    let sources = |name: &str| {
+        policy.split(';').find_map(|directive| {
+            let mut parts = directive.split_whitespace();
+            parts
+                .next()?
+                .eq_ignore_ascii_case(name)
+                .then(|| parts.map(str::to_lowercase).collect::<Vec<_>>())
+        })
+    };
+    let Some(sources) = sources("img-src").or_else(|| sources("default-src")) else {
+        return true;
+    };
+    sources.iter().any(|source| {
+        source == "*"
+            || source == "http:"
+            || source == "https:"
+            || source.starts_with("http://")
+            || source.starts_with("https://")
+    })
+}
+
+/// Which URLs documents may use in links, images and embedded HTML, for converting Markdown
+/// that isn't trusted
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum UrlPolicy {
+    Any,       // No restrictions
+    Allowlist, // Allowed schemes and paths in the document's folder, others removed with a warning
+    Strict,    // Like `Allowlist`, but documents using other URLs fail to convert
+}
+
+impl UrlPolicy {
+    /// Returns the display name for the policy
+    pub fn name(&self) -> &'static str {
+        match self {
+            UrlPolicy::Any => "Any URL",
+            UrlPolicy::Allowlist => "Allowed only, remove others",
+            UrlPolicy::Strict => "Allowed only, fail on others",
+        }
+    }
+
+    /// Returns all available policies
+    pub fn all() -> &'static [UrlPolicy] {
+        &[UrlPolicy::Any, UrlPolicy::Allowlist, UrlPolicy::Strict]
+    }
+
+    /// Identifier used in saved settings
+    pub fn keyword(&self) -> &'static str {
+        match self {
+            UrlPolicy::Any => "any",
+            UrlPolicy::Allowlist => "allowlist",
+            UrlPolicy::Strict => "strict",
+        }
+    }
+}
+
+/// Schemes allowed unless chosen otherwise. Relative paths are always allowed as long as
+/// they stay in the document's folder.
+pub const DEFAULT_ALLOWED_SCHEMES: &str = "https mailto";
+
+/// Start of the warning for each URL the policy removes, which strict mode turns into an
+/// error
+const NOT_ALLOWED: &str = "URL not allowed";
+
+/// Attributes of raw HTML tags that load or link to a URL
+const URL_ATTRIBUTES: &[&str] = &[
+    "src",
+    "href",
+    "xlink:href",
+    "srcset",
+    "data",
+    "poster",
+    "action",
+    "formaction",
+    "background",
+];
+
+/// Attributes SVG animations set other attributes to, such as an image's `href`
+const ANIMATION_ATTRIBUTES: &[&str] = &["to", "from", "by", "values"];
+
+/// Elements whose content is script or CSS rather than HTML, up to their closing tag
+const RAW_TEXT_ELEMENTS: &[&str] = &["script", "style"];
+
+/// CSS functions whose string arguments are URLs, besides `url()`
+const CSS_URL_FUNCTIONS: &[&str] = &["image-set(", "image(", "src("];
+
+/// A start or end tag of raw HTML
+pub struct HtmlTag {
+    pub name: String, // Lowercase, without the `/` of end tags
+    pub end: bool,
+    pub attributes: Vec<(String, String)>, // Names and values as written
+    pub length: usize,                     // Up to and including the `>`
+}
+
+/// Parses the tag at the start of `html` the way browsers do: quoted values may contain
+/// `>` and quotes elsewhere are part of the names. None if `html` doesn't start with a tag
+/// or the tag doesn't end.
+pub fn parse_tag(html: &str) -> Option<HtmlTag> {
+    let bytes = html.as_bytes();
+    let separator = |byte: u8| byte.is_ascii_whitespace() || byte == b'/' || byte == b'>';
+    let end = bytes.get(1) == Some(&b'/');
+    let mut i = 1 + usize::from(end);
+    if !bytes.get(i)?.is_ascii_alphabetic() {
+        return None;
+    }
+    let name_start = i;
+    while i < bytes.len() && !separator(bytes[i]) {
+        i += 1;
+    }
+    let name = html[name_start..i].to_ascii_lowercase();
+    let mut attributes = Vec::new();
+    loop {
+        while i < bytes.len() && (bytes[i].is_ascii_whitespace() || bytes[i] == b'/') {
+            i += 1;
+        }
+        if *bytes.get(i)? == b'>' {
+            return Some(HtmlTag {
+                name,
+                end,
+                attributes,
+                length: i + 1,
+            });
+        }
+        // A name takes at least one character, even `=`
+        let attribute_start = i;
+        i += 1;
+        while i < bytes.len() && !separator(bytes[i]) && bytes[i] != b'=' {
+            i += 1;
+        }
+        let attribute = &html[attribute_start..i];
+        while i < bytes.len() && bytes[i].is_ascii_whitespace() {
+            i += 1;
+        }
+        let mut value = "";
+        if bytes.get(i) == Some(&b'=') {
+            i += 1;
+            while i < bytes.len() && bytes[i].is_ascii_whitespace() {
+                i += 1;
+            }
+            match *bytes.get(i)? {
+                quote @ (b'"' | b'\'') => {
+                    let close = i + 1 + html[i + 1..].find(char::from(quote))?;
+                    value = &html[i + 1..close];
+                    i = close + 1;
+                }
+                _ => {
+                    let start = i;
+                    while i < bytes.len() && !bytes[i].is_ascii_whitespace() && bytes[i] != b'>' {
+                        i += 1;
+                    }
+                    value = &html[start..i];
+                }
+            }
+        }
+        attributes.push((attribute.to_string(), value.to_string()));
+    }
+}
+
+/// Where the closing tag of the `name` element starts in `html`, its content, and where
+/// it ends, as browsers find it: `</name` followed by a space, `/` or `>`
+pub fn closing_tag(html: &str, name: &str) -> Option<(usize, usize)> {
+    let lower = html.to_ascii_lowercase();
+    let pattern = format!("</{name}");
+    let mut search = 0;
+    while let Some(found) = lower[search..].find(&pattern) {
+        let start = search + found;
+        let after = start + pattern.len();
+        match lower.as_bytes().get(after) {
+            Some(byte) if byte.is_ascii_whitespace() || *byte == b'/' || *byte == b'>' => {
+                let end = lower[after..]
+                    .find('>')
+                    .map_or(html.len(), |end| after + end + 1);
+                return Some((start, end));
+            }
+            None => return Some((start, html.len())),
+            Some(_) => search = after,
+        }
+    }
+    None
+}
+
+/// `value` with its character references decoded as browsers do before using it, None if
+/// it has a named one that isn't known here and can't be checked
+pub fn decode_references(value: &str) -> Option<String> {
+    const NAMED: &[(&str, char)] = &[
+        ("amp", '&'),
+        ("lt", '<'),
+        ("gt", '>'),
+        ("quot", '"'),
+        ("apos", '\''),
+        ("colon", ':'),
+        ("sol", '/'),
+        ("bsol", '\\'),
+        ("period", '.'),
+        ("comma", ','),
+        ("semi", ';'),
+        ("num", '#'),
+        ("percnt", '%'),
+        ("quest", '?'),
+        ("equals", '='),
+        ("commat", '@'),
+        ("lpar", '('),
+        ("rpar", ')'),
+        ("lowbar", '_'),
+        ("hyphen", '-'),
+        ("tab", '\t'),
+        ("newline", '\n'),
+        ("nbsp", '\u{a0}'),
+    ];
+    let mut decoded = String::with_capacity(value.len());
+    let mut rest = value;
+    while let Some(start) = rest.find('&') {
+        decoded.push_str(&rest[..start]);
+        rest = &rest[start + 1..];
+        if let Some(number) = rest.strip_prefix('#') {
+            // The semicolon is optional for numbers
+            let (digits, radix) = match number.strip_prefix(['x', 'X']) {
+                Some(hex) => (hex, 16),
+                None => (number, 10),
+            };
+            let length = digits
+                .find(|c: char| !c.is_digit(radix))
+                .unwrap_or(digits.len());
+            let code = u32::from_str_radix(&digits[..length], radix).ok();
+            decoded.push(code.and_then(char::from_u32).unwrap_or('\u{fffd}'));
+            rest = &digits[length..];
+            rest = rest.strip_prefix(';').unwrap_or(rest);
+            continue;
+        }
+        let length = rest
+            .find(|c: char| !c.is_ascii_alphanumeric())
+            .unwrap_or(rest.len());
+        if length == 0 || !rest[length..].starts_with(';') {
+            decoded.push('&');
+            continue;
+        }
+        let name = &rest[..length];
+        let (_, character) = NAMED
+            .iter()
+            .find(|(named, _)| named.eq_ignore_ascii_case(name))?;
+        decoded.push(*character);
+        rest = &rest[length + 1..];
+    }
+    decoded.push_str(rest);
+    Some(decoded)
+}
+
+/// `css` with its escapes decoded, as in `u\72l(` or `\2F etc`
+fn decode_css_escapes(css: &str) -> String {
+    let mut decoded = String::with_capacity(css.len());
+    let mut chars = css.chars().peekable();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            decoded.push(c);
+            continue;
+        }
+        let mut hex = String::new();
+        while hex.len() < 6
+            && let Some(digit) = chars.next_if(char::is_ascii_hexdigit)
+        {
+            hex.push(digit);
+        }
+        if hex.is_empty() {
+            // An escaped newline continues a string, any other character stands for itself
+            match chars.next() {
+                Some('\n') | None => {}
+                Some(other) => decoded.push(other),
+            }
+        } else {
+            let code = u32::from_str_radix(&hex, 16).ok();
+            decoded.push(code.and_then(char::from_u32).unwrap_or('\u{fffd}'));
+            chars.next_if(|c| c.is_whitespace());
+        }
+    }
+    decoded
+}
+
+/// The URLs `css` loads: its `url()` references, `@import` strings and the strings of
+/// functions like `image-set()`. Comments aren't skipped, so URLs in them count too.
+fn css_urls(css: &str) -> Vec<String> {
+    let css = decode_css_escapes(css);
+    // Same length, so positions carry over
+    let lower = css.to_ascii_lowercase();
+    let string_at = |start: usize| -> Option<(String, usize)> {
+        let quote = css[start..]
+            .chars()
+            .next()
+            .filter(|c| *c == '"' || *c == '\'')?;
+        let end = css[start + 1..]
+            .find(quote)
+            .map_or(css.len(), |end| start + 1 + end);
+        Some((css[start + 1..end].to_string(), end + 1))
+    };
+    let skip_spaces = |at: usize| {
+        css[at..]
+            .find(|c: char| !c.is_whitespace())
+            .map_or(css.len(), |found| at + found)
+    };
+    let mut urls = Vec::new();
+    for (at, _) in lower.match_indices("url(") {
+        let start = skip_spaces(at + "url(".len());
+        match string_at(start) {
+            Some((url, _)) => urls.push(url),
+            None => {
+                let end = css[start..].find(')').map_or(css.len(), |end| start + end);
+                urls.push(css[start..end].trim().to_string());
+            }
+        }
+    }
+    for (at, _) in lower.match_indices("@import") {
+        if let Some((url, _)) = string_at(skip_spaces(at + "@import".len())) {
+            urls.push(url);
+        }
+    }
+    for function in CSS_URL_FUNCTIONS {
+        for (at, _) in lower.match_indices(function) {
+            let mut position = at + function.len();
+            while position < css.len() && !css[position..].starts_with(')') {
+                match string_at(position) {
+                    Some((url, after)) => {
+                        urls.push(url);
+                        position = after;
+                    }
+                    None => position += css[position..].chars().next().map_or(1, char::len_utf8),
+                }
+            }
+        }
+    }
+    urls
+}
+
+/// The schemes listed in `list`, separated by spaces or commas, lowercase and without colons
+fn allowed_schemes(list: &str) -> Vec<String> {
+    list.split([' ', ','])
+        .map(|scheme| scheme.trim().trim_end_matches(':').to_ascii_lowercase())
+        .filter(|scheme| !scheme.is_empty())
+        .collect()
+}
+
+/// `path` with `.` and `..` resolved, without looking at the file system
+fn normalize(path: &Path) -> PathBuf {
+    let mut normalized = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => {
+                normalized.pop();
+            }
+            other => normalized.push(other),
+        }
+    }
+    normalized
+}
+
+/// Why `url` isn't allowed, None if it is. Links within the document are always allowed,
+/// paths and `file:` URLs only within `root`, and other URLs if their scheme is `allowed`.
+fn violation(url: &str, root: &Path, allowed: &[String]) -> Option<&'static str> {
+    let url = url.trim();
+    if url.is_empty() || url.starts_with('#') {
+        return None;
+    }
+    let root = normalize(&std::path::absolute(root).unwrap_or_else(|_| root.to_path_buf()));
+    let path = match Url::parse(url) {
+        // One-letter schemes are Windows drive letters
+        Ok(parsed) if parsed.scheme().len() > 1 => {
+            if parsed.scheme() != "file" {
+                let allowed = allowed.iter().any(|scheme| scheme == parsed.scheme());
+                return (!allowed).then_some("scheme not allowed");
+            }
+            match parsed.to_file_path() {
+                Ok(path) => path,
+                Err(()) => return Some("file URL not understood"),
+            }
+        }
+        _ => {
+            let path = url.split(['?', '#']).next().unwrap_or(url);
+            root.join(images::percent_decode(path))
+        }
+    };
+    (!normalize(&path).starts_with(&root)).then_some("outside the document's folder")
+}
+
+/// The warning for `url` if it isn't allowed by `schemes`, a list of allowed schemes, or
+/// leads out of `root`, None if it's allowed
+pub fn not_allowed(url: &str, root: &Path, schemes: &str) -> Option<String> {
+    let reason = violation(url, root, &allowed_schemes(schemes))?;
+    Some(format!("{NOT_ALLOWED} ({reason}): '{url}'"))
+}
+
+/// The URL of `tag` that isn't allowed, see [`violation`], and why, None if all are.
+/// Event handlers are scripts, which can't be checked.
+fn tag_violation(tag: &HtmlTag, root: &Path, allowed: &[String]) -> Option<(String, &'static str)> {
+    let animation = tag.name == "set" || tag.name == "animate";
+    tag.attributes.iter().find_map(|(name, value)| {
+        let name = name.to_ascii_lowercase();
+        if name.starts_with("on") {
+            return Some((format!("{name}=\"{value}\""), "scripts can't be checked"));
+        }
+        if name == "srcdoc" {
+            return Some((
+                format!("{name}=\"{value}\""),
+                "embedded documents can't be checked",
+            ));
+        }
+        let urls: Vec<String> = if URL_ATTRIBUTES.contains(&name.as_str()) {
+            // A srcset lists several URLs, each followed by its size
+            value
+                .split(',')
+                .map(|candidate| {
+                    candidate
+                        .split_whitespace()
+                        .next()
+                        .unwrap_or("")
+                        .to_string()
+                })
+                .collect()
+        } else if animation && ANIMATION_ATTRIBUTES.contains(&name.as_str()) {
+            value.split(';').map(str::to_string).collect()
+        } else if name == "style" {
+            let Some(css) = decode_references(value) else {
+                return Some((value.clone(), "character reference not understood"));
+            };
+            return css_urls(&css)
+                .into_iter()
+                .find_map(|url| violation(&url, root, allowed).map(|reason| (url, reason)));
+        } else {
+            return None;
+        };
+        urls.into_iter()
+            .find_map(|url| match decode_references(&url) {
+                Some(decoded) => violation(&decoded, root, allowed).map(|reason| (url, reason)),
+                None => Some((url, "character reference not understood")),
+            })
+    })
+}
+
+/// `html` without the tags whose URLs aren't allowed, see [`violation`], None if all are.
+/// Tags are read as browsers read them. Scripts can't be checked and are removed, as are
+/// style elements whose CSS loads URLs that aren't allowed, and tags that don't end.
+fn filter_html(
+    html: &str,
+    root: &Path,
+    allowed: &[String],
+    warnings: &mut Vec<String>,
+) -> Option<String> {
+    let mut filtered = String::with_capacity(html.len());
+    let mut removed = false;
+    let mut rest = html;
+    while let Some(start) = rest.find('<') {
+        filtered.push_str(&rest[..start]);
+        rest = &rest[start..];
+        let starts_tag = rest[1..]
+            .trim_start_matches('/')
+            .starts_with(|c: char| c.is_ascii_alphabetic());
+        let Some(tag) = parse_tag(rest) else {
+            if starts_tag {
+                // Browsers would read on into the rest of the document
+                let line = rest.lines().next().unwrap_or(rest);
+                warnings.push(format!("{NOT_ALLOWED} (tag doesn't end): '{line}'"));
+                removed = true;
+                rest = "";
+                break;
+            }
+            filtered.push('<');
+            rest = &rest[1..];
+            continue;
+        };
+        let mut length = tag.length;
+        let mut blocked = tag_violation(&tag, root, allowed);
+        if !tag.end && RAW_TEXT_ELEMENTS.contains(&tag.name.as_str()) {
+            let content = &rest[length..];
+            let element = format!("<{}>", tag.name);
+            match closing_tag(content, &tag.name) {
+                None => {
+                    length = rest.len();
+                    blocked = blocked.or(Some((element, "element doesn't end")));
+                }
+                Some((_, end)) if tag.name == "script" => {
+                    length += end;
+                    blocked = blocked.or(Some((element, "scripts can't be checked")));
+                }
+                Some((content_end, end)) => {
+                    length += end;
+                    // Within SVG, the content is also read as tags
+                    let content = &content[..content_end];
+                    blocked = blocked
+                        .or_else(|| {
+                            css_urls(content).into_iter().find_map(|url| {
+                                violation(&url, root, allowed).map(|reason| (url, reason))
+                            })
+                        })
+                        .or_else(|| {
+                            filter_html(content, root, allowed, &mut Vec::new())
+                                .map(|_| (element, "content not allowed"))
+                        });
+                }
+            }
+        }
+        match blocked {
+            Some((url, reason)) => {
+                warnings.push(format!("{NOT_ALLOWED} ({reason}): '{url}'"));
+                removed = true;
+            }
+            None => filtered.push_str(&rest[..length]),
+        }
+        rest = &rest[length..];
+    }
+    filtered.push_str(rest);
+    removed.then_some(filtered)
+}
+
+/// Removes the links, images and raw HTML tags whose URLs aren't allowed by `schemes`, a
+/// list of allowed schemes, or lead out of `root`, the document's folder. The text of
+/// removed links and images stays. Each removal is recorded as a warning.
+pub fn block_urls<'a>(
+    events: Vec<Event<'a>>,
+    root: &Path,
+    schemes: &str,
+    warnings: &mut Vec<String>,
+) -> Vec<Event<'a>> {
+    let allowed = allowed_schemes(schemes);
+    let mut out = Vec::with_capacity(events.len());
+    let mut open = Vec::new(); // Whether each open link or image was removed, innermost last
+    // Raw HTML blocks come in pieces, tags can span several
+    let mut html_block: Option<Vec<CowStr<'a>>> = None;
+    // The content of a script or style element in a paragraph comes as text up to its
+    // closing tag, and goes with the removed element
+    let mut removed_content: Option<String> = None;
+    for event in events {
+        if let Some(name) = &removed_content {
+            match &event {
+                Event::InlineHtml(html) => {
+                    if parse_tag(html).is_some_and(|tag| tag.end && tag.name == *name) {
+                        removed_content = None;
+                    }
+                    continue;
+                }
+                Event::Text(_) | Event::Code(_) | Event::SoftBreak | Event::HardBreak => continue,
+                _ => removed_content = None,
+            }
+        }
+        match event {
+            Event::Start(Tag::Link { ref dest_url, .. } | Tag::Image { ref dest_url, .. }) => {
+                let reason = violation(dest_url, root, &allowed);
+                if let Some(reason) = reason {
+                    warnings.push(format!("{NOT_ALLOWED} ({reason}): '{dest_url}'"));
+                }
+                open.push(reason.is_some());
+                if reason.is_none() {
+                    out.push(event);
+                }
+            }
+            Event::End(TagEnd::Link | TagEnd::Image) => {
+                if open.pop() != Some(true) {
+                    out.push(event);
+                }
+            }
+            Event::Start(Tag::HtmlBlock) => {
+                html_block = Some(Vec::new());
+                out.push(event);
+            }
+            Event::Html(html) if html_block.is_some() => {
+                if let Some(block) = &mut html_block {
+                    block.push(html);
+                }
+            }
+            Event::End(TagEnd::HtmlBlock) => {
+                if let Some(block) = html_block.take() {
+                    // Blocks without removals are passed on piece by piece, as they came
+                    match filter_html(&block.concat(), root, &allowed, warnings) {
+                        Some(filtered) => out.push(Event::Html(filtered.into())),
+                        None => out.extend(block.into_iter().map(Event::Html)),
+                    }
+                }
+                out.push(event);
+            }
+            Event::Html(html) => match filter_html(&html, root, &allowed, warnings) {
+                Some(filtered) => out.push(Event::Html(filtered.into())),
+                None => out.push(Event::Html(html)),
+            },
+            Event::InlineHtml(html) => {
+                if let Some(tag) = parse_tag(&html)
+                    && !tag.end
+                    && RAW_TEXT_ELEMENTS.contains(&tag.name.as_str())
+                {
+                    removed_content = Some(tag.name);
+                }
+                match filter_html(&html, root, &allowed, warnings) {
+                    Some(filtered) => out.push(Event::InlineHtml(filtered.into())),
+                    None => out.push(Event::InlineHtml(html)),
+                }
+            }
+            other => out.push(other),
+        }
+    }
+    out
+}
+
+/// Fails when `policy` is strict and `warnings` report URLs that aren't allowed, listing them
+pub fn enforce(policy: UrlPolicy, warnings: &[String]) -> Result<(), String> {
+    if policy != UrlPolicy::Strict {
+        return Ok(());
+    }
+    let blocked: Vec<&str> = warnings
+        .iter()
+        .map(String::as_str)
+        .filter(|warning| warning.contains(NOT_ALLOWED))
+        .collect();
+    if blocked.is_empty() {
+        return Ok(());
+    }
+    Err(format!(
+        "The document uses URLs that aren't allowed:\n{}",
+        blocked.join("\n")
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// `html` filtered as raw HTML of a document in `/docs` allowing https only
+    fn filtered(html: &str) -> (String, Vec<String>) {
+        let mut warnings = Vec::new();
+        let allowed = allowed_schemes("https");
+        let filtered = filter_html(html, Path::new("/docs"), &allowed, &mut warnings);
+        (filtered.unwrap_or_else(|| html.to_string()), warnings)
+    }
+
+    #[test]
+    fn quoted_angle_bracket_doesnt_hide_attributes() {
+        let (html, warnings) = filtered(r#"<p><img alt="a>b" src="/etc/passwd"></p>"#);
+        assert_eq!(html, "<p></p>");
+        assert_eq!(warnings.len(), 1);
+        let (html, _) = filtered(r#"<img alt="a>b" src="images/photo.png">"#);
+        assert_eq!(html, r#"<img alt="a>b" src="images/photo.png">"#);
+    }
+
+    #[test]
+    fn unterminated_tag_is_removed() {
+        let (html, warnings) = filtered(r#"text <img alt="open"#);
+        assert_eq!(html, "text ");
+        assert_eq!(warnings.len(), 1);
+    }
+
+    #[test]
+    fn character_references_are_decoded() {
+        let (html, _) = filtered(r#"<img src="&#47;etc&sol;passwd">"#);
+        assert_eq!(html, "");
+        let (html, _) = filtered(r#"<a href="page.html?a=1&amp;b=2">x</a>"#);
+        assert_eq!(html, r#"<a href="page.html?a=1&amp;b=2">x</a>"#);
+    }
+
+    #[test]
+    fn style_attribute_urls_are_checked() {
+        let (html, warnings) = filtered(r#"<div style="background: url('/etc/passwd')">x</div>"#);
+        assert_eq!(html, "x</div>");
+        assert_eq!(warnings.len(), 1);
+        let (html, _) = filtered(r#"<div style="background: u\72l(/etc/passwd)">"#);
+        assert_eq!(html, "");
+        let (html, _) = filtered(r#"<div style="background: url(bg.png)">"#);
+        assert_eq!(html, r#"<div style="background: url(bg.png)">"#);
+    }
+
+    #[test]
+    fn style_elements_are_checked() {
+        let (html, warnings) =
+            filtered("<style>@import '/etc/x.css'; p { color: red }</style><p>text</p>");
+        assert_eq!(html, "<p>text</p>");
+        assert_eq!(warnings.len(), 1);
+        let (html, _) = filtered("<style>p { background: url(file:///etc/passwd) }</style>");
+        assert_eq!(html, "");
+        let (html, _) = filtered("<style>p { color: red }</style>");
+        assert_eq!(html, "<style>p { color: red }</style>");
+        // Browsers read on to `</style` followed by a space, `/` or `>` only
+        let (html, _) = filtered("<style></styles>p { background: url(/etc/passwd) }</style>");
+        assert_eq!(html, "");
+    }
+
+    #[test]
+    fn scripts_and_event_handlers_are_removed() {
+        let (html, warnings) = filtered("<script>fetch('file:///etc/passwd')</script><p>x</p>");
+        assert_eq!(html, "<p>x</p>");
+        assert_eq!(warnings.len(), 1);
+        let (html, _) = filtered(r#"<img src="a.png" onerror="alert(1)">"#);
+        assert_eq!(html, "");
+    }
+
+    #[test]
+    fn inline_style_content_goes_with_the_element() {
+        let events = pulldown_cmark::Parser::new("a <style>p { color: red }</style> b").collect();
+        let mut warnings = Vec::new();
+        let events = block_urls(events, Path::new("/docs"), "https", &mut warnings);
+        let mut html = String::new();
+        pulldown_cmark::html::push_html(&mut html, events.into_iter());
+        assert_eq!(html, "<p>a  b</p>\n");
+        assert_eq!(warnings.len(), 1);
+    }
+
+    #[test]
+    fn policy_is_narrowed_to_the_roots() {
+        let policy = scoped_policy(DEFAULT_CSP, &[PathBuf::from("/docs/report")]);
+        assert!(policy.contains("img-src file://*/docs/report/ data:"));
+        assert!(policy.contains("font-src file://*/docs/report/ data:"));
+        assert!(!policy.contains("'self'"));
+        assert!(!policy.split_whitespace().any(|source| source == "file:"));
+    }
+}
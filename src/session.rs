@@ -0,0 +1,504 @@
+use crate::budget::BudgetCheck;
+use crate::code::CodeOverflow;
+use crate::config;
+use crate::i18n::Locale;
+use crate::images::Density;
+use crate::layout::Direction;
+use crate::profile::{self, Reader};
+use crate::remote;
+use crate::security::UrlPolicy;
+use crate::settings::Settings;
+use crate::stamp::{BackgroundFit, BatesPosition};
+use crate::style;
+use crate::svg::SvgMode;
+use crate::theme::CustomStylesheet;
+use std::collections::BTreeMap;
+use std::path::Path;
+
+/// Configuration file the app's state is kept in between runs
+const SESSION_CONFIG: &str = "session.conf";
+
+/// Range of the interface scale, so a bad value can't make the app unusable
+pub const MIN_UI_SCALE: f32 = 0.5;
+pub const MAX_UI_SCALE: f32 = 3.0;
+
+/// Appearance of the app itself, independent of the PDF theme
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum UiTheme {
+    System, // Follows the OS setting, where the platform reports it
+    Light,
+    Dark,
+}
+
+impl UiTheme {
+    /// Returns the display name for the appearance
+    pub fn name(&self) -> &'static str {
+        match self {
+            UiTheme::System => "Follow system",
+            UiTheme::Light => "Light",
+            UiTheme::Dark => "Dark",
+        }
+    }
+
+    /// Returns all available appearances
+    pub fn all() -> &'static [UiTheme] {
+        &[UiTheme::System, UiTheme::Light, UiTheme::Dark]
+    }
+
+    /// Identifier used in saved settings
+    pub fn keyword(&self) -> &'static str {
+        match self {
+            UiTheme::System => "system",
+            UiTheme::Light => "light",
+            UiTheme::Dark => "dark",
+        }
+    }
+}
+
+/// A document open in a tab
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct SavedDocument {
+    pub md_path: String,
+    pub pdf_path: String,
+    pub overrides: String, // `key = value` lines applied on top of the global settings
+}
+
+/// What the app reopens with: the open documents, every setting and the window
+pub struct Session {
+    pub documents: Vec<SavedDocument>, // Never empty
+    pub active_document: usize,
+    pub settings: Settings,
+    pub window_position: Option<[f32; 2]>, // Outer top-left corner, in points
+    pub window_size: Option<[f32; 2]>,     // Inner size, in points
+    pub watch: bool,
+    pub preview: bool,
+    pub editing: bool,
+    pub spell_dictionary: String, // Empty turns spell checking off
+    pub auto_open: bool,
+    pub version_outputs: bool,
+    pub notify: bool,
+    pub ui_theme: UiTheme,
+    pub ui_language: Option<Locale>, // None follows the OS language
+    pub ui_scale: f32,               // On top of the system's display scaling
+    pub setup_done: bool,            // The setup help was seen, it only opens for missing programs
+}
+
+impl Default for Session {
+    fn default() -> Self {
+        Self {
+            documents: vec![SavedDocument::default()],
+            active_document: 0,
+            settings: Settings {
+                stylesheet: CustomStylesheet::load_saved(),
+                ..Settings::default()
+            },
+            window_position: None,
+            window_size: None,
+            watch: false,
+            preview: false,
+            editing: false,
+            spell_dictionary: String::new(),
+            auto_open: false,
+            version_outputs: false,
+            notify: true,
+            ui_theme: UiTheme::System,
+            ui_language: None,
+            ui_scale: 1.0,
+            setup_done: false,
+        }
+    }
+}
+
+impl Session {
+    /// Reads the state saved by the last run. Missing or unusable entries keep their defaults.
+    pub fn load() -> Session {
+        let entries = config::load(SESSION_CONFIG);
+        let mut session = Session::default();
+        let mut warnings = Vec::new();
+        let mut reader = Reader {
+            entries: &entries,
+            warnings: &mut warnings,
+        };
+        let documents: Vec<SavedDocument> = (0..)
+            .map_while(|index| {
+                let entry = |key: &str| entries.get(&format!("document.{index}.{key}")).cloned();
+                Some(SavedDocument {
+                    md_path: entry("md_path")?,
+                    pdf_path: entry("pdf_path").unwrap_or_default(),
+                    overrides: entry("overrides").unwrap_or_default(),
+                })
+            })
+            .collect();
+        if !documents.is_empty() {
+            session.documents = documents;
+        } else {
+            // Saved before there were tabs
+            let document = &mut session.documents[0];
+            if let Some(path) = entries.get("md_path") {
+                document.md_path = path.clone();
+            }
+            if let Some(path) = entries.get("pdf_path") {
+                document.pdf_path = path.clone();
+            }
+        }
+        reader.value("active_document", &mut session.active_document);
+        session.active_document = session.active_document.min(session.documents.len() - 1);
+        reader.value("watch", &mut session.watch);
+        reader.value("preview", &mut session.preview);
+        reader.value("editor", &mut session.editing);
+        if let Some(name) = entries.get("spell_dictionary") {
+            session.spell_dictionary = name.clone();
+        }
+        reader.value("auto_open", &mut session.auto_open);
+        reader.value("version_outputs", &mut session.version_outputs);
+        reader.value("notify", &mut session.notify);
+        if let Some(theme) = reader.keyword("ui_theme", UiTheme::all(), UiTheme::keyword) {
+            session.ui_theme = theme;
+        }
+        if let Some(locale) = entries.get("ui_language") {
+            session.ui_language = Locale::from_tag(locale);
+        }
+        reader.value("ui_scale", &mut session.ui_scale);
+        session.ui_scale = session.ui_scale.clamp(MIN_UI_SCALE, MAX_UI_SCALE);
+        session.window_position = pair(&mut reader, "window.x", "window.y");
+        session.window_size = pair(&mut reader, "window.width", "window.height");
+        // Sessions saved before there was a setup help are past their first run
+        session.setup_done = !entries.is_empty();
+        reader.value("setup_done", &mut session.setup_done);
+
+        apply_settings(&entries, Path::new(""), &mut session.settings);
+        session
+    }
+
+    /// Remembers the state for the next run
+    pub fn save(&self) -> Result<(), String> {
+        let mut entries = settings_entries(&self.settings);
+        let mut set = |key: &str, value: String| {
+            entries.insert(key.to_string(), value);
+        };
+        for (index, document) in self.documents.iter().enumerate() {
+            set(
+                &format!("document.{index}.md_path"),
+                document.md_path.clone(),
+            );
+            set(
+                &format!("document.{index}.pdf_path"),
+                document.pdf_path.clone(),
+            );
+            set(
+                &format!("document.{index}.overrides"),
+                document.overrides.clone(),
+            );
+        }
+        set("active_document", self.active_document.to_string());
+        set("watch", self.watch.to_string());
+        set("preview", self.preview.to_string());
+        set("editor", self.editing.to_string());
+        set("spell_dictionary", self.spell_dictionary.clone());
+        set("auto_open", self.auto_open.to_string());
+        set("version_outputs", self.version_outputs.to_string());
+        set("notify", self.notify.to_string());
+        set("ui_theme", self.ui_theme.keyword().to_string());
+        set(
+            "ui_language",
+            self.ui_language
+                .map_or("system", |locale| locale.keyword())
+                .to_string(),
+        );
+        set("ui_scale", self.ui_scale.to_string());
+        set("setup_done", self.setup_done.to_string());
+        if let Some([x, y]) = self.window_position {
+            set("window.x", x.to_string());
+            set("window.y", y.to_string());
+        }
+        if let Some([width, height]) = self.window_size {
+            set("window.width", width.to_string());
+            set("window.height", height.to_string());
+        }
+        config::save(SESSION_CONFIG, &entries)
+    }
+}
+
+/// Entries for every setting: those of style profiles, followed by the rest
+pub fn settings_entries(settings: &Settings) -> BTreeMap<String, String> {
+    let mut entries = profile::entries(settings);
+    let mut set = |key: &str, value: String| {
+        entries.insert(key.to_string(), value);
+    };
+    set("direction", settings.direction.keyword().to_string());
+    set(
+        "landscape_wide_blocks",
+        settings.landscape_wide_blocks.to_string(),
+    );
+    set("language", settings.language.clone());
+    set("justify", settings.justify.to_string());
+    set("hyphenate", settings.hyphenate.to_string());
+    set("code_line_numbers", settings.code_line_numbers.to_string());
+    set(
+        "code_overflow",
+        settings.code_overflow.keyword().to_string(),
+    );
+    set(
+        "image_density",
+        settings.image_density.keyword().to_string(),
+    );
+    set("fit_images", settings.fit_images.to_string());
+    set("downsample_images", settings.downsample_images.to_string());
+    set("image_dpi", settings.image_dpi.to_string());
+    set("svg_mode", settings.svg_mode.keyword().to_string());
+    set("svg_dpi", settings.svg_dpi.to_string());
+    for preset in &settings.presets {
+        let key = |option: &str| format!("preset.{}.{}", preset.name, option);
+        set(&key("compress_fonts"), preset.compress_fonts.to_string());
+        set(
+            &key("recompress_images"),
+            preset.recompress_images.to_string(),
+        );
+    }
+    set("grayscale", settings.grayscale.to_string());
+    set("draft_quality", settings.draft_quality.to_string());
+    set("inject_csp", settings.inject_csp.to_string());
+    set("csp_policy", settings.csp_policy.clone());
+    set("url_policy", settings.url_policy.keyword().to_string());
+    set("allowed_schemes", settings.allowed_schemes.clone());
+    set("accurate_toc", settings.accurate_toc.to_string());
+
+    let background = &settings.background;
+    set("background.enabled", background.enabled.to_string());
+    set("background.color", style::hex(background.color));
+    set("background.image", background.image.clone());
+    set("background.fit", background.fit.keyword().to_string());
+    set(
+        "print_marks.enabled",
+        settings.print_marks.enabled.to_string(),
+    );
+    set(
+        "print_marks.bleed_mm",
+        settings.print_marks.bleed_mm.to_string(),
+    );
+    set("booklet", settings.booklet.to_string());
+    let bates = &settings.bates;
+    set("bates.enabled", bates.enabled.to_string());
+    set("bates.prefix", bates.prefix.clone());
+    set("bates.start", bates.start.to_string());
+    set("bates.digits", bates.digits.to_string());
+    set("bates.position", bates.position.keyword().to_string());
+    set("rewrite_md_links", settings.rewrite_md_links.to_string());
+    set("base_url", settings.base_url.clone());
+    set("self_contained", settings.self_contained.to_string());
+    set("copy_assets", settings.copy_assets.to_string());
+    set("page_image_dpi", settings.page_image_dpi.to_string());
+    set("text_width", settings.text_width.to_string());
+    set("docx_reference", settings.docx_reference.clone());
+    let metadata = &settings.metadata;
+    set("metadata.title", metadata.title.clone());
+    set("metadata.author", metadata.author.clone());
+    set("metadata.subject", metadata.subject.clone());
+    set("metadata.keywords", metadata.keywords.clone());
+    set("metadata.date", metadata.date.clone());
+    set("reproducible", settings.reproducible.to_string());
+    // The passwords are left out, they'd be readable in the file
+    let encryption = &settings.encryption;
+    set("encryption.enabled", encryption.enabled.to_string());
+    set(
+        "encryption.allow_printing",
+        encryption.allow_printing.to_string(),
+    );
+    set(
+        "encryption.allow_copying",
+        encryption.allow_copying.to_string(),
+    );
+    set(
+        "encryption.allow_editing",
+        encryption.allow_editing.to_string(),
+    );
+    set("pdf_a", settings.pdf_a.to_string());
+    set("cover_pdf", settings.cover_pdf.clone());
+    set("appendix_pdf", settings.appendix_pdf.clone());
+    let optimization = &settings.optimization;
+    set("optimization.enabled", optimization.enabled.to_string());
+    set(
+        "optimization.recompress_images",
+        optimization.recompress_images.to_string(),
+    );
+    set(
+        "optimization.image_quality",
+        optimization.image_quality.to_string(),
+    );
+    set("optimization.linearize", optimization.linearize.to_string());
+    set("accessible", settings.accessible.to_string());
+    set("checksums", settings.checksums.to_string());
+    set("size_budget", settings.size_budget.keyword().to_string());
+    set("size_budget_mb", settings.size_budget_mb.to_string());
+    set("download_images", settings.download_images.to_string());
+    set("image_cache_days", settings.image_cache_days.to_string());
+    set("offline", settings.offline.to_string());
+    // The proxy's user name and password are left out too
+    set("proxy", remote::without_credentials(&settings.proxy));
+    set("post_command", settings.post_command.clone());
+    entries
+}
+
+/// Applies entries written by [`settings_entries`] to `settings`, resolving relative file
+/// paths against `base_dir`. Missing or unusable entries leave the setting alone. Returns
+/// warnings for the values that can't be used.
+pub fn apply_settings(
+    entries: &BTreeMap<String, String>,
+    base_dir: &Path,
+    settings: &mut Settings,
+) -> Vec<String> {
+    let mut warnings = profile::apply(entries, base_dir, settings);
+    let mut reader = Reader {
+        entries,
+        warnings: &mut warnings,
+    };
+    if let Some(direction) = reader.keyword("direction", Direction::all(), Direction::keyword) {
+        settings.direction = direction;
+    }
+    reader.value("landscape_wide_blocks", &mut settings.landscape_wide_blocks);
+    if let Some(language) = entries.get("language") {
+        settings.language = language.clone();
+    }
+    reader.value("justify", &mut settings.justify);
+    reader.value("hyphenate", &mut settings.hyphenate);
+    reader.value("code_line_numbers", &mut settings.code_line_numbers);
+    if let Some(overflow) =
+        reader.keyword("code_overflow", CodeOverflow::all(), CodeOverflow::keyword)
+    {
+        settings.code_overflow = overflow;
+    }
+    if let Some(density) = reader.keyword("image_density", Density::all(), Density::keyword) {
+        settings.image_density = density;
+    }
+    reader.value("fit_images", &mut settings.fit_images);
+    reader.value("downsample_images", &mut settings.downsample_images);
+    reader.value("image_dpi", &mut settings.image_dpi);
+    if let Some(mode) = reader.keyword("svg_mode", SvgMode::all(), SvgMode::keyword) {
+        settings.svg_mode = mode;
+    }
+    reader.value("svg_dpi", &mut settings.svg_dpi);
+    for preset in &mut settings.presets {
+        let key = |option: &str| format!("preset.{}.{}", preset.name, option);
+        reader.value(&key("compress_fonts"), &mut preset.compress_fonts);
+        reader.value(&key("recompress_images"), &mut preset.recompress_images);
+    }
+    reader.value("grayscale", &mut settings.grayscale);
+    reader.value("draft_quality", &mut settings.draft_quality);
+    reader.value("inject_csp", &mut settings.inject_csp);
+    if let Some(policy) = entries.get("csp_policy") {
+        settings.csp_policy = policy.clone();
+    }
+    if let Some(policy) = reader.keyword("url_policy", UrlPolicy::all(), UrlPolicy::keyword) {
+        settings.url_policy = policy;
+    }
+    if let Some(schemes) = entries.get("allowed_schemes") {
+        settings.allowed_schemes = schemes.clone();
+    }
+    reader.value("accurate_toc", &mut settings.accurate_toc);
+
+    let background = &mut settings.background;
+    reader.value("background.enabled", &mut background.enabled);
+    reader.color("background.color", &mut background.color);
+    if let Some(image) = entries.get("background.image") {
+        background.image = image.clone();
+    }
+    if let Some(fit) = reader.keyword(
+        "background.fit",
+        BackgroundFit::all(),
+        BackgroundFit::keyword,
+    ) {
+        background.fit = fit;
+    }
+    reader.value("print_marks.enabled", &mut settings.print_marks.enabled);
+    reader.value("print_marks.bleed_mm", &mut settings.print_marks.bleed_mm);
+    reader.value("booklet", &mut settings.booklet);
+    let bates = &mut settings.bates;
+    reader.value("bates.enabled", &mut bates.enabled);
+    if let Some(prefix) = entries.get("bates.prefix") {
+        bates.prefix = prefix.clone();
+    }
+    reader.value("bates.start", &mut bates.start);
+    reader.value("bates.digits", &mut bates.digits);
+    if let Some(position) = reader.keyword(
+        "bates.position",
+        BatesPosition::all(),
+        BatesPosition::keyword,
+    ) {
+        bates.position = position;
+    }
+    reader.value("rewrite_md_links", &mut settings.rewrite_md_links);
+    if let Some(base_url) = entries.get("base_url") {
+        settings.base_url = base_url.clone();
+    }
+    reader.value("self_contained", &mut settings.self_contained);
+    reader.value("copy_assets", &mut settings.copy_assets);
+    reader.value("page_image_dpi", &mut settings.page_image_dpi);
+    reader.value("text_width", &mut settings.text_width);
+    if let Some(reference) = entries.get("docx_reference") {
+        settings.docx_reference = reference.clone();
+    }
+    let metadata = &mut settings.metadata;
+    let fields = [
+        ("metadata.title", &mut metadata.title),
+        ("metadata.author", &mut metadata.author),
+        ("metadata.subject", &mut metadata.subject),
+        ("metadata.keywords", &mut metadata.keywords),
+        ("metadata.date", &mut metadata.date),
+    ];
+    for (key, field) in fields {
+        if let Some(value) = entries.get(key) {
+            *field = value.clone();
+        }
+    }
+    reader.value("reproducible", &mut settings.reproducible);
+    let encryption = &mut settings.encryption;
+    reader.value("encryption.enabled", &mut encryption.enabled);
+    reader.value("encryption.allow_printing", &mut encryption.allow_printing);
+    reader.value("encryption.allow_copying", &mut encryption.allow_copying);
+    reader.value("encryption.allow_editing", &mut encryption.allow_editing);
+    reader.value("pdf_a", &mut settings.pdf_a);
+    if let Some(cover) = entries.get("cover_pdf") {
+        settings.cover_pdf = cover.clone();
+    }
+    if let Some(appendix) = entries.get("appendix_pdf") {
+        settings.appendix_pdf = appendix.clone();
+    }
+    let optimization = &mut settings.optimization;
+    reader.value("optimization.enabled", &mut optimization.enabled);
+    reader.value(
+        "optimization.recompress_images",
+        &mut optimization.recompress_images,
+    );
+    reader.value(
+        "optimization.image_quality",
+        &mut optimization.image_quality,
+    );
+    reader.value("optimization.linearize", &mut optimization.linearize);
+    reader.value("accessible", &mut settings.accessible);
+    reader.value("checksums", &mut settings.checksums);
+    if let Some(check) = reader.keyword("size_budget", BudgetCheck::all(), BudgetCheck::keyword) {
+        settings.size_budget = check;
+    }
+    reader.value("size_budget_mb", &mut settings.size_budget_mb);
+    reader.value("download_images", &mut settings.download_images);
+    reader.value("image_cache_days", &mut settings.image_cache_days);
+    reader.value("offline", &mut settings.offline);
+    if let Some(proxy) = entries.get("proxy") {
+        settings.proxy = proxy.clone();
+    }
+    if let Some(command) = entries.get("post_command") {
+        settings.post_command = command.clone();
+    }
+    warnings
+}
+
+/// Reads two numbers saved together, such as a window's position
+fn pair(reader: &mut Reader, first: &str, second: &str) -> Option<[f32; 2]> {
+    let mut values = [f32::NAN; 2];
+    reader.value(first, &mut values[0]);
+    reader.value(second, &mut values[1]);
+    values
+        .iter()
+        .all(|value| value.is_finite())
+        .then_some(values)
+}
@@ -0,0 +1,314 @@
+use crate::budget::{self, BudgetCheck};
+use crate::code::CodeOverflow;
+use crate::encryption::Encryption;
+use crate::highlight::Palette;
+use crate::images::Density;
+use crate::layout::Direction;
+use crate::links::LinkStyle;
+use crate::metadata::Metadata;
+use crate::normalize;
+use crate::optimize::Optimization;
+use crate::pages;
+use crate::prepress::PrintMarks;
+use crate::preset::OutputPreset;
+use crate::remote;
+use crate::security::{self, UrlPolicy};
+use crate::stamp::{Bates, PageBackground, Watermark};
+use crate::style::{HeadingPreset, StyleOptions};
+use crate::svg::{self, SvgMode};
+use crate::text;
+use crate::theme::{self, ColorScheme, CustomStylesheet, StyleSource, Theme};
+use crate::themes;
+use std::borrow::Cow;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use url::Url;
+
+/// Everything that controls how a document is converted, shared by the GUI and the CLI
+#[derive(Debug, Clone, PartialEq)]
+pub struct Settings {
+    pub theme: Theme,
+    pub user_theme: Option<String>, // Id of an installed theme used instead of `theme`
+    pub stylesheet: CustomStylesheet, // Replaces the theme's CSS unless set to the built-in theme
+    pub color_scheme: ColorScheme, // What `prefers-color-scheme` styles (e.g. GitHub Auto) resolve to
+    pub style: StyleOptions,       // Font size, colors and margins layered over the theme
+    pub print_media: bool, // Render with print media styles, including the built-in print layer
+    pub link_style: LinkStyle,
+    pub rewrite_md_links: bool, // Point links to other Markdown files at their PDFs
+    pub heading_preset: HeadingPreset, // Overridden by `headings:` in the front matter
+    pub additional_css: String, // Appended after all other styles, for small tweaks
+    pub direction: Direction,
+    pub columns: u8,
+    pub zoom: f32,                   // Content scale factor passed to wkhtmltopdf
+    pub landscape_wide_blocks: bool, // Put oversized tables/code blocks on landscape pages
+    pub language: String,            // Default `lang`, used when the front matter doesn't set one
+    pub justify: bool,
+    pub book_paragraphs: bool, // Indented first lines instead of space between paragraphs
+    pub hyphenate: bool,
+    pub code_line_numbers: bool,
+    pub code_overflow: CodeOverflow,
+    pub syntax_highlighting: bool,
+    pub highlight_palette: Option<Palette>, // None uses the palette paired with the theme
+    pub image_density: Density,
+    pub image_backing: bool,     // Light card behind images in dark output
+    pub fit_images: bool,        // Scale images down to the printable width
+    pub downsample_images: bool, // Resample images larger than `image_dpi` needs before converting
+    pub image_dpi: u32,
+    pub svg_mode: SvgMode,
+    pub svg_dpi: u32, // Resolution of SVG images rasterized with `SvgMode::Rasterize`
+    pub presets: Vec<OutputPreset>,
+    pub selected_preset: usize, // Index into `presets`
+    pub grayscale: bool,
+    pub draft_quality: bool, // Low quality rendering and downsampled images
+    pub force_backgrounds: bool, // Print background colors even with light themes
+    pub inject_csp: bool,
+    pub csp_policy: String,
+    pub url_policy: UrlPolicy,
+    pub allowed_schemes: String, // URL schemes `url_policy` lets through, separated by spaces
+    pub accurate_toc: bool, // Render a draft first to put page numbers in the table of contents
+    pub page_numbers: bool, // Stamp page numbers, in the styles set by `page-numbers` directives
+    pub chapters_on_odd_pages: bool, // Start each `#` heading on a right-hand page for duplex printing
+    pub watermark: Watermark,
+    pub bates: Bates,
+    pub background: PageBackground,
+    pub print_marks: PrintMarks,
+    pub booklet: bool, // Impose the pages two to a sheet in saddle-stitch order
+    pub self_contained: bool, // Embed local images and stylesheet fonts in the HTML as data URIs
+    pub size_budget: BudgetCheck, // Whether documents larger than `size_budget_mb` warn or fail
+    pub size_budget_mb: f32, // Largest PDF, and total of embedded assets, a document should have
+    pub page_image_dpi: u32, // Resolution of pages exported as images
+    pub text_width: usize, // Characters per line of exported plain text
+    pub docx_reference: String, // Word document whose styles exported Word documents use, relative to the document
+    pub metadata: Metadata, // Title, author etc. of the PDFs, the front matter's taking precedence
+    pub reproducible: bool, // Fixed timestamps and ID, so the same input always gives the same PDF, never encrypted
+    pub encryption: Encryption,
+    pub pdf_a: bool,          // Archive as PDF/A-2b with Ghostscript
+    pub cover_pdf: String,    // PDF whose pages go before the document's, relative to the document
+    pub appendix_pdf: String, // PDF whose pages go after the document's, relative to the document
+    pub optimization: Optimization,
+    pub accessible: bool, // Tagged PDFs for screen readers, rendered with Chromium instead of wkhtmltopdf
+    pub checksums: bool,  // Write a `.sha256` file with the SHA-256 of each output next to it
+    pub copy_assets: bool, // Copy the local files exported HTML uses into an `assets` folder next to it
+    pub download_images: bool, // Fetch http(s) images while converting instead of leaving them to wkhtmltopdf
+    pub image_cache_days: u32, // Downloaded images are reused for this many days before fetching them again
+    pub offline: bool,         // Never use the network, remote resources become warnings
+    pub proxy: String, // Proxy for downloads and wkhtmltopdf, empty to use the HTTP(S)_PROXY variables
+    pub post_command: String, // External command run on the finished PDF, see `hook`
+    pub base_url: String, // Relative links and images resolve against this URL or directory when set
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self {
+            theme: Theme::GitHubLight, // Default to light mode
+            user_theme: None,
+            stylesheet: CustomStylesheet::default(),
+            color_scheme: ColorScheme::Light,
+            style: StyleOptions::default(),
+            print_media: false,
+            link_style: LinkStyle::Theme,
+            rewrite_md_links: true,
+            heading_preset: HeadingPreset::Theme,
+            additional_css: String::new(),
+            direction: Direction::Auto,
+            columns: 1,
+            zoom: 1.0,
+            landscape_wide_blocks: false,
+            language: String::from("en-US"),
+            justify: false,
+            book_paragraphs: false,
+            hyphenate: false,
+            code_line_numbers: false,
+            code_overflow: CodeOverflow::Clip,
+            syntax_highlighting: true,
+            highlight_palette: None,
+            image_density: Density::Print,
+            image_backing: false,
+            fit_images: true,
+            downsample_images: false,
+            image_dpi: normalize::DEFAULT_IMAGE_DPI,
+            svg_mode: SvgMode::Inline,
+            svg_dpi: svg::DEFAULT_DPI,
+            presets: OutputPreset::builtin(),
+            selected_preset: 1, // Standard
+            grayscale: false,
+            draft_quality: false,
+            force_backgrounds: false,
+            inject_csp: true,
+            csp_policy: security::DEFAULT_CSP.to_string(),
+            url_policy: UrlPolicy::Any,
+            allowed_schemes: security::DEFAULT_ALLOWED_SCHEMES.to_string(),
+            accurate_toc: false,
+            page_numbers: false,
+            chapters_on_odd_pages: false,
+            watermark: Watermark::default(),
+            bates: Bates::default(),
+            background: PageBackground::default(),
+            print_marks: PrintMarks::default(),
+            booklet: false,
+            self_contained: false,
+            size_budget: BudgetCheck::Off,
+            size_budget_mb: budget::DEFAULT_BUDGET_MB,
+            page_image_dpi: pages::DEFAULT_IMAGE_DPI,
+            text_width: text::DEFAULT_TEXT_WIDTH,
+            docx_reference: String::new(),
+            metadata: Metadata::default(),
+            reproducible: false,
+            encryption: Encryption::default(),
+            pdf_a: false,
+            cover_pdf: String::new(),
+            appendix_pdf: String::new(),
+            optimization: Optimization::default(),
+            accessible: false,
+            checksums: false,
+            copy_assets: false,
+            download_images: false,
+            image_cache_days: remote::DEFAULT_CACHE_DAYS,
+            offline: false,
+            proxy: String::new(),
+            post_command: String::new(),
+            base_url: String::new(),
+        }
+    }
+}
+
+impl Settings {
+    /// Returns the directory relative images and links resolve against when `base_url` is a
+    /// path or a `file:` URL rather than a web address, e.g. the asset folder of a wiki
+    /// export. Relative paths are taken from `base_dir`, the document's directory.
+    pub fn asset_root(&self, base_dir: &Path) -> Option<PathBuf> {
+        let base = self.base_url.trim();
+        if base.is_empty() {
+            return None;
+        }
+        match Url::parse(base) {
+            Ok(url) if url.scheme() == "file" => url.to_file_path().ok(),
+            // One-letter schemes are Windows drive letters
+            Ok(url) if url.scheme().len() > 1 => None,
+            _ => Some(base_dir.join(base)),
+        }
+    }
+
+    /// The folders a document in `base_dir` may load local files from: its own and the asset
+    /// root, the custom stylesheet's, the image cache and the temporary folder, where
+    /// converted images go
+    pub fn local_roots(&self, base_dir: &Path) -> Vec<PathBuf> {
+        let mut roots = vec![base_dir.to_path_buf()];
+        roots.extend(self.asset_root(base_dir));
+        if let Some(file) = self.stylesheet_file()
+            && let Some(parent) = file.parent()
+        {
+            roots.push(parent.to_path_buf());
+        }
+        roots.push(remote::cache_dir());
+        roots.push(std::env::temp_dir());
+        roots
+    }
+
+    /// How long downloaded images are reused for
+    pub fn image_cache_age(&self) -> Duration {
+        Duration::from_secs(u64::from(self.image_cache_days) * 24 * 60 * 60)
+    }
+
+    /// Returns the currently selected output preset
+    pub fn preset(&self) -> &OutputPreset {
+        &self.presets[self.selected_preset]
+    }
+
+    /// Returns the document stylesheet, with `prefers-color-scheme` styles resolved to the
+    /// chosen color scheme so the output doesn't depend on the renderer
+    pub fn resolved_stylesheet_css(&self) -> Result<String, String> {
+        let css = self.stylesheet_css()?;
+        Ok(theme::resolve_color_scheme(&css, self.color_scheme))
+    }
+
+    /// Returns the document stylesheet: the custom stylesheet if one is chosen, otherwise
+    /// the selected user theme or built-in theme
+    pub fn stylesheet_css(&self) -> Result<Cow<'static, str>, String> {
+        if self.stylesheet.source == StyleSource::BuiltIn
+            && let Some(id) = &self.user_theme
+        {
+            let theme =
+                themes::find(id).ok_or_else(|| format!("Theme '{}' is not installed", id))?;
+            return theme.css().map(Cow::Owned);
+        }
+        self.stylesheet.css(self.theme)
+    }
+
+    /// Returns the palette code blocks are highlighted with: the chosen one, or else the one
+    /// paired with the selected built-in theme
+    pub fn highlight_palette(&self) -> Palette {
+        self.highlight_palette
+            .unwrap_or_else(|| self.theme.highlight_palette(self.color_scheme))
+    }
+
+    /// Returns the page color of the selected built-in theme if it's dark, see
+    /// [`Theme::page_color`]. Custom stylesheets and user themes are left as they are.
+    pub fn theme_page_color(&self) -> Option<[u8; 3]> {
+        (self.stylesheet.source == StyleSource::BuiltIn && self.user_theme.is_none())
+            .then(|| self.theme.page_color(self.color_scheme))
+            .flatten()
+    }
+
+    /// Whether the output is dark, for choosing color variants and dark mode images
+    pub fn dark_output(&self) -> bool {
+        self.theme_page_color().is_some()
+    }
+
+    /// Whether background colors and images are printed: when forced, for dark themes
+    /// (which would otherwise come out as dark boxes on white paper) and for page backgrounds
+    pub fn prints_backgrounds(&self) -> bool {
+        self.force_backgrounds || self.background.enabled || self.theme_page_color().is_some()
+    }
+
+    /// Returns the background painted behind the pages: the chosen page background, or else
+    /// a dark theme's page color so it reaches the page edges
+    pub fn page_background(&self) -> PageBackground {
+        match self.theme_page_color() {
+            Some(color) if !self.background.enabled => PageBackground {
+                enabled: true,
+                color,
+                ..PageBackground::default()
+            },
+            _ => self.background.clone(),
+        }
+    }
+
+    /// Checks the document stylesheet and the additional CSS before a conversion
+    pub fn check_styles(&self) -> Result<(), String> {
+        self.stylesheet_css()?;
+        if !self.additional_css.trim().is_empty() {
+            theme::validate_css(&self.additional_css)
+                .map_err(|e| format!("Additional CSS: {}", e))?;
+        }
+        Ok(())
+    }
+
+    /// Checks that the PDF options turned on can be combined, before a conversion
+    pub fn check_pdf_options(&self) -> Result<(), String> {
+        if self.pdf_a && self.encryption.enabled {
+            return Err("PDF/A doesn't allow encryption, turn off one of them".to_string());
+        }
+        // See `encryption::apply`
+        if self.reproducible && self.encryption.enabled {
+            return Err(
+                "Encrypted PDFs differ every time, so they can't be reproducible, turn off one of them"
+                    .to_string(),
+            );
+        }
+        Ok(())
+    }
+
+    /// Returns the file the document stylesheet is read from, if it isn't built in
+    pub fn stylesheet_file(&self) -> Option<PathBuf> {
+        match self.stylesheet.source {
+            StyleSource::File => Some(PathBuf::from(self.stylesheet.file.trim())),
+            StyleSource::Pasted => None,
+            StyleSource::BuiltIn => self
+                .user_theme
+                .as_deref()
+                .and_then(themes::find)
+                .map(|theme| theme.css_path().to_path_buf()),
+        }
+    }
+}
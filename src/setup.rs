@@ -0,0 +1,160 @@
+use crate::pdfa;
+use std::process::Command;
+
+/// Page with the wkhtmltopdf installers for every platform
+pub const WKHTMLTOPDF_DOWNLOADS: &str = "https://wkhtmltopdf.org/downloads.html";
+
+/// An external program the app runs
+pub struct Dependency {
+    pub program: &'static str,
+    pub purpose: &'static str,
+    pub required: bool, // Nothing can be converted without it
+    pub download_url: Option<&'static str>,
+}
+
+/// The programs the app runs on this platform, the required ones first
+pub fn dependencies() -> Vec<Dependency> {
+    let mut dependencies = vec![
+        Dependency {
+            program: "wkhtmltopdf",
+            purpose: "Converts the documents to PDF",
+            required: true,
+            download_url: Some(WKHTMLTOPDF_DOWNLOADS),
+        },
+        Dependency {
+            program: "wkhtmltoimage",
+            purpose: "Renders the preview and the theme thumbnails, installed with wkhtmltopdf",
+            required: false,
+            download_url: Some(WKHTMLTOPDF_DOWNLOADS),
+        },
+        Dependency {
+            program: "pandoc",
+            purpose: "Exports Word documents",
+            required: false,
+            download_url: Some("https://pandoc.org/installing.html"),
+        },
+        Dependency {
+            program: "pdftoppm",
+            purpose: "Exports pages as images, installed with Poppler",
+            required: false,
+            download_url: Some("https://poppler.freedesktop.org/"),
+        },
+        Dependency {
+            program: pdfa::GHOSTSCRIPT,
+            purpose: "Writes archival PDF/A files",
+            required: false,
+            download_url: Some("https://ghostscript.com/releases/gsdnld.html"),
+        },
+        Dependency {
+            program: "chromium",
+            purpose: "Renders accessible, tagged PDFs, as does Google Chrome",
+            required: false,
+            download_url: Some("https://www.chromium.org/getting-involved/download-chromium/"),
+        },
+        Dependency {
+            program: "qpdf",
+            purpose: "Linearizes optimized PDFs for fast web view",
+            required: false,
+            download_url: Some("https://github.com/qpdf/qpdf/releases"),
+        },
+        Dependency {
+            program: "curl",
+            purpose: "Downloads documents dropped or pasted as URLs",
+            required: false,
+            download_url: Some("https://curl.se/download.html"),
+        },
+    ];
+    // Windows prints through the shell
+    if !cfg!(target_os = "windows") {
+        dependencies.push(Dependency {
+            program: "lp",
+            purpose: "Sends PDFs to the printer",
+            required: false,
+            download_url: None,
+        });
+    }
+    dependencies
+}
+
+/// Version line `program` reports, None if it can't be run. A program that runs is found
+/// even if it doesn't understand `--version`, which is then reported as an empty string.
+pub fn find(program: &str) -> Option<String> {
+    let output = Command::new(program).arg("--version").output().ok()?;
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    let version = stdout
+        .lines()
+        .chain(stderr.lines())
+        .map(str::trim)
+        .find(|line| !line.is_empty())
+        .filter(|_| output.status.success())
+        .unwrap_or_default();
+    Some(version.to_string())
+}
+
+/// How to install `program` on this platform
+pub fn install_hint(program: &str) -> &'static str {
+    match program {
+        "wkhtmltopdf" | "wkhtmltoimage" => {
+            if cfg!(target_os = "windows") {
+                "Download the installer from the wkhtmltopdf website and run it, then add its bin folder (usually C:\\Program Files\\wkhtmltopdf\\bin) to the PATH"
+            } else if cfg!(target_os = "macos") {
+                "Install it with Homebrew (brew install --cask wkhtmltopdf) or download the package from the wkhtmltopdf website"
+            } else {
+                "Install it with your package manager (e.g. sudo apt install wkhtmltopdf), or download a package with patched Qt from the wkhtmltopdf website for the best results"
+            }
+        }
+        "curl" => {
+            if cfg!(target_os = "linux") {
+                "Install it with your package manager, e.g. sudo apt install curl"
+            } else {
+                "It comes with the system. Download it from the curl website if it was removed"
+            }
+        }
+        "pandoc" => {
+            if cfg!(target_os = "windows") {
+                "Download the installer from the pandoc website and run it"
+            } else if cfg!(target_os = "macos") {
+                "Install it with Homebrew (brew install pandoc) or download the package from the pandoc website"
+            } else {
+                "Install it with your package manager, e.g. sudo apt install pandoc"
+            }
+        }
+        "pdftoppm" => {
+            if cfg!(target_os = "windows") {
+                "Download Poppler for Windows, unpack it and add its bin folder to the PATH"
+            } else if cfg!(target_os = "macos") {
+                "Install Poppler with Homebrew: brew install poppler"
+            } else {
+                "Install Poppler's tools with your package manager, e.g. sudo apt install poppler-utils"
+            }
+        }
+        "gs" | "gswin64c" => {
+            if cfg!(target_os = "windows") {
+                "Download the Ghostscript installer and run it, then add its bin folder to the PATH"
+            } else if cfg!(target_os = "macos") {
+                "Install it with Homebrew: brew install ghostscript"
+            } else {
+                "Install it with your package manager, e.g. sudo apt install ghostscript"
+            }
+        }
+        "chromium" => {
+            if cfg!(target_os = "windows") || cfg!(target_os = "macos") {
+                "Install Google Chrome, or download Chromium"
+            } else {
+                "Install it with your package manager, e.g. sudo apt install chromium, or install Google Chrome"
+            }
+        }
+        "qpdf" => {
+            if cfg!(target_os = "windows") {
+                "Download the qpdf installer from its releases page and run it, then add its bin folder to the PATH"
+            } else if cfg!(target_os = "macos") {
+                "Install it with Homebrew: brew install qpdf"
+            } else {
+                "Install it with your package manager, e.g. sudo apt install qpdf"
+            }
+        }
+        "lp" => "Install the CUPS client, e.g. sudo apt install cups-client",
+        _ => "",
+    }
+}
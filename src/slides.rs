@@ -0,0 +1,232 @@
+use crate::budget;
+use crate::convert;
+use crate::front_matter::FrontMatter;
+use crate::headings;
+use crate::hook;
+use crate::layout::Direction;
+use crate::paths::TempDir;
+use crate::pdf;
+use crate::security;
+use crate::settings::Settings;
+use crate::toc;
+use pulldown_cmark::Event;
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+
+/// Where the reveal.js decks load reveal.js from
+const REVEAL_URL: &str = "https://cdn.jsdelivr.net/npm/reveal.js@5";
+
+/// Slides of the PDF: a page each, with text large enough to read on a projector
+const SLIDES_CSS: &str = r#".markdown-body .md-slide {
+  font-size: 1.5em;
+  page-break-after: always;
+  page-break-inside: avoid;
+}
+.markdown-body .md-slide:last-child {
+  page-break-after: auto;
+}
+"#;
+
+/// Fits the theme, made for pages, into reveal.js's slides
+const REVEAL_CSS: &str = r#".reveal .slides > section.markdown-body {
+  font-size: 28px;
+  text-align: left;
+  background-color: transparent;
+}
+.reveal .slides > section.markdown-body img {
+  max-height: 60vh;
+}
+"#;
+
+/// The slides of a document, rendered
+struct Deck {
+    settings: Settings, // With the document's front matter applied
+    sections: String,   // The slides, a `section` each
+    direction: Direction,
+    lang: String,
+    title: String,
+    warnings: Vec<String>,
+}
+
+/// Splits `events` into slides at the thematic breaks (`---`) outside other blocks.
+/// Empty slides are left out.
+fn split(events: Vec<Event<'_>>) -> Vec<Vec<Event<'_>>> {
+    let mut slides = vec![Vec::new()];
+    let mut depth = 0usize;
+    for event in events {
+        match event {
+            Event::Rule if depth == 0 => slides.push(Vec::new()),
+            event => {
+                match event {
+                    Event::Start(_) => depth += 1,
+                    Event::End(_) => depth = depth.saturating_sub(1),
+                    _ => {}
+                }
+                if let Some(slide) = slides.last_mut() {
+                    slide.push(event);
+                }
+            }
+        }
+    }
+    slides.retain(|slide| !slide.is_empty());
+    slides
+}
+
+/// Renders the Markdown file at `md_path` as slides, each in a `section` with the
+/// `class` attribute `class`
+fn render(settings: &Settings, md_path: &Path, class: &str) -> Result<Deck, String> {
+    let md_text =
+        fs::read_to_string(md_path).map_err(|e| format!("Failed to read Markdown file: {}", e))?;
+    let base_dir = md_path.parent().unwrap_or(Path::new("."));
+    let mut warnings = Vec::new();
+    let (front_matter, md_body) = FrontMatter::extract(&md_text);
+    let settings = Settings {
+        columns: 1,
+        landscape_wide_blocks: false,
+        chapters_on_odd_pages: false,
+        ..convert::document_settings(settings, &front_matter, base_dir, &mut warnings)
+    };
+    settings.check_styles()?;
+    let direction = settings.direction.resolve(&front_matter, md_body);
+    let lang = convert::document_lang(&settings, &front_matter).to_string();
+    let events = convert::render_events(&settings, md_body, base_dir, &lang, &mut warnings);
+    let (events, headings) = headings::assign_ids(events, "");
+    let events = toc::insert(events, &headings, &HashMap::new());
+    security::enforce(settings.url_policy, &warnings)?;
+
+    let slides = split(events);
+    if slides.is_empty() {
+        return Err("The document has no slides".to_string());
+    }
+    let mut sections = String::new();
+    for slide in slides {
+        sections.push_str(&format!("<section class=\"{}\">\n", class));
+        pulldown_cmark::html::push_html(&mut sections, slide.into_iter());
+        sections.push_str("</section>\n");
+    }
+    let title = front_matter
+        .get("title")
+        .map(str::to_string)
+        .or_else(|| {
+            md_path
+                .file_stem()
+                .map(|stem| stem.to_string_lossy().to_string())
+        })
+        .unwrap_or_default();
+    Ok(Deck {
+        settings,
+        sections,
+        direction,
+        lang,
+        title,
+        warnings,
+    })
+}
+
+/// Converts the Markdown file at `md_path` to a landscape PDF at `pdf_path` with a page
+/// per slide, the slides separated by `---` lines. Returns the warnings of the conversion.
+pub fn export_pdf(
+    settings: &Settings,
+    md_path: &Path,
+    pdf_path: &Path,
+) -> Result<Vec<String>, String> {
+    let deck = render(settings, md_path, "md-slide")?;
+    let settings = &deck.settings;
+    let mut warnings = deck.warnings;
+    let local_roots = settings.local_roots(md_path.parent().unwrap_or(Path::new(".")));
+    let html = convert::wrap_document(
+        settings,
+        &local_roots,
+        &deck.sections,
+        deck.direction,
+        &deck.lang,
+        SLIDES_CSS,
+    );
+    if let Some(parent) = pdf_path.parent() {
+        fs::create_dir_all(parent)
+            .map_err(|e| format!("Failed to create output directory: {}", e))?;
+    }
+    let temp_dir = TempDir::new()?;
+    convert::run_wkhtmltopdf(
+        settings,
+        &html,
+        &local_roots,
+        pdf_path,
+        true,
+        &temp_dir.path().join("slides"),
+        &[],
+    )?;
+    pdf::post_process(settings, pdf_path, temp_dir.path(), &mut warnings)?;
+    hook::run_post_command(&settings.post_command, pdf_path, temp_dir.path())?;
+    budget::check(settings, &html, pdf_path, &mut warnings)?;
+    Ok(warnings)
+}
+
+/// Converts the Markdown file at `md_path` to a reveal.js presentation at `html_path`, the
+/// slides separated by `---` lines and styled by the theme. Images are embedded, reveal.js
+/// itself is loaded from the web when the deck is opened. Returns the warnings of the
+/// conversion.
+pub fn export_reveal(
+    settings: &Settings,
+    md_path: &Path,
+    html_path: &Path,
+) -> Result<Vec<String>, String> {
+    let settings = Settings {
+        self_contained: true,
+        rewrite_md_links: false,
+        ..settings.clone()
+    };
+    let deck = render(&settings, md_path, "markdown-body")?;
+    let settings = &deck.settings;
+    let background = settings
+        .theme
+        .page_color(settings.color_scheme)
+        .map(|[r, g, b]| format!("html, body {{\n  background-color: rgb({r}, {g}, {b});\n}}\n"))
+        .unwrap_or_default();
+    let lang_attr = if deck.lang.is_empty() {
+        String::new()
+    } else {
+        format!(" lang=\"{}\"", convert::escape_attr(&deck.lang))
+    };
+    let html = format!(
+        r#"<!DOCTYPE html>
+<html dir="{}"{}>
+<head>
+<meta charset="utf-8">
+<meta name="viewport" content="width=device-width, initial-scale=1.0">
+<title>{}</title>
+<link rel="stylesheet" href="{REVEAL_URL}/dist/reveal.css">
+<style>
+{}
+{}
+{}
+</style>
+</head>
+<body>
+<div class="reveal">
+<div class="slides">
+{}</div>
+</div>
+<script src="{REVEAL_URL}/dist/reveal.js"></script>
+<script>
+Reveal.initialize({{ hash: true }});
+</script>
+</body>
+</html>
+"#,
+        deck.direction.html_attr(),
+        lang_attr,
+        convert::escape_attr(&deck.title),
+        convert::document_css(settings, ""),
+        REVEAL_CSS,
+        background,
+        deck.sections
+    );
+    if let Some(parent) = html_path.parent() {
+        fs::create_dir_all(parent)
+            .map_err(|e| format!("Failed to create output directory: {}", e))?;
+    }
+    fs::write(html_path, html).map_err(|e| format!("Failed to write HTML file: {}", e))?;
+    Ok(deck.warnings)
+}
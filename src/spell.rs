@@ -0,0 +1,487 @@
+use crate::checksum;
+use crate::config;
+use crate::convert;
+use crate::front_matter::FrontMatter;
+use pulldown_cmark::{Event, Tag, TagEnd};
+use std::cell::RefCell;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Configuration file the words ignored in each document are kept in
+const IGNORED_CONFIG: &str = "spelling.conf";
+
+/// Folders searched for Hunspell dictionaries (`.dic` and `.aff` pairs), the app's own first
+pub fn dictionary_dirs() -> Vec<PathBuf> {
+    let mut dirs: Vec<PathBuf> = config::dir()
+        .map(|dir| dir.join("dictionaries"))
+        .into_iter()
+        .collect();
+    if let Some(home) = env::var_os("HOME").map(PathBuf::from) {
+        dirs.push(home.join(".local/share/hunspell"));
+        dirs.push(home.join("Library/Spelling"));
+    }
+    for dir in [
+        "/usr/share/hunspell",
+        "/usr/share/myspell",
+        "/usr/share/myspell/dicts",
+        "/Library/Spelling",
+    ] {
+        dirs.push(PathBuf::from(dir));
+    }
+    dirs
+}
+
+/// Names of the installed dictionaries, such as `en_US`, sorted
+pub fn list() -> Vec<String> {
+    let mut names: Vec<String> = dictionary_dirs()
+        .iter()
+        .filter_map(|dir| fs::read_dir(dir).ok())
+        .flatten()
+        .filter_map(|entry| {
+            let path = entry.ok()?.path();
+            if path.extension()? != "dic" || !path.with_extension("aff").is_file() {
+                return None;
+            }
+            Some(path.file_stem()?.to_string_lossy().to_string())
+        })
+        .collect();
+    names.sort();
+    names.dedup();
+    names
+}
+
+/// Checks words against a Hunspell dictionary: its word list and the prefixes and suffixes its
+/// affix rules allow on them. Compound words and the rarer rules aren't supported, so some
+/// valid words of languages that compound freely, like German, are reported.
+pub struct Dictionary {
+    words: HashMap<String, Vec<String>>, // Flags of each word, naming the affixes it takes
+    prefixes: Vec<Affix>,
+    suffixes: Vec<Affix>,
+    checked: RefCell<HashMap<String, bool>>, // Words looked up so far, the editor asks every frame
+}
+
+/// A prefix or suffix rule of an affix file
+struct Affix {
+    flag: String,
+    strip: String, // Removed from the word before adding
+    add: String,
+    condition: Vec<CharClass>, // What the word must start (prefixes) or end (suffixes) with
+    cross: bool,               // Combines with affixes of the other kind
+}
+
+/// One position of an affix condition
+enum CharClass {
+    Any,
+    Char(char),
+    Set(bool, Vec<char>), // Whether the set is negated, and its characters
+}
+
+impl CharClass {
+    fn matches(&self, c: char) -> bool {
+        match self {
+            CharClass::Any => true,
+            CharClass::Char(expected) => c == *expected,
+            CharClass::Set(negated, chars) => chars.contains(&c) != *negated,
+        }
+    }
+}
+
+impl Dictionary {
+    /// Loads the installed dictionary `name`
+    pub fn load(name: &str) -> Result<Dictionary, String> {
+        let dic_path = dictionary_dirs()
+            .into_iter()
+            .map(|dir| dir.join(format!("{name}.dic")))
+            .find(|path| path.is_file() && path.with_extension("aff").is_file())
+            .ok_or_else(|| format!("Dictionary '{name}' is not installed"))?;
+        let read = |path: &Path| {
+            fs::read(path)
+                .map_err(|e| format!("Failed to read dictionary '{}': {}", path.display(), e))
+        };
+        let aff = read(&dic_path.with_extension("aff"))?;
+        let dic = read(&dic_path)?;
+        Ok(Dictionary::parse(&aff, &dic))
+    }
+
+    /// Reads a dictionary from the contents of its `.aff` and `.dic` files
+    pub fn parse(aff: &[u8], dic: &[u8]) -> Dictionary {
+        // The affix file names the encoding of both files, UTF-8 or a Latin one
+        let utf8 = String::from_utf8_lossy(aff)
+            .lines()
+            .find_map(|line| line.strip_prefix("SET "))
+            .is_none_or(|set| set.trim().eq_ignore_ascii_case("UTF-8"));
+        let decode = |bytes: &[u8]| {
+            if utf8 {
+                String::from_utf8_lossy(bytes).to_string()
+            } else {
+                bytes.iter().map(|&byte| char::from(byte)).collect()
+            }
+        };
+        let (aff, dic) = (decode(aff), decode(dic));
+
+        let mut flag_format = "";
+        let mut prefixes = Vec::new();
+        let mut suffixes = Vec::new();
+        let mut cross = HashMap::new(); // Whether each affix flag combines
+        for line in aff.lines() {
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            match fields.as_slice() {
+                ["FLAG", format, ..] => {
+                    flag_format = if *format == "long" {
+                        "long"
+                    } else if *format == "num" {
+                        "num"
+                    } else {
+                        ""
+                    }
+                }
+                [kind @ ("PFX" | "SFX"), flag, combines, count]
+                    if count.parse::<usize>().is_ok() =>
+                {
+                    cross.insert((kind.to_string(), flag.to_string()), *combines == "Y");
+                }
+                [kind @ ("PFX" | "SFX"), flag, strip, add, rest @ ..] => {
+                    let affix = Affix {
+                        flag: flag.to_string(),
+                        strip: if *strip == "0" {
+                            String::new()
+                        } else {
+                            strip.to_string()
+                        },
+                        // Flags after a slash continue the affix, which isn't supported
+                        add: match add.split('/').next() {
+                            Some("0") | None => String::new(),
+                            Some(add) => add.to_string(),
+                        },
+                        condition: parse_condition(rest.first().copied().unwrap_or(".")),
+                        cross: cross
+                            .get(&(kind.to_string(), flag.to_string()))
+                            .copied()
+                            .unwrap_or(false),
+                    };
+                    if *kind == "PFX" {
+                        prefixes.push(affix);
+                    } else {
+                        suffixes.push(affix);
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        let mut words = HashMap::new();
+        let mut lines = dic.lines();
+        // The first line holds the approximate word count
+        if dic
+            .lines()
+            .next()
+            .is_some_and(|line| line.trim().parse::<usize>().is_ok())
+        {
+            lines.next();
+        }
+        for line in lines {
+            // Morphological fields follow the word after whitespace
+            let Some(entry) = line.split_whitespace().next() else {
+                continue;
+            };
+            let (word, flags) = entry.split_once('/').unwrap_or((entry, ""));
+            words
+                .entry(word.to_string())
+                .or_insert_with(Vec::new)
+                .extend(parse_flags(flags, flag_format));
+        }
+        Dictionary {
+            words,
+            prefixes,
+            suffixes,
+            checked: RefCell::new(HashMap::new()),
+        }
+    }
+
+    /// Whether `word` is spelled correctly. Capitalized words, as at the start of a sentence,
+    /// and words in capitals are also accepted in lowercase.
+    pub fn check(&self, word: &str) -> bool {
+        if let Some(known) = self.checked.borrow().get(word) {
+            return *known;
+        }
+        let typed = word;
+        let normalized = word.replace('’', "'");
+        let word = normalized.as_str();
+        let lower = word.to_lowercase();
+        let mut chars = lower.chars();
+        let capitalized: String = chars
+            .next()
+            .map(|first| first.to_uppercase().chain(chars).collect())
+            .unwrap_or_default();
+        let correct = self.check_exact(word)
+            || (word != lower
+                && (word == capitalized || word.chars().all(|c| !c.is_lowercase()))
+                && (self.check_exact(&lower) || self.check_exact(&capitalized)));
+        self.checked.borrow_mut().insert(typed.to_string(), correct);
+        correct
+    }
+
+    /// Whether `word` is in the word list, possibly with a prefix, a suffix or both added
+    fn check_exact(&self, word: &str) -> bool {
+        if self.words.contains_key(word) {
+            return true;
+        }
+        self.suffixes.iter().any(|suffix| {
+            let Some(base) = strip_suffix(word, suffix) else {
+                return false;
+            };
+            self.has_flag(&base, &suffix.flag)
+                || (suffix.cross
+                    && self.prefixes.iter().any(|prefix| {
+                        prefix.cross
+                            && strip_prefix(&base, prefix).is_some_and(|root| {
+                                self.has_flag(&root, &prefix.flag)
+                                    && self.has_flag(&root, &suffix.flag)
+                            })
+                    }))
+        }) || self.prefixes.iter().any(|prefix| {
+            strip_prefix(word, prefix).is_some_and(|base| self.has_flag(&base, &prefix.flag))
+        })
+    }
+
+    fn has_flag(&self, word: &str, flag: &str) -> bool {
+        self.words
+            .get(word)
+            .is_some_and(|flags| flags.iter().any(|other| other == flag))
+    }
+}
+
+/// The word `suffix` was added to, if it could have been
+fn strip_suffix(word: &str, suffix: &Affix) -> Option<String> {
+    let stem = word.strip_suffix(suffix.add.as_str())?;
+    if stem.is_empty() {
+        return None;
+    }
+    let base = format!("{stem}{}", suffix.strip);
+    let chars: Vec<char> = base.chars().collect();
+    let start = chars.len().checked_sub(suffix.condition.len())?;
+    let matches = suffix
+        .condition
+        .iter()
+        .zip(&chars[start..])
+        .all(|(class, c)| class.matches(*c));
+    matches.then_some(base)
+}
+
+/// The word `prefix` was added to, if it could have been
+fn strip_prefix(word: &str, prefix: &Affix) -> Option<String> {
+    let stem = word.strip_prefix(prefix.add.as_str())?;
+    if stem.is_empty() {
+        return None;
+    }
+    let base = format!("{}{stem}", prefix.strip);
+    let matches = base.chars().count() >= prefix.condition.len()
+        && prefix
+            .condition
+            .iter()
+            .zip(base.chars())
+            .all(|(class, c)| class.matches(c));
+    matches.then_some(base)
+}
+
+/// Reads an affix condition such as `[^aeiou]y` or `.`
+fn parse_condition(condition: &str) -> Vec<CharClass> {
+    let mut classes = Vec::new();
+    let mut chars = condition.chars();
+    while let Some(c) = chars.next() {
+        classes.push(match c {
+            '.' => CharClass::Any,
+            '[' => {
+                let set: String = chars.by_ref().take_while(|c| *c != ']').collect();
+                match set.strip_prefix('^') {
+                    Some(negated) => CharClass::Set(true, negated.chars().collect()),
+                    None => CharClass::Set(false, set.chars().collect()),
+                }
+            }
+            c => CharClass::Char(c),
+        });
+    }
+    classes
+}
+
+/// Splits the flags of a word or affix, which are single characters, pairs of characters
+/// (`FLAG long`) or comma separated numbers (`FLAG num`)
+fn parse_flags(flags: &str, format: &str) -> Vec<String> {
+    match format {
+        "long" => {
+            let chars: Vec<char> = flags.chars().collect();
+            chars.chunks(2).map(|pair| pair.iter().collect()).collect()
+        }
+        "num" => flags
+            .split(',')
+            .map(|flag| flag.trim().to_string())
+            .collect(),
+        _ => flags.chars().map(String::from).collect(),
+    }
+}
+
+/// The words of `text` worth checking, with their byte offsets. Apostrophes inside words are
+/// part of them, words with digits and single letters are left out.
+pub fn words(text: &str) -> Vec<(usize, &str)> {
+    let mut words = Vec::new();
+    let mut start = None;
+    let mut chars = text.char_indices().peekable();
+    while let Some((i, c)) = chars.next() {
+        let inside = c.is_alphanumeric()
+            || (matches!(c, '\'' | '’')
+                && start.is_some()
+                && chars.peek().is_some_and(|(_, next)| next.is_alphabetic()));
+        match (inside, start) {
+            (true, None) => start = Some(i),
+            (false, Some(begin)) => {
+                words.push((begin, &text[begin..i]));
+                start = None;
+            }
+            _ => {}
+        }
+    }
+    if let Some(begin) = start {
+        words.push((begin, &text[begin..]));
+    }
+    words.retain(|(_, word)| word.chars().count() > 1 && !word.chars().any(|c| c.is_numeric()));
+    words
+}
+
+/// The misspelled words of a Markdown document's text, each once, in the order they first
+/// appear. Code, link targets and words in `ignored` aren't checked.
+pub fn misspellings(
+    dictionary: &Dictionary,
+    markdown: &str,
+    ignored: &BTreeSet<String>,
+) -> Vec<String> {
+    let (_, md_body) = FrontMatter::extract(markdown);
+    let mut found = Vec::new();
+    let mut in_code = false;
+    for event in pulldown_cmark::Parser::new_ext(md_body, convert::markdown_options()) {
+        match event {
+            Event::Start(Tag::CodeBlock(_)) => in_code = true,
+            Event::End(TagEnd::CodeBlock) => in_code = false,
+            Event::Text(text) if !in_code => {
+                for (_, word) in words(&text) {
+                    let word = word.replace('’', "'");
+                    if !ignored.contains(&word)
+                        && !dictionary.check(&word)
+                        && !found.contains(&word)
+                    {
+                        found.push(word);
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+    found
+}
+
+/// Key of the words ignored in the Markdown file at `md_path`. Paths may hold `=` and
+/// surrounding spaces, which the configuration file's keys can't, so they are hashed.
+fn ignored_key(md_path: &str) -> String {
+    checksum::key(&[md_path.as_bytes()])
+}
+
+/// Words the user chose to ignore in the Markdown file at `md_path`
+pub fn ignored_words(md_path: &str) -> BTreeSet<String> {
+    let entries = config::load(IGNORED_CONFIG);
+    // Earlier versions keyed the words by the path itself
+    entries
+        .get(&ignored_key(md_path))
+        .or_else(|| entries.get(md_path))
+        .map(|words| words.split_whitespace().map(str::to_string).collect())
+        .unwrap_or_default()
+}
+
+/// Remembers the words to ignore in the Markdown file at `md_path`
+pub fn save_ignored_words(md_path: &str, words: &BTreeSet<String>) -> Result<(), String> {
+    let mut entries: BTreeMap<String, String> = config::load(IGNORED_CONFIG);
+    entries.remove(md_path);
+    let key = ignored_key(md_path);
+    if words.is_empty() {
+        entries.remove(&key);
+    } else {
+        let words: Vec<&str> = words.iter().map(String::as_str).collect();
+        entries.insert(key, words.join(" "));
+    }
+    config::save(IGNORED_CONFIG, &entries)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const AFF: &str = "SET UTF-8
+PFX U Y 1
+PFX U 0 un .
+SFX S Y 2
+SFX S y ies [^aeiou]y
+SFX S 0 s [aeiou]y
+SFX D N 1
+SFX D 0 ed [^e]
+";
+
+    fn dictionary(aff: &str, dic: &str) -> Dictionary {
+        Dictionary::parse(aff.as_bytes(), dic.as_bytes())
+    }
+
+    #[test]
+    fn suffixes_follow_their_conditions() {
+        let dictionary = dictionary(AFF, "3\nfly/S\nboy/S\nwalk/D\n");
+        assert!(dictionary.check("flies"));
+        assert!(dictionary.check("boys"));
+        assert!(dictionary.check("walked"));
+        assert!(!dictionary.check("flys"));
+        assert!(!dictionary.check("boies"));
+        assert!(!dictionary.check("walks"));
+    }
+
+    #[test]
+    fn prefixes_combine_with_suffixes_when_both_allow_it() {
+        let dictionary = dictionary(AFF, "2\nlock/UD\nfly/US\n");
+        assert!(dictionary.check("unlock"));
+        assert!(dictionary.check("unflies"));
+        // The D suffix doesn't combine
+        assert!(!dictionary.check("unlocked"));
+    }
+
+    #[test]
+    fn capitalized_words_are_checked_in_lowercase() {
+        let dictionary = dictionary(AFF, "1\nfly/S\n");
+        assert!(dictionary.check("Flies"));
+        assert!(dictionary.check("FLIES"));
+        assert!(!dictionary.check("fLies"));
+    }
+
+    #[test]
+    fn long_and_numeric_flags_are_split() {
+        let aff = "FLAG long\nSFX Aa Y 1\nSFX Aa 0 s .\n";
+        assert!(dictionary(aff, "1\ncat/AaBb\n").check("cats"));
+        let aff = "FLAG num\nSFX 12 Y 1\nSFX 12 0 s .\n";
+        assert!(dictionary(aff, "1\ncat/3,12\n").check("cats"));
+        assert!(!dictionary(aff, "1\ncat/1,2\n").check("cats"));
+    }
+
+    #[test]
+    fn ignored_words_keys_have_no_equals_sign_or_spaces() {
+        let key = ignored_key(" notes = draft.md ");
+        assert_eq!(
+            config::parse(&format!("{key} = word"))
+                .get(&key)
+                .map(String::as_str),
+            Some("word")
+        );
+    }
+
+    #[test]
+    fn ignored_words_keys_stay_the_same_between_builds() {
+        assert_eq!(
+            ignored_key("notes.md"),
+            "b19af007df7eb7178dcd480f1b725a44f9e036d82f8bc7b61507156161b26b48"
+        );
+    }
+}
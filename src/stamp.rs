@@ -0,0 +1,492 @@
+use crate::numbering;
+use crate::pdf;
+use lopdf::content::{Content, Operation};
+use lopdf::{Document, Object, ObjectId, Stream, dictionary};
+use std::path::Path;
+
+/// Average glyph advance of Helvetica Bold in text space units, used to center stamped text
+const HELVETICA_BOLD_AVG_ADVANCE: f32 = 0.62;
+/// Same for regular Helvetica
+const HELVETICA_AVG_ADVANCE: f32 = 0.55;
+
+/// Font size and distance from the bottom edge of stamped page numbers, in points
+const PAGE_NUMBER_SIZE: f32 = 9.0;
+const PAGE_NUMBER_OFFSET: f32 = 16.0;
+
+/// Font size of Bates numbers and their distance from the side edges, in points. They're as
+/// far from the top or bottom edge as page numbers.
+const BATES_SIZE: f32 = 10.0;
+const BATES_SIDE_OFFSET: f32 = 28.0;
+
+/// Text and/or image stamped across every page of the output
+#[derive(Debug, Clone, PartialEq)]
+pub struct Watermark {
+    pub enabled: bool,
+    pub text: String,  // e.g. "DRAFT", left empty for an image-only watermark
+    pub image: String, // Path to a PNG/JPEG, left empty for a text-only watermark
+    pub opacity: f32,  // 0.0 (invisible) to 1.0 (opaque)
+    pub rotation: f32, // Degrees counter-clockwise
+}
+
+impl Default for Watermark {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            text: String::from("DRAFT"),
+            image: String::new(),
+            opacity: 0.15,
+            rotation: 45.0,
+        }
+    }
+}
+
+/// How a page background image covers the page
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum BackgroundFit {
+    FullBleed, // Stretched over the whole page, e.g. a letterhead
+    Tiled,     // Repeated at its natural size (96 dpi)
+}
+
+impl BackgroundFit {
+    /// Returns the display name for the fit
+    pub fn name(&self) -> &'static str {
+        match self {
+            BackgroundFit::FullBleed => "Full page",
+            BackgroundFit::Tiled => "Tiled",
+        }
+    }
+
+    /// Returns all available fits
+    pub fn all() -> &'static [BackgroundFit] {
+        &[BackgroundFit::FullBleed, BackgroundFit::Tiled]
+    }
+
+    /// Identifier used in saved settings
+    pub fn keyword(&self) -> &'static str {
+        match self {
+            BackgroundFit::FullBleed => "full-page",
+            BackgroundFit::Tiled => "tiled",
+        }
+    }
+}
+
+/// Corner of the page Bates numbers are stamped in
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum BatesPosition {
+    BottomRight,
+    BottomLeft,
+    TopRight,
+    TopLeft,
+}
+
+impl BatesPosition {
+    /// Returns the display name for the position
+    pub fn name(&self) -> &'static str {
+        match self {
+            BatesPosition::BottomRight => "Bottom right",
+            BatesPosition::BottomLeft => "Bottom left",
+            BatesPosition::TopRight => "Top right",
+            BatesPosition::TopLeft => "Top left",
+        }
+    }
+
+    /// Returns all available positions
+    pub fn all() -> &'static [BatesPosition] {
+        &[
+            BatesPosition::BottomRight,
+            BatesPosition::BottomLeft,
+            BatesPosition::TopRight,
+            BatesPosition::TopLeft,
+        ]
+    }
+
+    /// Identifier used in saved settings and on the command line
+    pub fn keyword(&self) -> &'static str {
+        match self {
+            BatesPosition::BottomRight => "bottom-right",
+            BatesPosition::BottomLeft => "bottom-left",
+            BatesPosition::TopRight => "top-right",
+            BatesPosition::TopLeft => "top-left",
+        }
+    }
+}
+
+/// Sequential reference numbers stamped on every page, which legal document productions
+/// identify pages by, e.g. ACME000001
+#[derive(Debug, Clone, PartialEq)]
+pub struct Bates {
+    pub enabled: bool,
+    pub prefix: String, // Put before the number, may be empty
+    pub start: u64,     // Number of the first page
+    pub digits: usize,  // The number is padded with zeros to this many digits
+    pub position: BatesPosition,
+}
+
+impl Default for Bates {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            prefix: String::new(),
+            start: 1,
+            digits: 6,
+            position: BatesPosition::BottomRight,
+        }
+    }
+}
+
+impl Bates {
+    /// The Bates number of the page at `index`, counting from 0
+    pub fn label(&self, index: usize) -> String {
+        format!(
+            "{}{:0digits$}",
+            self.prefix.trim(),
+            self.start.saturating_add(index as u64),
+            digits = self.digits
+        )
+    }
+}
+
+/// Page background painted behind the content, independently of the theme
+#[derive(Debug, Clone, PartialEq)]
+pub struct PageBackground {
+    pub enabled: bool,
+    pub color: [u8; 3],
+    pub image: String, // Path to a PNG/JPEG, left empty for a plain color
+    pub fit: BackgroundFit,
+}
+
+impl Default for PageBackground {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            color: [255, 255, 255],
+            image: String::new(),
+            fit: BackgroundFit::FullBleed,
+        }
+    }
+}
+
+/// Makes the theme's own backgrounds transparent so the page background shows through
+pub const TRANSPARENT_BODY_CSS: &str = r#"html,
+body.markdown-body {
+  background: transparent !important;
+}
+"#;
+
+/// Keeps the renderer from dropping background colors and images when printing
+pub const PRINT_BACKGROUNDS_CSS: &str = r#"* {
+  -webkit-print-color-adjust: exact !important;
+  print-color-adjust: exact !important;
+}
+"#;
+
+/// Paints `background` behind the content of every page of `document`,
+/// extending `bleed` points past the page edges
+pub fn apply_background(
+    document: &mut Document,
+    background: &PageBackground,
+    bleed: f32,
+) -> Result<(), String> {
+    let image = background.image.trim();
+    let image = if image.is_empty() {
+        None
+    } else {
+        let (stream, width, height) = image_xobject(document, Path::new(image))?;
+        Some((document.add_object(stream), width as f32, height as f32))
+    };
+    let [r, g, b] = background.color.map(|channel| channel as f32 / 255.0);
+
+    for page_id in document.get_pages().into_values() {
+        let [x0, y0, x1, y1] =
+            pdf::media_box(document, page_id).unwrap_or([0.0, 0.0, 595.0, 842.0]);
+        let [x0, y0, x1, y1] = [x0 - bleed, y0 - bleed, x1 + bleed, y1 + bleed];
+        let (width, height) = (x1 - x0, y1 - y0);
+
+        let mut operations = vec![
+            Operation::new("q", vec![]),
+            Operation::new("rg", vec![r.into(), g.into(), b.into()]),
+            Operation::new(
+                "re",
+                vec![x0.into(), y0.into(), width.into(), height.into()],
+            ),
+            Operation::new("f", vec![]),
+        ];
+
+        if let Some((_, image_width, image_height)) = image {
+            let placements = match background.fit {
+                BackgroundFit::FullBleed => vec![(x0, y0, width, height)],
+                BackgroundFit::Tiled => {
+                    // Pixels at 96 dpi to points
+                    let (w, h) = (image_width * 0.75, image_height * 0.75);
+                    let mut tiles = Vec::new();
+                    let mut y = y1 - h;
+                    while y + h > y0 {
+                        let mut x = x0;
+                        while x < x1 {
+                            tiles.push((x, y, w, h));
+                            x += w;
+                        }
+                        y -= h;
+                    }
+                    tiles
+                }
+            };
+            for (x, y, w, h) in placements {
+                operations.extend([
+                    Operation::new("q", vec![]),
+                    Operation::new(
+                        "cm",
+                        vec![w.into(), 0.into(), 0.into(), h.into(), x.into(), y.into()],
+                    ),
+                    Operation::new("Do", vec!["ImBackground".into()]),
+                    Operation::new("Q", vec![]),
+                ]);
+            }
+        }
+        operations.push(Operation::new("Q", vec![]));
+
+        if let Some((image_id, _, _)) = image {
+            pdf::add_page_resource(document, page_id, b"XObject", b"ImBackground", image_id)?;
+        }
+        pdf::prepend_page_content(document, page_id, Content { operations })?;
+    }
+    Ok(())
+}
+
+/// Stamps `watermark` onto every page of `document`
+pub fn apply_watermark(document: &mut Document, watermark: &Watermark) -> Result<(), String> {
+    let text = watermark.text.trim();
+    let image = watermark.image.trim();
+
+    let font_id = document.add_object(dictionary! {
+        "Type" => "Font",
+        "Subtype" => "Type1",
+        "BaseFont" => "Helvetica-Bold",
+        "Encoding" => "WinAnsiEncoding",
+    });
+    let state_id = document.add_object(dictionary! {
+        "Type" => "ExtGState",
+        "ca" => watermark.opacity.clamp(0.0, 1.0),
+        "CA" => watermark.opacity.clamp(0.0, 1.0),
+    });
+    let image = if image.is_empty() {
+        None
+    } else {
+        let (stream, width, height) = image_xobject(document, Path::new(image))?;
+        Some((document.add_object(stream), width as f32, height as f32))
+    };
+
+    for page_id in document.get_pages().into_values() {
+        let [x0, y0, x1, y1] =
+            pdf::media_box(document, page_id).unwrap_or([0.0, 0.0, 595.0, 842.0]);
+        let (width, height) = (x1 - x0, y1 - y0);
+        let center = (x0 + width / 2.0, y0 + height / 2.0);
+
+        let mut operations = vec![
+            Operation::new("q", vec![]),
+            Operation::new("gs", vec!["GSWatermark".into()]),
+            rotate_about(center, watermark.rotation),
+        ];
+
+        if let Some((_, image_width, image_height)) = image {
+            // Fit the image into 60% of the page width, keeping its aspect ratio
+            let scale = (width * 0.6 / image_width).min(height * 0.6 / image_height);
+            let (w, h) = (image_width * scale, image_height * scale);
+            operations.extend([
+                Operation::new("q", vec![]),
+                Operation::new(
+                    "cm",
+                    vec![
+                        w.into(),
+                        0.into(),
+                        0.into(),
+                        h.into(),
+                        (-w / 2.0).into(),
+                        (-h / 2.0).into(),
+                    ],
+                ),
+                Operation::new("Do", vec!["ImWatermark".into()]),
+                Operation::new("Q", vec![]),
+            ]);
+        }
+
+        if !text.is_empty() {
+            // Size the text to span roughly 70% of the page diagonal
+            let chars = text.chars().count() as f32;
+            let diagonal = (width * width + height * height).sqrt();
+            let size = (diagonal * 0.7 / (chars * HELVETICA_BOLD_AVG_ADVANCE)).min(height / 4.0);
+            operations.extend([
+                Operation::new("BT", vec![]),
+                Operation::new("Tf", vec!["FWatermark".into(), size.into()]),
+                Operation::new("rg", vec![0.5.into(), 0.5.into(), 0.5.into()]),
+                Operation::new(
+                    "Td",
+                    vec![
+                        (-chars * HELVETICA_BOLD_AVG_ADVANCE * size / 2.0).into(),
+                        (-size / 3.0).into(),
+                    ],
+                ),
+                Operation::new("Tj", vec![Object::string_literal(win_ansi(text))]),
+                Operation::new("ET", vec![]),
+            ]);
+        }
+        operations.push(Operation::new("Q", vec![]));
+
+        pdf::add_page_resource(document, page_id, b"Font", b"FWatermark", font_id)?;
+        pdf::add_page_resource(document, page_id, b"ExtGState", b"GSWatermark", state_id)?;
+        if let Some((image_id, _, _)) = image {
+            pdf::add_page_resource(document, page_id, b"XObject", b"ImWatermark", image_id)?;
+        }
+        pdf::append_page_content(document, page_id, Content { operations })?;
+    }
+    Ok(())
+}
+
+/// Stamps each page's label (see [`numbering::page_labels`]) centered at the bottom of the page
+pub fn apply_page_numbers(document: &mut Document) -> Result<(), String> {
+    let labels = numbering::page_labels(document);
+    let font_id = document.add_object(dictionary! {
+        "Type" => "Font",
+        "Subtype" => "Type1",
+        "BaseFont" => "Helvetica",
+        "Encoding" => "WinAnsiEncoding",
+    });
+
+    for (page_id, label) in document.get_pages().into_values().zip(labels) {
+        if label.is_empty() {
+            continue;
+        }
+        let [x0, y0, x1, _] = pdf::media_box(document, page_id).unwrap_or([0.0, 0.0, 595.0, 842.0]);
+        let width = label.chars().count() as f32 * HELVETICA_AVG_ADVANCE * PAGE_NUMBER_SIZE;
+        let operations = vec![
+            Operation::new("q", vec![]),
+            Operation::new("BT", vec![]),
+            Operation::new("Tf", vec!["FPageNumber".into(), PAGE_NUMBER_SIZE.into()]),
+            Operation::new("rg", vec![0.4.into(), 0.4.into(), 0.4.into()]),
+            Operation::new(
+                "Td",
+                vec![
+                    ((x0 + x1 - width) / 2.0).into(),
+                    (y0 + PAGE_NUMBER_OFFSET).into(),
+                ],
+            ),
+            Operation::new("Tj", vec![Object::string_literal(win_ansi(&label))]),
+            Operation::new("ET", vec![]),
+            Operation::new("Q", vec![]),
+        ];
+
+        pdf::add_page_resource(document, page_id, b"Font", b"FPageNumber", font_id)?;
+        pdf::append_page_content(document, page_id, Content { operations })?;
+    }
+    Ok(())
+}
+
+/// Stamps the numbers of `bates` onto every page of `document`, counting up from its start
+pub fn apply_bates(document: &mut Document, bates: &Bates) -> Result<(), String> {
+    let font_id = document.add_object(dictionary! {
+        "Type" => "Font",
+        "Subtype" => "Type1",
+        "BaseFont" => "Helvetica",
+        "Encoding" => "WinAnsiEncoding",
+    });
+
+    for (index, page_id) in document.get_pages().into_values().enumerate() {
+        let label = bates.label(index);
+        let [x0, y0, x1, y1] =
+            pdf::media_box(document, page_id).unwrap_or([0.0, 0.0, 595.0, 842.0]);
+        let width = label.chars().count() as f32 * HELVETICA_AVG_ADVANCE * BATES_SIZE;
+        let x = match bates.position {
+            BatesPosition::BottomLeft | BatesPosition::TopLeft => x0 + BATES_SIDE_OFFSET,
+            BatesPosition::BottomRight | BatesPosition::TopRight => x1 - BATES_SIDE_OFFSET - width,
+        };
+        let y = match bates.position {
+            BatesPosition::BottomLeft | BatesPosition::BottomRight => y0 + PAGE_NUMBER_OFFSET,
+            BatesPosition::TopLeft | BatesPosition::TopRight => {
+                y1 - PAGE_NUMBER_OFFSET - BATES_SIZE
+            }
+        };
+        let operations = vec![
+            Operation::new("q", vec![]),
+            Operation::new("BT", vec![]),
+            Operation::new("Tf", vec!["FBates".into(), BATES_SIZE.into()]),
+            Operation::new("rg", vec![0.into(), 0.into(), 0.into()]),
+            Operation::new("Td", vec![x.into(), y.into()]),
+            Operation::new("Tj", vec![Object::string_literal(win_ansi(&label))]),
+            Operation::new("ET", vec![]),
+            Operation::new("Q", vec![]),
+        ];
+
+        pdf::add_page_resource(document, page_id, b"Font", b"FBates", font_id)?;
+        pdf::append_page_content(document, page_id, Content { operations })?;
+    }
+    Ok(())
+}
+
+/// `cm` operation that rotates the coordinate system by `degrees` around `center`,
+/// leaving the origin at the center
+fn rotate_about(center: (f32, f32), degrees: f32) -> Operation {
+    let (sin, cos) = degrees.to_radians().sin_cos();
+    Operation::new(
+        "cm",
+        vec![
+            cos.into(),
+            sin.into(),
+            (-sin).into(),
+            cos.into(),
+            center.0.into(),
+            center.1.into(),
+        ],
+    )
+}
+
+/// Encodes text for a WinAnsi font, replacing characters it can't represent
+pub fn win_ansi(text: &str) -> Vec<u8> {
+    text.chars()
+        .map(|c| if (c as u32) < 0x100 { c as u8 } else { b'?' })
+        .collect()
+}
+
+/// Loads an image file as a PDF image XObject, with its alpha channel as a soft mask.
+/// Returns the stream together with the image's pixel size.
+fn image_xobject(document: &mut Document, path: &Path) -> Result<(Stream, u32, u32), String> {
+    let image = image::open(path)
+        .map_err(|e| format!("Failed to load image '{}': {}", path.display(), e))?
+        .into_rgba8();
+    let (width, height) = image.dimensions();
+
+    let mut rgb = Vec::with_capacity((width * height * 3) as usize);
+    let mut alpha = Vec::with_capacity((width * height) as usize);
+    for pixel in image.pixels() {
+        rgb.extend_from_slice(&pixel.0[..3]);
+        alpha.push(pixel.0[3]);
+    }
+
+    let mut mask = Stream::new(
+        dictionary! {
+            "Type" => "XObject",
+            "Subtype" => "Image",
+            "Width" => width,
+            "Height" => height,
+            "ColorSpace" => "DeviceGray",
+            "BitsPerComponent" => 8,
+        },
+        alpha,
+    );
+    let _ = mask.compress();
+    let mask_id: ObjectId = document.add_object(mask);
+
+    let mut stream = Stream::new(
+        dictionary! {
+            "Type" => "XObject",
+            "Subtype" => "Image",
+            "Width" => width,
+            "Height" => height,
+            "ColorSpace" => "DeviceRGB",
+            "BitsPerComponent" => 8,
+            "SMask" => mask_id,
+        },
+        rgb,
+    );
+    let _ = stream.compress();
+    Ok((stream, width, height))
+}
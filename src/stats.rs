@@ -0,0 +1,70 @@
+use crate::convert;
+use crate::front_matter::FrontMatter;
+use pulldown_cmark::{Event, Tag, TagEnd};
+
+/// Reading speed assumed for the reading time, in words per minute
+const WORDS_PER_MINUTE: usize = 230;
+
+/// Counts describing a Markdown document
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Stats {
+    pub words: usize,      // In the text, not counting code blocks
+    pub characters: usize, // Of the text as shown, without the Markdown syntax or code blocks
+    pub headings: usize,
+    pub images: usize,
+    pub code_blocks: usize,
+}
+
+impl Stats {
+    /// Minutes an average reader takes for the text, at least one for any text
+    pub fn reading_minutes(&self) -> usize {
+        self.words.div_ceil(WORDS_PER_MINUTE)
+    }
+}
+
+/// Counts the words, characters, headings, images and code blocks of `markdown`. The front
+/// matter isn't part of the document's text.
+pub fn count(markdown: &str) -> Stats {
+    let (_, md_body) = FrontMatter::extract(markdown);
+    let mut stats = Stats::default();
+    let mut in_code = false;
+    let mut in_image = false; // Alt text isn't shown
+    let mut in_word = false; // Words can continue across events, e.g. "**bold**er"
+    for event in pulldown_cmark::Parser::new_ext(md_body, convert::markdown_options()) {
+        match event {
+            Event::Start(Tag::CodeBlock(_)) => {
+                in_code = true;
+                stats.code_blocks += 1;
+            }
+            Event::End(TagEnd::CodeBlock) => in_code = false,
+            Event::Start(Tag::Heading { .. }) => stats.headings += 1,
+            Event::Start(Tag::Image { .. }) => {
+                in_image = true;
+                stats.images += 1;
+            }
+            Event::End(TagEnd::Image) => in_image = false,
+            Event::Text(text) | Event::Code(text) if !in_code && !in_image => {
+                for c in text.chars() {
+                    stats.characters += 1;
+                    // Punctuation on its own isn't a word
+                    if c.is_whitespace() {
+                        in_word = false;
+                    } else if !in_word && c.is_alphanumeric() {
+                        stats.words += 1;
+                        in_word = true;
+                    }
+                }
+            }
+            Event::SoftBreak | Event::HardBreak => {
+                stats.characters += 1;
+                in_word = false;
+            }
+            Event::End(
+                TagEnd::Emphasis | TagEnd::Strong | TagEnd::Strikethrough | TagEnd::Link,
+            ) => {}
+            Event::End(_) => in_word = false,
+            _ => {}
+        }
+    }
+    stats
+}
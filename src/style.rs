@@ -0,0 +1,405 @@
+/// Common style adjustments layered over the selected theme, for users who don't write CSS
+#[derive(Debug, Clone, PartialEq)]
+pub struct StyleOptions {
+    pub enabled: bool,
+    pub font_size: f32,     // Body text size in px
+    pub line_height: f32,   // Multiple of the font size
+    pub heading_scale: f32, // 1.0 keeps the theme's heading sizes, 0.0 makes them body-sized
+    pub link_color: [u8; 3],
+    pub table_striping: bool, // Shade every other table row
+    pub table_width: TableWidth,
+    pub table_borders: TableBorders,
+    pub table_header_shading: bool,
+    pub accents: Accents,
+    pub margin_mm: f32, // Page margin on all sides
+}
+
+/// Accent colors of blockquotes and of the GitHub-style alert boxes (`.markdown-alert-*`).
+/// Dark themes get lightened variants so the colors stay readable.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Accents {
+    pub enabled: bool,
+    pub blockquote: [u8; 3],
+    pub note: [u8; 3],
+    pub tip: [u8; 3],
+    pub important: [u8; 3],
+    pub warning: [u8; 3],
+    pub caution: [u8; 3],
+}
+
+impl Default for Accents {
+    fn default() -> Self {
+        // GitHub's light theme colors
+        Self {
+            enabled: false,
+            blockquote: [209, 217, 224],
+            note: [9, 105, 218],
+            tip: [26, 127, 55],
+            important: [130, 80, 223],
+            warning: [154, 103, 0],
+            caution: [209, 36, 47],
+        }
+    }
+}
+
+/// How far dark theme variants are mixed towards white
+const DARK_LIGHTEN: f32 = 0.35;
+
+impl Accents {
+    /// The colors as (keyword, display name, color); the keyword is used in CSS class names
+    /// and style profiles
+    pub fn colors_mut(&mut self) -> [(&'static str, &'static str, &mut [u8; 3]); 6] {
+        [
+            ("blockquote", "Blockquote", &mut self.blockquote),
+            ("note", "Note", &mut self.note),
+            ("tip", "Tip", &mut self.tip),
+            ("important", "Important", &mut self.important),
+            ("warning", "Warning", &mut self.warning),
+            ("caution", "Caution", &mut self.caution),
+        ]
+    }
+
+    /// All colors as (keyword, color)
+    pub fn colors(&self) -> [(&'static str, [u8; 3]); 6] {
+        let [note, tip, important, warning, caution] = self.alerts();
+        [
+            ("blockquote", self.blockquote),
+            note,
+            tip,
+            important,
+            warning,
+            caution,
+        ]
+    }
+
+    /// The alert colors as (keyword, color)
+    fn alerts(&self) -> [(&'static str, [u8; 3]); 5] {
+        [
+            ("note", self.note),
+            ("tip", self.tip),
+            ("important", self.important),
+            ("warning", self.warning),
+            ("caution", self.caution),
+        ]
+    }
+
+    /// Returns the CSS for the accent colors, or nothing when disabled
+    fn css(&self, dark: bool) -> String {
+        if !self.enabled {
+            return String::new();
+        }
+        let variant = |color: [u8; 3]| {
+            if dark {
+                hex(color.map(|channel| {
+                    (channel as f32 + (255.0 - channel as f32) * DARK_LIGHTEN).round() as u8
+                }))
+            } else {
+                hex(color)
+            }
+        };
+        let mut css = format!(
+            "\n.markdown-body blockquote {{\n  border-left-color: {};\n}}\n",
+            variant(self.blockquote)
+        );
+        for (kind, color) in self.alerts() {
+            let color = variant(color);
+            css.push_str(&format!(
+                "\n.markdown-body .markdown-alert.markdown-alert-{kind} {{\n  border-left-color: {color};\n}}\n"
+            ));
+            css.push_str(&format!(
+                "\n.markdown-body .markdown-alert.markdown-alert-{kind} .markdown-alert-title {{\n  color: {color};\n}}\n"
+            ));
+        }
+        css
+    }
+}
+
+/// Ready-made heading styles, selectable per document with `headings:` in the front matter
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum HeadingPreset {
+    Theme,            // As the theme styles them
+    Numbered,         // Numbered 1, 1.1, 1.1.1 with rules under the top two levels
+    SmallCaps,        // Small capitals without rules
+    CenteredChapters, // `#` headings centered below a "Chapter n" line
+}
+
+impl HeadingPreset {
+    /// Returns the display name for the preset
+    pub fn name(&self) -> &'static str {
+        match self {
+            HeadingPreset::Theme => "As in the theme",
+            HeadingPreset::Numbered => "Numbered with rules",
+            HeadingPreset::SmallCaps => "Small caps",
+            HeadingPreset::CenteredChapters => "Centered chapter titles",
+        }
+    }
+
+    /// Returns all available presets
+    pub fn all() -> &'static [HeadingPreset] {
+        &[
+            HeadingPreset::Theme,
+            HeadingPreset::Numbered,
+            HeadingPreset::SmallCaps,
+            HeadingPreset::CenteredChapters,
+        ]
+    }
+
+    /// Identifier used in front matter and style profiles
+    pub fn keyword(&self) -> &'static str {
+        match self {
+            HeadingPreset::Theme => "theme",
+            HeadingPreset::Numbered => "numbered",
+            HeadingPreset::SmallCaps => "small-caps",
+            HeadingPreset::CenteredChapters => "chapters",
+        }
+    }
+
+    /// Returns the preset with the given keyword
+    pub fn from_keyword(keyword: &str) -> Option<HeadingPreset> {
+        HeadingPreset::all()
+            .iter()
+            .find(|preset| preset.keyword() == keyword.trim())
+            .copied()
+    }
+
+    /// Returns the CSS layer of the preset
+    pub fn css(&self) -> &'static str {
+        match self {
+            HeadingPreset::Theme => "",
+            HeadingPreset::Numbered => concat!(
+                ".markdown-body h1 {\n  border-bottom: 2px solid currentColor;\n}\n\n",
+                ".markdown-body h2 {\n  border-bottom: 1px solid currentColor;\n}\n",
+            ),
+            HeadingPreset::SmallCaps => concat!(
+                ".markdown-body h1,\n.markdown-body h2,\n.markdown-body h3,\n",
+                ".markdown-body h4,\n.markdown-body h5,\n.markdown-body h6 {\n",
+                "  font-variant: small-caps;\n  letter-spacing: .04em;\n",
+                "  border-bottom: none;\n}\n",
+            ),
+            HeadingPreset::CenteredChapters => concat!(
+                ".markdown-body h1 {\n  text-align: center;\n  border-bottom: none;\n",
+                "  margin-top: 2.5em;\n  margin-bottom: 1.5em;\n}\n\n",
+                ".markdown-body h1 .md-heading-number {\n  display: block;\n",
+                "  font-size: .5em;\n  font-weight: normal;\n  letter-spacing: .15em;\n",
+                "  text-transform: uppercase;\n  margin-bottom: .5em;\n}\n\n",
+                ".markdown-body h1 .md-heading-number:before {\n  content: \"Chapter \";\n}\n",
+            ),
+        }
+    }
+}
+
+/// Formats a color as `#rrggbb`
+pub fn hex([r, g, b]: [u8; 3]) -> String {
+    format!("#{r:02x}{g:02x}{b:02x}")
+}
+
+/// How wide tables are laid out
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum TableWidth {
+    Auto, // As wide as the content, the theme's default
+    Full, // Across the whole text width
+}
+
+impl TableWidth {
+    /// Returns the display name for the width
+    pub fn name(&self) -> &'static str {
+        match self {
+            TableWidth::Auto => "Fit content",
+            TableWidth::Full => "Full width",
+        }
+    }
+
+    /// Returns all available widths
+    pub fn all() -> &'static [TableWidth] {
+        &[TableWidth::Auto, TableWidth::Full]
+    }
+
+    /// Identifier used in style profiles
+    pub fn keyword(&self) -> &'static str {
+        match self {
+            TableWidth::Auto => "auto",
+            TableWidth::Full => "full",
+        }
+    }
+}
+
+/// Which lines are drawn around table cells
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum TableBorders {
+    Grid,    // Every cell boxed, the theme's default
+    Minimal, // Only rules between the rows, heavier under the header
+}
+
+impl TableBorders {
+    /// Returns the display name for the borders
+    pub fn name(&self) -> &'static str {
+        match self {
+            TableBorders::Grid => "Bordered",
+            TableBorders::Minimal => "Minimal",
+        }
+    }
+
+    /// Returns all available border styles
+    pub fn all() -> &'static [TableBorders] {
+        &[TableBorders::Grid, TableBorders::Minimal]
+    }
+
+    /// Identifier used in style profiles
+    pub fn keyword(&self) -> &'static str {
+        match self {
+            TableBorders::Grid => "grid",
+            TableBorders::Minimal => "minimal",
+        }
+    }
+}
+
+impl Default for StyleOptions {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            font_size: 16.0,
+            line_height: 1.5,
+            heading_scale: 1.0,
+            link_color: [9, 105, 218],
+            table_striping: true,
+            table_width: TableWidth::Auto,
+            table_borders: TableBorders::Grid,
+            table_header_shading: false,
+            accents: Accents::default(),
+            margin_mm: DEFAULT_MARGIN_MM,
+        }
+    }
+}
+
+/// wkhtmltopdf's default page margin
+pub const DEFAULT_MARGIN_MM: f32 = 10.0;
+
+/// Heading sizes of the GitHub themes in em, from h1 to h6
+const HEADING_SIZES: [f32; 6] = [2.0, 1.5, 1.25, 1.0, 0.875, 0.85];
+
+impl StyleOptions {
+    /// Returns the CSS for the chosen values, or nothing when disabled. The values are also
+    /// exposed as `--md-*` variables, which additional CSS can refer to.
+    /// `dark` selects the accent color variants for dark themes.
+    pub fn css(&self, dark: bool) -> String {
+        if !self.enabled {
+            return String::new();
+        }
+        let link_color = hex(self.link_color);
+        let mut css = format!(
+            ".markdown-body {{\n  --md-font-size: {font_size}px;\n  --md-line-height: {line_height};\n  --md-link-color: {link_color};\n}}\n\n",
+            font_size = self.font_size,
+            line_height = self.line_height,
+        );
+        css.push_str(&rule(
+            ".markdown-body",
+            &[
+                (
+                    "font-size",
+                    format!("{}px", self.font_size),
+                    "--md-font-size",
+                ),
+                (
+                    "line-height",
+                    self.line_height.to_string(),
+                    "--md-line-height",
+                ),
+            ],
+        ));
+        css.push_str(&rule(
+            ".markdown-body a",
+            &[("color", link_color.clone(), "--md-link-color")],
+        ));
+        for (level, size) in HEADING_SIZES.iter().enumerate() {
+            let scaled = 1.0 + (size - 1.0) * self.heading_scale.max(0.0);
+            css.push_str(&format!(
+                ".markdown-body h{} {{\n  font-size: {:.3}em;\n}}\n\n",
+                level + 1,
+                scaled
+            ));
+        }
+        let stripe = if self.table_striping {
+            "rgba(127, 127, 127, .12)"
+        } else {
+            "transparent"
+        };
+        css.push_str(&format!(
+            ".markdown-body table tr:nth-child(2n) {{\n  background-color: {stripe};\n}}\n"
+        ));
+        css.push_str(&self.table_css());
+        css.push_str(&self.accents.css(dark));
+        css
+    }
+
+    /// Table width, borders and header shading; the theme's defaults add nothing
+    fn table_css(&self) -> String {
+        let mut css = String::new();
+        if self.table_width == TableWidth::Full {
+            css.push_str(
+                "\n.markdown-body table {\n  display: table;\n  width: 100%;\n  max-width: 100%;\n}\n",
+            );
+        }
+        if self.table_borders == TableBorders::Minimal {
+            css.push_str(concat!(
+                "\n.markdown-body table th,\n.markdown-body table td {\n  border: none;\n}\n",
+                "\n.markdown-body table tr {\n  border-top: none;\n",
+                "  border-bottom: 1px solid rgba(127, 127, 127, .35);\n}\n",
+                "\n.markdown-body table thead tr {\n",
+                "  border-bottom: 2px solid rgba(127, 127, 127, .8);\n}\n",
+            ));
+        }
+        if self.table_header_shading {
+            css.push_str(
+                "\n.markdown-body table th {\n  background-color: rgba(127, 127, 127, .2);\n}\n",
+            );
+        }
+        css
+    }
+
+    /// How much larger the text is than the themes' 16px, for layout estimates
+    pub fn text_scale(&self) -> f32 {
+        if self.enabled {
+            self.font_size / 16.0
+        } else {
+            1.0
+        }
+    }
+
+    /// Returns the wkhtmltopdf arguments for the page margins, or none when disabled
+    pub fn wkhtmltopdf_args(&self) -> Vec<String> {
+        if !self.enabled {
+            return Vec::new();
+        }
+        let margin = format!("{}mm", self.margin_mm.max(0.0));
+        [
+            "--margin-top",
+            "--margin-bottom",
+            "--margin-left",
+            "--margin-right",
+        ]
+        .into_iter()
+        .flat_map(|flag| [flag.to_string(), margin.clone()])
+        .collect()
+    }
+}
+
+/// Print layer applied with print media emulation: page margins through `@page` and elements
+/// marked `.no-print` hidden
+pub fn print_css(margin_mm: f32) -> String {
+    format!(
+        "@page {{\n  margin: {}mm;\n}}\n\n@media print {{\n  .no-print {{\n    display: none !important;\n  }}\n}}\n",
+        margin_mm.max(0.0)
+    )
+}
+
+/// A rule overriding the theme with `(property, value, variable)` declarations. Each value is
+/// given literally first, for renderers without CSS variables, then through its variable.
+fn rule(selector: &str, declarations: &[(&str, String, &str)]) -> String {
+    let mut css = format!("{selector} {{\n");
+    for (property, value, variable) in declarations {
+        css.push_str(&format!("  {property}: {value};\n"));
+        css.push_str(&format!("  {property}: var({variable}, {value});\n"));
+    }
+    css.push_str("}\n\n");
+    css
+}
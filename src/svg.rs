@@ -0,0 +1,223 @@
+use crate::checksum;
+use crate::convert::escape_attr;
+use crate::images;
+use pulldown_cmark::{Event, Tag, TagEnd};
+use resvg::{tiny_skia, usvg};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Resolution SVG images are rasterized at unless chosen otherwise
+pub const DEFAULT_DPI: u32 = 300;
+
+/// Pixels on either side of a rasterized image at most, to keep huge drawings from using
+/// up the memory
+const MAX_RASTER_SIDE: f32 = 16384.0;
+
+/// How SVG images are put into the document. wkhtmltopdf draws SVG files loaded through
+/// `<img>` blank or blurry in many versions, while inline SVG and PNG work everywhere.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum SvgMode {
+    Inline,    // SVG markup in the HTML, kept as vectors
+    Rasterize, // PNG rendered with resvg, for backends that mishandle SVG altogether
+    Image,     // Left to the backend as an `<img>`
+}
+
+impl SvgMode {
+    /// Returns the display name for the mode
+    pub fn name(&self) -> &'static str {
+        match self {
+            SvgMode::Inline => "Inline (vector)",
+            SvgMode::Rasterize => "Rasterize to PNG",
+            SvgMode::Image => "Leave as image",
+        }
+    }
+
+    /// Returns all available modes
+    pub fn all() -> &'static [SvgMode] {
+        &[SvgMode::Inline, SvgMode::Rasterize, SvgMode::Image]
+    }
+
+    /// Identifier used in saved settings
+    pub fn keyword(&self) -> &'static str {
+        match self {
+            SvgMode::Inline => "inline",
+            SvgMode::Rasterize => "rasterize",
+            SvgMode::Image => "image",
+        }
+    }
+}
+
+/// Styles inlined SVG images and the boxes that give rasterized ones their size
+pub const SVG_CSS: &str = r#".markdown-body .md-svg svg {
+  max-width: 100%;
+}
+
+.markdown-body .md-svg-raster {
+  display: inline-block;
+  max-width: 100%;
+}
+
+.markdown-body .md-svg-raster img {
+  width: 100%;
+}
+"#;
+
+/// Returns true for sources that name an SVG file
+fn is_svg(src: &str) -> bool {
+    let path = src.split(['?', '#']).next().unwrap_or(src);
+    Path::new(path)
+        .extension()
+        .is_some_and(|extension| extension.eq_ignore_ascii_case("svg"))
+}
+
+/// The markup of the SVG file at `path`, ready to be put into HTML: without the XML
+/// declaration and doctype. Fails for files that refer to other files, whose paths wouldn't
+/// resolve in the HTML.
+fn inline_markup(path: &Path) -> Result<String, String> {
+    let text = fs::read_to_string(path)
+        .map_err(|e| format!("Failed to read SVG '{}': {}", path.display(), e))?;
+    let start = text
+        .find("<svg")
+        .ok_or_else(|| format!("'{}' is not an SVG image", path.display()))?;
+    let markup = &text[start..];
+    let external = ["href=\"", "href='"].iter().any(|attribute| {
+        markup.match_indices(attribute).any(|(index, _)| {
+            let target = &markup[index + attribute.len()..];
+            !(target.starts_with('#') || target.starts_with("data:"))
+        })
+    });
+    if external {
+        return Err(format!(
+            "'{}' refers to other files and can't be inlined",
+            path.display()
+        ));
+    }
+    Ok(markup.trim_end().to_string())
+}
+
+/// Renders the SVG file at `path` to a PNG at `dpi`. Returns the PNG's path and the width
+/// the image is meant to be shown at, in CSS pixels.
+fn rasterize(path: &Path, dpi: u32) -> Result<(PathBuf, f32), String> {
+    let data =
+        fs::read(path).map_err(|e| format!("Failed to read SVG '{}': {}", path.display(), e))?;
+    let mut options = usvg::Options {
+        resources_dir: path.parent().map(Path::to_path_buf),
+        ..usvg::Options::default()
+    };
+    options.fontdb_mut().load_system_fonts();
+    let tree = usvg::Tree::from_data(&data, &options)
+        .map_err(|e| format!("Failed to read SVG '{}': {}", path.display(), e))?;
+    let size = tree.size();
+    // CSS pixels are 96 per inch
+    let scale = (dpi as f32 / 96.0).min(MAX_RASTER_SIDE / size.width().max(size.height()));
+    let width = (size.width() * scale).ceil() as u32;
+    let height = (size.height() * scale).ceil() as u32;
+    let mut pixmap = tiny_skia::Pixmap::new(width, height)
+        .ok_or_else(|| format!("SVG '{}' has no size to render", path.display()))?;
+    resvg::render(
+        &tree,
+        tiny_skia::Transform::from_scale(scale, scale),
+        &mut pixmap.as_mut(),
+    );
+
+    let key = checksum::key(&[
+        path.as_os_str().as_encoded_bytes(),
+        &dpi.to_le_bytes(),
+        &data,
+    ]);
+    let png = std::env::temp_dir().join(format!("md-to-pdf-svg-{key}.png"));
+    pixmap
+        .save_png(&png)
+        .map_err(|e| format!("Failed to write rasterized SVG: {}", e))?;
+    Ok((png, size.width()))
+}
+
+/// Puts the local SVG images of the document in as `mode` says, rasterizing at `dpi`.
+/// Images that can't be inlined or rasterized stay images, with a warning.
+pub fn apply<'a>(
+    events: Vec<Event<'a>>,
+    base_dir: &Path,
+    mode: SvgMode,
+    dpi: u32,
+    warnings: &mut Vec<String>,
+) -> Vec<Event<'a>> {
+    if mode == SvgMode::Image {
+        return events;
+    }
+    let mut out = Vec::with_capacity(events.len());
+    // Markup and title of the image being inlined, and its alt text collected from the
+    // events, which are dropped
+    let mut inlining: Option<(String, String, String)> = None;
+    let mut rasterized = 0usize; // Open boxes around rasterized images, closed at their ends
+    for event in events {
+        match event {
+            Event::Start(Tag::Image {
+                link_type,
+                dest_url,
+                title,
+                id,
+            }) if inlining.is_none() && images::is_local(&dest_url) && is_svg(&dest_url) => {
+                let path = dest_url.split(['?', '#']).next().unwrap_or(&dest_url);
+                let path = base_dir.join(images::percent_decode(path));
+                let outcome = if mode == SvgMode::Inline {
+                    inline_markup(&path).map(|markup| {
+                        inlining = Some((markup, title.to_string(), String::new()));
+                    })
+                } else {
+                    rasterize(&path, dpi).map(|(png, width)| {
+                        out.push(Event::InlineHtml(
+                            format!("<span class=\"md-svg-raster\" style=\"width: {width}px\">")
+                                .into(),
+                        ));
+                        out.push(Event::Start(Tag::Image {
+                            link_type,
+                            dest_url: png.to_string_lossy().to_string().into(),
+                            title: title.clone(),
+                            id: id.clone(),
+                        }));
+                        rasterized += 1;
+                    })
+                };
+                if let Err(message) = outcome {
+                    warnings.push(message);
+                    out.push(Event::Start(Tag::Image {
+                        link_type,
+                        dest_url,
+                        title,
+                        id,
+                    }));
+                }
+            }
+            Event::Text(text) | Event::Code(text) if inlining.is_some() => {
+                if let Some((_, _, alt)) = &mut inlining {
+                    alt.push_str(&text);
+                }
+            }
+            Event::End(TagEnd::Image) if inlining.is_some() => {
+                let Some((markup, title, alt)) = inlining.take() else {
+                    continue;
+                };
+                // The alt text is the drawing's accessible name
+                let mut attributes = String::new();
+                if !alt.trim().is_empty() {
+                    let label = escape_attr(alt.trim());
+                    attributes.push_str(&format!(" role=\"img\" aria-label=\"{label}\""));
+                }
+                if !title.is_empty() {
+                    attributes.push_str(&format!(" title=\"{}\"", escape_attr(&title)));
+                }
+                out.push(Event::InlineHtml(
+                    format!("<span class=\"md-svg\"{attributes}>{markup}</span>").into(),
+                ));
+            }
+            _ if inlining.is_some() => {}
+            Event::End(TagEnd::Image) if rasterized > 0 => {
+                rasterized -= 1;
+                out.push(Event::End(TagEnd::Image));
+                out.push(Event::InlineHtml("</span>".into()));
+            }
+            event => out.push(event),
+        }
+    }
+    out
+}
@@ -0,0 +1,419 @@
+use crate::convert;
+use crate::front_matter::FrontMatter;
+use crate::settings::Settings;
+use pulldown_cmark::{Alignment, Event, HeadingLevel, Tag, TagEnd};
+use std::fs;
+use std::path::Path;
+
+/// Line width of plain text unless chosen otherwise, in characters
+pub const DEFAULT_TEXT_WIDTH: usize = 80;
+
+/// Narrowest the text is wrapped to, however deeply it's indented
+const MIN_WIDTH: usize = 20;
+
+/// A block the lines inside it are indented by: a quote, list item or footnote
+struct Container {
+    first: String, // Prefix of its first line, e.g. the list marker
+    rest: String,  // Prefix of the lines after it
+    item: bool,    // Whether it's a list item
+    started: bool, // Whether its first line has been written
+}
+
+/// A list being written
+struct List {
+    next: Option<u64>, // Number of the next item, None for bullets
+    items: usize,      // Items started so far
+    loose: bool,       // Whether its items are paragraphs, separated by blank lines
+}
+
+/// A table being read, written when it ends
+struct Table {
+    alignments: Vec<Alignment>,
+    rows: Vec<Vec<String>>, // The header first
+}
+
+/// Writes the document as plain text, block by block
+struct Writer {
+    width: usize,
+    out: String,
+    inline: String, // Text of the block being read, with `\n` at hard breaks
+    containers: Vec<Container>,
+    lists: Vec<List>,
+    links: Vec<String>,   // URLs of the open links, innermost last
+    code: Option<String>, // Text of the code block being read
+    table: Option<Table>, // Table being read
+    gap: bool,            // Whether a blank line goes before the next block
+}
+
+/// Splits `text` into lines of at most `width` characters at whitespace. Words longer than
+/// that get a line of their own.
+fn wrap(text: &str, width: usize) -> Vec<String> {
+    let mut lines = Vec::new();
+    let mut line = String::new();
+    for word in text.split_whitespace() {
+        if !line.is_empty() && line.chars().count() + 1 + word.chars().count() > width {
+            lines.push(std::mem::take(&mut line));
+        }
+        if !line.is_empty() {
+            line.push(' ');
+        }
+        line.push_str(word);
+    }
+    if !line.is_empty() {
+        lines.push(line);
+    }
+    lines
+}
+
+/// `text` padded with spaces to `width` characters as `alignment` asks
+fn pad(text: &str, width: usize, alignment: Alignment) -> String {
+    let space = width.saturating_sub(text.chars().count());
+    let (before, after) = match alignment {
+        Alignment::Right => (space, 0),
+        Alignment::Center => (space / 2, space - space / 2),
+        Alignment::Left | Alignment::None => (0, space),
+    };
+    format!("{}{}{}", " ".repeat(before), text, " ".repeat(after))
+}
+
+impl Writer {
+    fn new(width: usize) -> Self {
+        Writer {
+            width,
+            out: String::new(),
+            inline: String::new(),
+            containers: Vec::new(),
+            lists: Vec::new(),
+            links: Vec::new(),
+            code: None,
+            table: None,
+            gap: false,
+        }
+    }
+
+    /// Prefixes of the next block's first line and of the lines after it
+    fn prefixes(&mut self) -> (String, String) {
+        let mut first = String::new();
+        let mut rest = String::new();
+        for container in &mut self.containers {
+            first.push_str(if container.started {
+                &container.rest
+            } else {
+                &container.first
+            });
+            rest.push_str(&container.rest);
+            container.started = true;
+        }
+        (first, rest)
+    }
+
+    /// Width left for the text next to the prefixes
+    fn available(&self) -> usize {
+        let indent: usize = self
+            .containers
+            .iter()
+            .map(|container| container.rest.chars().count())
+            .sum();
+        self.width.saturating_sub(indent).max(MIN_WIDTH)
+    }
+
+    /// Writes `lines` as the next block
+    fn block(&mut self, lines: Vec<String>) {
+        if lines.is_empty() {
+            return;
+        }
+        if self.gap && !self.out.is_empty() {
+            // Inside the quotes around the block, not those it starts
+            let blank: String = self
+                .containers
+                .iter()
+                .filter(|c| c.started)
+                .map(|c| c.rest.as_str())
+                .collect();
+            self.out.push_str(blank.trim_end());
+            self.out.push('\n');
+        }
+        let (first, rest) = self.prefixes();
+        for (i, line) in lines.iter().enumerate() {
+            let prefix = if i == 0 { &first } else { &rest };
+            self.out.push_str(format!("{}{}", prefix, line).trim_end());
+            self.out.push('\n');
+        }
+        self.gap = true;
+    }
+
+    /// Writes the text read so far as a wrapped paragraph
+    fn paragraph(&mut self) {
+        let text = std::mem::take(&mut self.inline);
+        let width = self.available();
+        let lines = text
+            .split('\n')
+            .flat_map(|segment| wrap(segment, width))
+            .collect();
+        self.block(lines);
+    }
+
+    /// The text read so far on one line
+    fn take_line(&mut self) -> String {
+        let text = std::mem::take(&mut self.inline);
+        text.split_whitespace().collect::<Vec<_>>().join(" ")
+    }
+
+    fn start(&mut self, tag: Tag) {
+        match tag {
+            Tag::Paragraph => {
+                if self.containers.last().is_some_and(|c| c.item)
+                    && let Some(list) = self.lists.last_mut()
+                {
+                    list.loose = true;
+                }
+            }
+            Tag::Heading { .. } => self.paragraph(),
+            Tag::BlockQuote => {
+                self.paragraph();
+                self.containers.push(Container {
+                    first: "> ".to_string(),
+                    rest: "> ".to_string(),
+                    item: false,
+                    started: false,
+                });
+            }
+            Tag::CodeBlock(_) => {
+                self.paragraph();
+                self.code = Some(String::new());
+            }
+            Tag::List(start) => {
+                // Text of a tight item goes right above its nested list
+                self.paragraph();
+                if self.containers.last().is_some_and(|c| c.item)
+                    && self.lists.last().is_some_and(|list| !list.loose)
+                {
+                    self.gap = false;
+                }
+                self.lists.push(List {
+                    next: start,
+                    items: 0,
+                    loose: false,
+                });
+            }
+            Tag::Item => {
+                let Some(list) = self.lists.last_mut() else {
+                    return;
+                };
+                let marker = match &mut list.next {
+                    Some(number) => {
+                        *number += 1;
+                        format!("{}. ", *number - 1)
+                    }
+                    None => "- ".to_string(),
+                };
+                if list.items > 0 {
+                    self.gap = list.loose;
+                }
+                list.items += 1;
+                self.containers.push(Container {
+                    rest: " ".repeat(marker.chars().count()),
+                    first: marker,
+                    item: true,
+                    started: false,
+                });
+            }
+            Tag::FootnoteDefinition(label) => {
+                self.paragraph();
+                let marker = format!("[{}] ", label);
+                self.containers.push(Container {
+                    rest: " ".repeat(marker.chars().count()),
+                    first: marker,
+                    item: false,
+                    started: false,
+                });
+            }
+            Tag::Table(alignments) => {
+                self.paragraph();
+                self.table = Some(Table {
+                    alignments,
+                    rows: Vec::new(),
+                });
+            }
+            Tag::TableHead | Tag::TableRow => {
+                if let Some(table) = &mut self.table {
+                    table.rows.push(Vec::new());
+                }
+            }
+            Tag::Link { dest_url, .. } => self.links.push(dest_url.to_string()),
+            Tag::Image { .. } => self.inline.push('['),
+            _ => {}
+        }
+    }
+
+    fn end(&mut self, tag: TagEnd) {
+        match tag {
+            TagEnd::Paragraph => self.paragraph(),
+            TagEnd::Heading(level) => {
+                let text = self.take_line();
+                let underline = match level {
+                    HeadingLevel::H1 => Some('='),
+                    HeadingLevel::H2 => Some('-'),
+                    _ => None,
+                };
+                let mut lines = vec![text.clone()];
+                lines.extend(underline.map(|c| c.to_string().repeat(text.chars().count())));
+                self.block(lines);
+            }
+            TagEnd::BlockQuote => {
+                self.paragraph();
+                self.containers.pop();
+                self.gap = true;
+            }
+            TagEnd::CodeBlock => {
+                let code = self.code.take().unwrap_or_default();
+                let lines = code
+                    .trim_end_matches('\n')
+                    .lines()
+                    .map(|line| format!("    {}", line))
+                    .collect();
+                self.block(lines);
+            }
+            TagEnd::List(_) => {
+                self.lists.pop();
+                self.gap = true;
+            }
+            TagEnd::Item => {
+                self.paragraph();
+                self.containers.pop();
+            }
+            TagEnd::FootnoteDefinition => {
+                self.paragraph();
+                self.containers.pop();
+                self.gap = true;
+            }
+            TagEnd::TableCell => {
+                let cell = self.take_line();
+                if let Some(row) = self.table.as_mut().and_then(|table| table.rows.last_mut()) {
+                    row.push(cell);
+                }
+            }
+            TagEnd::Table => {
+                if let Some(table) = self.table.take() {
+                    self.write_table(table);
+                }
+            }
+            TagEnd::Link => {
+                let url = self.links.pop().unwrap_or_default();
+                // Autolinks show their URL already, and anchors lead nowhere in plain text
+                if !url.is_empty() && !url.starts_with('#') && !self.inline.ends_with(&url) {
+                    self.inline.push_str(&format!(" <{}>", url));
+                }
+            }
+            TagEnd::Image => self.inline.push(']'),
+            _ => {}
+        }
+    }
+
+    /// Writes `table` with its columns aligned and a line under the header
+    fn write_table(&mut self, table: Table) {
+        let columns = table.rows.iter().map(Vec::len).max().unwrap_or(0);
+        let widths: Vec<usize> = (0..columns)
+            .map(|column| {
+                table
+                    .rows
+                    .iter()
+                    .filter_map(|row| row.get(column))
+                    .map(|cell| cell.chars().count())
+                    .max()
+                    .unwrap_or(0)
+            })
+            .collect();
+        let row_line = |row: &[String]| {
+            widths
+                .iter()
+                .enumerate()
+                .map(|(column, width)| {
+                    let alignment = table
+                        .alignments
+                        .get(column)
+                        .copied()
+                        .unwrap_or(Alignment::None);
+                    pad(
+                        row.get(column).map_or("", String::as_str),
+                        *width,
+                        alignment,
+                    )
+                })
+                .collect::<Vec<_>>()
+                .join("  ")
+        };
+        let mut lines = Vec::new();
+        for (i, row) in table.rows.iter().enumerate() {
+            lines.push(row_line(row));
+            if i == 0 {
+                lines.push(
+                    widths
+                        .iter()
+                        .map(|width| "-".repeat(*width))
+                        .collect::<Vec<_>>()
+                        .join("  "),
+                );
+            }
+        }
+        self.block(lines);
+    }
+
+    fn event(&mut self, event: Event) {
+        match event {
+            Event::Start(tag) => self.start(tag),
+            Event::End(tag) => self.end(tag),
+            Event::Text(text) => match &mut self.code {
+                Some(code) => code.push_str(&text),
+                None => self.inline.push_str(&text),
+            },
+            Event::Code(code) => self.inline.push_str(&code),
+            Event::FootnoteReference(label) => self.inline.push_str(&format!("[{}]", label)),
+            Event::SoftBreak => self.inline.push(' '),
+            Event::HardBreak => self.inline.push('\n'),
+            Event::Rule => {
+                self.paragraph();
+                let width = self.available();
+                self.block(vec!["-".repeat(width)]);
+            }
+            Event::TaskListMarker(checked) => {
+                self.inline.push_str(if checked { "[x] " } else { "[ ] " })
+            }
+            // HTML has no plain-text form
+            _ => {}
+        }
+    }
+}
+
+/// Renders `md_body` (without front matter) as plain text wrapped at `width` characters:
+/// headings underlined, lists and quotes indented, link URLs after their text and tables
+/// lined up in columns
+pub fn render(md_body: &str, width: usize) -> String {
+    let mut writer = Writer::new(width);
+    for event in pulldown_cmark::Parser::new_ext(md_body, convert::markdown_options()) {
+        writer.event(event);
+    }
+    writer.paragraph();
+    let mut out = writer.out.trim_end().to_string();
+    out.push('\n');
+    out
+}
+
+/// Converts the Markdown file at `md_path` to plain text at `text_path`, wrapped at the
+/// text width of the settings
+pub fn export(
+    settings: &Settings,
+    md_path: &Path,
+    text_path: &Path,
+) -> Result<Vec<String>, String> {
+    let md_text =
+        fs::read_to_string(md_path).map_err(|e| format!("Failed to read Markdown file: {}", e))?;
+    let (_, md_body) = FrontMatter::extract(&md_text);
+    if let Some(parent) = text_path.parent() {
+        fs::create_dir_all(parent)
+            .map_err(|e| format!("Failed to create output directory: {}", e))?;
+    }
+    fs::write(text_path, render(md_body, settings.text_width))
+        .map_err(|e| format!("Failed to write text file: {}", e))?;
+    Ok(Vec::new())
+}
@@ -0,0 +1,388 @@
+use crate::config;
+use crate::highlight::Palette;
+use std::borrow::Cow;
+use std::collections::BTreeMap;
+use std::fs;
+
+/// Configuration file remembering the custom stylesheet
+const STYLESHEET_CONFIG: &str = "stylesheet.conf";
+
+/// Enum to represent the different CSS themes
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Theme {
+    GitHubLight,
+    GitHubDark,
+    GitHubAuto, // This would typically involve media queries for light/dark preference
+    PrintSerif,
+    Academic, // Modeled on the LaTeX article class
+    SolarizedLight,
+    SolarizedDark,
+    Handout, // Compact, to fit more on a page
+}
+
+// Embed the CSS files directly into the binary using include_str!
+// Ensure these paths are correct relative to your Cargo.toml or src/theme.rs
+const GITHUB_LIGHT_CSS: &str = include_str!("../CSS/github-markdown-light.css");
+const GITHUB_DARK_CSS: &str = include_str!("../CSS/github-markdown-dark.css");
+const GITHUB_AUTO_CSS: &str = include_str!("../CSS/github-markdown-auto.css");
+const PRINT_SERIF_CSS: &str = include_str!("../CSS/print-serif.css");
+const ACADEMIC_CSS: &str = include_str!("../CSS/academic.css");
+const SOLARIZED_LIGHT_CSS: &str = include_str!("../CSS/solarized-light.css");
+const SOLARIZED_DARK_CSS: &str = include_str!("../CSS/solarized-dark.css");
+const HANDOUT_CSS: &str = include_str!("../CSS/handout.css");
+
+impl Theme {
+    /// Returns the display name for the theme
+    pub fn name(&self) -> &'static str {
+        match self {
+            Theme::GitHubLight => "GitHub Light",
+            Theme::GitHubDark => "GitHub Dark",
+            Theme::GitHubAuto => "GitHub Auto",
+            Theme::PrintSerif => "Print Serif",
+            Theme::Academic => "Academic",
+            Theme::SolarizedLight => "Solarized Light",
+            Theme::SolarizedDark => "Solarized Dark",
+            Theme::Handout => "Handout",
+        }
+    }
+
+    /// Returns all available themes
+    pub fn all() -> &'static [Theme] {
+        &[
+            Theme::GitHubLight,
+            Theme::GitHubDark,
+            Theme::GitHubAuto,
+            Theme::PrintSerif,
+            Theme::Academic,
+            Theme::SolarizedLight,
+            Theme::SolarizedDark,
+            Theme::Handout,
+        ]
+    }
+
+    /// Returns the id used to pick the theme in front matter, e.g. `github-dark`
+    pub fn id(&self) -> &'static str {
+        match self {
+            Theme::GitHubLight => "github-light",
+            Theme::GitHubDark => "github-dark",
+            Theme::GitHubAuto => "github-auto",
+            Theme::PrintSerif => "print-serif",
+            Theme::Academic => "academic",
+            Theme::SolarizedLight => "solarized-light",
+            Theme::SolarizedDark => "solarized-dark",
+            Theme::Handout => "handout",
+        }
+    }
+
+    /// Finds the built-in theme with the given id (case-insensitive)
+    pub fn from_id(id: &str) -> Option<Theme> {
+        Theme::all()
+            .iter()
+            .find(|theme| theme.id().eq_ignore_ascii_case(id.trim()))
+            .copied()
+    }
+
+    /// Returns the theme's stylesheet
+    pub fn css(&self) -> &'static str {
+        match self {
+            Theme::GitHubLight => GITHUB_LIGHT_CSS,
+            Theme::GitHubDark => GITHUB_DARK_CSS,
+            Theme::GitHubAuto => GITHUB_AUTO_CSS,
+            Theme::PrintSerif => PRINT_SERIF_CSS,
+            Theme::Academic => ACADEMIC_CSS,
+            Theme::SolarizedLight => SOLARIZED_LIGHT_CSS,
+            Theme::SolarizedDark => SOLARIZED_DARK_CSS,
+            Theme::Handout => HANDOUT_CSS,
+        }
+    }
+
+    /// Returns the blockquote border color used by the theme's stylesheet
+    pub fn border_color(&self) -> &'static str {
+        match self {
+            Theme::GitHubLight => "#d1d9e0",
+            Theme::GitHubDark => "#3d444d",
+            Theme::GitHubAuto => "var(--borderColor-default)",
+            Theme::PrintSerif => "#999",
+            Theme::Academic => "#000",
+            Theme::SolarizedLight => "#dcd5bf",
+            Theme::SolarizedDark => "#0f4b59",
+            Theme::Handout => "#333",
+        }
+    }
+
+    /// Page color of dark themes, which also has to fill the margins and the space below the
+    /// content. Light themes print on the paper's white and give None.
+    pub fn page_color(&self, scheme: ColorScheme) -> Option<[u8; 3]> {
+        match self {
+            Theme::GitHubDark => Some([13, 17, 23]),
+            Theme::GitHubAuto if scheme == ColorScheme::Dark => Some([13, 17, 23]),
+            Theme::SolarizedDark => Some([0, 43, 54]),
+            _ => None,
+        }
+    }
+
+    /// Highlight palette paired with the theme, used unless another one is chosen
+    pub fn highlight_palette(&self, scheme: ColorScheme) -> Palette {
+        match self {
+            Theme::GitHubLight | Theme::PrintSerif | Theme::Academic | Theme::Handout => {
+                Palette::GitHubLight
+            }
+            Theme::GitHubDark => Palette::GitHubDark,
+            Theme::GitHubAuto => match scheme {
+                ColorScheme::Light => Palette::GitHubLight,
+                ColorScheme::Dark => Palette::GitHubDark,
+            },
+            Theme::SolarizedLight => Palette::SolarizedLight,
+            Theme::SolarizedDark => Palette::SolarizedDark,
+        }
+    }
+}
+
+/// Color scheme `prefers-color-scheme` media queries are resolved to, since wkhtmltopdf
+/// doesn't reliably pick one
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum ColorScheme {
+    Light,
+    Dark,
+}
+
+impl ColorScheme {
+    /// Returns the display name for the color scheme
+    pub fn name(&self) -> &'static str {
+        match self {
+            ColorScheme::Light => "Light",
+            ColorScheme::Dark => "Dark",
+        }
+    }
+
+    /// Returns all available color schemes
+    pub fn all() -> &'static [ColorScheme] {
+        &[ColorScheme::Light, ColorScheme::Dark]
+    }
+
+    /// Value of the `prefers-color-scheme` media feature
+    pub fn keyword(&self) -> &'static str {
+        match self {
+            ColorScheme::Light => "light",
+            ColorScheme::Dark => "dark",
+        }
+    }
+}
+
+/// Resolves the `@media (prefers-color-scheme: ...)` blocks of `css` for `scheme`: the
+/// matching blocks' rules are applied unconditionally and the others are dropped
+pub fn resolve_color_scheme(css: &str, scheme: ColorScheme) -> String {
+    const QUERY: &str = "@media (prefers-color-scheme:";
+    let mut out = String::with_capacity(css.len());
+    let mut rest = css;
+    while let Some(start) = rest.find(QUERY) {
+        let after_query = &rest[start + QUERY.len()..];
+        let (Some(close), Some(open)) = (after_query.find(')'), after_query.find('{')) else {
+            break;
+        };
+        if open < close || !after_query[close + 1..open].trim().is_empty() {
+            // Combined with other conditions, leave it to the renderer
+            out.push_str(&rest[..start + QUERY.len()]);
+            rest = after_query;
+            continue;
+        }
+        let Some(end) = matching_brace(&after_query[open..]) else {
+            break;
+        };
+        out.push_str(&rest[..start]);
+        if after_query[..close].trim() == scheme.keyword() {
+            out.push_str(&after_query[open + 1..open + end]);
+        }
+        rest = &after_query[open + end + 1..];
+    }
+    out.push_str(rest);
+    out
+}
+
+/// Offset of the `}` closing the block that `css` starts with
+fn matching_brace(css: &str) -> Option<usize> {
+    let mut depth = 0usize;
+    for (i, c) in css.char_indices() {
+        match c {
+            '{' => depth += 1,
+            '}' => {
+                depth = depth.checked_sub(1)?;
+                if depth == 0 {
+                    return Some(i);
+                }
+            }
+            _ => {}
+        }
+    }
+    None
+}
+
+/// Where the document stylesheet comes from
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum StyleSource {
+    BuiltIn, // The selected built-in or user theme
+    File,    // A local .css file
+    Pasted,  // CSS typed or pasted into the app
+}
+
+impl StyleSource {
+    /// Returns the display name for the source
+    pub fn name(&self) -> &'static str {
+        match self {
+            StyleSource::BuiltIn => "Theme",
+            StyleSource::File => "CSS file",
+            StyleSource::Pasted => "Pasted CSS",
+        }
+    }
+
+    /// Returns all available sources
+    pub fn all() -> &'static [StyleSource] {
+        &[StyleSource::BuiltIn, StyleSource::File, StyleSource::Pasted]
+    }
+
+    fn keyword(&self) -> &'static str {
+        match self {
+            StyleSource::BuiltIn => "builtin",
+            StyleSource::File => "file",
+            StyleSource::Pasted => "pasted",
+        }
+    }
+}
+
+/// A user stylesheet replacing the built-in theme. The file path and pasted CSS are both
+/// kept so switching between them doesn't lose either.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CustomStylesheet {
+    pub source: StyleSource,
+    pub file: String,
+    pub pasted: String,
+}
+
+impl Default for CustomStylesheet {
+    fn default() -> Self {
+        Self {
+            source: StyleSource::BuiltIn,
+            file: String::new(),
+            pasted: String::new(),
+        }
+    }
+}
+
+impl CustomStylesheet {
+    /// Returns the document stylesheet: `theme`'s, or the validated custom CSS
+    pub fn css(&self, theme: Theme) -> Result<Cow<'static, str>, String> {
+        let css = match self.source {
+            StyleSource::BuiltIn => return Ok(Cow::Borrowed(theme.css())),
+            StyleSource::File => {
+                let path = self.file.trim();
+                if path.is_empty() {
+                    return Err("No CSS file selected".to_string());
+                }
+                fs::read_to_string(path)
+                    .map_err(|e| format!("Failed to read CSS file '{}': {}", path, e))?
+            }
+            StyleSource::Pasted => self.pasted.clone(),
+        };
+        validate_css(&css)?;
+        Ok(Cow::Owned(css))
+    }
+
+    /// Loads the stylesheet chosen last time, or the default if none was saved
+    pub fn load_saved() -> CustomStylesheet {
+        CustomStylesheet::from_entries(&config::load(STYLESHEET_CONFIG))
+    }
+
+    /// Remembers the stylesheet for the next start
+    pub fn save(&self) -> Result<(), String> {
+        config::save(STYLESHEET_CONFIG, &self.entries())
+    }
+
+    /// Reads the stylesheet from configuration entries, see [`CustomStylesheet::entries`]
+    pub fn from_entries(entries: &BTreeMap<String, String>) -> CustomStylesheet {
+        let source = entries
+            .get("source")
+            .and_then(|keyword| {
+                StyleSource::all()
+                    .iter()
+                    .find(|source| source.keyword() == keyword)
+            })
+            .copied()
+            .unwrap_or(StyleSource::BuiltIn);
+        CustomStylesheet {
+            source,
+            file: entries.get("file").cloned().unwrap_or_default(),
+            pasted: entries.get("pasted").cloned().unwrap_or_default(),
+        }
+    }
+
+    /// The stylesheet as configuration entries
+    pub fn entries(&self) -> BTreeMap<String, String> {
+        BTreeMap::from([
+            ("source".to_string(), self.source.keyword().to_string()),
+            ("file".to_string(), self.file.clone()),
+            ("pasted".to_string(), self.pasted.clone()),
+        ])
+    }
+}
+
+/// Checks that `css` is non-empty and its braces, comments and strings are balanced,
+/// which catches truncated files and files that aren't CSS at all
+pub fn validate_css(css: &str) -> Result<(), String> {
+    if css.trim().is_empty() {
+        return Err("The stylesheet is empty".to_string());
+    }
+    let mut depth = 0usize;
+    let mut line = 1;
+    let mut chars = css.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '\n' => line += 1,
+            '\\' => {
+                chars.next();
+            }
+            '/' if chars.peek() == Some(&'*') => {
+                chars.next();
+                let mut closed = false;
+                while let Some(c) = chars.next() {
+                    if c == '\n' {
+                        line += 1;
+                    } else if c == '*' && chars.peek() == Some(&'/') {
+                        chars.next();
+                        closed = true;
+                        break;
+                    }
+                }
+                if !closed {
+                    return Err(format!("Unclosed comment in the stylesheet (line {line})"));
+                }
+            }
+            '"' | '\'' => {
+                let quote = c;
+                loop {
+                    match chars.next() {
+                        Some('\\') => {
+                            chars.next();
+                        }
+                        Some(c) if c == quote => break,
+                        Some('\n') | None => {
+                            return Err(format!("Unclosed string in the stylesheet (line {line})"));
+                        }
+                        Some(_) => {}
+                    }
+                }
+            }
+            '{' => depth += 1,
+            '}' => {
+                if depth == 0 {
+                    return Err(format!("Unexpected '}}' in the stylesheet (line {line})"));
+                }
+                depth -= 1;
+            }
+            _ => {}
+        }
+    }
+    if depth > 0 {
+        return Err("Unclosed '{' in the stylesheet".to_string());
+    }
+    Ok(())
+}
@@ -0,0 +1,153 @@
+use crate::config;
+use crate::theme;
+use std::fs;
+use std::path::{Path, PathBuf};
+use toml::Table;
+
+/// Optional manifest next to a theme's stylesheet, giving its name and description
+const MANIFEST: &str = "theme.toml";
+
+/// A theme installed in the user's themes directory, either as a single `.css` file or as a
+/// directory holding a `.css` file and optionally a `theme.toml` manifest
+#[derive(Debug, Clone, PartialEq)]
+pub struct UserTheme {
+    pub id: String, // File stem or directory name, unique within the themes directory
+    pub name: String,
+    pub description: String,
+    css_path: PathBuf,
+    root: PathBuf, // What gets deleted when the theme is removed
+}
+
+impl UserTheme {
+    /// Path of the theme's stylesheet
+    pub fn css_path(&self) -> &Path {
+        &self.css_path
+    }
+
+    /// Reads and validates the theme's stylesheet
+    pub fn css(&self) -> Result<String, String> {
+        let css = fs::read_to_string(&self.css_path).map_err(|e| {
+            format!(
+                "Failed to read theme '{}' ({}): {}",
+                self.name,
+                self.css_path.display(),
+                e
+            )
+        })?;
+        theme::validate_css(&css).map_err(|e| format!("Theme '{}': {}", self.name, e))?;
+        Ok(css)
+    }
+}
+
+/// Directory user themes are installed to, e.g. `~/.config/md-to-pdf/themes`
+pub fn dir() -> Option<PathBuf> {
+    config::dir().map(|dir| dir.join("themes"))
+}
+
+/// Lists the installed themes, sorted by name
+pub fn scan() -> Vec<UserTheme> {
+    let Some(entries) = dir().and_then(|dir| fs::read_dir(dir).ok()) else {
+        return Vec::new();
+    };
+    let mut themes: Vec<UserTheme> = entries
+        .filter_map(Result::ok)
+        .filter_map(|entry| read_theme(&entry.path()))
+        .collect();
+    themes.sort_by_key(|theme| theme.name.to_lowercase());
+    themes
+}
+
+/// Returns the installed theme with the given id
+pub fn find(id: &str) -> Option<UserTheme> {
+    scan().into_iter().find(|theme| theme.id == id)
+}
+
+fn read_theme(path: &Path) -> Option<UserTheme> {
+    let id = path.file_stem()?.to_string_lossy().to_string();
+    if path.is_file() {
+        return is_css(path).then(|| UserTheme {
+            name: id.clone(),
+            id,
+            description: String::new(),
+            css_path: path.to_path_buf(),
+            root: path.to_path_buf(),
+        });
+    }
+
+    let mut stylesheets: Vec<PathBuf> = fs::read_dir(path)
+        .ok()?
+        .filter_map(Result::ok)
+        .map(|entry| entry.path())
+        .filter(|path| is_css(path))
+        .collect();
+    stylesheets.sort();
+    let css_path = stylesheets.into_iter().next()?;
+    // A manifest that can't be read leaves the name and description to their defaults
+    let manifest: Table = fs::read_to_string(path.join(MANIFEST))
+        .ok()
+        .and_then(|text| text.parse().ok())
+        .unwrap_or_default();
+    Some(UserTheme {
+        name: manifest_value(&manifest, "name").unwrap_or_else(|| id.clone()),
+        description: manifest_value(&manifest, "description").unwrap_or_default(),
+        id,
+        css_path,
+        root: path.to_path_buf(),
+    })
+}
+
+fn is_css(path: &Path) -> bool {
+    path.is_file()
+        && path
+            .extension()
+            .is_some_and(|extension| extension.eq_ignore_ascii_case("css"))
+}
+
+/// Reads a top-level string from a TOML manifest
+fn manifest_value(manifest: &Table, key: &str) -> Option<String> {
+    manifest
+        .get(key)?
+        .as_str()
+        .filter(|value| !value.is_empty())
+        .map(str::to_string)
+}
+
+/// Installs the stylesheet at `css_path` as a user theme. A `theme.toml` next to it is
+/// installed along with it.
+pub fn install(css_path: &Path) -> Result<UserTheme, String> {
+    let css = fs::read_to_string(css_path)
+        .map_err(|e| format!("Failed to read '{}': {}", css_path.display(), e))?;
+    theme::validate_css(&css)?;
+    let dir = dir().ok_or("Failed to find the configuration directory")?;
+    let id = css_path
+        .file_stem()
+        .map(|stem| stem.to_string_lossy().to_string())
+        .ok_or("The stylesheet has no file name")?;
+    if read_theme(&dir.join(&id))
+        .or_else(|| read_theme(&dir.join(format!("{id}.css"))))
+        .is_some()
+    {
+        return Err(format!("A theme named '{}' is already installed", id));
+    }
+
+    let manifest = css_path.with_file_name(MANIFEST);
+    let target = dir.join(&id);
+    fs::create_dir_all(&target).map_err(|e| format!("Failed to create theme directory: {}", e))?;
+    fs::write(target.join(format!("{id}.css")), css)
+        .map_err(|e| format!("Failed to install theme: {}", e))?;
+    if manifest.is_file() {
+        fs::copy(&manifest, target.join(MANIFEST))
+            .map_err(|e| format!("Failed to install theme manifest: {}", e))?;
+    }
+    read_theme(&target).ok_or_else(|| format!("Failed to install theme '{}'", id))
+}
+
+/// Deletes an installed theme
+pub fn remove(theme: &UserTheme) -> Result<(), String> {
+    let result = if theme.root.is_dir() {
+        fs::remove_dir_all(&theme.root)
+    } else {
+        fs::remove_file(&theme.root)
+    };
+    result.map_err(|e| format!("Failed to remove theme '{}': {}", theme.name, e))
+}
@@ -0,0 +1,96 @@
+use crate::checksum;
+use crate::config;
+use crate::convert;
+use crate::paths::TempDir;
+use crate::settings::Settings;
+use image::RgbaImage;
+use image::imageops::FilterType;
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::Sender;
+use std::thread;
+
+/// Sample document rendered for each theme: the parts themes differ most in
+const SAMPLE: &str = r#"# Heading
+
+Body text with a [link](https://example.com), **bold** and `inline code`.
+
+## Subheading
+
+> A quotation.
+
+```rust
+fn main() {
+    println!("Hello"); // Comment
+}
+```
+
+| Name | Value |
+|------|-------|
+| One  | 1     |
+| Two  | 2     |
+"#;
+
+/// Viewport the sample is rendered in, in pixels; the rendering is cropped to this height
+const RENDER_SIZE: (u32, u32) = (480, 360);
+/// Width of the cached thumbnails
+pub const WIDTH: u32 = 120;
+
+/// A finished thumbnail, or why it couldn't be rendered, along with the key it was queued by
+pub type Rendered = (String, Result<RgbaImage, String>);
+
+/// Directory thumbnails are cached in, e.g. `~/.cache/md-to-pdf/thumbnails`
+fn cache_dir() -> Option<PathBuf> {
+    config::cache_dir().map(|dir| dir.join("thumbnails"))
+}
+
+/// Returns a thumbnail of the sample document rendered with `settings`' stylesheet.
+/// Thumbnails are cached by the stylesheet's content, so edited themes get a new one.
+pub fn render(settings: &Settings) -> Result<RgbaImage, String> {
+    let css = settings.resolved_stylesheet_css()?;
+    let dir = cache_dir().ok_or("Failed to find the cache directory")?;
+    let name = checksum::key(&[css.as_bytes()]);
+    let cached = dir.join(format!("{name}.png"));
+    if let Ok(image) = image::open(&cached) {
+        return Ok(image.into_rgba8());
+    }
+
+    let local_roots = settings.local_roots(Path::new("."));
+    let (html, _) = convert::build_html(
+        settings,
+        SAMPLE,
+        Path::new("."),
+        &local_roots,
+        &HashMap::new(),
+        &mut Vec::new(),
+    );
+    fs::create_dir_all(&dir).map_err(|e| format!("Failed to create thumbnail directory: {}", e))?;
+    let temp_dir = TempDir::new()?;
+    let image = convert::run_wkhtmltoimage(
+        settings,
+        &html,
+        &local_roots,
+        &temp_dir.path().join("thumbnail"),
+        RENDER_SIZE.0,
+        Some(RENDER_SIZE.1),
+    )?;
+    let height = WIDTH * image.height() / image.width().max(1);
+    let thumbnail = image::imageops::resize(&image, WIDTH, height, FilterType::Triangle);
+    thumbnail
+        .save(&cached)
+        .map_err(|e| format!("Failed to save thumbnail: {}", e))?;
+    Ok(thumbnail)
+}
+
+/// Renders the thumbnails of `themes`, given as (key, settings using the theme), on a
+/// background thread. Each result is sent along with its key as soon as it's ready.
+pub fn render_in_background(themes: Vec<(String, Settings)>, sender: Sender<Rendered>) {
+    thread::spawn(move || {
+        for (key, settings) in themes {
+            if sender.send((key, render(&settings))).is_err() {
+                break; // The window was closed
+            }
+        }
+    });
+}
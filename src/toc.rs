@@ -0,0 +1,187 @@
+use crate::convert::{self, escape_attr};
+use crate::directives;
+use crate::headings::Heading;
+use crate::layout;
+use crate::numbering;
+use crate::settings::Settings;
+use lopdf::Document;
+use pulldown_cmark::{Event, Tag, TagEnd};
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Deepest heading level listed by a plain `<!-- toc -->`
+const DEFAULT_DEPTH: u8 = 3;
+
+/// Layout of the generated table of contents, with page numbers flush right
+pub const TOC_CSS: &str = r#".markdown-body .md-toc ul {
+  list-style: none;
+  padding-left: 0;
+}
+
+.markdown-body .md-toc li {
+  overflow: hidden;
+  margin: .15em 0;
+}
+
+.markdown-body .md-toc .md-toc-page {
+  float: right;
+  padding-left: 1em;
+}
+
+.markdown-body .md-toc .md-toc-level-2 { padding-left: 1.5em; }
+.markdown-body .md-toc .md-toc-level-3 { padding-left: 3em; }
+.markdown-body .md-toc .md-toc-level-4 { padding-left: 4.5em; }
+.markdown-body .md-toc .md-toc-level-5 { padding-left: 6em; }
+.markdown-body .md-toc .md-toc-level-6 { padding-left: 7.5em; }
+
+html[dir="rtl"] .markdown-body .md-toc .md-toc-page {
+  float: left;
+  padding-left: 0;
+  padding-right: 1em;
+}
+"#;
+
+/// Returns the depth of a table of contents requested by `<!-- toc -->` or `<!-- toc: 2 -->`
+fn requested_depth(html: &str) -> Option<u8> {
+    if let Some((name, value)) = directives::parse(html) {
+        return (name == "toc").then(|| value.parse().unwrap_or(DEFAULT_DEPTH).clamp(1, 6));
+    }
+    let inner = html.trim().strip_prefix("<!--")?.strip_suffix("-->")?;
+    inner
+        .trim()
+        .eq_ignore_ascii_case("toc")
+        .then_some(DEFAULT_DEPTH)
+}
+
+/// Replaces top-level `<!-- toc -->` directives with a list of the headings that follow them,
+/// linking to their anchors. `pages` maps anchors to page labels, which are shown when known.
+pub fn insert<'a>(
+    events: Vec<Event<'a>>,
+    headings: &[Heading],
+    pages: &HashMap<String, String>,
+) -> Vec<Event<'a>> {
+    let mut out = Vec::with_capacity(events.len());
+    let mut depth = 0usize;
+    let mut headings_seen = 0;
+    for event in events {
+        match &event {
+            Event::Start(Tag::HtmlBlock) | Event::End(TagEnd::HtmlBlock) => {}
+            Event::Start(tag) => {
+                if let Tag::Heading { .. } = tag {
+                    headings_seen += 1;
+                }
+                depth += 1;
+            }
+            Event::End(_) => depth = depth.saturating_sub(1),
+            Event::Html(html) if depth == 0 => {
+                if let Some(max_level) = requested_depth(html) {
+                    let following = headings.get(headings_seen..).unwrap_or_default();
+                    out.push(Event::Html(toc_html(following, max_level, pages).into()));
+                    continue;
+                }
+            }
+            _ => {}
+        }
+        out.push(event);
+    }
+    out
+}
+
+fn toc_html(headings: &[Heading], max_level: u8, pages: &HashMap<String, String>) -> String {
+    let mut html = String::from("<nav class=\"md-toc\">\n<ul>\n");
+    for heading in headings.iter().filter(|heading| heading.level <= max_level) {
+        // The page number comes first so it can float to the end of the line
+        let page = match pages.get(&heading.id) {
+            Some(page) => format!("<span class=\"md-toc-page\">{}</span>", escape_attr(page)),
+            None => String::new(),
+        };
+        html.push_str(&format!(
+            "<li class=\"md-toc-level-{}\">{}<a href=\"#{}\">{}</a></li>\n",
+            heading.level,
+            page,
+            escape_attr(&heading.id),
+            escape_attr(&heading.text)
+        ));
+    }
+    html.push_str("</ul>\n</nav>\n");
+    html
+}
+
+/// Returns true if the rendered document contains a table of contents
+pub fn is_present(full_html: &str) -> bool {
+    full_html.contains("<nav class=\"md-toc\">")
+}
+
+/// Renders a draft of `full_html` and reads back which page each of `headings` landed on,
+/// by matching the draft's outline against the headings in document order.
+/// Returns the page label (as shown in the page numbers) for each heading anchor.
+/// Local files are only loaded from `local_roots`, the drafts are written to `temp_dir`.
+pub fn locate_headings(
+    settings: &Settings,
+    full_html: &str,
+    local_roots: &[PathBuf],
+    temp_dir: &Path,
+    headings: &[Heading],
+) -> Result<HashMap<String, String>, String> {
+    // Sections are rendered separately like the final output, so their pagination matches
+    let sections = layout::split_sections(full_html);
+    let mut outline = Vec::new(); // (page index, title)
+    let mut ranges = Vec::new();
+    let mut first_page = 0;
+    for (i, section) in sections.iter().enumerate() {
+        let draft_path = temp_dir.join(format!("draft_{i}.pdf"));
+        let result = convert::run_wkhtmltopdf(
+            settings,
+            &section.html,
+            local_roots,
+            &draft_path,
+            section.landscape,
+            &draft_path,
+            &["--outline", "--outline-depth", "6"],
+        )
+        .and_then(|()| {
+            Document::load(&draft_path).map_err(|e| format!("Failed to read draft PDF: {}", e))
+        });
+        let _ = fs::remove_file(&draft_path);
+        let draft = result?;
+
+        let entries = match draft.get_toc() {
+            Ok(toc) => toc.toc,
+            Err(lopdf::Error::NoOutline) => Vec::new(), // A section without headings
+            Err(e) => {
+                return Err(format!(
+                    "Failed to read the draft's outline (is wkhtmltopdf built with patched Qt?): {}",
+                    e
+                ));
+            }
+        };
+        // Matches the blank page inserted before chapters that would start on an even page
+        if section.recto && first_page % 2 == 1 {
+            first_page += 1;
+        }
+        if let Some(style) = section.numbering {
+            ranges.push((first_page, style));
+        }
+        outline.extend(
+            entries
+                .into_iter()
+                .map(|entry| (first_page + entry.page - 1, entry.title)),
+        );
+        first_page += draft.get_pages().len();
+    }
+
+    let mut pages = HashMap::new();
+    let mut cursor = 0;
+    for heading in headings {
+        if let Some(offset) = outline[cursor..]
+            .iter()
+            .position(|(_, title)| title.trim() == heading.text)
+        {
+            let page = outline[cursor + offset].0;
+            pages.insert(heading.id.clone(), numbering::label(page, &ranges));
+            cursor += offset + 1;
+        }
+    }
+    Ok(pages)
+}
@@ -0,0 +1,132 @@
+use hyphenation::{Hyphenator, Language, Load, Standard};
+use pulldown_cmark::{Event, Tag, TagEnd};
+
+/// Words shorter than this are never hyphenated
+const MIN_HYPHENATION_LENGTH: usize = 6;
+
+/// Builds the CSS layer for the justification, hyphenation and book paragraph options.
+/// `hyphens: auto` is only honoured by newer engines, so hyphenated documents also get
+/// soft hyphens inserted by [`SoftHyphenator`].
+pub fn css(justify: bool, hyphenate: bool, book_paragraphs: bool) -> String {
+    let mut css = String::new();
+    if justify {
+        css.push_str(
+            r#".markdown-body p,
+.markdown-body li,
+.markdown-body dd {
+  text-align: justify;
+}
+"#,
+        );
+    }
+    if hyphenate {
+        css.push_str(
+            r#".markdown-body {
+  -webkit-hyphens: auto;
+  hyphens: auto;
+}
+
+.markdown-body h1, .markdown-body h2, .markdown-body h3,
+.markdown-body h4, .markdown-body h5, .markdown-body h6,
+.markdown-body pre, .markdown-body code, .markdown-body table {
+  -webkit-hyphens: manual;
+  hyphens: manual;
+}
+"#,
+        );
+    }
+    if book_paragraphs {
+        // Paragraphs follow each other without a gap, all but the first indented; other
+        // blocks keep their spacing
+        css.push_str(
+            r#".markdown-body p {
+  margin-top: 0;
+  margin-bottom: 0;
+}
+
+.markdown-body p + p {
+  text-indent: 1.5em;
+}
+
+.markdown-body p + :not(p) {
+  margin-top: 1rem;
+}
+"#,
+        );
+    }
+    css
+}
+
+/// Inserts soft hyphens (U+00AD) into running text using the TeX patterns bundled with the
+/// hyphenation crate, for backends that ignore `hyphens: auto`.
+pub struct SoftHyphenator {
+    dictionary: Standard,
+}
+
+impl SoftHyphenator {
+    /// Loads the dictionary for a BCP 47 tag such as `en-US` or `de`.
+    /// Returns `None` when no patterns exist for the language.
+    pub fn for_lang(lang: &str) -> Option<Self> {
+        let code = lang.trim().to_lowercase().replace('_', "-");
+        let primary = code.split('-').next().unwrap_or_default();
+        let language = Language::try_from_code(&code).or_else(|| match primary {
+            "en" => Some(Language::EnglishUS),
+            "de" => Some(Language::German1996),
+            _ => Language::try_from_code(primary),
+        })?;
+        let dictionary = Standard::from_embedded(language).ok()?;
+        Some(Self { dictionary })
+    }
+
+    /// Hyphenates every long enough word in `text`
+    pub fn hyphenate_text(&self, text: &str) -> String {
+        let mut out = String::with_capacity(text.len() + text.len() / 8);
+        let mut word_start = None;
+        for (i, c) in text.char_indices() {
+            if c.is_alphabetic() {
+                word_start.get_or_insert(i);
+            } else {
+                if let Some(start) = word_start.take() {
+                    self.push_word(&mut out, &text[start..i]);
+                }
+                out.push(c);
+            }
+        }
+        if let Some(start) = word_start {
+            self.push_word(&mut out, &text[start..]);
+        }
+        out
+    }
+
+    fn push_word(&self, out: &mut String, word: &str) {
+        if word.chars().count() < MIN_HYPHENATION_LENGTH {
+            out.push_str(word);
+            return;
+        }
+        let mut segments = self.dictionary.hyphenate(word).into_iter();
+        segments.mark_with("\u{ad}");
+        out.extend(segments);
+    }
+
+    /// Applies [`Self::hyphenate_text`] to prose, leaving headings and code untouched
+    pub fn apply<'a>(&self, events: Vec<Event<'a>>) -> Vec<Event<'a>> {
+        let mut skip_depth = 0usize;
+        events
+            .into_iter()
+            .map(|event| match event {
+                Event::Start(Tag::Heading { .. } | Tag::CodeBlock(_)) => {
+                    skip_depth += 1;
+                    event
+                }
+                Event::End(TagEnd::Heading(_) | TagEnd::CodeBlock) => {
+                    skip_depth = skip_depth.saturating_sub(1);
+                    event
+                }
+                Event::Text(text) if skip_depth == 0 => {
+                    Event::Text(self.hyphenate_text(&text).into())
+                }
+                other => other,
+            })
+            .collect()
+    }
+}
@@ -0,0 +1,241 @@
+use crate::convert::escape_attr;
+use crate::security;
+use pulldown_cmark::{Event, Tag, TagEnd};
+
+/// Elements that have neither content nor an end tag in HTML
+const VOID_ELEMENTS: &[&str] = &[
+    "area", "base", "br", "col", "embed", "hr", "img", "input", "link", "meta", "param", "source",
+    "track", "wbr",
+];
+
+/// An element of raw HTML that isn't closed yet
+struct OpenElement {
+    name: String, // As written
+    depth: usize, // Markdown elements around it
+}
+
+/// Rewrites the raw HTML among `events` as XHTML, which e-book readers parse strictly:
+/// void elements are closed, attribute values are quoted, character references become
+/// characters and elements are closed inside the Markdown element they start in. Scripts
+/// and end tags without a start tag are left out with a warning, comments silently.
+pub fn events<'a>(events: Vec<Event<'a>>, warnings: &mut Vec<String>) -> Vec<Event<'a>> {
+    let mut out = Vec::with_capacity(events.len());
+    let mut open: Vec<OpenElement> = Vec::new();
+    let mut depth = 0;
+    // Consecutive raw HTML, as tags may span the lines of an HTML block
+    let mut raw = String::new();
+    for event in events {
+        match event {
+            Event::Html(html) | Event::InlineHtml(html) => raw.push_str(&html),
+            event => {
+                if !raw.is_empty() {
+                    out.push(Event::Html(
+                        rewrite(&raw, depth, &mut open, warnings).into(),
+                    ));
+                    raw.clear();
+                }
+                match event {
+                    Event::Start(Tag::HtmlBlock) | Event::End(TagEnd::HtmlBlock) => {}
+                    Event::Start(_) => depth += 1,
+                    Event::End(_) => {
+                        out.extend(close(&mut open, |element| element.depth == depth));
+                        depth -= 1;
+                    }
+                    _ => {}
+                }
+                out.push(event);
+            }
+        }
+    }
+    if !raw.is_empty() {
+        out.push(Event::Html(
+            rewrite(&raw, depth, &mut open, warnings).into(),
+        ));
+    }
+    out.extend(close(&mut open, |_| true));
+    out
+}
+
+/// End tags of the innermost open elements for which `inside` holds
+fn close<'a>(
+    open: &mut Vec<OpenElement>,
+    inside: impl Fn(&OpenElement) -> bool,
+) -> Option<Event<'a>> {
+    let mut html = String::new();
+    while let Some(element) = open.pop_if(|element| inside(element)) {
+        html.push_str(&format!("</{}>", element.name));
+    }
+    (!html.is_empty()).then(|| Event::Html(html.into()))
+}
+
+/// `text` with its character references decoded and the characters XML reserves escaped
+fn text(text: &str, warnings: &mut Vec<String>) -> String {
+    match security::decode_references(text) {
+        Some(decoded) => escape_attr(&decoded),
+        None => {
+            warnings.push(format!(
+                "Character reference not understood, written as text in the e-book: '{}'",
+                text.trim()
+            ));
+            escape_attr(text)
+        }
+    }
+}
+
+/// Whether XML accepts `name` as an attribute name
+fn is_name(name: &str) -> bool {
+    let mut chars = name.chars();
+    chars
+        .next()
+        .is_some_and(|c| c.is_ascii_alphabetic() || c == '_' || c == ':')
+        && chars.all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.' | ':'))
+}
+
+/// `html` as XHTML, see [`events`]. `open` holds the elements not closed yet, and
+/// elements started here are at `depth`.
+fn rewrite(
+    html: &str,
+    depth: usize,
+    open: &mut Vec<OpenElement>,
+    warnings: &mut Vec<String>,
+) -> String {
+    let mut out = String::new();
+    let mut rest = html;
+    while let Some(start) = rest.find('<') {
+        out.push_str(&text(&rest[..start], warnings));
+        rest = &rest[start..];
+        if let Some(comment) = rest.strip_prefix("<!--") {
+            rest = comment.find("-->").map_or("", |end| &comment[end + 3..]);
+            continue;
+        }
+        if rest.starts_with("<![CDATA[") {
+            let end = rest.find("]]>").map_or(rest.len(), |end| end + 3);
+            out.push_str(&rest[..end]);
+            rest = &rest[end..];
+            continue;
+        }
+        if rest.starts_with("<!") || rest.starts_with("<?") {
+            // Doctypes and processing instructions
+            rest = rest.find('>').map_or("", |end| &rest[end + 1..]);
+            continue;
+        }
+        let Some(tag) = security::parse_tag(rest) else {
+            out.push_str("&lt;");
+            rest = &rest[1..];
+            continue;
+        };
+        let name = &rest[1 + usize::from(tag.end)..][..tag.name.len()];
+        let self_closing = rest[..tag.length - 1].trim_end().ends_with('/');
+        rest = &rest[tag.length..];
+
+        if tag.end {
+            let found = open.iter().rposition(|element| {
+                element.depth == depth && element.name.eq_ignore_ascii_case(name)
+            });
+            match found {
+                Some(index) => {
+                    while open.len() > index {
+                        if let Some(element) = open.pop() {
+                            out.push_str(&format!("</{}>", element.name));
+                        }
+                    }
+                }
+                None if VOID_ELEMENTS.contains(&tag.name.as_str()) => {}
+                None => warnings.push(format!(
+                    "End tag without a start tag left out of the e-book: '</{name}>'"
+                )),
+            }
+            continue;
+        }
+        if tag.name == "script" {
+            warnings.push("Script left out of the e-book".to_string());
+            rest = security::closing_tag(rest, "script").map_or("", |(_, end)| &rest[end..]);
+            continue;
+        }
+
+        out.push('<');
+        out.push_str(name);
+        let mut written: Vec<String> = Vec::new();
+        for (attribute, value) in &tag.attributes {
+            if !is_name(attribute) || written.iter().any(|w| w.eq_ignore_ascii_case(attribute)) {
+                continue;
+            }
+            // Attributes without a value are named after themselves
+            let value = match value.as_str() {
+                "" => attribute.clone(),
+                value => security::decode_references(value).unwrap_or(value.to_string()),
+            };
+            out.push_str(&format!(" {attribute}=\"{}\"", escape_attr(&value)));
+            written.push(attribute.clone());
+        }
+        if self_closing || VOID_ELEMENTS.contains(&tag.name.as_str()) {
+            out.push_str(" />");
+        } else if tag.name == "style" {
+            // The style sheet is raw text in HTML but parsed in XHTML
+            let (content, after) = match security::closing_tag(rest, "style") {
+                Some((start, end)) => (&rest[..start], &rest[end..]),
+                None => (rest, ""),
+            };
+            out.push_str(&format!(">{}</{name}>", escape_attr(content)));
+            rest = after;
+        } else {
+            out.push('>');
+            open.push(OpenElement {
+                name: name.to_string(),
+                depth,
+            });
+        }
+    }
+    out.push_str(&text(rest, warnings));
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn xhtml(markdown: &str) -> (String, Vec<String>) {
+        let mut warnings = Vec::new();
+        let parsed = pulldown_cmark::Parser::new(markdown).collect();
+        let mut html = String::new();
+        pulldown_cmark::html::push_html(&mut html, events(parsed, &mut warnings).into_iter());
+        (html, warnings)
+    }
+
+    #[test]
+    fn void_elements_are_closed_and_attributes_quoted() {
+        let (html, _) = xhtml("Line<br>break <img src=a.png alt='a &amp; b' hidden>\n");
+        assert_eq!(
+            html,
+            "<p>Line<br />break <img src=\"a.png\" alt=\"a &amp; b\" hidden=\"hidden\" /></p>\n"
+        );
+    }
+
+    #[test]
+    fn elements_close_inside_their_markdown_element() {
+        let (html, _) = xhtml("Some <span>text\n\nMore</span> text\n");
+        assert_eq!(html, "<p>Some <span>text</span></p>\n<p>More text</p>\n");
+    }
+
+    #[test]
+    fn svg_keeps_its_case_and_self_closing_tags() {
+        let (html, _) = xhtml("<svg viewBox=\"0 0 1 1\"><path d=\"M0 0\"/></svg>\n");
+        assert_eq!(
+            html,
+            "<p><svg viewBox=\"0 0 1 1\"><path d=\"M0 0\" /></svg></p>\n"
+        );
+    }
+
+    #[test]
+    fn scripts_and_comments_are_left_out() {
+        let (html, _) = xhtml("<div><!-- note --><script>if (a < b) {}</script>&nbsp;&</div>\n");
+        assert_eq!(html, "<div>\u{a0}&amp;</div>\n");
+    }
+
+    #[test]
+    fn unmatched_end_tags_are_left_out_with_a_warning() {
+        let (html, warnings) = xhtml("text</div>\n");
+        assert_eq!(html, "<p>text</p>\n");
+        assert_eq!(warnings.len(), 1);
+    }
+}